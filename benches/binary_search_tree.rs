@@ -0,0 +1,77 @@
+//! Compares the boxed `BinarySearchTree` with the `Vec` backed
+//! `BinarySearchTreeArena`.
+//!
+//! Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use trees::{BinarySearchTree, BinarySearchTreeArena};
+
+/// Deterministic pseudo random keys, so that the trees stay reasonably balanced
+fn keys(len: usize) -> Vec<u64> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+
+    (0..len)
+        .map(|_| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        })
+        .collect()
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+
+    for len in [1_000, 100_000] {
+        let keys = keys(len);
+
+        group.bench_with_input(BenchmarkId::new("boxed", len), &keys, |b, keys| {
+            b.iter(|| keys.iter().copied().collect::<BinarySearchTree<_>>())
+        });
+        group.bench_with_input(BenchmarkId::new("arena", len), &keys, |b, keys| {
+            b.iter(|| keys.iter().copied().collect::<BinarySearchTreeArena<_>>())
+        });
+    }
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+
+    for len in [1_000, 100_000] {
+        let keys = keys(len);
+        let boxed: BinarySearchTree<_> = keys.iter().copied().collect();
+        let arena: BinarySearchTreeArena<_> = keys.iter().copied().collect();
+
+        group.bench_with_input(BenchmarkId::new("boxed", len), &keys, |b, keys| {
+            b.iter(|| keys.iter().filter(|key| boxed.contains(*key)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("arena", len), &keys, |b, keys| {
+            b.iter(|| keys.iter().filter(|key| arena.contains(*key)).count())
+        });
+    }
+    group.finish();
+}
+
+fn iterate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterate");
+
+    for len in [1_000, 100_000] {
+        let keys = keys(len);
+        let boxed: BinarySearchTree<_> = keys.iter().copied().collect();
+        let arena: BinarySearchTreeArena<_> = keys.iter().copied().collect();
+
+        group.bench_function(BenchmarkId::new("boxed", len), |b| {
+            b.iter(|| black_box(&boxed).iter().sum::<u64>())
+        });
+        group.bench_function(BenchmarkId::new("arena", len), |b| {
+            b.iter(|| black_box(&arena).iter().sum::<u64>())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert, search, iterate);
+criterion_main!(benches);
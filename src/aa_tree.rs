@@ -0,0 +1,494 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    fmt, mem,
+};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+    /// one for leaves, like the black height of the equivalent red-black tree
+    level: usize,
+}
+
+fn level<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.level)
+}
+
+/// A self-balancing binary search tree behaving like a set
+///
+/// An AA tree is a red-black tree where only right children may be red,
+/// expressed through levels instead of colors: a left child is always one
+/// level below its parent and a right child at most as high, but never two in
+/// a row. Only two rebalancing operations exist, a right rotation (`skew`) and
+/// a left rotation with promotion (`split`), which keeps the code short while
+/// bounding the height by `2 log2(n + 1)`.
+#[derive(Clone)]
+pub struct AaTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+    len: usize,
+}
+
+impl<T> AaTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        fn height<T>(link: &Link<T>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return Some(&current.data),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        let mut inserted = false;
+
+        self.root = Some(Self::insert_into(self.root.take(), data, &mut inserted));
+
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    fn insert_into(link: Link<T>, data: T, inserted: &mut bool) -> Box<Node<T>> {
+        let Some(mut node) = link else {
+            *inserted = true;
+            return Box::new(Node {
+                data,
+                left: None,
+                right: None,
+                level: 1,
+            });
+        };
+
+        match data.cmp(&node.data) {
+            Equal => return node,
+            Less => node.left = Some(Self::insert_into(node.left.take(), data, inserted)),
+            Greater => node.right = Some(Self::insert_into(node.right.take(), data, inserted)),
+        }
+
+        Self::split(Self::skew(node))
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // leaves the path untouched instead of rebalancing it for nothing
+        if !self.contains(key) {
+            return None;
+        }
+
+        let mut removed = None;
+        let root = self.root.take().expect("key is present");
+        self.root = Self::take_from(root, key, &mut removed);
+
+        self.len -= 1;
+        removed
+    }
+
+    fn take_from<Q>(mut node: Box<Node<T>>, key: &Q, removed: &mut Option<T>) -> Link<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match key.cmp(node.data.borrow()) {
+            Less => {
+                let left = node.left.take().expect("key is in the left subtree");
+                node.left = Self::take_from(left, key, removed);
+            }
+            Greater => {
+                let right = node.right.take().expect("key is in the right subtree");
+                node.right = Self::take_from(right, key, removed);
+            }
+            // a node with a left child always has a right one, so a node
+            // without a right child is a leaf
+            Equal => match node.right.take() {
+                None => {
+                    *removed = Some(node.data);
+                    return None;
+                }
+                // replace with the in-order successor, the leftmost of the right
+                Some(right) => {
+                    let (right, successor) = Self::take_min(right);
+                    *removed = Some(mem::replace(&mut node.data, successor));
+                    node.right = right;
+                }
+            },
+        }
+
+        Some(Self::rebalance(node))
+    }
+
+    /// Removes the smallest element under `node`, returning what is left of
+    /// the subtree along with the element
+    fn take_min(mut node: Box<Node<T>>) -> (Link<T>, T) {
+        let Some(left) = node.left.take() else {
+            return (node.right.take(), node.data);
+        };
+
+        let (left, min) = Self::take_min(left);
+        node.left = left;
+
+        (Some(Self::rebalance(node)), min)
+    }
+
+    /// Restores the levels below a node that lost a descendant
+    fn rebalance(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        let expected = 1 + level(&node.left).min(level(&node.right));
+        if expected < node.level {
+            node.level = expected;
+
+            if let Some(right) = &mut node.right {
+                right.level = right.level.min(expected);
+            }
+        }
+
+        // up to three nodes of the same level may now form a horizontal chain
+        let mut node = Self::skew(node);
+        if let Some(right) = node.right.take() {
+            let mut right = Self::skew(right);
+            right.right = right.right.take().map(Self::skew);
+            node.right = Some(right);
+        }
+
+        let mut node = Self::split(node);
+        node.right = node.right.take().map(Self::split);
+        node
+    }
+
+    /// Rotates right when the left child is on the same level
+    fn skew(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        match node.left.take() {
+            Some(mut left) if left.level == node.level => {
+                node.left = left.right.take();
+                left.right = Some(node);
+                left
+            }
+            left => {
+                node.left = left;
+                node
+            }
+        }
+    }
+
+    /// Rotates left and promotes the middle node when two right children in a
+    /// row are on the same level
+    fn split(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        let too_wide = node
+            .right
+            .as_ref()
+            .is_some_and(|right| level(&right.right) == node.level);
+        if !too_wide {
+            return node;
+        }
+
+        let mut right = node.right.take().expect("checked above");
+        node.right = right.left.take();
+        right.left = Some(node);
+        right.level += 1;
+        right
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> AaTreeIterator<'_, T> {
+        let mut iter = AaTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the elements are in order and all the AA invariants
+    /// hold: leaves are on level one, left children one level below their
+    /// parent, right children at most as high and right grandchildren strictly
+    /// lower, and every node above level one has two children
+    pub fn is_valid(&self) -> bool {
+        fn check<T: Ord>(link: &Link<T>, lower: Option<&T>, upper: Option<&T>) -> bool {
+            let Some(node) = link else {
+                return true;
+            };
+
+            let right_grandchild = node.right.as_ref().map_or(0, |right| level(&right.right));
+            let shape_ok = level(&node.left) + 1 == node.level
+                && (level(&node.right) + 1 == node.level || level(&node.right) == node.level)
+                && right_grandchild < node.level
+                && (node.level == 1 || (node.left.is_some() && node.right.is_some()));
+
+            shape_ok
+                && lower.is_none_or(|lower| node.data > *lower)
+                && upper.is_none_or(|upper| node.data < *upper)
+                && check(&node.left, lower, Some(&node.data))
+                && check(&node.right, Some(&node.data), upper)
+        }
+
+        check(&self.root, None, None) && self.iter().count() == self.len
+    }
+}
+
+impl<T> Default for AaTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for AaTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements, regardless of their shape
+impl<T> PartialEq for AaTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for AaTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for AaTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for AaTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of an [`AaTree`] in order
+pub struct AaTreeIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> AaTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for AaTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for AaTreeIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a AaTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = AaTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+
+    #[test]
+    fn test_insert_search() {
+        let mut tree: AaTree<u32> = prequel();
+
+        assert_eq!(tree.len(), 7);
+        assert!(!tree.insert(21));
+        assert!(tree.insert(22));
+        assert_eq!(tree.get(&22), Some(&22));
+        assert_eq!((tree.min(), tree.max()), (Some(&3), Some(&70)));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &22, &28, &36, &70]
+        );
+        assert!(tree.is_valid());
+
+        let tree: AaTree<u32> = (0..1023).collect();
+        assert!(tree.is_valid());
+        assert!(tree.height() <= 20);
+    }
+
+    /// Returns the elements with their levels in preorder, which shows the
+    /// shape of the tree
+    fn levels(tree: &AaTree<u32>) -> Vec<(u32, usize)> {
+        let mut levels = Vec::new();
+        let mut stack = vec![&tree.root];
+
+        while let Some(link) = stack.pop() {
+            if let Some(node) = link {
+                levels.push((node.data, node.level));
+                stack.push(&node.right);
+                stack.push(&node.left);
+            }
+        }
+        levels
+    }
+
+    #[test]
+    fn test_remove_lowers_levels() {
+        // sorted insertions split every third node of a level into a perfect
+        // tree
+        let mut tree: AaTree<u32> = (1..=7).collect();
+        assert_eq!(
+            levels(&tree),
+            vec![(4, 3), (2, 2), (1, 1), (3, 1), (6, 2), (5, 1), (7, 1)]
+        );
+
+        // a node losing its left child drops to the level of its right one,
+        // which then hangs from it horizontally, and so does the root
+        assert!(tree.remove(&1));
+        assert_eq!(
+            levels(&tree),
+            vec![(4, 2), (2, 1), (3, 1), (6, 2), (5, 1), (7, 1)]
+        );
+
+        // the successor taking the place of the root lowers its old parent
+        assert_eq!(tree.take(&4), Some(4));
+        assert_eq!(levels(&tree), vec![(5, 2), (2, 1), (3, 1), (6, 1), (7, 1)]);
+        assert!(tree.is_valid());
+
+        // emptying the tree from one end keeps the height within its bound
+        let mut tree: AaTree<u32> = (0..1023).collect();
+        for data in 0..1023 {
+            assert!(tree.remove(&data));
+            assert!(tree.is_valid());
+
+            let len = tree.len() as f64;
+            assert!(tree.height() as f64 <= 2.0 * (len + 1.0).log2());
+        }
+        assert_eq!(tree.min(), None);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::Trie;
+use std::fmt;
+
+const ROOT: u32 = 0;
+const NIL: u32 = u32::MAX;
+
+#[derive(Clone)]
+struct State {
+    /// the trie children of this prefix, sorted by byte
+    next: Vec<(u8, u32)>,
+    /// state of the longest proper suffix of this prefix that is a prefix of
+    /// some pattern
+    fail: u32,
+    /// index of the pattern ending here
+    pattern: Option<u32>,
+    /// nearest state along the failure links with a pattern, or `NIL`
+    output: u32,
+}
+
+impl State {
+    fn child(&self, byte: u8) -> Option<u32> {
+        let index = self
+            .next
+            .binary_search_by_key(&byte, |&(byte, _)| byte)
+            .ok()?;
+        Some(self.next[index].1)
+    }
+}
+
+/// An occurrence of a pattern in a haystack
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Match {
+    pattern: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Match {
+    /// Returns the index of the pattern matched, in the order the patterns
+    /// were given
+    pub fn pattern(&self) -> usize {
+        self.pattern
+    }
+
+    /// Returns the position of the first byte of the match
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the position just past the last byte of the match
+    pub fn end(&self) -> usize {
+        self.end
+    }
+}
+
+/// An automaton finding all occurrences of a set of patterns in one pass over
+/// a haystack
+///
+/// The patterns are put in a [`Trie`] first, whose nodes become the states of
+/// the automaton. Each state also gets a failure link to the state of its
+/// longest proper suffix in the trie, followed on a mismatch, so scanning a
+/// haystack of `n` bytes takes `O(n)` steps plus one per match, whatever the
+/// number of patterns.
+///
+/// Empty patterns never match, and a pattern given more than once is reported
+/// under its first index.
+#[derive(Clone)]
+pub struct AhoCorasick {
+    states: Vec<State>,
+    /// length of each pattern, by index
+    lens: Vec<usize>,
+}
+
+impl AhoCorasick {
+    /// Builds the automaton of `patterns`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the patterns need too many states to index them with `u32`.
+    pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        let mut trie = Trie::new();
+        let mut lens = Vec::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            if !pattern.is_empty() && !trie.contains_key(pattern) {
+                trie.insert(pattern, lens.len() as u32);
+            }
+            lens.push(pattern.len());
+        }
+
+        let root = State {
+            next: Vec::new(),
+            fail: ROOT,
+            pattern: None,
+            output: NIL,
+        };
+        let mut states = vec![root];
+        trie.for_each_breadth_first(|parent, byte, pattern| {
+            assert!(
+                states.len() < NIL as usize,
+                "AhoCorasick has too many states"
+            );
+            let state = states.len() as u32;
+            states[parent].next.push((byte, state));
+            states.push(State {
+                next: Vec::new(),
+                fail: ROOT,
+                pattern: pattern.copied(),
+                output: NIL,
+            });
+        });
+
+        let mut automaton = Self { states, lens };
+        automaton.link();
+        automaton
+    }
+
+    /// Sets the failure and output links, in breadth-first order so that the
+    /// links of shorter prefixes are known first
+    fn link(&mut self) {
+        for parent in 0..self.states.len() {
+            for index in 0..self.states[parent].next.len() {
+                let (byte, child) = self.states[parent].next[index];
+                let fail = if parent == ROOT as usize {
+                    ROOT
+                } else {
+                    self.step(self.states[parent].fail, byte)
+                };
+
+                let fail_state = &self.states[fail as usize];
+                let output = if fail_state.pattern.is_some() {
+                    fail
+                } else {
+                    fail_state.output
+                };
+                let child = &mut self.states[child as usize];
+                child.fail = fail;
+                child.output = output;
+            }
+        }
+    }
+
+    /// Returns the state after reading `byte` in `state`
+    fn step(&self, mut state: u32, byte: u8) -> u32 {
+        loop {
+            if let Some(next) = self.states[state as usize].child(byte) {
+                return next;
+            }
+            if state == ROOT {
+                return ROOT;
+            }
+            state = self.states[state as usize].fail;
+        }
+    }
+
+    /// Returns the number of patterns, counting repeated and empty ones
+    pub fn pattern_count(&self) -> usize {
+        self.lens.len()
+    }
+
+    /// Returns the number of states, including the root
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns an iterator over the occurrences of the patterns in
+    /// `haystack`, overlapping ones included, by end position and then from
+    /// the longest
+    pub fn find_iter<'a, 'h>(&'a self, haystack: &'h [u8]) -> FindIter<'a, 'h> {
+        FindIter {
+            automaton: self,
+            haystack,
+            position: 0,
+            state: ROOT,
+            output: NIL,
+        }
+    }
+
+    /// Returns `true` if any pattern occurs in `haystack`
+    pub fn is_match(&self, haystack: &[u8]) -> bool {
+        self.find_iter(haystack).next().is_some()
+    }
+
+    /// Returns `true` if the failure links of all states but the root lead to
+    /// states of shorter prefixes, and the output links to the nearest states
+    /// with a pattern along them
+    pub fn is_valid(&self) -> bool {
+        let mut depths = vec![0; self.states.len()];
+        for (parent, state) in self.states.iter().enumerate() {
+            if !state.next.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+                return false;
+            }
+            for &(_, child) in &state.next {
+                // children come after their parents in breadth-first order
+                if child as usize <= parent {
+                    return false;
+                }
+                depths[child as usize] = depths[parent] + 1;
+            }
+        }
+
+        self.states.iter().enumerate().all(|(index, state)| {
+            let fail = state.fail as usize;
+            let shorter = (index == 0 && fail == 0) || depths[fail] < depths[index];
+            let mut nearest = state.fail;
+            while nearest != ROOT && self.states[nearest as usize].pattern.is_none() {
+                nearest = self.states[nearest as usize].fail;
+            }
+            let nearest = if nearest == ROOT { NIL } else { nearest };
+            let output = index == 0 || state.output == nearest;
+            let pattern = state
+                .pattern
+                .is_none_or(|pattern| self.lens.get(pattern as usize) == Some(&depths[index]));
+            shorter && output && pattern
+        })
+    }
+}
+
+impl fmt::Debug for AhoCorasick {
+    /// Formats the number of patterns and states
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AhoCorasick")
+            .field("patterns", &self.lens.len())
+            .field("states", &self.states.len())
+            .finish()
+    }
+}
+
+/// An iterator over the occurrences of the patterns of an [`AhoCorasick`] in
+/// a haystack, reading it one byte at a time as matches are asked for
+pub struct FindIter<'a, 'h> {
+    automaton: &'a AhoCorasick,
+    haystack: &'h [u8],
+    /// position of the next byte to read
+    position: usize,
+    state: u32,
+    /// next state with a pattern ending at the last byte read, or `NIL`
+    output: u32,
+}
+
+impl Iterator for FindIter<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let states = &self.automaton.states;
+
+        while self.output == NIL {
+            let &byte = self.haystack.get(self.position)?;
+            self.position += 1;
+            self.state = self.automaton.step(self.state, byte);
+
+            let state = &states[self.state as usize];
+            self.output = if state.pattern.is_some() {
+                self.state
+            } else {
+                state.output
+            };
+        }
+
+        let state = &states[self.output as usize];
+        let pattern = state.pattern.expect("output states have a pattern") as usize;
+        self.output = state.output;
+        Some(Match {
+            pattern,
+            start: self.position - self.automaton.lens[pattern],
+            end: self.position,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(automaton: &AhoCorasick, haystack: &str) -> Vec<(usize, usize, usize)> {
+        automaton
+            .find_iter(haystack.as_bytes())
+            .map(|found| (found.pattern(), found.start(), found.end()))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let automaton = AhoCorasick::new(["he", "she", "his", "hers"]);
+
+        assert!(automaton.is_valid());
+        assert_eq!(automaton.pattern_count(), 4);
+        assert_eq!(
+            matches(&automaton, "ushers"),
+            vec![(1, 1, 4), (0, 2, 4), (3, 2, 6)]
+        );
+        assert_eq!(
+            matches(&automaton, "ahishe"),
+            vec![(2, 1, 4), (1, 3, 6), (0, 4, 6)]
+        );
+        assert!(automaton.is_match(b"xhex"));
+        assert!(!automaton.is_match(b"hi s"));
+        assert!(!automaton.is_match(b""));
+    }
+
+    #[test]
+    fn test_prequel_patterns() {
+        let patterns = ["tea", "ten", "to", "inn", "in", "i", "team", "", "in"];
+        let automaton = AhoCorasick::new(patterns);
+        let haystack = "i tried to eat at the inn with a team of ten";
+
+        assert!(automaton.is_valid());
+        let mut found = matches(&automaton, haystack);
+        let mut expected = Vec::new();
+        for (index, pattern) in patterns.iter().enumerate() {
+            if pattern.is_empty() || patterns[..index].contains(pattern) {
+                continue;
+            }
+            expected.extend(
+                haystack
+                    .match_indices(pattern)
+                    .map(|(start, _)| (index, start, start + pattern.len())),
+            );
+            // match_indices skips overlapping occurrences, which these
+            // patterns don't have in the haystack
+        }
+        found.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,722 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    fmt, mem,
+};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+    /// number of nodes on the longest path from this node down to a leaf
+    height: usize,
+    /// number of nodes in the subtree rooted here
+    size: usize,
+}
+
+impl<T> Node<T> {
+    fn new(data: T) -> Box<Self> {
+        Box::new(Self {
+            data,
+            left: None,
+            right: None,
+            height: 1,
+            size: 1,
+        })
+    }
+
+    /// Recomputes the height and size from the children
+    fn update(&mut self) {
+        self.height = 1 + height(&self.left).max(height(&self.right));
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+
+    /// Height of the right subtree minus height of the left subtree
+    fn balance_factor(&self) -> isize {
+        height(&self.right) as isize - height(&self.left) as isize
+    }
+}
+
+fn height<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.height)
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// A self-balancing binary search tree behaving like a set
+///
+/// After every insertion and removal the heights of the two subtrees of any
+/// node differ by at most one, so the tree stays `O(log n)` deep even when the
+/// elements arrive in sorted order.
+///
+/// Every node also stores the size of its subtree, so that [`Self::join`] and
+/// [`Self::split`] can hand out whole subtrees in `O(log n)`.
+#[derive(Clone)]
+pub struct AvlTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+    len: usize,
+}
+
+impl<T> AvlTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// Builds a tree in `O(n)` from elements yielded in ascending order
+    ///
+    /// Each subtree takes the middle element as its root, which leaves the
+    /// heights of any two sibling subtrees at most one apart. Runs of equal
+    /// elements are reduced to their first one. The input must be sorted,
+    /// otherwise the resulting tree violates the ordering invariant (checked
+    /// with a debug assertion).
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut sorted: Vec<T> = iter.into_iter().collect();
+        sorted.dedup();
+        debug_assert!(sorted.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let len = sorted.len();
+        Self::from_root(Self::build(&mut sorted.into_iter(), len))
+    }
+
+    /// Builds a balanced subtree out of the next `len` elements of `iter`,
+    /// consuming them in order: left subtree, middle element, right subtree
+    fn build<I>(iter: &mut I, len: usize) -> Link<T>
+    where
+        I: Iterator<Item = T>,
+    {
+        if len == 0 {
+            return None;
+        }
+
+        let left_len = len / 2;
+        let left = Self::build(iter, left_len);
+        let mut node = Node::new(iter.next().expect("len elements remain"));
+        node.left = left;
+        node.right = Self::build(iter, len - left_len - 1);
+        node.update();
+        Some(node)
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        height(&self.root)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return Some(&current.data),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        let inserted = Self::insert_into(&mut self.root, data);
+
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    fn insert_into(link: &mut Link<T>, data: T) -> bool {
+        let Some(node) = link else {
+            *link = Some(Node::new(data));
+            return true;
+        };
+
+        let inserted = match data.cmp(&node.data) {
+            Equal => false,
+            // data < stored_data
+            Less => Self::insert_into(&mut node.left, data),
+            // data > stored_data
+            Greater => Self::insert_into(&mut node.right, data),
+        };
+
+        if inserted {
+            Self::rebalance(link);
+        }
+        inserted
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let data = Self::take_from(&mut self.root, key)?;
+
+        self.len -= 1;
+        Some(data)
+    }
+
+    fn take_from<Q>(link: &mut Link<T>, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node = link.as_mut()?;
+
+        let data = match key.cmp(node.data.borrow()) {
+            Less => Self::take_from(&mut node.left, key)?,
+            Greater => Self::take_from(&mut node.right, key)?,
+            Equal => match (node.left.is_some(), node.right.is_some()) {
+                // replace with the in-order successor, the leftmost of the right
+                (true, true) => {
+                    let successor = Self::take_min(&mut node.right);
+                    mem::replace(&mut node.data, successor)
+                }
+                _ => {
+                    let node = link.take().expect("matched above");
+                    let Node {
+                        data, left, right, ..
+                    } = *node;
+
+                    *link = left.or(right);
+                    return Some(data);
+                }
+            },
+        };
+
+        Self::rebalance(link);
+        Some(data)
+    }
+
+    /// Removes and returns the smallest element under the non-empty `link`
+    fn take_min(link: &mut Link<T>) -> T {
+        let node = link.as_mut().expect("link is not empty");
+
+        if node.left.is_some() {
+            let min = Self::take_min(&mut node.left);
+            Self::rebalance(link);
+            return min;
+        }
+
+        let node = link.take().expect("link is not empty");
+        *link = node.right;
+        node.data
+    }
+
+    /// Restores the height, the size and the AVL balance of the node at `link`,
+    /// whose subtrees are balanced and differ in height by at most two
+    fn rebalance(link: &mut Link<T>) {
+        let Some(node) = link else {
+            return;
+        };
+
+        node.update();
+
+        match node.balance_factor() {
+            // left heavy
+            -2 => {
+                if node
+                    .left
+                    .as_ref()
+                    .is_some_and(|left| left.balance_factor() > 0)
+                {
+                    Self::rotate_left(&mut node.left);
+                }
+                Self::rotate_right(link);
+            }
+            // right heavy
+            2 => {
+                if node
+                    .right
+                    .as_ref()
+                    .is_some_and(|right| right.balance_factor() < 0)
+                {
+                    Self::rotate_right(&mut node.right);
+                }
+                Self::rotate_left(link);
+            }
+            _ => {}
+        }
+    }
+
+    /// Lifts the left child of the node at `link` into its place
+    fn rotate_right(link: &mut Link<T>) {
+        let mut node = link.take().expect("rotating an empty link");
+        let mut left = node
+            .left
+            .take()
+            .expect("rotating right without a left child");
+
+        node.left = left.right.take();
+        node.update();
+        left.right = Some(node);
+        left.update();
+
+        *link = Some(left);
+    }
+
+    /// Lifts the right child of the node at `link` into its place
+    fn rotate_left(link: &mut Link<T>) {
+        let mut node = link.take().expect("rotating an empty link");
+        let mut right = node
+            .right
+            .take()
+            .expect("rotating left without a right child");
+
+        node.right = right.left.take();
+        node.update();
+        right.left = Some(node);
+        right.update();
+
+        *link = Some(right);
+    }
+
+    /// Joins `left`, `pivot` and `right` into one tree
+    ///
+    /// The shorter tree is hung off the side of the taller one at the matching
+    /// height, below a node holding `pivot`, then rebalanced on the way back
+    /// up, in `O(|left.height() - right.height()| + 1)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless every element of `left` is less than `pivot` and every
+    /// element of `right` is greater than it.
+    pub fn join(left: Self, pivot: T, right: Self) -> Self {
+        assert!(
+            left.max().is_none_or(|max| *max < pivot) && right.min().is_none_or(|min| pivot < *min),
+            "the pivot must lie between the joined trees"
+        );
+
+        Self::from_root(Self::join_links(left.root, Node::new(pivot), right.root))
+    }
+
+    /// Splits the tree at `key` into the elements less than it, the element
+    /// equal to it if there is one, and the elements greater than it
+    ///
+    /// The subtrees hanging off the search path for `key` are joined back
+    /// together on each side, in `O(log n)` overall.
+    pub fn split<Q>(self, key: &Q) -> (Self, Option<T>, Self)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (less, found, greater) = Self::split_link(self.root, key);
+        (Self::from_root(less), found, Self::from_root(greater))
+    }
+
+    /// Moves the elements of both trees into one, keeping the element of
+    /// `self` when both hold equal ones
+    ///
+    /// `self` is split at the root of `other`, and the unions of the two sides
+    /// are joined back with that root in between, in `O(m log(n / m + 1))` for
+    /// trees of `m <= n` elements.
+    pub fn union(self, other: Self) -> Self {
+        Self::from_root(Self::union_links(self.root, other.root))
+    }
+
+    fn from_root(root: Link<T>) -> Self {
+        Self {
+            len: size(&root),
+            root,
+        }
+    }
+
+    /// Joins two balanced subtrees with `pivot`, a lone node, in between
+    fn join_links(left: Link<T>, mut pivot: Box<Node<T>>, right: Link<T>) -> Link<T> {
+        let (left_height, right_height) = (height(&left), height(&right));
+        let mut link;
+
+        if left_height > right_height + 1 {
+            let mut node = left.expect("left is the taller tree");
+            node.right = Self::join_links(node.right.take(), pivot, right);
+            link = Some(node);
+        } else if right_height > left_height + 1 {
+            let mut node = right.expect("right is the taller tree");
+            node.left = Self::join_links(left, pivot, node.left.take());
+            link = Some(node);
+        } else {
+            pivot.left = left;
+            pivot.right = right;
+            link = Some(pivot);
+        }
+
+        // the joined side grew by at most one level
+        Self::rebalance(&mut link);
+        link
+    }
+
+    fn union_links(link: Link<T>, other: Link<T>) -> Link<T> {
+        let Some(mut pivot) = other else {
+            return link;
+        };
+        if link.is_none() {
+            return Some(pivot);
+        }
+
+        let (other_left, other_right) = (pivot.left.take(), pivot.right.take());
+        let (less, found, greater) = Self::split_link(link, &pivot.data);
+        if let Some(found) = found {
+            pivot.data = found;
+        }
+
+        Self::join_links(
+            Self::union_links(less, other_left),
+            pivot,
+            Self::union_links(greater, other_right),
+        )
+    }
+
+    fn split_link<Q>(link: Link<T>, key: &Q) -> (Link<T>, Option<T>, Link<T>)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(mut node) = link else {
+            return (None, None, None);
+        };
+        let (left, right) = (node.left.take(), node.right.take());
+
+        match key.cmp(node.data.borrow()) {
+            Equal => (left, Some(node.data), right),
+            Less => {
+                let (less, found, greater) = Self::split_link(left, key);
+                (less, found, Self::join_links(greater, node, right))
+            }
+            Greater => {
+                let (less, found, greater) = Self::split_link(right, key);
+                (Self::join_links(left, node, less), found, greater)
+            }
+        }
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> AvlTreeIterator<'_, T> {
+        let mut iter = AvlTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the elements are in order, every stored height and
+    /// size is correct and no node is out of balance
+    pub fn is_valid(&self) -> bool {
+        fn check<T: Ord>(link: &Link<T>, lower: Option<&T>, upper: Option<&T>) -> Option<usize> {
+            let Some(node) = link else {
+                return Some(0);
+            };
+
+            if lower.is_some_and(|lower| node.data <= *lower)
+                || upper.is_some_and(|upper| node.data >= *upper)
+            {
+                return None;
+            }
+
+            let left = check(&node.left, lower, Some(&node.data))?;
+            let right = check(&node.right, Some(&node.data), upper)?;
+
+            let balanced = left.abs_diff(right) <= 1 && node.height == 1 + left.max(right);
+            let sized = node.size == 1 + size(&node.left) + size(&node.right);
+            (balanced && sized).then_some(node.height)
+        }
+
+        check(&self.root, None, None).is_some() && self.iter().count() == self.len
+    }
+}
+
+impl<T> Default for AvlTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for AvlTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements, regardless of their shape
+impl<T> PartialEq for AvlTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for AvlTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for AvlTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for AvlTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of an [`AvlTree`] in order
+pub struct AvlTreeIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> AvlTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for AvlTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for AvlTreeIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a AvlTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = AvlTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+
+    #[test]
+    fn test_insert_search() {
+        let mut tree: AvlTree<u32> = prequel();
+
+        assert_eq!(tree.len(), 7);
+        assert!(tree.contains(&21));
+        assert!(!tree.contains(&22));
+        assert!(!tree.insert(21));
+        assert!(tree.insert(22));
+        assert_eq!(tree.get(&22), Some(&22));
+        assert_eq!(tree.min(), Some(&3));
+        assert_eq!(tree.max(), Some(&70));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &22, &28, &36, &70]
+        );
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_sorted_insertions_stay_balanced() {
+        let mut tree: AvlTree<u32> = (0..1024).collect();
+
+        assert_eq!(tree.len(), 1024);
+        assert_eq!(tree.height(), 11);
+        assert!(tree.is_valid());
+
+        for data in (0..1024).rev() {
+            tree.insert(data + 1024);
+        }
+        assert!(tree.height() <= 12);
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree: AvlTree<u32> = (0..200).collect();
+
+        for data in (0..200).step_by(3) {
+            assert!(tree.remove(&data));
+            assert!(tree.is_valid());
+        }
+        assert!(!tree.remove(&0));
+        assert_eq!(tree.take(&1), Some(1));
+        assert_eq!(tree.len(), 132);
+        assert!(tree.iter().all(|data| data % 3 != 0 && *data != 1));
+        assert!(tree.height() <= 10);
+
+        for data in 0..200 {
+            tree.remove(&data);
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.height(), 0);
+        assert_eq!(tree.min(), None);
+    }
+
+    #[test]
+    fn test_join_split() {
+        let (less, found, greater) = prequel::<AvlTree<u32>>().split(&21);
+
+        assert_eq!(less.iter().collect::<Vec<_>>(), vec![&3, &7, &16]);
+        assert_eq!(found, Some(21));
+        assert_eq!(greater.iter().collect::<Vec<_>>(), vec![&28, &36, &70]);
+        assert!(less.is_valid() && greater.is_valid());
+
+        let tree = AvlTree::join(less, 21, greater);
+        assert_eq!(tree, prequel());
+        assert!(tree.is_valid());
+
+        let (less, found, greater) = tree.split(&22);
+        assert_eq!((less.len(), found, greater.len()), (4, None, 3));
+
+        // trees of very different heights
+        let tree = AvlTree::join((0..1000).collect(), 1000, (1001..1004).collect());
+        assert_eq!(tree.len(), 1004);
+        assert!(tree.iter().copied().eq(0..1004));
+        assert!(tree.is_valid());
+
+        for key in [0, 1, 500, 1003, 2000] {
+            let (less, _, greater) = tree.clone().split(&key);
+            assert!(less.is_valid() && greater.is_valid());
+            assert_eq!(less.len(), key.min(1004) as usize);
+        }
+    }
+
+    #[test]
+    fn test_union() {
+        let evens: AvlTree<u32> = (0..1000).step_by(2).collect();
+        let threes: AvlTree<u32> = (0..1000).step_by(3).collect();
+
+        let union = evens.union(threes);
+        assert!(union.is_valid());
+        assert!(union
+            .iter()
+            .copied()
+            .eq((0..1000).filter(|data| data % 2 == 0 || data % 3 == 0)));
+
+        let union = union.union(AvlTree::new()).union(prequel::<AvlTree<u32>>());
+        assert_eq!(union.len(), 668);
+        assert!(union.is_valid());
+    }
+
+    #[test]
+    fn test_from_sorted_iter() {
+        let tree = AvlTree::from_sorted_iter(0..1000);
+
+        assert_eq!(tree.len(), 1000);
+        assert_eq!(tree.height(), 10);
+        assert!(tree.is_valid());
+        assert_eq!(tree, (0..1000).collect());
+
+        let tree = AvlTree::from_sorted_iter([3, 7, 7, 16, 21, 21, 21, 28, 36, 70]);
+        assert_eq!(tree, prequel());
+        assert!(tree.is_valid());
+        assert!(AvlTree::<u32>::from_sorted_iter([]).is_empty());
+    }
+}
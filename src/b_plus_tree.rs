@@ -0,0 +1,1289 @@
+use std::{
+    borrow::Borrow,
+    fmt, mem,
+    ops::{Bound, RangeBounds},
+};
+
+/// Marks a missing leaf link, or the end of the free list
+const NIL: u32 = u32::MAX;
+
+/// Most entries a leaf holds, and most children an inner node has, unless
+/// sized with [`BPlusTree::with_node_bytes`]
+const DEFAULT_CAPACITY: usize = 16;
+
+/// Fewest entries or children a node may be sized for, so that splitting and
+/// merging keep nodes at least half full
+const MIN_CAPACITY: usize = 4;
+
+/// An ordered map which keeps its values in linked leaves
+///
+/// Inner nodes only hold copies of keys to guide searches, every entry lives
+/// in a leaf and each leaf links to the next one. Scans, whether over the
+/// whole map or a [`Self::range`], descend once and then walk from leaf to
+/// leaf, reading entries stored next to each other. Leaves link back to the
+/// previous one as well, so a [`Self::range_cursor`] can scan both ways.
+///
+/// Nodes are kept in a single `Vec` and linked by `u32` indices, like
+/// [`crate::BinarySearchTreeArena`], which is what makes the leaf links
+/// possible without shared ownership. Each node keeps its keys in one flat
+/// sorted array, allocated for a full node up front, and
+/// [`Self::with_node_bytes`] sizes nodes to a byte budget such as a 4 KiB page.
+#[derive(Clone)]
+pub struct BPlusTree<K, V>
+where
+    K: Ord,
+{
+    nodes: Vec<Slot<K, V>>,
+    root: u32,
+    /// leftmost leaf, where full scans start
+    first_leaf: u32,
+    /// head of the list of vacant slots, threaded through `Slot::Vacant`
+    free: u32,
+    len: usize,
+    /// most entries a leaf holds
+    leaf_capacity: usize,
+    /// most children an inner node has
+    inner_capacity: usize,
+}
+
+#[derive(Clone)]
+enum Slot<K, V> {
+    Occupied(Node<K, V>),
+    Vacant { next_free: u32 },
+}
+
+/// A leaf when it has no children
+///
+/// In an inner node `keys[i]` separates `children[i]`, whose keys are all less
+/// than it, from `children[i + 1]`, whose keys are all greater or equal.
+#[derive(Clone)]
+struct Node<K, V> {
+    keys: Vec<K>,
+    children: Vec<u32>,
+    /// values of a leaf, in the order of its keys
+    values: Vec<V>,
+    /// previous leaf in key order
+    prev: u32,
+    /// next leaf in key order
+    next: u32,
+}
+
+impl<K, V> Node<K, V> {
+    /// Makes a leaf with room for `capacity` entries and one to overflow
+    fn leaf(mut keys: Vec<K>, mut values: Vec<V>, links: (u32, u32), capacity: usize) -> Self {
+        keys.reserve_exact((capacity + 1).saturating_sub(keys.len()));
+        values.reserve_exact((capacity + 1).saturating_sub(values.len()));
+
+        Self {
+            keys,
+            children: Vec::new(),
+            values,
+            prev: links.0,
+            next: links.1,
+        }
+    }
+
+    /// Makes an inner node with room for `capacity` children and one to
+    /// overflow
+    fn inner(mut keys: Vec<K>, mut children: Vec<u32>, capacity: usize) -> Self {
+        keys.reserve_exact(capacity.saturating_sub(keys.len()));
+        children.reserve_exact((capacity + 1).saturating_sub(children.len()));
+
+        Self {
+            keys,
+            children,
+            values: Vec::new(),
+            prev: NIL,
+            next: NIL,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Entries of a leaf or children of an inner node
+    fn occupancy(&self) -> usize {
+        if self.is_leaf() {
+            self.keys.len()
+        } else {
+            self.children.len()
+        }
+    }
+
+    /// Index of the child whose keys may include `key`
+    fn child_position<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.keys
+            .partition_point(|separator| separator.borrow() <= key)
+    }
+}
+
+/// What an insertion below a node reports back to it
+enum Insertion<K, V> {
+    /// The key was already present and its value replaced
+    Replaced(V),
+    /// The entry was added and the node did not overflow
+    Added,
+    /// The node overflowed and was split, the separator and the new right
+    /// sibling have to be added to the parent
+    Split(K, u32),
+}
+
+impl<K, V> BPlusTree<K, V>
+where
+    K: Ord,
+{
+    pub fn new() -> Self {
+        Self::with_capacities(DEFAULT_CAPACITY, DEFAULT_CAPACITY)
+    }
+
+    /// Creates an empty map whose nodes are sized so that the arrays of a full
+    /// node take at most `bytes`, from the sizes of `K` and `V`
+    ///
+    /// A leaf holds `bytes / (size_of::<K>() + size_of::<V>())` entries and an
+    /// inner node `bytes / (size_of::<K>() + size_of::<u32>())` children,
+    /// the keys and the `u32` links to the children. Both are raised to at
+    /// least 4 when the budget is too tight for that.
+    pub fn with_node_bytes(bytes: usize) -> Self {
+        let capacity = |entry_bytes: usize| (bytes / entry_bytes.max(1)).max(MIN_CAPACITY);
+
+        Self::with_capacities(
+            capacity(mem::size_of::<K>() + mem::size_of::<V>()),
+            capacity(mem::size_of::<K>() + mem::size_of::<u32>()),
+        )
+    }
+
+    fn with_capacities(leaf_capacity: usize, inner_capacity: usize) -> Self {
+        Self {
+            nodes: Vec::new(),
+            root: NIL,
+            first_leaf: NIL,
+            free: NIL,
+            len: 0,
+            leaf_capacity,
+            inner_capacity,
+        }
+    }
+
+    /// Returns the most entries a leaf holds
+    pub fn leaf_capacity(&self) -> usize {
+        self.leaf_capacity
+    }
+
+    /// Returns the most children an inner node has
+    pub fn inner_capacity(&self) -> usize {
+        self.inner_capacity
+    }
+
+    /// Returns the most entries or children `node` may hold
+    fn capacity(&self, node: &Node<K, V>) -> usize {
+        if node.is_leaf() {
+            self.leaf_capacity
+        } else {
+            self.inner_capacity
+        }
+    }
+
+    /// Returns the fewest entries or children `node` may be left with, unless
+    /// it is the root
+    fn min_occupancy(&self, node: &Node<K, V>) -> usize {
+        self.capacity(node) / 2
+    }
+
+    /// Returns the number of entries in the map
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the map contains no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all entries, keeping the allocated node storage
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = NIL;
+        self.first_leaf = NIL;
+        self.free = NIL;
+        self.len = 0;
+    }
+
+    /// Returns the number of nodes on the path from the root to any leaf
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut index = self.root;
+
+        while index != NIL {
+            height += 1;
+            index = self.node(index).children.first().copied().unwrap_or(NIL);
+        }
+        height
+    }
+
+    /// Returns `true` if the map contains a value for `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value stored under `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let leaf = self.node(self.find_leaf(key)?);
+        let index = Self::search(leaf, key).ok()?;
+        Some(&leaf.values[index])
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let leaf = self.node_mut(self.find_leaf(key)?);
+        let index = Self::search(leaf, key).ok()?;
+        Some(&mut leaf.values[index])
+    }
+
+    /// Returns the entry with the smallest key
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        if self.first_leaf == NIL {
+            return None;
+        }
+
+        let leaf = self.node(self.first_leaf);
+        Some((&leaf.keys[0], &leaf.values[0]))
+    }
+
+    /// Returns the entry with the largest key
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let node = self.node(self.last_leaf()?);
+        Some((node.keys.last()?, node.values.last()?))
+    }
+
+    /// Returns the rightmost leaf
+    fn last_leaf(&self) -> Option<u32> {
+        if self.root == NIL {
+            return None;
+        }
+
+        let mut index = self.root;
+        while let Some(&child) = self.node(index).children.last() {
+            index = child;
+        }
+        Some(index)
+    }
+
+    /// Returns an iterator over the entries in key order
+    ///
+    /// After reaching the first leaf, it only follows the leaf links.
+    pub fn iter(&self) -> BPlusTreeIterator<'_, K, V> {
+        self.range::<K, _>(..)
+    }
+
+    /// Returns an iterator over the keys in order
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values in key order
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over the entries whose keys are in `range`, in key
+    /// order
+    ///
+    /// Both ends are found by one descent each, the entries in between are
+    /// read by walking the leaves. An empty or inverted range yields no
+    /// entries.
+    pub fn range<Q, R>(&self, range: R) -> BPlusTreeIterator<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let start = self.position(range.start_bound(), false);
+        let end = self.position(range.end_bound(), true);
+
+        // both positions are on entries, or past the last one
+        let inverted = start.0 != NIL
+            && end.0 != NIL
+            && self.node(start.0).keys[start.1] > self.node(end.0).keys[end.1];
+
+        BPlusTreeIterator {
+            tree: self,
+            leaf: if inverted { NIL } else { start.0 },
+            position: start.1,
+            end,
+        }
+    }
+
+    /// Returns a cursor over the entries whose keys are in `range`, starting
+    /// before the first of them
+    ///
+    /// Unlike [`Self::range`], the cursor can step back as well as forward
+    /// and [`BPlusTreeCursor::seek`] to another key within the range, walking
+    /// the leaf links, so that a scan can be paused and resumed from where it
+    /// was.
+    pub fn range_cursor<Q, R>(&self, range: R) -> BPlusTreeCursor<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let range = self.range(range);
+        let start = (range.leaf, range.position);
+        // an empty range starts and ends on the same position
+        let end = if range.leaf == NIL { start } else { range.end };
+
+        BPlusTreeCursor {
+            tree: self,
+            start,
+            end,
+            position: start,
+        }
+    }
+
+    /// Returns the leaf and index of the first entry after the keys excluded
+    /// by `bound`, as a start bound or as an end bound
+    ///
+    /// Positions past the end of a leaf move to the start of the next one, so
+    /// that equal positions compare equal.
+    fn position<Q>(&self, bound: Bound<&Q>, is_end: bool) -> (u32, usize)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (key, inclusive) = match bound {
+            Bound::Unbounded if is_end => return (NIL, 0),
+            Bound::Unbounded => return (self.first_leaf, 0),
+            Bound::Included(key) => (key, true),
+            Bound::Excluded(key) => (key, false),
+        };
+        let Some(leaf) = self.find_leaf(key) else {
+            return (NIL, 0);
+        };
+
+        // an excluded start and an included end both land after `key`
+        let skips_key = inclusive == is_end;
+        let node = self.node(leaf);
+        let index = node.keys.partition_point(|stored_key| {
+            if skips_key {
+                stored_key.borrow() <= key
+            } else {
+                stored_key.borrow() < key
+            }
+        });
+
+        if index == node.keys.len() {
+            (node.next, 0)
+        } else {
+            (leaf, index)
+        }
+    }
+
+    /// Returns the leaf whose keys may include `key`
+    fn find_leaf<Q>(&self, key: &Q) -> Option<u32>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if self.root == NIL {
+            return None;
+        }
+
+        let mut index = self.root;
+        loop {
+            let node = self.node(index);
+            if node.is_leaf() {
+                return Some(index);
+            }
+            index = node.children[node.child_position(key)];
+        }
+    }
+
+    fn search<Q>(leaf: &Node<K, V>, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        leaf.keys
+            .binary_search_by(|stored_key| stored_key.borrow().cmp(key))
+    }
+
+    /// Returns `true` if the keys are in order and all the B+ tree invariants
+    /// hold: nodes other than the root are at least half full, separators
+    /// bound the keys of their children, every leaf is on the same depth and
+    /// the leaf links visit every leaf in order
+    pub fn is_valid(&self) -> bool {
+        if self.root == NIL {
+            return self.len == 0 && self.first_leaf == NIL;
+        }
+
+        let mut leaves = Vec::new();
+        let shape_ok = self
+            .check(self.root, None, None, true, &mut leaves)
+            .is_some();
+
+        let mut linked = Vec::new();
+        let mut leaf = self.first_leaf;
+        let mut prev = NIL;
+        while leaf != NIL && linked.len() <= leaves.len() {
+            if self.node(leaf).prev != prev {
+                return false;
+            }
+            linked.push(leaf);
+            prev = leaf;
+            leaf = self.node(leaf).next;
+        }
+
+        shape_ok && linked == leaves && self.iter().count() == self.len
+    }
+
+    /// Returns the height of the subtree at `index`, collecting its leaves
+    fn check(
+        &self,
+        index: u32,
+        lower: Option<&K>,
+        upper: Option<&K>,
+        is_root: bool,
+        leaves: &mut Vec<u32>,
+    ) -> Option<usize> {
+        let node = self.node(index);
+        let keys = &node.keys;
+        let min_occupancy = match (is_root, node.is_leaf()) {
+            (false, _) => self.min_occupancy(node),
+            (true, true) => 1,
+            (true, false) => 2,
+        };
+
+        if !(min_occupancy..=self.capacity(node)).contains(&node.occupancy())
+            || keys.windows(2).any(|pair| pair[0] >= pair[1])
+            || lower.is_some_and(|lower| keys[0] < *lower)
+            || upper.is_some_and(|upper| keys[keys.len() - 1] >= *upper)
+        {
+            return None;
+        }
+
+        if node.is_leaf() {
+            leaves.push(index);
+            return (node.values.len() == keys.len()).then_some(1);
+        }
+        if node.children.len() != keys.len() + 1 || !node.values.is_empty() {
+            return None;
+        }
+
+        let mut height = None;
+        for (position, &child) in node.children.iter().enumerate() {
+            let lower = if position == 0 {
+                lower
+            } else {
+                Some(&keys[position - 1])
+            };
+            let upper = keys.get(position).or(upper);
+            let child_height = self.check(child, lower, upper, false, leaves)?;
+
+            if height.is_some_and(|height| height != child_height) {
+                return None;
+            }
+            height = Some(child_height);
+        }
+        height.map(|height| height + 1)
+    }
+
+    fn node(&self, index: u32) -> &Node<K, V> {
+        match &self.nodes[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    fn node_mut(&mut self, index: u32) -> &mut Node<K, V> {
+        match &mut self.nodes[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    /// Stores `node` in a vacant slot, or a new one, and returns its index
+    fn allocate(&mut self, node: Node<K, V>) -> u32 {
+        let node = Slot::Occupied(node);
+
+        if self.free == NIL {
+            let index = self.nodes.len();
+            assert!(index < NIL as usize, "BPlusTree is full");
+
+            self.nodes.push(node);
+            index as u32
+        } else {
+            let index = self.free;
+
+            match mem::replace(&mut self.nodes[index as usize], node) {
+                Slot::Vacant { next_free } => self.free = next_free,
+                Slot::Occupied(_) => unreachable!("free list points to an occupied slot"),
+            }
+            index
+        }
+    }
+
+    /// Moves the node out of the slot at `index` and puts the slot on the free
+    /// list
+    fn deallocate(&mut self, index: u32) -> Node<K, V> {
+        let vacant = Slot::Vacant {
+            next_free: self.free,
+        };
+        self.free = index;
+
+        match mem::replace(&mut self.nodes[index as usize], vacant) {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("deallocating a vacant slot"),
+        }
+    }
+}
+
+/// Updates copy keys into the inner nodes as separators
+impl<K, V> BPlusTree<K, V>
+where
+    K: Ord + Clone,
+{
+    /// Inserts `value` under `key`, returning the value previously stored
+    /// under an equal key
+    ///
+    /// Like `BTreeMap`, the stored key is kept when its value is replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if self.root == NIL {
+            let leaf = Node::leaf(vec![key], vec![value], (NIL, NIL), self.leaf_capacity);
+            self.root = self.allocate(leaf);
+            self.first_leaf = self.root;
+            self.len = 1;
+            return None;
+        }
+
+        match self.insert_into(self.root, key, value) {
+            Insertion::Replaced(old) => return Some(old),
+            Insertion::Added => {}
+            // the tree grows at the root, keeping all leaves on the same depth
+            Insertion::Split(separator, right) => {
+                let root =
+                    Node::inner(vec![separator], vec![self.root, right], self.inner_capacity);
+                self.root = self.allocate(root);
+            }
+        }
+
+        self.len += 1;
+        None
+    }
+
+    fn insert_into(&mut self, index: u32, key: K, value: V) -> Insertion<K, V> {
+        let (leaf_capacity, inner_capacity) = (self.leaf_capacity, self.inner_capacity);
+        let node = self.node_mut(index);
+
+        if node.is_leaf() {
+            match Self::search(node, &key) {
+                Ok(position) => {
+                    return Insertion::Replaced(mem::replace(&mut node.values[position], value))
+                }
+                Err(position) => {
+                    node.keys.insert(position, key);
+                    node.values.insert(position, value);
+                }
+            }
+
+            if node.keys.len() <= leaf_capacity {
+                return Insertion::Added;
+            }
+
+            // the upper half moves to a new leaf linked right after this one
+            let middle = node.keys.len() / 2;
+            let right = Node::leaf(
+                node.keys.split_off(middle),
+                node.values.split_off(middle),
+                (index, node.next),
+                leaf_capacity,
+            );
+            let separator = right.keys[0].clone();
+            let after = right.next;
+
+            let right = self.allocate(right);
+            self.node_mut(index).next = right;
+            if after != NIL {
+                self.node_mut(after).prev = right;
+            }
+            return Insertion::Split(separator, right);
+        }
+
+        let position = node.child_position(&key);
+        let child = node.children[position];
+
+        let (separator, right) = match self.insert_into(child, key, value) {
+            Insertion::Split(separator, right) => (separator, right),
+            unchanged => return unchanged,
+        };
+        let node = self.node_mut(index);
+        node.keys.insert(position, separator);
+        node.children.insert(position + 1, right);
+
+        if node.children.len() <= inner_capacity {
+            return Insertion::Added;
+        }
+
+        // the separator between both halves moves up instead of being copied
+        let middle = node.children.len() / 2;
+        let children = node.children.split_off(middle);
+        let keys = node.keys.split_off(middle);
+        let separator = node.keys.pop().expect("an overflowing node has keys");
+
+        let right = self.allocate(Node::inner(keys, children, inner_capacity));
+        Insertion::Split(separator, right)
+    }
+
+    /// Removes `key` from the map, returning the value stored under it
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes `key` from the map, returning the stored key and value
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if self.root == NIL {
+            return None;
+        }
+
+        let removed = self.remove_from(self.root, key)?;
+        self.len -= 1;
+
+        // the tree shrinks at the root once it is left with a single child
+        let root = self.node(self.root);
+        if self.len == 0 {
+            self.clear();
+        } else if root.children.len() == 1 {
+            let child = root.children[0];
+            self.deallocate(self.root);
+            self.root = child;
+        }
+
+        Some(removed)
+    }
+
+    fn remove_from<Q>(&mut self, index: u32, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node = self.node_mut(index);
+
+        if node.is_leaf() {
+            // separators equal to the removed key may stay, they still bound
+            // the keys on both sides
+            let position = Self::search(node, key).ok()?;
+            return Some((node.keys.remove(position), node.values.remove(position)));
+        }
+
+        let position = node.child_position(key);
+        let child = node.children[position];
+        let removed = self.remove_from(child, key)?;
+
+        let child_node = self.node(child);
+        if child_node.occupancy() < self.min_occupancy(child_node) {
+            self.fix_underflow(index, position);
+        }
+        Some(removed)
+    }
+
+    /// Refills the child at `position` of `parent`, by moving an entry or a
+    /// child over from a sibling which can spare one, or else by merging the
+    /// child with a sibling
+    fn fix_underflow(&mut self, parent: u32, position: usize) {
+        let children = &self.node(parent).children;
+        let can_spare = |sibling: u32| {
+            let sibling = self.node(sibling);
+            sibling.occupancy() > self.min_occupancy(sibling)
+        };
+
+        if position > 0 && can_spare(children[position - 1]) {
+            self.move_from_left(parent, position);
+        } else if position + 1 < children.len() && can_spare(children[position + 1]) {
+            self.move_from_right(parent, position);
+        } else {
+            self.merge_children(parent, position.saturating_sub(1));
+        }
+    }
+
+    /// Moves the last entry or child of the left sibling to the front of the
+    /// child at `position`
+    fn move_from_left(&mut self, parent: u32, position: usize) {
+        let left = self.node(parent).children[position - 1];
+        let child = self.node(parent).children[position];
+
+        let left_node = self.node_mut(left);
+        let key = left_node
+            .keys
+            .pop()
+            .expect("a sibling with entries to spare");
+
+        if left_node.is_leaf() {
+            let value = left_node.values.pop().expect("a value for every key");
+            self.node_mut(parent).keys[position - 1] = key.clone();
+
+            let child = self.node_mut(child);
+            child.keys.insert(0, key);
+            child.values.insert(0, value);
+        } else {
+            // the old separator comes down and the moved key takes its place
+            let grandchild = left_node.children.pop().expect("one child more than keys");
+            let old_separator = mem::replace(&mut self.node_mut(parent).keys[position - 1], key);
+
+            let child = self.node_mut(child);
+            child.keys.insert(0, old_separator);
+            child.children.insert(0, grandchild);
+        }
+    }
+
+    /// Moves the first entry or child of the right sibling to the end of the
+    /// child at `position`
+    fn move_from_right(&mut self, parent: u32, position: usize) {
+        let child = self.node(parent).children[position];
+        let right = self.node(parent).children[position + 1];
+
+        let right_node = self.node_mut(right);
+        let key = right_node.keys.remove(0);
+
+        if right_node.is_leaf() {
+            let value = right_node.values.remove(0);
+            let separator = right_node.keys[0].clone();
+
+            let child = self.node_mut(child);
+            child.keys.push(key);
+            child.values.push(value);
+            self.node_mut(parent).keys[position] = separator;
+        } else {
+            let grandchild = right_node.children.remove(0);
+            let old_separator = mem::replace(&mut self.node_mut(parent).keys[position], key);
+
+            let child = self.node_mut(child);
+            child.keys.push(old_separator);
+            child.children.push(grandchild);
+        }
+    }
+
+    /// Merges the children at `position` and `position + 1` into the first
+    fn merge_children(&mut self, parent: u32, position: usize) {
+        let parent_node = self.node_mut(parent);
+        let separator = parent_node.keys.remove(position);
+        let left = parent_node.children[position];
+        let right = parent_node.children.remove(position + 1);
+
+        let right = self.deallocate(right);
+        if right.is_leaf() && right.next != NIL {
+            self.node_mut(right.next).prev = left;
+        }
+        let left = self.node_mut(left);
+
+        if left.is_leaf() {
+            left.next = right.next;
+        } else {
+            left.keys.push(separator);
+        }
+        left.keys.extend(right.keys);
+        left.values.extend(right.values);
+        left.children.extend(right.children);
+    }
+}
+
+impl<K, V> Default for BPlusTree<K, V>
+where
+    K: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for BPlusTree<K, V>
+where
+    K: Ord + fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Two maps are equal when they hold equal entries, regardless of their shape
+impl<K, V> PartialEq for BPlusTree<K, V>
+where
+    K: Ord,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<K, V> Eq for BPlusTree<K, V>
+where
+    K: Ord,
+    V: Eq,
+{
+}
+
+impl<K, V> FromIterator<(K, V)> for BPlusTree<K, V>
+where
+    K: Ord + Clone,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V> Extend<(K, V)> for BPlusTree<K, V>
+where
+    K: Ord + Clone,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over entries of a [`BPlusTree`] in key order, walking the leaf
+/// links
+pub struct BPlusTreeIterator<'a, K, V>
+where
+    K: Ord,
+{
+    tree: &'a BPlusTree<K, V>,
+    /// leaf of the next entry, `NIL` once exhausted
+    leaf: u32,
+    position: usize,
+    /// leaf and index of the first entry not to yield
+    end: (u32, usize),
+}
+
+impl<'a, K, V> Iterator for BPlusTreeIterator<'a, K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.leaf == NIL || (self.leaf, self.position) == self.end {
+            return None;
+        }
+
+        let node = self.tree.node(self.leaf);
+        let entry = (&node.keys[self.position], &node.values[self.position]);
+
+        self.position += 1;
+        if self.position == node.keys.len() {
+            self.leaf = node.next;
+            self.position = 0;
+        }
+        Some(entry)
+    }
+}
+
+/// A cursor over entries of a [`BPlusTree`] within a range, made by
+/// [`BPlusTree::range_cursor`]
+///
+/// The cursor rests between two entries. Iterating moves it forward and
+/// [`Self::prev`] moves it back, walking the leaf links both ways.
+pub struct BPlusTreeCursor<'a, K, V>
+where
+    K: Ord,
+{
+    tree: &'a BPlusTree<K, V>,
+    /// leaf and index of the first entry in the range
+    start: (u32, usize),
+    /// leaf and index of the first entry after the range
+    end: (u32, usize),
+    /// leaf and index of the entry after the cursor
+    position: (u32, usize),
+}
+
+impl<'a, K, V> BPlusTreeCursor<'a, K, V>
+where
+    K: Ord,
+{
+    /// Returns the entry after the cursor without moving it
+    pub fn peek_next(&self) -> Option<(&'a K, &'a V)> {
+        (self.position != self.end).then(|| self.entry(self.position))
+    }
+
+    /// Returns the entry before the cursor without moving it
+    pub fn peek_prev(&self) -> Option<(&'a K, &'a V)> {
+        (self.position != self.start).then(|| self.entry(self.before(self.position)))
+    }
+
+    /// Moves the cursor back over one entry and returns it
+    pub fn prev(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.position == self.start {
+            return None;
+        }
+
+        self.position = self.before(self.position);
+        Some(self.entry(self.position))
+    }
+
+    /// Moves the cursor right before the first entry in the range whose key
+    /// is not less than `key`, or to either end of the range
+    ///
+    /// A key within the current leaf is found there, without descending from
+    /// the root again.
+    pub fn seek<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let tree = self.tree;
+        let (leaf, _) = self.position;
+        let in_leaf = leaf != NIL && {
+            let keys = &tree.node(leaf).keys;
+            keys[0].borrow() <= key && key <= keys[keys.len() - 1].borrow()
+        };
+
+        let position = if in_leaf {
+            let index = tree
+                .node(leaf)
+                .keys
+                .partition_point(|stored_key| stored_key.borrow() < key);
+            (leaf, index)
+        } else {
+            tree.position(Bound::Included(key), false)
+        };
+
+        // a key past every entry is past the range as well
+        let Some(key) = self.key(position) else {
+            self.position = self.end;
+            return;
+        };
+        self.position = if self.key(self.end).is_some_and(|end| key >= end) {
+            self.end
+        } else if self.key(self.start).is_none_or(|start| key < start) {
+            self.start
+        } else {
+            position
+        };
+    }
+
+    /// Returns the key at `position`, `None` past the last entry
+    fn key(&self, position: (u32, usize)) -> Option<&'a K> {
+        (position.0 != NIL).then(|| &self.tree.node(position.0).keys[position.1])
+    }
+
+    fn entry(&self, (leaf, index): (u32, usize)) -> (&'a K, &'a V) {
+        let node = self.tree.node(leaf);
+        (&node.keys[index], &node.values[index])
+    }
+
+    /// Returns the position of the entry before `position`
+    fn before(&self, (leaf, index): (u32, usize)) -> (u32, usize) {
+        if index > 0 {
+            return (leaf, index - 1);
+        }
+
+        let leaf = if leaf == NIL {
+            self.tree.last_leaf().expect("a position before the end")
+        } else {
+            self.tree.node(leaf).prev
+        };
+        (leaf, self.tree.node(leaf).keys.len() - 1)
+    }
+}
+
+impl<'a, K, V> Iterator for BPlusTreeCursor<'a, K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+
+    /// Moves the cursor forward over one entry and returns it
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.peek_next()?;
+
+        let (leaf, index) = self.position;
+        let node = self.tree.node(leaf);
+        self.position = if index + 1 == node.keys.len() {
+            (node.next, 0)
+        } else {
+            (leaf, index + 1)
+        };
+        Some(entry)
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BPlusTree<K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = BPlusTreeIterator<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{prequel_entries, shuffled};
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut tree: BPlusTree<u32, u32> = prequel_entries();
+
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.get(&21), Some(&210));
+        assert_eq!(tree.insert(21, 0), Some(210));
+        *tree.get_mut(&21).unwrap() += 5;
+        assert_eq!(tree.get(&21), Some(&5));
+        assert_eq!(tree.first_key_value(), Some((&3, &30)));
+        assert_eq!(tree.last_key_value(), Some((&70, &700)));
+
+        assert_eq!(tree.remove(&16), Some(160));
+        assert_eq!(tree.remove(&16), None);
+        assert_eq!(
+            tree.keys().copied().collect::<Vec<_>>(),
+            vec![3, 7, 21, 28, 36, 70]
+        );
+        assert!(tree.is_valid());
+
+        for key in [3, 7, 21, 28, 36, 70] {
+            assert!(tree.remove(&key).is_some());
+        }
+        assert!(tree.is_empty());
+        assert!(tree.is_valid());
+        assert_eq!(tree.first_key_value(), None);
+    }
+
+    #[test]
+    fn test_overwrite_in_multi_level_tree() {
+        let mut tree: BPlusTree<u32, u32> = (0..1000).map(|key| (key, key)).collect();
+        assert!(tree.height() >= 3);
+
+        for key in 0..1000 {
+            assert_eq!(tree.insert(key, key + 1), Some(key));
+        }
+        assert_eq!(tree.len(), 1000);
+        assert!(tree.is_valid());
+        assert!(tree.iter().all(|(key, value)| *value == key + 1));
+
+        tree.extend((0..1000).map(|key| (key, 0)));
+        assert_eq!(tree.len(), 1000);
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_range() {
+        let tree: BPlusTree<u32, ()> = (0..1000).map(|key| (key * 2, ())).collect();
+        let keys =
+            |range: BPlusTreeIterator<'_, u32, ()>| range.map(|(key, _)| *key).collect::<Vec<_>>();
+
+        assert_eq!(keys(tree.range(10..16)), vec![10, 12, 14]);
+        assert_eq!(keys(tree.range(11..=16)), vec![12, 14, 16]);
+        assert_eq!(keys(tree.range(1990..)), vec![1990, 1992, 1994, 1996, 1998]);
+        assert_eq!(keys(tree.range(..3)), vec![0, 2]);
+        assert_eq!(tree.range(100..900).count(), 400);
+        assert_eq!(tree.range(17..17).count(), 0);
+        assert_eq!(tree.range(18..18).count(), 0);
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = tree.range(20..10);
+        assert_eq!(inverted.count(), 0);
+        assert_eq!(tree.range(5000..).count(), 0);
+        assert_eq!(tree.iter().count(), 1000);
+    }
+
+    /// Returns the keys of every leaf, following the links from the first
+    /// leaf, after checking that the links back lead through the same leaves
+    fn leaves(tree: &BPlusTree<u32, u32>) -> Vec<Vec<u32>> {
+        let mut leaves = Vec::new();
+        let (mut index, mut last) = (tree.first_leaf, NIL);
+
+        while index != NIL {
+            let leaf = tree.node(index);
+            assert_eq!(leaf.prev, last);
+            leaves.push(leaf.keys.clone());
+            (last, index) = (index, leaf.next);
+        }
+        leaves
+    }
+
+    #[test]
+    fn test_remove_at_leaf_boundaries() {
+        // leaves of four entries, split into two and three
+        let mut tree: BPlusTree<u32, u32> = BPlusTree::with_node_bytes(0);
+        tree.extend((0..5).map(|key| (key, key)));
+        assert_eq!(leaves(&tree), vec![vec![0, 1], vec![2, 3, 4]]);
+
+        // a leaf left with a single entry takes one from the right sibling,
+        // whose new first key becomes the separator
+        assert_eq!(tree.remove(&0), Some(0));
+        assert_eq!(leaves(&tree), vec![vec![1, 2], vec![3, 4]]);
+        assert_eq!(tree.node(tree.root).keys, vec![3]);
+
+        // or from the left one when it can spare an entry
+        tree.insert(0, 0);
+        assert_eq!(tree.remove(&4), Some(4));
+        assert_eq!(leaves(&tree), vec![vec![0, 1], vec![2, 3]]);
+        assert_eq!(tree.node(tree.root).keys, vec![2]);
+
+        // and merges with it otherwise, the root going away with the last
+        // separator
+        assert_eq!(tree.remove(&0), Some(0));
+        assert_eq!(leaves(&tree), vec![vec![1, 2, 3]]);
+        assert_eq!(tree.height(), 1);
+        assert!(tree.is_valid());
+
+        // merges relink the leaves on both sides at every level
+        let mut tree: BPlusTree<u32, u32> = BPlusTree::with_node_bytes(0);
+        tree.extend((0..200).map(|key| (key, key)));
+        let height = tree.height();
+        assert!(height >= 4);
+        for key in (0..200).filter(|key| key % 4 != 1) {
+            assert_eq!(tree.remove(&key), Some(key));
+            assert!(tree.is_valid());
+        }
+        let keys: Vec<u32> = leaves(&tree).concat();
+        assert!(keys.into_iter().eq((0..200).filter(|key| key % 4 == 1)));
+        assert!(tree.height() < height);
+    }
+
+    #[test]
+    fn test_with_node_bytes() {
+        let tree: BPlusTree<u64, u64> = BPlusTree::with_node_bytes(4096);
+        assert_eq!(tree.leaf_capacity(), 256);
+        assert_eq!(tree.inner_capacity(), 341);
+
+        let tree: BPlusTree<u64, [u8; 1024]> = BPlusTree::with_node_bytes(4096);
+        assert_eq!(tree.leaf_capacity(), 4);
+
+        let tree: BPlusTree<(), ()> = BPlusTree::with_node_bytes(64);
+        assert_eq!(tree.leaf_capacity(), 64);
+        assert_eq!(tree.inner_capacity(), 16);
+
+        let tree: BPlusTree<u8, u8> = BPlusTree::new();
+        assert_eq!(tree.leaf_capacity(), 16);
+        assert_eq!(tree.inner_capacity(), 16);
+    }
+
+    #[test]
+    fn test_node_bytes_keep_invariants() {
+        for bytes in [0, 100, 4096] {
+            let mut tree = BPlusTree::with_node_bytes(bytes);
+            for key in shuffled(1009) {
+                tree.insert(key, key * 10);
+            }
+            assert!(tree.is_valid());
+            assert_eq!(tree.len(), 1009);
+            assert!(tree.iter().map(|(key, _)| *key).eq(0..1009));
+
+            for key in shuffled(1009).into_iter().filter(|key| key % 3 != 0) {
+                assert_eq!(tree.remove(&key), Some(key * 10));
+            }
+            assert!(tree.is_valid());
+            assert!(tree.iter().map(|(key, _)| *key).eq((0..1009).step_by(3)));
+        }
+
+        let small: BPlusTree<u64, u64> = (0..1000).map(|key| (key, key)).collect();
+        let mut paged = BPlusTree::with_node_bytes(4096);
+        paged.extend((0..1000u64).map(|key| (key, key)));
+        assert!(paged.height() < small.height());
+    }
+
+    #[test]
+    fn test_range_cursor() {
+        let tree: BPlusTree<u32, u32> = prequel_entries();
+        let mut cursor = tree.range_cursor(7..=36);
+
+        assert_eq!(cursor.peek_prev(), None);
+        assert_eq!(cursor.next(), Some((&7, &70)));
+        assert_eq!(cursor.next(), Some((&16, &160)));
+        assert_eq!(cursor.prev(), Some((&16, &160)));
+        assert_eq!(cursor.prev(), Some((&7, &70)));
+        assert_eq!(cursor.prev(), None);
+
+        cursor.seek(&22);
+        assert_eq!(cursor.peek_next(), Some((&28, &280)));
+        assert_eq!(cursor.peek_prev(), Some((&21, &210)));
+        assert_eq!(
+            cursor.by_ref().map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec![28, 36]
+        );
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.prev(), Some((&36, &360)));
+
+        // seeking outside the range stops at its ends
+        cursor.seek(&0);
+        assert_eq!(cursor.peek_next(), Some((&7, &70)));
+        cursor.seek(&70);
+        assert_eq!(cursor.peek_next(), None);
+        assert_eq!(cursor.peek_prev(), Some((&36, &360)));
+
+        let mut cursor = tree.range_cursor(40..);
+        assert_eq!(cursor.prev(), None);
+        assert_eq!(cursor.next(), Some((&70, &700)));
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.prev(), Some((&70, &700)));
+
+        #[allow(clippy::reversed_empty_ranges)]
+        let mut cursor = tree.range_cursor(30..20);
+        cursor.seek(&21);
+        assert_eq!((cursor.peek_prev(), cursor.peek_next()), (None, None));
+    }
+
+    #[test]
+    fn test_range_cursor_across_leaves() {
+        let mut tree: BPlusTree<u64, u64> =
+            shuffled(1009).into_iter().map(|key| (key, key)).collect();
+        for key in (0..1009).step_by(3) {
+            tree.remove(&key);
+        }
+        assert!(tree.is_valid());
+
+        let expected: Vec<u64> = (0..1009).filter(|key| key % 3 != 0).collect();
+        let mut cursor = tree.range_cursor::<u64, _>(..);
+        assert!(cursor
+            .by_ref()
+            .map(|(key, _)| *key)
+            .eq(expected.iter().copied()));
+
+        let mut backward = Vec::new();
+        while let Some((key, _)) = cursor.prev() {
+            backward.push(*key);
+        }
+        assert!(backward.into_iter().eq(expected.iter().rev().copied()));
+
+        let mut cursor = tree.range_cursor(100..900);
+        for key in [500, 10, 899, 300, 1000, 301] {
+            cursor.seek(&key);
+            let next = expected
+                .iter()
+                .find(|&&stored| stored >= key.clamp(100, 900));
+            let next = next.filter(|&&stored| stored < 900);
+            assert_eq!(cursor.peek_next().map(|(key, _)| key), next);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::binary_search_tree_map::BinarySearchTreeMap;
+use std::{borrow::Borrow, fmt, iter};
+
+/// A multiset which stores each distinct element once along with its count
+///
+/// Unlike [`crate::BinarySearchTree`] under [`crate::DuplicatePolicy::Allow`],
+/// equal elements never occupy separate nodes, so the tree only grows with
+/// the number of distinct elements.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BinarySearchMultiSet<T>
+where
+    T: Ord,
+{
+    counts: BinarySearchTreeMap<T, usize>,
+    len: usize,
+}
+
+impl<T> BinarySearchMultiSet<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self {
+            counts: BinarySearchTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of elements, counting multiplicity
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of distinct elements
+    pub fn distinct_len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns `true` if the multiset contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.len = 0;
+    }
+
+    /// Adds one occurrence of `value`, returning its new count
+    ///
+    /// When `value` is already present the stored element is kept and `value`
+    /// is dropped.
+    pub fn insert(&mut self, value: T) -> usize {
+        self.insert_many(value, 1)
+    }
+
+    /// Adds `n` occurrences of `value`, returning its new count
+    ///
+    /// Adding zero occurrences of an absent element leaves the multiset
+    /// untouched.
+    pub fn insert_many(&mut self, value: T, n: usize) -> usize {
+        if n == 0 {
+            return self.count(&value);
+        }
+
+        self.len += n;
+
+        let count = self.counts.entry(value).or_insert(0);
+        *count += n;
+        *count
+    }
+
+    /// Returns the number of occurrences of `value`
+    pub fn count<Q>(&self, value: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.counts.get(value).copied().unwrap_or(0)
+    }
+
+    /// Returns `true` if at least one occurrence of `value` is present
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.counts.contains_key(value)
+    }
+
+    /// Removes one occurrence of `value`, returning whether it was present
+    pub fn remove_one<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(count) = self.counts.get_mut(value) else {
+            return false;
+        };
+
+        *count -= 1;
+        if *count == 0 {
+            self.counts.remove(value);
+        }
+
+        self.len -= 1;
+        true
+    }
+
+    /// Removes every occurrence of `value`, returning how many were removed
+    pub fn remove_all<Q>(&mut self, value: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let count = self.counts.remove(value).unwrap_or(0);
+        self.len -= count;
+        count
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        self.counts.first_key_value().map(|(value, _)| value)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        self.counts.last_key_value().map(|(value, _)| value)
+    }
+
+    /// Returns the number of heap bytes used by the nodes of the multiset
+    ///
+    /// Grows with the number of distinct elements, not with their counts.
+    pub fn memory_usage(&self) -> usize {
+        self.counts.memory_usage()
+    }
+
+    /// Returns a new iterator over the elements in order, repeating each one
+    /// by its multiplicity
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.counts
+            .iter()
+            .flat_map(|(value, &count)| iter::repeat_n(value, count))
+    }
+
+    /// Returns a new iterator over the distinct elements in order, paired with
+    /// their counts
+    pub fn iter_counts(&self) -> impl DoubleEndedIterator<Item = (&T, usize)> + ExactSizeIterator {
+        self.counts.iter().map(|(value, &count)| (value, count))
+    }
+}
+
+impl<T> Default for BinarySearchMultiSet<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for BinarySearchMultiSet<T>
+where
+    T: Ord + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter_counts()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for BinarySearchMultiSet<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut multiset = Self::new();
+        multiset.extend(iter);
+        multiset
+    }
+}
+
+impl<T> From<Vec<T>> for BinarySearchMultiSet<T>
+where
+    T: Ord,
+{
+    fn from(elements: Vec<T>) -> Self {
+        elements.into_iter().collect()
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for BinarySearchMultiSet<T>
+where
+    T: Ord,
+{
+    fn from(elements: [T; N]) -> Self {
+        elements.into_iter().collect()
+    }
+}
+
+/// Repeats every element by its multiplicity, in order
+impl<T> From<BinarySearchMultiSet<T>> for Vec<T>
+where
+    T: Ord + Clone,
+{
+    fn from(multiset: BinarySearchMultiSet<T>) -> Self {
+        multiset.iter().cloned().collect()
+    }
+}
+
+impl<T> Extend<T> for BinarySearchMultiSet<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn prequel_multiset() -> BinarySearchMultiSet<u32> {
+        vec![16, 7, 28, 7, 3, 16, 7].into_iter().collect()
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut multiset = prequel_multiset();
+
+        assert_eq!(multiset.len(), 7);
+        assert_eq!(multiset.distinct_len(), 4);
+        assert_eq!(multiset.count(&7), 3);
+        assert_eq!(multiset.count(&8), 0);
+        assert_eq!(multiset.insert(3), 2);
+        assert_eq!(multiset.insert_many(21, 2), 2);
+        assert_eq!(multiset.insert_many(22, 0), 0);
+        assert!(!multiset.contains(&22));
+
+        assert!(multiset.remove_one(&28));
+        assert!(!multiset.remove_one(&28));
+        assert!(!multiset.contains(&28));
+        assert_eq!(multiset.remove_all(&7), 3);
+        assert_eq!(multiset.remove_all(&7), 0);
+
+        assert_eq!(multiset.len(), 6);
+        assert_eq!(multiset.min(), Some(&3));
+        assert_eq!(multiset.max(), Some(&21));
+        assert_eq!(
+            multiset.memory_usage(),
+            BinarySearchTreeMap::<u32, usize>::from_iter([(3, 2), (16, 2), (21, 2)]).memory_usage()
+        );
+    }
+
+    #[test]
+    fn test_iter_repeats_by_multiplicity() {
+        let multiset = prequel_multiset();
+
+        assert_eq!(
+            multiset.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &7, &7, &16, &16, &28]
+        );
+        assert_eq!(multiset.iter().next_back(), Some(&28));
+        assert_eq!(
+            multiset.iter_counts().collect::<Vec<_>>(),
+            vec![(&3, 1), (&7, 3), (&16, 2), (&28, 1)]
+        );
+        assert_eq!(format!("{:?}", multiset), "{3: 1, 7: 3, 16: 2, 28: 1}");
+        assert_eq!(Vec::from(multiset), vec![3, 7, 7, 7, 16, 16, 28]);
+        assert_eq!(BinarySearchMultiSet::from([1, 1]).count(&1), 2);
+    }
+}
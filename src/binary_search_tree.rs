@@ -1,8 +1,99 @@
+use crate::codec::{Codec, DecodeError};
 use std::{
-    cmp::Ordering::{Equal, Greater, Less},
-    ops::Deref,
+    borrow::Borrow,
+    cmp::Ordering::{self, Equal, Greater, Less},
+    collections::{BTreeSet, VecDeque},
+    fmt,
+    iter::Peekable,
+    mem,
+    ops::{
+        Bound::{Excluded, Included, Unbounded},
+        Deref, Index, RangeBounds, Sub,
+    },
 };
 
+/// How a [`BinarySearchTree`] handles inserting an element equal to one it
+/// already holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Equal elements are all stored, `insert` always succeeds
+    ///
+    /// Equal elements are yielded next to each other by the in-order iterators,
+    /// in the order they were inserted. `search` and `remove` act on whichever
+    /// equal element is reached first from the root, and [`BinarySearchTree::count`]
+    /// reports how many are stored.
+    #[default]
+    Allow,
+    /// The tree behaves like a set, `insert` of an element which is already
+    /// present leaves the tree unchanged and returns `false`
+    Reject,
+}
+
+/// Which element [`BinarySearchTree::nearest_by`] returns when the floor and
+/// the ceiling of the key are equally close
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NearestTie {
+    /// pick the floor
+    Smaller,
+    /// pick the ceiling
+    Larger,
+}
+
+/// Why [`BinarySearchTree::try_insert`] or a similar method refused a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertErrorKind {
+    /// An equal element is stored and the tree rejects duplicates
+    Duplicate,
+    /// Memory for the new node could not be allocated
+    ///
+    /// Only [`BinarySearchTreeArena::try_insert`] reports this, when its node
+    /// buffer fails to grow or already holds `u32::MAX` nodes.
+    /// [`BinarySearchTree`] boxes every node and, like `Box::new`, aborts when
+    /// the allocation fails.
+    ///
+    /// [`BinarySearchTreeArena::try_insert`]: crate::BinarySearchTreeArena::try_insert
+    AllocationFailed,
+}
+
+/// The error of a failed insertion, which hands the value back to the caller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsertError<T> {
+    kind: InsertErrorKind,
+    value: T,
+}
+
+impl<T> InsertError<T> {
+    pub(crate) fn new(kind: InsertErrorKind, value: T) -> Self {
+        Self { kind, value }
+    }
+
+    /// Returns why the value was refused
+    pub fn kind(&self) -> InsertErrorKind {
+        self.kind
+    }
+
+    /// Returns a reference to the refused value
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Takes back ownership of the refused value
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+impl<T> fmt::Display for InsertError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            InsertErrorKind::Duplicate => f.write_str("an equal element is already stored"),
+            InsertErrorKind::AllocationFailed => f.write_str("failed to allocate a node"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for InsertError<T> {}
+
 pub struct BinarySearchTree<T>
 where
     T: Ord,
@@ -10,6 +101,10 @@ where
     data: Option<T>,
     left: Option<Box<BinarySearchTree<T>>>,
     right: Option<Box<BinarySearchTree<T>>>,
+    /// number of elements stored in this subtree
+    size: usize,
+    /// shared by every node of the tree, so that subtrees keep the same behavior
+    duplicate_policy: DuplicatePolicy,
 }
 
 impl<T> BinarySearchTree<T>
@@ -17,183 +112,4098 @@ where
     T: Ord,
 {
     pub fn new() -> Self {
+        Self::with_duplicate_policy(DuplicatePolicy::default())
+    }
+
+    /// Creates an empty tree which handles duplicates according to `duplicate_policy`
+    pub fn with_duplicate_policy(duplicate_policy: DuplicatePolicy) -> Self {
         Self {
             data: None,
             left: None,
             right: None,
+            size: 0,
+            duplicate_policy,
         }
     }
 
+    /// Returns how the tree handles duplicate elements
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
     pub fn search(&self, data: &T) -> bool {
-        match &self.data {
-            Some(stored_data) => match data.cmp(stored_data) {
-                Equal => true,
-                Less => {
-                    // data < stored_data
-                    // search in the left
-                    match &self.left {
-                        Some(node) => node.search(data),
-                        None => false,
-                    }
-                }
-                Greater => {
-                    // data > stored_data
-                    // search in the right
-                    match &self.right {
-                        Some(node) => node.search(data),
-                        None => false,
-                    }
-                }
-            },
-            None => false,
+        self.contains(data)
+    }
+
+    /// Builds a height-balanced tree in `O(n)` from elements yielded in
+    /// ascending order
+    ///
+    /// The input must be sorted, otherwise the resulting tree violates the
+    /// ordering invariant (checked with a debug assertion).
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        Self::from_sorted_vec(iter.into_iter().collect(), DuplicatePolicy::default())
+    }
+
+    /// Builds a height-balanced tree in `O(n)` from a sorted slice, cloning
+    /// its elements
+    ///
+    /// See [`Self::from_sorted_iter`] for the sortedness requirement.
+    pub fn from_sorted_slice(sorted: &[T]) -> Self
+    where
+        T: Clone,
+    {
+        Self::from_sorted_vec(sorted.to_vec(), DuplicatePolicy::default())
+    }
+
+    /// Builds a height-balanced tree from elements which are already sorted
+    fn from_sorted_vec(sorted: Vec<T>, duplicate_policy: DuplicatePolicy) -> Self {
+        debug_assert!(sorted.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let len = sorted.len();
+
+        match Self::build_balanced(&mut sorted.into_iter(), len, duplicate_policy) {
+            Some(node) => *node,
+            None => Self::with_duplicate_policy(duplicate_policy),
         }
     }
 
-    pub fn insert(&mut self, data: T) {
-        match &self.data {
-            None => self.data = Some(data),
-            Some(stored_data) => {
-                let target_node = if data < *stored_data {
-                    &mut self.left
-                } else {
-                    &mut self.right
-                };
+    /// Builds a balanced subtree out of the next `len` elements of `iter`,
+    /// consuming them in order: left subtree, middle element, right subtree
+    fn build_balanced<I>(
+        iter: &mut I,
+        len: usize,
+        duplicate_policy: DuplicatePolicy,
+    ) -> Option<Box<Self>>
+    where
+        I: Iterator<Item = T>,
+    {
+        if len == 0 {
+            return None;
+        }
 
-                match target_node {
-                    Some(ref mut node) => {
-                        node.insert(data);
-                    }
-                    None => {
-                        let mut node = Self::new();
+        let left_len = len / 2;
+        let left = Self::build_balanced(iter, left_len, duplicate_policy);
+        let data = iter.next();
+        let right = Self::build_balanced(iter, len - left_len - 1, duplicate_policy);
 
-                        node.insert(data);
+        Some(Box::new(Self {
+            data,
+            left,
+            right,
+            size: len,
+            duplicate_policy,
+        }))
+    }
+
+    /// Moves all elements of `other` into `self`, leaving `other` empty
+    ///
+    /// Both trees are drained in order, merged in `O(n + m)` and rebuilt into a
+    /// height-balanced tree. Elements of `self` come before equal elements of
+    /// `other`, under [`DuplicatePolicy::Reject`] the latter are dropped.
+    pub fn append(&mut self, other: &mut Self) {
+        if other.is_empty() {
+            return;
+        }
+
+        let mut merged = Vec::with_capacity(self.len() + other.len());
+        let mut left = self.drain().peekable();
+        let mut right = other.drain().peekable();
 
-                        *target_node = Some(Box::new(node));
+        loop {
+            let next = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) => match l.cmp(r) {
+                    Greater => right.next(),
+                    Less => left.next(),
+                    Equal => {
+                        if self.duplicate_policy == DuplicatePolicy::Reject {
+                            right.next();
+                        }
+                        left.next()
                     }
-                }
+                },
+                (Some(_), None) => left.next(),
+                (None, Some(_)) => right.next(),
+                (None, None) => break,
+            };
+            merged.extend(next);
+        }
+
+        *self = Self::from_sorted_vec(merged, self.duplicate_policy);
+    }
+
+    /// Keeps only the elements for which `f` returns `true`
+    ///
+    /// `f` is called once per element, in ascending order. The retained
+    /// elements are rebuilt into a height-balanced tree in `O(n)`.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let retained: Vec<T> = self.drain().filter(|data| f(data)).collect();
+
+        *self = Self::from_sorted_vec(retained, self.duplicate_policy);
+    }
+
+    /// Splits the tree in two at `key`, returning every element greater than or
+    /// equal to `key` and keeping the rest
+    ///
+    /// Nodes are relinked along the search path for `key`, so this runs in
+    /// `O(height)` time and constant stack space without moving any element.
+    /// Both trees keep the duplicate policy of `self`.
+    pub fn split_off(&mut self, key: &T) -> Self {
+        let duplicate_policy = self.duplicate_policy;
+        let empty = || Self::with_duplicate_policy(duplicate_policy);
+
+        if self.is_empty() {
+            return empty();
+        }
+
+        let self_len = self.len();
+        let mut link = Some(Box::new(mem::replace(self, empty())));
+        let (mut less, mut greater_or_equal) = (None, None);
+        let (mut less_tail, mut greater_or_equal_tail) = (&mut less, &mut greater_or_equal);
+        let mut less_len = 0;
+
+        // hang every node on the search path below the tail of its side
+        while let Some(mut node) = link {
+            if node.data.as_ref().is_some_and(|data| data < key) {
+                // node and its left subtree are less than key
+                less_len += 1 + Self::subtree_size(&node.left);
+                link = node.right.take();
+                less_tail = &mut less_tail.insert(node).right;
+            } else {
+                // node and its right subtree are greater than or equal to key
+                link = node.left.take();
+                greater_or_equal_tail = &mut greater_or_equal_tail.insert(node).left;
             }
         }
+
+        // only the sizes along both spines changed
+        let mut remaining = less_len;
+        let mut spine = less.as_deref_mut();
+        while let Some(node) = spine {
+            node.size = remaining;
+            remaining -= 1 + Self::subtree_size(&node.left);
+            spine = node.right.as_deref_mut();
+        }
+
+        let mut remaining = self_len - less_len;
+        let mut spine = greater_or_equal.as_deref_mut();
+        while let Some(node) = spine {
+            node.size = remaining;
+            remaining -= 1 + Self::subtree_size(&node.right);
+            spine = node.left.as_deref_mut();
+        }
+
+        *self = less.map_or_else(empty, |node| *node);
+        greater_or_equal.map_or_else(empty, |node| *node)
     }
 
-    pub fn min(&self) -> Option<&T> {
-        match &self.left {
-            Some(node) => node.min(),
-            None => self.data.as_ref(),
+    /// Returns a cursor positioned at the smallest element
+    pub fn cursor_front(&self) -> Cursor<'_, T> {
+        Cursor::new(self, 0)
+    }
+
+    /// Returns a cursor positioned at the largest element
+    pub fn cursor_back(&self) -> Cursor<'_, T> {
+        Cursor::new(self, self.size.saturating_sub(1))
+    }
+
+    /// Returns a cursor positioned at the smallest element greater than or
+    /// equal to `key`, or at the ghost position if there is none
+    pub fn cursor_at(&self, key: &T) -> Cursor<'_, T> {
+        Cursor::new(self, self.rank(key))
+    }
+
+    /// Returns a mutable cursor positioned at the smallest element
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            tree: self,
+            index: 0,
         }
     }
 
-    pub fn max(&self) -> Option<&T> {
-        match &self.right {
-            Some(node) => node.max(),
-            None => self.data.as_ref(),
+    /// Returns a mutable cursor positioned at the largest element
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+        let index = self.size.saturating_sub(1);
+
+        CursorMut { tree: self, index }
+    }
+
+    /// Returns a mutable cursor positioned at the smallest element greater
+    /// than or equal to `key`, or at the ghost position if there is none
+    pub fn cursor_at_mut(&mut self, key: &T) -> CursorMut<'_, T> {
+        let index = self.rank(key);
+
+        CursorMut { tree: self, index }
+    }
+
+    /// Returns `true` if the tree contains an element equal to `key`
+    ///
+    /// The key may be any borrowed form of the element type, so a
+    /// `BinarySearchTree<String>` can be probed with a `&str`.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the stored element equal to `key`
+    ///
+    /// Useful when the ordering only considers part of the element, e.g. an
+    /// id, and the rest of the stored value is needed. Like [`Self::contains`]
+    /// the key may be any borrowed form of the element type.
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_by(|stored_data| key.cmp(stored_data.borrow()))
+    }
+
+    /// Returns the element for which `locate` reports `Equal`, see
+    /// [`Self::take_by`]
+    pub(crate) fn get_by<F>(&self, mut locate: F) -> Option<&T>
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        let mut node = Some(self);
+
+        while let Some(current) = node {
+            let stored_data = current.data.as_ref()?;
+
+            match locate(stored_data) {
+                Equal => return Some(stored_data),
+                // key < stored_data
+                // search in the left
+                Less => node = current.left.as_deref(),
+                // key > stored_data
+                // search in the right
+                Greater => node = current.right.as_deref(),
+            }
         }
+        None
     }
 
-    /// Returns a new iterator which iterates over this tree in order
-    pub fn iter(&self) -> impl Iterator<Item = &T> {
-        BinarySearchTreeIterator::new(self)
+    /// Returns the subtree rooted at the node holding `key` as a read-only tree
+    ///
+    /// Every node is a tree itself, so the view supports all the read-only
+    /// queries and iterators, scoped to the elements below that node.
+    pub fn subtree(&self, key: &T) -> Option<&Self> {
+        let mut node = self;
+
+        loop {
+            node = match key.cmp(node.data.as_ref()?) {
+                Equal => return Some(node),
+                Less => node.left.as_deref()?,
+                Greater => node.right.as_deref()?,
+            };
+        }
     }
-}
 
-impl<T> Default for BinarySearchTree<T>
-where
-    T: Ord,
-{
-    fn default() -> Self {
-        Self::new()
+    /// Detaches the subtree rooted at the node holding `key` and returns it
+    ///
+    /// The remaining elements stay in `self`, which is left empty when `key` is
+    /// stored at the root.
+    pub fn split_subtree(&mut self, key: &T) -> Option<Self> {
+        let detached_size = self.subtree(key)?.size;
+
+        if self.data.as_ref() == Some(key) {
+            let empty = Self::with_duplicate_policy(self.duplicate_policy);
+            return Some(mem::replace(self, empty));
+        }
+
+        // the subtree exists, so walk down to its parent updating the sizes
+        let mut node = self;
+        loop {
+            node.size -= detached_size;
+
+            let target_node = if key < node.data.as_ref().unwrap() {
+                &mut node.left
+            } else {
+                &mut node.right
+            };
+
+            if target_node.as_ref().unwrap().data.as_ref() == Some(key) {
+                return target_node.take().map(|node| *node);
+            }
+            node = target_node.as_deref_mut().unwrap();
+        }
     }
-}
 
-struct BinarySearchTreeIterator<'a, T>
-where
-    T: Ord,
-{
-    stack: Vec<&'a BinarySearchTree<T>>,
-}
+    /// Returns a mutable reference to the stored element equal to `key`
+    ///
+    /// Private since changing the ordering of the element would break the tree.
+    fn get_mut(&mut self, key: &T) -> Option<&mut T> {
+        self.get_mut_by(|stored_data| key.cmp(stored_data))
+    }
 
-impl<'a, T> BinarySearchTreeIterator<'a, T>
-where
-    T: Ord,
-{
-    pub fn new(tree: &'a BinarySearchTree<T>) -> Self {
-        let mut iter = Self { stack: vec![tree] };
+    /// Returns a mutable reference to the element for which `locate` reports
+    /// `Equal`, see [`Self::take_by`]
+    ///
+    /// Callers must not change the ordering of the element.
+    pub(crate) fn get_mut_by<F>(&mut self, mut locate: F) -> Option<&mut T>
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        let mut node = self;
 
-        iter.stack_push_left();
-        iter
+        loop {
+            node = match locate(node.data.as_ref()?) {
+                Equal => return node.data.as_mut(),
+                Less => node.left.as_deref_mut()?,
+                Greater => node.right.as_deref_mut()?,
+            };
+        }
     }
 
-    fn stack_push_left(&mut self) {
-        while let Some(child) = &self.stack.last().unwrap().left {
-            self.stack.push(child);
+    /// Searches for the element for which `locate` reports `Equal`, returning
+    /// whether it was found and the turns taken from the root
+    ///
+    /// On a miss the last turn points at the empty link where the element
+    /// belongs, so the path can be handed to [`Self::insert_at_path`] without
+    /// comparing elements again.
+    pub(crate) fn search_path_by<F>(&self, mut locate: F) -> (bool, Vec<Ordering>)
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        let mut path = Vec::new();
+        let mut node = Some(self);
+
+        while let Some(current) = node {
+            let Some(stored_data) = &current.data else {
+                break;
+            };
+
+            let turn = locate(stored_data);
+            if turn == Equal {
+                return (true, path);
+            }
+
+            path.push(turn);
+            node = match turn {
+                Less => current.left.as_deref(),
+                _ => current.right.as_deref(),
+            };
         }
+
+        (false, path)
     }
-}
 
-impl<'a, T> Iterator for BinarySearchTreeIterator<'a, T>
-where
-    T: Ord,
-{
-    type Item = &'a T;
+    /// Returns the element reached by following `path` from the root
+    ///
+    /// `path` must come from a successful [`Self::search_path_by`] with no
+    /// modification of the tree in between. Callers must not change the
+    /// ordering of the element.
+    pub(crate) fn get_at_path_mut(&mut self, path: &[Ordering]) -> &mut T {
+        let mut node = self;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.stack.is_empty() {
-            None
-        } else {
-            let node = self.stack.pop().unwrap();
+        for turn in path {
+            node = match turn {
+                Less => node.left.as_deref_mut(),
+                _ => node.right.as_deref_mut(),
+            }
+            .expect("path leads to an existing node");
+        }
 
-            if node.right.is_some() {
-                self.stack.push(node.right.as_ref().unwrap().deref());
-                self.stack_push_left();
+        node.data.as_mut().expect("path leads to an existing node")
+    }
+
+    /// Inserts `data` at the empty link reached by following `path` from the
+    /// root, returning a reference to the stored element
+    ///
+    /// `path` must come from a failed [`Self::search_path_by`] with no
+    /// modification of the tree in between.
+    pub(crate) fn insert_at_path(&mut self, path: &[Ordering], data: T) -> &mut T {
+        let Some((last, rest)) = path.split_last() else {
+            self.size += 1;
+            return self.data.insert(data);
+        };
+
+        let mut node = self;
+
+        for turn in rest {
+            node.size += 1;
+            node = match turn {
+                Less => node.left.as_deref_mut(),
+                _ => node.right.as_deref_mut(),
             }
-            node.data.as_ref()
+            .expect("path leads to an existing node");
         }
+
+        node.size += 1;
+
+        let mut child = Self::with_duplicate_policy(node.duplicate_policy);
+        child.size = 1;
+
+        let target_node = match last {
+            Less => &mut node.left,
+            _ => &mut node.right,
+        };
+
+        target_node.insert(Box::new(child)).data.insert(data)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    /// Returns the stored element equal to `key`, inserting the result of `f`
+    /// if there is none
+    ///
+    /// The path found while searching is reused for the insertion, so the tree
+    /// is only searched once. `f` must produce an element equal to `key`.
+    pub fn get_or_insert_with<F>(&mut self, key: T, f: F) -> &T
+    where
+        F: FnOnce() -> T,
+    {
+        let (found, path) = self.search_path_by(|stored_data| key.cmp(stored_data));
 
-    fn prequel_tree() -> BinarySearchTree<u32> {
-        let mut tree = BinarySearchTree::new();
-        tree.insert(16);
-        tree.insert(7);
-        tree.insert(28);
-        tree.insert(3);
-        tree.insert(21);
-        tree.insert(36);
-        tree.insert(70);
-        tree
+        if found {
+            return self.get_at_path_mut(&path);
+        }
+
+        let data = f();
+        debug_assert!(data == key, "f must produce an element equal to key");
+
+        self.insert_at_path(&path, data)
     }
 
-    #[test]
-    fn test_search() {
-        let tree = prequel_tree();
+    /// Inserts `value`, replacing and returning the stored element equal to it
+    ///
+    /// Unlike [`Self::insert`] an equal element is never kept next to `value`:
+    /// it is swapped out regardless of the [`DuplicatePolicy`], which matters
+    /// when equality ignores some fields of `T`.
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        if let Some(stored_data) = self.get_mut(&value) {
+            return Some(mem::replace(stored_data, value));
+        }
 
-        assert!(tree.search(&16));
-        assert!(tree.search(&7));
-        assert!(tree.search(&28));
-        assert!(tree.search(&3));
-        assert!(tree.search(&21));
-        assert!(tree.search(&36));
-        assert!(tree.search(&70));
+        self.insert(value);
+        None
+    }
 
-        assert!(!tree.search(&1));
-        assert!(!tree.search(&90));
+    /// Inserts `data` into the tree, returning whether it was inserted
+    ///
+    /// Under [`DuplicatePolicy::Reject`] an element equal to `data` is looked up
+    /// first and, if found, `data` is dropped and `false` is returned.
+    pub fn insert(&mut self, data: T) -> bool {
+        if self.duplicate_policy == DuplicatePolicy::Reject && self.contains(&data) {
+            return false;
+        }
+
+        let mut node = self;
+
+        // walk down iteratively so that deep, skewed trees can't overflow the stack
+        loop {
+            node.size += 1;
+
+            let stored_data = match &node.data {
+                None => {
+                    node.data = Some(data);
+                    return true;
+                }
+                Some(stored_data) => stored_data,
+            };
+
+            let target_node = if data < *stored_data {
+                &mut node.left
+            } else {
+                &mut node.right
+            };
+
+            match target_node {
+                Some(child) => node = child,
+                None => {
+                    let mut child = Self::with_duplicate_policy(node.duplicate_policy);
+
+                    child.insert(data);
+
+                    *target_node = Some(Box::new(child));
+                    return true;
+                }
+            }
+        }
     }
 
-    #[test]
-    fn test_iterator() {
-        let tree = prequel_tree();
-        let mut iter = tree.iter();
+    /// Inserts `data` into the tree, handing it back inside the error if it
+    /// can't be stored
+    ///
+    /// Fails with [`InsertErrorKind::Duplicate`] under
+    /// [`DuplicatePolicy::Reject`] when an equal element is stored, and never
+    /// with [`InsertErrorKind::AllocationFailed`]. Unlike [`Self::insert`] the
+    /// tree is only searched once.
+    pub fn try_insert(&mut self, data: T) -> Result<(), InsertError<T>> {
+        let duplicate_policy = self.duplicate_policy;
 
-        assert_eq!(iter.next(), Some(&3));
-        assert_eq!(iter.next(), Some(&7));
-        assert_eq!(iter.next(), Some(&16));
-        assert_eq!(iter.next(), Some(&21));
-        assert_eq!(iter.next(), Some(&28));
-        assert_eq!(iter.next(), Some(&36));
-        assert_eq!(iter.next(), Some(&70));
-        assert_eq!(iter.next(), None);
+        let (found, path) = self.search_path_by(|stored_data| match data.cmp(stored_data) {
+            // equal elements go to the right when they are allowed
+            Equal if duplicate_policy == DuplicatePolicy::Allow => Greater,
+            ordering => ordering,
+        });
+
+        if found {
+            return Err(InsertError::new(InsertErrorKind::Duplicate, data));
+        }
+
+        self.insert_at_path(&path, data);
+        Ok(())
+    }
+
+    /// Removes all elements, leaving the tree empty
+    pub fn clear(&mut self) {
+        self.data = None;
+        self.left = None;
+        self.right = None;
+        self.size = 0;
+    }
+
+    /// Removes `data` from the tree, returning whether it was present
+    pub fn remove(&mut self, data: &T) -> bool {
+        self.take(data).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    ///
+    /// Unlike [`Self::remove`] the stored element is handed back instead of
+    /// being dropped, which matters when equality only considers part of it.
+    pub fn take(&mut self, key: &T) -> Option<T> {
+        self.take_by(&mut |stored_data| key.cmp(stored_data))
+    }
+
+    /// Removes and returns the element for which `locate` reports `Equal`
+    ///
+    /// `locate` compares the searched key against a stored element, it lets
+    /// wrappers such as the tree map search by a part of the element.
+    pub(crate) fn take_by<F>(&mut self, locate: &mut F) -> Option<T>
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        let (found, path) = self.search_path_by(locate);
+        if !found {
+            return None;
+        }
+
+        self.take_at_path(&path)
+    }
+
+    /// Removes and returns the element at sorted position `index`
+    fn take_at(&mut self, mut index: usize) -> Option<T> {
+        if index >= self.size {
+            return None;
+        }
+
+        let mut path = Vec::new();
+        let mut node = &*self;
+
+        loop {
+            let left_size = Self::subtree_size(&node.left);
+            let turn = index.cmp(&left_size);
+
+            match turn {
+                Equal => break,
+                Less => {}
+                Greater => index -= left_size + 1,
+            }
+
+            path.push(turn);
+            node = match turn {
+                Less => node.left.as_deref(),
+                _ => node.right.as_deref(),
+            }
+            // index < size, so the element is in this child
+            .expect("index is within the subtree");
+        }
+
+        self.take_at_path(&path)
+    }
+
+    /// Removes and returns the element reached by following `path` from the
+    /// root
+    ///
+    /// `path` must lead to an existing node, as after a successful
+    /// [`Self::search_path_by`]. The walk down is iterative so that deep,
+    /// skewed trees can't overflow the stack.
+    fn take_at_path(&mut self, path: &[Ordering]) -> Option<T> {
+        let Some((last, rest)) = path.split_last() else {
+            return self.take_data();
+        };
+
+        let mut node = self;
+
+        for turn in rest {
+            node.size -= 1;
+            node = match turn {
+                Less => node.left.as_deref_mut(),
+                _ => node.right.as_deref_mut(),
+            }
+            .expect("path leads to an existing node");
+        }
+
+        node.size -= 1;
+
+        let target_node = match last {
+            Less => &mut node.left,
+            _ => &mut node.right,
+        };
+        let child = target_node
+            .as_deref_mut()
+            .expect("path leads to an existing node");
+        let taken = child.take_data();
+
+        // the child held a single element which was taken
+        if child.data.is_none() {
+            *target_node = None;
+        }
+        taken
+    }
+
+    /// Takes the data stored in this node and restructures the subtree
+    /// so that it stays a valid binary search tree
+    fn take_data(&mut self) -> Option<T> {
+        match (self.left.take(), self.right.take()) {
+            // leaf: the node becomes empty and is pruned by its parent
+            (None, None) => {
+                self.size = 0;
+                self.data.take()
+            }
+            // single child: the child takes the place of this node
+            (Some(node), None) | (None, Some(node)) => {
+                let data = self.data.take();
+                *self = *node;
+                data
+            }
+            // two children: the in-order successor takes the place of the data
+            (Some(left), Some(mut right)) => {
+                let data = mem::replace(&mut self.data, right.pop_min());
+                self.size -= 1;
+                self.left = Some(left);
+                if right.data.is_some() {
+                    self.right = Some(right);
+                }
+                data
+            }
+        }
+    }
+
+    /// Removes and returns the smallest element, if any
+    pub fn pop_min(&mut self) -> Option<T> {
+        if self.left.is_none() {
+            let min = self.data.take();
+
+            match self.right.take() {
+                Some(node) => *self = *node,
+                None => self.size = 0,
+            }
+            return min;
+        }
+
+        // walk down to the parent of the leftmost node
+        let mut node = self;
+        loop {
+            node.size -= 1;
+
+            let left = node
+                .left
+                .as_deref()
+                .expect("the walk stays on left children");
+            if left.left.is_none() {
+                break;
+            }
+            node = node.left.as_deref_mut().expect("checked above");
+        }
+
+        let mut min_node = node.left.take().expect("checked above");
+        node.left = min_node.right.take();
+        min_node.data.take()
+    }
+
+    /// Removes and returns the largest element, if any
+    pub fn pop_max(&mut self) -> Option<T> {
+        if self.right.is_none() {
+            let max = self.data.take();
+
+            match self.left.take() {
+                Some(node) => *self = *node,
+                None => self.size = 0,
+            }
+            return max;
+        }
+
+        // walk down to the parent of the rightmost node
+        let mut node = self;
+        loop {
+            node.size -= 1;
+
+            let right = node
+                .right
+                .as_deref()
+                .expect("the walk stays on right children");
+            if right.right.is_none() {
+                break;
+            }
+            node = node.right.as_deref_mut().expect("checked above");
+        }
+
+        let mut max_node = node.right.take().expect("checked above");
+        node.right = max_node.left.take();
+        max_node.data.take()
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        match &self.left {
+            Some(node) => node.min(),
+            None => self.data.as_ref(),
+        }
+    }
+
+    pub fn max(&self) -> Option<&T> {
+        match &self.right {
+            Some(node) => node.max(),
+            None => self.data.as_ref(),
+        }
+    }
+
+    /// Returns the number of stored elements equal to `key`
+    ///
+    /// This is at most `1` under [`DuplicatePolicy::Reject`].
+    pub fn count(&self, key: &T) -> usize {
+        self.count_range((Included(key), Included(key)))
+    }
+
+    /// Returns the largest element less than or equal to `key`
+    pub fn floor(&self, key: &T) -> Option<&T> {
+        let mut floor = None;
+        let mut node = Some(self);
+
+        while let Some(current) = node {
+            let Some(stored_data) = &current.data else {
+                break;
+            };
+
+            match key.cmp(stored_data) {
+                Equal => return Some(stored_data),
+                Less => node = current.left.as_deref(),
+                Greater => {
+                    // stored_data is a candidate, look for a larger one
+                    floor = Some(stored_data);
+                    node = current.right.as_deref();
+                }
+            }
+        }
+        floor
+    }
+
+    /// Returns the smallest element greater than or equal to `key`
+    pub fn ceiling(&self, key: &T) -> Option<&T> {
+        let mut ceiling = None;
+        let mut node = Some(self);
+
+        while let Some(current) = node {
+            let Some(stored_data) = &current.data else {
+                break;
+            };
+
+            match key.cmp(stored_data) {
+                Equal => return Some(stored_data),
+                Less => {
+                    // stored_data is a candidate, look for a smaller one
+                    ceiling = Some(stored_data);
+                    node = current.left.as_deref();
+                }
+                Greater => node = current.right.as_deref(),
+            }
+        }
+        ceiling
+    }
+
+    /// Returns the element closest to `key`, preferring the smaller one on ties
+    ///
+    /// The distance is computed by subtraction, `key - floor` and
+    /// `ceiling - key`, which never underflows since the floor is not greater
+    /// and the ceiling is not less than `key`. Use [`Self::nearest_by`] to pick
+    /// the tie-breaker or a custom distance.
+    pub fn nearest<D>(&self, key: &T) -> Option<&T>
+    where
+        for<'a> &'a T: Sub<&'a T, Output = D>,
+        D: Ord,
+    {
+        self.nearest_by(
+            key,
+            NearestTie::Smaller,
+            |a, b| {
+                if a < b {
+                    b - a
+                } else {
+                    a - b
+                }
+            },
+        )
+    }
+
+    /// Returns whichever of the floor and the ceiling of `key` is closer to it
+    /// according to `distance`, breaking ties with `tie`
+    pub fn nearest_by<D, F>(&self, key: &T, tie: NearestTie, mut distance: F) -> Option<&T>
+    where
+        F: FnMut(&T, &T) -> D,
+        D: Ord,
+    {
+        match (self.floor(key), self.ceiling(key)) {
+            (Some(floor), Some(ceiling)) => {
+                match distance(key, floor).cmp(&distance(key, ceiling)) {
+                    Less => Some(floor),
+                    Greater => Some(ceiling),
+                    Equal => match tie {
+                        NearestTie::Smaller => Some(floor),
+                        NearestTie::Larger => Some(ceiling),
+                    },
+                }
+            }
+            (floor, ceiling) => floor.or(ceiling),
+        }
+    }
+
+    /// Returns the smallest element strictly greater than `key`
+    ///
+    /// `key` does not need to be stored in the tree.
+    pub fn successor(&self, key: &T) -> Option<&T> {
+        let mut successor = None;
+        let mut node = Some(self);
+
+        while let Some(current) = node {
+            let Some(stored_data) = &current.data else {
+                break;
+            };
+
+            if key < stored_data {
+                successor = Some(stored_data);
+                node = current.left.as_deref();
+            } else {
+                node = current.right.as_deref();
+            }
+        }
+        successor
+    }
+
+    /// Returns the largest element strictly less than `key`
+    ///
+    /// `key` does not need to be stored in the tree.
+    pub fn predecessor(&self, key: &T) -> Option<&T> {
+        let mut predecessor = None;
+        let mut node = Some(self);
+
+        while let Some(current) = node {
+            let Some(stored_data) = &current.data else {
+                break;
+            };
+
+            if key > stored_data {
+                predecessor = Some(stored_data);
+                node = current.right.as_deref();
+            } else {
+                node = current.left.as_deref();
+            }
+        }
+        predecessor
+    }
+
+    /// Returns the `k`-th smallest element, counting from zero
+    ///
+    /// Runs in `O(height)` using the subtree sizes stored in every node.
+    pub fn select(&self, k: usize) -> Option<&T> {
+        let mut k = k;
+        let mut node = Some(self);
+
+        while let Some(current) = node {
+            let left_size = current.left.as_ref().map_or(0, |node| node.size);
+
+            match k.cmp(&left_size) {
+                Equal => return current.data.as_ref(),
+                Less => node = current.left.as_deref(),
+                Greater => {
+                    // skip the left subtree and the current node
+                    k -= left_size + 1;
+                    node = current.right.as_deref();
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the number of elements strictly less than `key`
+    ///
+    /// `key` does not need to be stored in the tree. Runs in `O(height)`.
+    pub fn rank(&self, key: &T) -> usize {
+        self.count_below(key, false)
+    }
+
+    /// Returns the number of elements less than `key`, or less than or equal
+    /// to `key` if `inclusive`
+    fn count_below(&self, key: &T, inclusive: bool) -> usize {
+        let mut rank = 0;
+        let mut node = Some(self);
+
+        while let Some(current) = node {
+            let Some(stored_data) = &current.data else {
+                break;
+            };
+
+            if key > stored_data || (inclusive && key == stored_data) {
+                rank += current.left.as_ref().map_or(0, |node| node.size) + 1;
+                node = current.right.as_deref();
+            } else {
+                node = current.left.as_deref();
+            }
+        }
+        rank
+    }
+
+    /// Returns the number of elements contained in `range`
+    ///
+    /// Computed from the ranks of both bounds in `O(height)`, without visiting
+    /// the elements in between.
+    pub fn count_range<R>(&self, range: R) -> usize
+    where
+        R: RangeBounds<T>,
+    {
+        let before_start = match range.start_bound() {
+            Included(start) => self.count_below(start, false),
+            Excluded(start) => self.count_below(start, true),
+            Unbounded => 0,
+        };
+        let up_to_end = match range.end_bound() {
+            Included(end) => self.count_below(end, true),
+            Excluded(end) => self.count_below(end, false),
+            Unbounded => self.size,
+        };
+
+        // an inverted range contains nothing
+        up_to_end.saturating_sub(before_start)
+    }
+
+    /// Returns the lowest common ancestor of `a` and `b`, the deepest node
+    /// whose subtree holds both keys
+    ///
+    /// Walks down to the node where the search paths of `a` and `b` split, so
+    /// it runs in `O(height)`. Returns `None` unless both keys are stored.
+    pub fn lowest_common_ancestor(&self, a: &T, b: &T) -> Option<&T> {
+        let mut node = self;
+
+        loop {
+            let stored_data = node.data.as_ref()?;
+
+            let next = if a < stored_data && b < stored_data {
+                &node.left
+            } else if a > stored_data && b > stored_data {
+                &node.right
+            } else {
+                // the paths split here, or one of the keys is this node
+                return (node.contains(a) && node.contains(b)).then_some(stored_data);
+            };
+
+            node = next.as_deref()?;
+        }
+    }
+
+    /// Returns the number of edges between the root and the node holding `key`
+    pub fn depth_of(&self, key: &T) -> Option<usize> {
+        self.path_to(key)
+            .enumerate()
+            .find_map(|(depth, data)| (data == key).then_some(depth))
+    }
+
+    /// Returns a new iterator over the elements compared against while
+    /// searching for `key`, from the root down to `key`
+    ///
+    /// If `key` is not stored the whole search path is yielded, ending with
+    /// the node under which `key` would be inserted.
+    pub fn path_to<'a>(&'a self, key: &'a T) -> BinarySearchTreePathIterator<'a, T> {
+        BinarySearchTreePathIterator {
+            node: self.data.is_some().then_some(self),
+            key,
+        }
+    }
+
+    /// Returns the number of heap bytes used by the nodes of the tree
+    ///
+    /// The root is stored inline, so only the other nodes count. Heap memory
+    /// owned by the elements themselves is not included.
+    pub fn memory_usage(&self) -> usize {
+        self.len().saturating_sub(1) * mem::size_of::<Self>()
+    }
+
+    /// Collects structural statistics of the tree in a single traversal
+    pub fn stats(&self) -> BinarySearchTreeStats {
+        let mut stats = BinarySearchTreeStats::default();
+        let mut level: Vec<&Self> = if self.data.is_some() {
+            vec![self]
+        } else {
+            vec![]
+        };
+
+        // walk level by level to measure the widths
+        while !level.is_empty() {
+            stats.height += 1;
+            stats.width = stats.width.max(level.len());
+            stats.nodes += level.len();
+
+            let mut next_level = Vec::new();
+            for node in level {
+                if node.left.is_none() && node.right.is_none() {
+                    stats.leaves += 1;
+                }
+                next_level.extend(node.left.as_deref());
+                next_level.extend(node.right.as_deref());
+            }
+            level = next_level;
+        }
+
+        if self.data.is_some() {
+            stats.diameter = self.height_and_diameter().1;
+        }
+        stats
+    }
+
+    /// Returns the height of the subtree and the number of nodes on the
+    /// longest path between any two of its nodes
+    fn height_and_diameter(&self) -> (usize, usize) {
+        self.fold_subtrees(
+            (0, 0),
+            |_, (left_height, left_diameter), (right_height, right_diameter)| {
+                // the longest path through this node joins both subtrees
+                let through = 1 + left_height + right_height;
+
+                (
+                    1 + left_height.max(right_height),
+                    through.max(left_diameter).max(right_diameter),
+                )
+            },
+        )
+    }
+
+    /// Swaps the left and right children of every node
+    ///
+    /// The mirrored tree is ordered descending, so its in-order iterators yield
+    /// the elements from largest to smallest. The lookup and mutation methods
+    /// assume ascending order and must not be used until the tree is mirrored
+    /// back; [`Self::is_valid_bst`] reports `false` in the meantime.
+    pub fn mirror(&mut self) {
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            mem::swap(&mut node.left, &mut node.right);
+
+            stack.extend(node.left.as_deref_mut());
+            stack.extend(node.right.as_deref_mut());
+        }
+    }
+
+    /// Returns `true` if `other` has the shape and elements of `self` with the
+    /// left and right children of every node swapped
+    pub fn is_mirror_of(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+
+        while let Some((node, other)) = stack.pop() {
+            if node.data != other.data {
+                return false;
+            }
+
+            for (child, other_child) in [(&node.left, &other.right), (&node.right, &other.left)] {
+                match (child, other_child) {
+                    (Some(child), Some(other_child)) => stack.push((child, other_child)),
+                    (None, None) => {}
+                    _ => return false,
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    ///
+    /// An empty tree has a height of `0` and a tree with a single element has a
+    /// height of `1`.
+    pub fn height(&self) -> usize {
+        if self.data.is_none() {
+            return 0;
+        }
+
+        let mut height = 0;
+        let mut stack = vec![(self, 1)];
+
+        while let Some((node, depth)) = stack.pop() {
+            height = height.max(depth);
+            stack.extend(node.left.as_deref().map(|left| (left, depth + 1)));
+            stack.extend(node.right.as_deref().map(|right| (right, depth + 1)));
+        }
+        height
+    }
+
+    /// Returns `true` if, for every node, the heights of its two subtrees
+    /// differ by at most one
+    pub fn is_balanced(&self) -> bool {
+        if self.data.is_none() {
+            return true;
+        }
+
+        // the height of each subtree, or `None` once an unbalanced one is found
+        self.fold_subtrees(Some(0), |_, left_height: Option<usize>, right_height| {
+            let (left_height, right_height) = (left_height?, right_height?);
+            (left_height.abs_diff(right_height) <= 1).then_some(1 + left_height.max(right_height))
+        })
+        .is_some()
+    }
+
+    /// Combines the results of the subtrees from the bottom up, calling `f` with
+    /// each node and the results of its left and right subtrees
+    ///
+    /// Missing subtrees contribute `empty`. The nodes are visited in postorder
+    /// with an explicit stack, so deep trees can't overflow the call stack.
+    fn fold_subtrees<A, F>(&self, empty: A, mut f: F) -> A
+    where
+        A: Clone,
+        F: FnMut(&Self, A, A) -> A,
+    {
+        let mut stack = vec![(self, false)];
+        // the results of the finished subtrees, left before right
+        let mut results = Vec::new();
+
+        while let Some((node, children_done)) = stack.pop() {
+            if !children_done {
+                stack.push((node, true));
+                stack.extend(node.right.as_deref().map(|right| (right, false)));
+                stack.extend(node.left.as_deref().map(|left| (left, false)));
+                continue;
+            }
+
+            let mut result = |child: &Option<Box<Self>>| match child {
+                Some(_) => results.pop().unwrap(),
+                None => empty.clone(),
+            };
+            let right = result(&node.right);
+            let left = result(&node.left);
+            results.push(f(node, left, right));
+        }
+        results.pop().unwrap()
+    }
+
+    /// Checks the structural invariants of the tree
+    ///
+    /// Every element must be greater than or equal to all elements of its left
+    /// subtree and less than or equal to all elements of its right subtree, only
+    /// an empty tree may have a root without data and every node must record
+    /// the correct subtree size.
+    pub fn is_valid_bst(&self) -> bool {
+        if self.data.is_none() {
+            return self.left.is_none() && self.right.is_none() && self.size == 0;
+        }
+
+        // each node with the bounds its ancestors put on it
+        let mut stack: Vec<(&Self, Option<&T>, Option<&T>)> = vec![(self, None, None)];
+
+        while let Some((node, lower, upper)) = stack.pop() {
+            let Some(data) = &node.data else {
+                return false;
+            };
+
+            if lower.is_some_and(|lower| data < lower) || upper.is_some_and(|upper| data > upper) {
+                return false;
+            }
+
+            let expected_size =
+                1 + Self::subtree_size(&node.left) + Self::subtree_size(&node.right);
+            if node.size != expected_size {
+                return false;
+            }
+
+            stack.extend(node.left.as_deref().map(|left| (left, lower, Some(data))));
+            stack.extend(
+                node.right
+                    .as_deref()
+                    .map(|right| (right, Some(data), upper)),
+            );
+        }
+        true
+    }
+
+    /// Rebalances the tree in place in `O(n)` time and `O(1)` extra space
+    ///
+    /// Uses the Day–Stout–Warren algorithm: the tree is first flattened into a
+    /// sorted "vine" by right rotations and then compressed by left rotations
+    /// into a tree where every level but the last is full.
+    pub fn rebalance(&mut self) {
+        if self.size < 3 {
+            return;
+        }
+
+        // the pseudo root lets rotations treat the real root like any other node
+        let mut pseudo_root = Self::new();
+        pseudo_root.right = Some(Box::new(mem::take(self)));
+
+        let size = Self::tree_to_vine(&mut pseudo_root);
+        Self::vine_to_tree(&mut pseudo_root, size);
+
+        *self = *pseudo_root.right.take().unwrap();
+    }
+
+    /// Flattens the tree hanging right of `pseudo_root` into a vine of right
+    /// children, returning its length
+    fn tree_to_vine(pseudo_root: &mut Self) -> usize {
+        let mut tail = pseudo_root;
+        let mut size = 0;
+
+        while let Some(rest) = &tail.right {
+            if rest.left.is_some() {
+                Self::rotate_right(&mut tail.right);
+            } else {
+                tail = tail.right.as_deref_mut().unwrap();
+                size += 1;
+            }
+        }
+        size
+    }
+
+    /// Compresses the vine hanging right of `pseudo_root` into a balanced tree
+    fn vine_to_tree(pseudo_root: &mut Self, size: usize) {
+        // number of nodes in the largest perfect tree which fits in `size`
+        let mut leaves = (size + 1).next_power_of_two();
+        if leaves > size + 1 {
+            leaves /= 2;
+        }
+        let mut perfect = leaves - 1;
+
+        Self::compress(pseudo_root, size - perfect);
+        while perfect > 1 {
+            perfect /= 2;
+            Self::compress(pseudo_root, perfect);
+        }
+    }
+
+    /// Left rotates every other node along the right spine, `count` times
+    fn compress(pseudo_root: &mut Self, count: usize) {
+        let mut scanner = pseudo_root;
+
+        for _ in 0..count {
+            Self::rotate_left(&mut scanner.right);
+            scanner = scanner.right.as_deref_mut().unwrap();
+        }
+    }
+
+    fn subtree_size(node: &Option<Box<Self>>) -> usize {
+        node.as_ref().map_or(0, |node| node.size)
+    }
+
+    /// Recomputes the size of this node from the sizes of its children
+    fn update_size(&mut self) {
+        self.size = 1 + Self::subtree_size(&self.left) + Self::subtree_size(&self.right);
+    }
+
+    /// Rotates the subtree in `link` to the right, its left child becomes its root
+    fn rotate_right(link: &mut Option<Box<Self>>) {
+        let mut node = link.take().unwrap();
+        let mut left = node.left.take().unwrap();
+
+        node.left = left.right.take();
+        node.update_size();
+        left.right = Some(node);
+        left.update_size();
+
+        *link = Some(left);
+    }
+
+    /// Rotates the subtree in `link` to the left, its right child becomes its root
+    fn rotate_left(link: &mut Option<Box<Self>>) {
+        let mut node = link.take().unwrap();
+        let mut right = node.right.take().unwrap();
+
+        node.right = right.left.take();
+        node.update_size();
+        right.left = Some(node);
+        right.update_size();
+
+        *link = Some(right);
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> BinarySearchTreeIterator<'_, T> {
+        BinarySearchTreeIterator::new(self)
+    }
+
+    /// Returns a new iterator which iterates over mutable references to the
+    /// elements in order
+    ///
+    /// Callers must not change the ordering of the elements, which is why this
+    /// is only available to wrappers such as the tree map.
+    pub(crate) fn iter_mut(&mut self) -> BinarySearchTreeIterMut<'_, T> {
+        BinarySearchTreeIterMut::new(self)
+    }
+
+    /// Returns a new iterator which visits each node before its left and then
+    /// its right subtree
+    pub fn iter_preorder(&self) -> BinarySearchTreePreorderIterator<'_, T> {
+        BinarySearchTreePreorderIterator::new(self)
+    }
+
+    /// Returns a new iterator which visits the left and then the right subtree
+    /// of each node before the node itself
+    pub fn iter_postorder(&self) -> BinarySearchTreePostorderIterator<'_, T> {
+        BinarySearchTreePostorderIterator::new(self)
+    }
+
+    /// Returns a new iterator which visits the nodes level by level from the
+    /// root, left to right within a level
+    pub fn iter_level_order(&self) -> BinarySearchTreeLevelOrderIterator<'_, T> {
+        BinarySearchTreeLevelOrderIterator::new(self)
+    }
+
+    /// Consumes the tree, returning its elements in order
+    ///
+    /// The elements are moved, not cloned, into a vector allocated once with
+    /// the exact capacity.
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        let mut sorted = Vec::with_capacity(self.size);
+
+        sorted.extend(self);
+        sorted
+    }
+
+    /// Removes all elements, returning them in order through an owning iterator
+    ///
+    /// The tree is empty as soon as this returns; elements which are not
+    /// consumed from the iterator are dropped along with it.
+    pub fn drain(&mut self) -> BinarySearchTreeIntoIterator<T> {
+        let empty = Self::with_duplicate_policy(self.duplicate_policy);
+
+        BinarySearchTreeIntoIterator::new(mem::replace(self, empty))
+    }
+
+    /// Returns a new iterator which iterates in order over the elements
+    /// greater than or equal to `key`
+    ///
+    /// The walk starts directly at the first matching element, so resuming a
+    /// scan costs `O(height)` rather than skipping the smaller elements.
+    pub fn iter_from<'a>(&'a self, key: &'a T) -> impl Iterator<Item = &'a T> {
+        self.range((Included(key), Unbounded))
+    }
+
+    /// Returns an iterator over the elements in `self` or `other`, in order
+    ///
+    /// The set operations merge the in-order traversals of both trees without
+    /// collecting them. Equal elements are matched one to one, so with
+    /// duplicates they behave like multiset operations.
+    pub fn union<'a>(&'a self, other: &'a Self) -> BinarySearchTreeUnion<'a, T> {
+        BinarySearchTreeUnion(MergeIterator::new(self, other))
+    }
+
+    /// Returns an iterator over the elements in both `self` and `other`, in order
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> BinarySearchTreeIntersection<'a, T> {
+        BinarySearchTreeIntersection(MergeIterator::new(self, other))
+    }
+
+    /// Returns an iterator over the elements in `self` but not in `other`, in order
+    pub fn difference<'a>(&'a self, other: &'a Self) -> BinarySearchTreeDifference<'a, T> {
+        BinarySearchTreeDifference(MergeIterator::new(self, other))
+    }
+
+    /// Returns an iterator over the elements in exactly one of `self` and
+    /// `other`, in order
+    pub fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a Self,
+    ) -> BinarySearchTreeSymmetricDifference<'a, T> {
+        BinarySearchTreeSymmetricDifference(MergeIterator::new(self, other))
+    }
+
+    /// Returns a new iterator which iterates in order over the elements
+    /// contained in `range`
+    ///
+    /// Only the subtrees intersecting the range are visited. An empty or
+    /// inverted range yields no elements.
+    pub fn range<R>(&self, range: R) -> impl Iterator<Item = &T>
+    where
+        R: RangeBounds<T>,
+    {
+        BinarySearchTreeRangeIterator::new(self, range)
+    }
+}
+
+/// A step of the renderers of a [`BinarySearchTree`], which keep them on an
+/// explicit stack so that deep trees can't overflow the call stack
+enum RenderStep<'a, T, P>
+where
+    T: Ord,
+{
+    /// a subtree left to render, with its position in the output
+    Subtree(&'a BinarySearchTree<T>, P),
+    /// text to write once the steps above it are done
+    Text(String),
+}
+
+impl<T> BinarySearchTree<T>
+where
+    T: Ord + fmt::Display,
+{
+    /// Renders the shape of the tree sideways, one element per line
+    ///
+    /// The root is on the left edge, right subtrees are drawn above their
+    /// parent and left subtrees below it:
+    ///
+    /// ```text
+    ///     ┌── 28
+    /// 16
+    /// └── 7
+    ///     └── 3
+    /// ```
+    pub fn to_pretty_string(&self) -> String {
+        let mut output = String::new();
+
+        if let Some(data) = &self.data {
+            if let Some(node) = &self.right {
+                node.write_pretty(&mut output, "", false);
+            }
+            output.push_str(&format!("{}\n", data));
+            if let Some(node) = &self.left {
+                node.write_pretty(&mut output, "", true);
+            }
+        }
+        output
+    }
+
+    /// Prints the shape of the tree to stdout, see [`Self::to_pretty_string`]
+    pub fn pretty_print(&self) {
+        print!("{}", self.to_pretty_string());
+    }
+
+    /// Exports the tree as a Graphviz DOT digraph
+    ///
+    /// Every element becomes a node labelled with its `Display` output and every
+    /// child link becomes an edge labelled `L` or `R`. The result can be rendered
+    /// with e.g. `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut output = String::from("digraph BinarySearchTree {\n");
+
+        if self.data.is_some() {
+            self.write_dot(&mut output);
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    /// Writes the nodes of the tree and the edges between them
+    ///
+    /// Ids are assigned in preorder, and each edge is written once the subtree
+    /// it leads to is.
+    fn write_dot(&self, output: &mut String) {
+        let mut next_id = 0;
+        let mut stack = vec![RenderStep::Subtree(self, None)];
+
+        while let Some(step) = stack.pop() {
+            let (node, parent) = match step {
+                RenderStep::Text(text) => {
+                    output.push_str(&text);
+                    continue;
+                }
+                RenderStep::Subtree(node, parent) => (node, parent),
+            };
+
+            let id = next_id;
+            next_id += 1;
+
+            if let Some(data) = &node.data {
+                let label = data.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+                output.push_str(&format!("    n{} [label=\"{}\"];\n", id, label));
+            }
+
+            if let Some((parent_id, side)) = parent {
+                stack.push(RenderStep::Text(format!(
+                    "    n{} -> n{} [label=\"{}\"];\n",
+                    parent_id, id, side
+                )));
+            }
+            for (child, side) in [(&node.right, "R"), (&node.left, "L")] {
+                if let Some(child) = child {
+                    stack.push(RenderStep::Subtree(child, Some((id, side))));
+                }
+            }
+        }
+    }
+
+    /// Writes the subtree below `prefix`, its right subtree first
+    fn write_pretty(&self, output: &mut String, prefix: &str, is_left: bool) {
+        let mut stack = vec![RenderStep::Subtree(self, (prefix.to_owned(), is_left))];
+
+        while let Some(step) = stack.pop() {
+            let (node, (prefix, is_left)) = match step {
+                RenderStep::Text(text) => {
+                    output.push_str(&text);
+                    continue;
+                }
+                RenderStep::Subtree(node, position) => (node, position),
+            };
+
+            // pushed in reverse, the left subtree is drawn last
+            if let Some(child) = &node.left {
+                let prefix = format!("{}{}", prefix, if is_left { "    " } else { "│   " });
+                stack.push(RenderStep::Subtree(child, (prefix, true)));
+            }
+
+            if let Some(data) = &node.data {
+                let branch = if is_left { "└── " } else { "┌── " };
+                stack.push(RenderStep::Text(format!("{}{}{}\n", prefix, branch, data)));
+            }
+
+            if let Some(child) = &node.right {
+                let prefix = format!("{}{}", prefix, if is_left { "│   " } else { "    " });
+                stack.push(RenderStep::Subtree(child, (prefix, false)));
+            }
+        }
+    }
+}
+
+impl<T> BinarySearchTree<T>
+where
+    T: Ord + Codec,
+{
+    /// Encodes the tree, including its exact shape, as a compact byte stream
+    ///
+    /// The stream starts with the [`DuplicatePolicy`] and then lists the nodes
+    /// in preorder, each one as a `1` marker followed by the element, with a
+    /// `0` marker for every missing child.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![self.duplicate_policy as u8];
+        let mut stack = vec![Some(self)];
+
+        while let Some(node) = stack.pop() {
+            match node.and_then(|node| node.data.as_ref().map(|data| (node, data))) {
+                None => out.push(0),
+                Some((node, data)) => {
+                    out.push(1);
+                    data.encode(&mut out);
+
+                    // right is pushed first so that left is written first
+                    stack.push(node.right.as_deref());
+                    stack.push(node.left.as_deref());
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Decodes a tree written by [`Self::to_bytes`], restoring its exact shape
+    ///
+    /// Fails if the stream is malformed, has bytes left over, or holds its
+    /// elements out of search order.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut input = bytes;
+
+        let duplicate_policy = match u8::decode(&mut input)? {
+            0 => DuplicatePolicy::Allow,
+            1 => DuplicatePolicy::Reject,
+            _ => return Err(DecodeError::InvalidValue),
+        };
+
+        // read the preorder stream up to the point where every node has both
+        // children, `pending` counts the links which are still expected
+        let mut tokens = Vec::new();
+        let mut pending = 1usize;
+
+        while pending > 0 {
+            pending -= 1;
+
+            match u8::decode(&mut input)? {
+                0 => tokens.push(None),
+                1 => {
+                    tokens.push(Some(T::decode(&mut input)?));
+                    pending += 2;
+                }
+                _ => return Err(DecodeError::InvalidValue),
+            }
+        }
+
+        if !input.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+
+        // build bottom-up from the back of the stream, so that the left
+        // subtree is always on top of the right one
+        let mut stack: Vec<Option<Box<Self>>> = Vec::new();
+
+        for token in tokens.into_iter().rev() {
+            let link = match token {
+                None => None,
+                Some(data) => {
+                    let left = stack.pop().ok_or(DecodeError::InvalidValue)?;
+                    let right = stack.pop().ok_or(DecodeError::InvalidValue)?;
+
+                    let mut node = Self::with_duplicate_policy(duplicate_policy);
+                    node.size = 1 + Self::subtree_size(&left) + Self::subtree_size(&right);
+                    node.data = Some(data);
+                    node.left = left;
+                    node.right = right;
+
+                    Some(Box::new(node))
+                }
+            };
+            stack.push(link);
+        }
+
+        let tree = match stack.pop() {
+            Some(Some(root)) => *root,
+            _ => Self::with_duplicate_policy(duplicate_policy),
+        };
+
+        let mut elements = tree.iter().peekable();
+        while let Some(data) = elements.next() {
+            let in_order = elements.peek().is_none_or(|next| match duplicate_policy {
+                DuplicatePolicy::Allow => data <= *next,
+                DuplicatePolicy::Reject => data < *next,
+            });
+
+            if !in_order {
+                return Err(DecodeError::InvalidOrder);
+            }
+        }
+
+        Ok(tree)
+    }
+}
+
+impl<T> Default for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for BinarySearchTree<T>
+where
+    T: Ord + Clone,
+{
+    /// Copies the nodes iteratively, keeping the shape of the tree, a derived
+    /// clone recurses into the boxed children and overflows the stack on
+    /// deep, skewed trees
+    fn clone(&self) -> Self {
+        // the nodes in preorder, with `None` for every missing child
+        let mut preorder = Vec::new();
+        let mut stack = vec![Some(self)];
+
+        while let Some(node) = stack.pop() {
+            preorder.push(node);
+            if let Some(node) = node {
+                stack.push(node.right.as_deref());
+                stack.push(node.left.as_deref());
+            }
+        }
+
+        // build bottom-up from the back, so that the left subtree is always
+        // on top of the right one
+        let mut links: Vec<Option<Box<Self>>> = Vec::new();
+
+        for node in preorder.into_iter().rev() {
+            let link = node.map(|node| {
+                let left = links.pop().expect("the left subtree was built");
+                let right = links.pop().expect("the right subtree was built");
+
+                Box::new(Self {
+                    data: node.data.clone(),
+                    left,
+                    right,
+                    size: node.size,
+                    duplicate_policy: node.duplicate_policy,
+                })
+            });
+            links.push(link);
+        }
+
+        *links.pop().flatten().expect("the root is built last")
+    }
+}
+
+impl<T> Drop for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    /// Unlinks the nodes iteratively, the default recursive drop of the boxed
+    /// children overflows the stack on deep, skewed trees
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Self>> = Vec::new();
+
+        stack.extend(self.left.take());
+        stack.extend(self.right.take());
+
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+            // `node` has no children left and is dropped here
+        }
+    }
+}
+
+impl<T> fmt::Debug for BinarySearchTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold the same elements in the same order,
+/// regardless of their shape
+impl<T> PartialEq for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for BinarySearchTree<T> where T: Ord {}
+
+/// Serializes the tree as an ordered sequence of its elements
+///
+/// The [`DuplicatePolicy`] is not part of the sequence, see the
+/// `Deserialize` impl.
+#[cfg(feature = "serde")]
+impl<T> serde::Serialize for BinarySearchTree<T>
+where
+    T: Ord + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self)
+    }
+}
+
+/// Deserializes a sequence of elements into a height-balanced tree
+///
+/// The sequence does not need to be sorted, but sorted input (as produced by
+/// `Serialize`) is rebuilt in `O(n)` without replaying skewed insertions.
+///
+/// The sequence carries no [`DuplicatePolicy`], so the tree always comes back
+/// with the default [`DuplicatePolicy::Allow`]: a tree serialized under
+/// [`DuplicatePolicy::Reject`] accepts duplicates once deserialized. Use
+/// [`BinarySearchTree::to_bytes`] and [`BinarySearchTree::from_bytes`] to keep
+/// the policy.
+#[cfg(feature = "serde")]
+impl<'de, T> serde::Deserialize<'de> for BinarySearchTree<T>
+where
+    T: Ord + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut elements = Vec::<T>::deserialize(deserializer)?;
+        elements.sort();
+
+        Ok(Self::from_sorted_vec(elements, DuplicatePolicy::default()))
+    }
+}
+
+/// Indexes the elements by sorted position, `tree[0]` is the smallest element
+///
+/// Resolved through the subtree sizes in `O(height)`, see
+/// [`BinarySearchTree::select`].
+impl<T> Index<usize> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.select(index) {
+            Some(data) => data,
+            None => panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                self.size, index
+            ),
+        }
+    }
+}
+
+/// A parallel iterator over references to the elements of a
+/// [`BinarySearchTree`], created by `par_iter()`
+///
+/// Work is split along the tree: a subtree is divided into its left subtree
+/// and the root element followed by its right subtree.
+#[cfg(feature = "rayon")]
+pub struct BinarySearchTreeParallelIterator<'a, T>
+where
+    T: Ord,
+{
+    tree: &'a BinarySearchTree<T>,
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T> rayon::iter::ParallelIterator for BinarySearchTreeParallelIterator<'a, T>
+where
+    T: Ord + Sync,
+{
+    type Item = &'a T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: rayon::iter::plumbing::UnindexedConsumer<Self::Item>,
+    {
+        let producer = BinarySearchTreeProducer {
+            head: None,
+            tree: self.tree.data.as_ref().map(|_| self.tree),
+        };
+
+        rayon::iter::plumbing::bridge_unindexed(producer, consumer)
+    }
+}
+
+/// Yields `head` followed by the elements of `tree` in order
+#[cfg(feature = "rayon")]
+struct BinarySearchTreeProducer<'a, T>
+where
+    T: Ord,
+{
+    head: Option<&'a T>,
+    tree: Option<&'a BinarySearchTree<T>>,
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T> rayon::iter::plumbing::UnindexedProducer for BinarySearchTreeProducer<'a, T>
+where
+    T: Ord + Sync,
+{
+    type Item = &'a T;
+
+    fn split(self) -> (Self, Option<Self>) {
+        match self.tree {
+            Some(node) if node.size > 1 => (
+                Self {
+                    head: self.head,
+                    tree: node.left.as_deref(),
+                },
+                Some(Self {
+                    head: node.data.as_ref(),
+                    tree: node.right.as_deref(),
+                }),
+            ),
+            _ => (self, None),
+        }
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: rayon::iter::plumbing::Folder<Self::Item>,
+    {
+        let elements = self.head.into_iter().chain(self.tree.into_iter().flatten());
+
+        folder.consume_iter(elements)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T> rayon::iter::IntoParallelIterator for &'a BinarySearchTree<T>
+where
+    T: Ord + Sync,
+{
+    type Item = &'a T;
+    type Iter = BinarySearchTreeParallelIterator<'a, T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        BinarySearchTreeParallelIterator { tree: self }
+    }
+}
+
+/// Consumes the tree into a parallel iterator over its elements in order
+#[cfg(feature = "rayon")]
+impl<T> rayon::iter::IntoParallelIterator for BinarySearchTree<T>
+where
+    T: Ord + Send,
+{
+    type Item = T;
+    type Iter = rayon::vec::IntoIter<T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.into_sorted_vec().into_par_iter()
+    }
+}
+
+impl<T> FromIterator<T> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+/// Sorts the elements and builds a height-balanced tree, equal elements keep
+/// their order in the vector
+impl<T> From<Vec<T>> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn from(mut elements: Vec<T>) -> Self {
+        elements.sort();
+        Self::from_sorted_vec(elements, DuplicatePolicy::default())
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn from(elements: [T; N]) -> Self {
+        Self::from(Vec::from(elements))
+    }
+}
+
+/// Builds a height-balanced tree in `O(n)`, the set is already sorted
+impl<T> From<BTreeSet<T>> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn from(set: BTreeSet<T>) -> Self {
+        Self::from_sorted_vec(set.into_iter().collect(), DuplicatePolicy::default())
+    }
+}
+
+impl<T> From<BinarySearchTree<T>> for Vec<T>
+where
+    T: Ord,
+{
+    fn from(tree: BinarySearchTree<T>) -> Self {
+        tree.into_sorted_vec()
+    }
+}
+
+/// Equal elements collapse into one, like in [`BTreeSet::from_iter`]
+impl<T> From<BinarySearchTree<T>> for BTreeSet<T>
+where
+    T: Ord,
+{
+    fn from(tree: BinarySearchTree<T>) -> Self {
+        tree.into_iter().collect()
+    }
+}
+
+impl<T> Extend<T> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+impl<'a, T> Extend<&'a T> for BinarySearchTree<T>
+where
+    T: Ord + Copy + 'a,
+{
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+/// Structural statistics of a [`BinarySearchTree`], see [`BinarySearchTree::stats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BinarySearchTreeStats {
+    /// number of nodes, equal to the number of elements
+    pub nodes: usize,
+    /// number of nodes without children
+    pub leaves: usize,
+    /// number of nodes on the longest path from the root to a leaf
+    pub height: usize,
+    /// largest number of nodes on a single level
+    pub width: usize,
+    /// number of nodes on the longest path between any two nodes
+    pub diameter: usize,
+}
+
+/// An iterator over references to the elements of a [`BinarySearchTree`] in order
+///
+/// The iterator is double-ended, so `tree.iter().rev()` yields the elements in
+/// descending order.
+pub struct BinarySearchTreeIterator<'a, T>
+where
+    T: Ord,
+{
+    stack: Vec<&'a BinarySearchTree<T>>,
+    back_stack: Vec<&'a BinarySearchTree<T>>,
+    /// number of elements not yet yielded from either end
+    remaining: usize,
+}
+
+impl<'a, T> BinarySearchTreeIterator<'a, T>
+where
+    T: Ord,
+{
+    fn new(tree: &'a BinarySearchTree<T>) -> Self {
+        let mut iter = Self {
+            stack: vec![tree],
+            back_stack: vec![tree],
+            remaining: tree.size,
+        };
+
+        iter.stack_push_left();
+        iter.back_stack_push_right();
+        iter
+    }
+
+    fn stack_push_left(&mut self) {
+        while let Some(child) = &self.stack.last().unwrap().left {
+            self.stack.push(child);
+        }
+    }
+
+    fn back_stack_push_right(&mut self) {
+        while let Some(child) = &self.back_stack.last().unwrap().right {
+            self.back_stack.push(child);
+        }
+    }
+}
+
+impl<'a, T> Iterator for BinarySearchTreeIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            None
+        } else {
+            let node = self.stack.pop().unwrap();
+
+            if let Some(right) = &node.right {
+                self.stack.push(right.deref());
+                self.stack_push_left();
+            }
+            self.remaining -= 1;
+            node.data.as_ref()
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> DoubleEndedIterator for BinarySearchTreeIterator<'_, T>
+where
+    T: Ord,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            None
+        } else {
+            let node = self.back_stack.pop().unwrap();
+
+            if let Some(left) = &node.left {
+                self.back_stack.push(left.deref());
+                self.back_stack_push_right();
+            }
+            self.remaining -= 1;
+            node.data.as_ref()
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for BinarySearchTreeIterator<'_, T> where T: Ord {}
+
+/// An iterator over mutable references to the elements of a
+/// [`BinarySearchTree`] in order, see [`BinarySearchTree::iter_mut`]
+pub(crate) struct BinarySearchTreeIterMut<'a, T>
+where
+    T: Ord,
+{
+    /// elements paired with the right subtree still to be visited after them
+    stack: Vec<(&'a mut T, Option<&'a mut BinarySearchTree<T>>)>,
+}
+
+impl<'a, T> BinarySearchTreeIterMut<'a, T>
+where
+    T: Ord,
+{
+    fn new(tree: &'a mut BinarySearchTree<T>) -> Self {
+        let mut iter = Self { stack: Vec::new() };
+
+        iter.stack_push_left(Some(tree));
+        iter
+    }
+
+    fn stack_push_left(&mut self, mut node: Option<&'a mut BinarySearchTree<T>>) {
+        while let Some(current) = node {
+            // split the node into disjoint borrows of its fields
+            let BinarySearchTree {
+                data, left, right, ..
+            } = current;
+
+            if let Some(data) = data {
+                self.stack.push((data, right.as_deref_mut()));
+            }
+            node = left.as_deref_mut();
+        }
+    }
+}
+
+impl<'a, T> Iterator for BinarySearchTreeIterMut<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (data, right) = self.stack.pop()?;
+
+        self.stack_push_left(right);
+        Some(data)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BinarySearchTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = BinarySearchTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An owning iterator over the elements of a [`BinarySearchTree`] in order
+pub struct BinarySearchTreeIntoIterator<T>
+where
+    T: Ord,
+{
+    stack: Vec<BinarySearchTree<T>>,
+}
+
+impl<T> BinarySearchTreeIntoIterator<T>
+where
+    T: Ord,
+{
+    fn new(tree: BinarySearchTree<T>) -> Self {
+        let mut iter = Self { stack: vec![tree] };
+
+        iter.stack_push_left();
+        iter
+    }
+
+    fn stack_push_left(&mut self) {
+        while let Some(child) = self.stack.last_mut().unwrap().left.take() {
+            self.stack.push(*child);
+        }
+    }
+}
+
+impl<T> Iterator for BinarySearchTreeIntoIterator<T>
+where
+    T: Ord,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut node = self.stack.pop()?;
+
+        if let Some(right) = node.right.take() {
+            self.stack.push(*right);
+            self.stack_push_left();
+        }
+        node.data.take()
+    }
+}
+
+impl<T> IntoIterator for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    type Item = T;
+    type IntoIter = BinarySearchTreeIntoIterator<T>;
+
+    /// Consumes the tree, returning an iterator which yields its elements in order
+    fn into_iter(self) -> Self::IntoIter {
+        BinarySearchTreeIntoIterator::new(self)
+    }
+}
+
+/// A pre-order iterator over a [`BinarySearchTree`], see
+/// [`BinarySearchTree::iter_preorder`]
+pub struct BinarySearchTreePreorderIterator<'a, T>
+where
+    T: Ord,
+{
+    stack: Vec<&'a BinarySearchTree<T>>,
+}
+
+impl<'a, T> BinarySearchTreePreorderIterator<'a, T>
+where
+    T: Ord,
+{
+    fn new(tree: &'a BinarySearchTree<T>) -> Self {
+        let stack = if tree.data.is_some() {
+            vec![tree]
+        } else {
+            vec![]
+        };
+
+        Self { stack }
+    }
+}
+
+impl<'a, T> Iterator for BinarySearchTreePreorderIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        // the left child is pushed last so that it is visited first
+        self.stack.extend(node.right.as_deref());
+        self.stack.extend(node.left.as_deref());
+        node.data.as_ref()
+    }
+}
+
+/// A post-order iterator over a [`BinarySearchTree`], see
+/// [`BinarySearchTree::iter_postorder`]
+pub struct BinarySearchTreePostorderIterator<'a, T>
+where
+    T: Ord,
+{
+    /// nodes paired with whether their children were already pushed
+    stack: Vec<(&'a BinarySearchTree<T>, bool)>,
+}
+
+impl<'a, T> BinarySearchTreePostorderIterator<'a, T>
+where
+    T: Ord,
+{
+    fn new(tree: &'a BinarySearchTree<T>) -> Self {
+        let stack = if tree.data.is_some() {
+            vec![(tree, false)]
+        } else {
+            vec![]
+        };
+
+        Self { stack }
+    }
+}
+
+impl<'a, T> Iterator for BinarySearchTreePostorderIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, expanded) = self.stack.pop()?;
+
+            if expanded {
+                return node.data.as_ref();
+            }
+
+            self.stack.push((node, true));
+            if let Some(right) = &node.right {
+                self.stack.push((right, false));
+            }
+            if let Some(left) = &node.left {
+                self.stack.push((left, false));
+            }
+        }
+    }
+}
+
+/// A level-order (breadth-first) iterator over a [`BinarySearchTree`], see
+/// [`BinarySearchTree::iter_level_order`]
+pub struct BinarySearchTreeLevelOrderIterator<'a, T>
+where
+    T: Ord,
+{
+    queue: VecDeque<&'a BinarySearchTree<T>>,
+}
+
+impl<'a, T> BinarySearchTreeLevelOrderIterator<'a, T>
+where
+    T: Ord,
+{
+    fn new(tree: &'a BinarySearchTree<T>) -> Self {
+        let mut queue = VecDeque::new();
+
+        if tree.data.is_some() {
+            queue.push_back(tree);
+        }
+        Self { queue }
+    }
+}
+
+impl<'a, T> Iterator for BinarySearchTreeLevelOrderIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+
+        self.queue.extend(node.left.as_deref());
+        self.queue.extend(node.right.as_deref());
+        node.data.as_ref()
+    }
+}
+
+/// An iterator over the search path for a key, see [`BinarySearchTree::path_to`]
+pub struct BinarySearchTreePathIterator<'a, T>
+where
+    T: Ord,
+{
+    node: Option<&'a BinarySearchTree<T>>,
+    key: &'a T,
+}
+
+impl<'a, T> Iterator for BinarySearchTreePathIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.node?;
+        let data = node.data.as_ref()?;
+
+        self.node = match self.key.cmp(data) {
+            Equal => None,
+            Less => node.left.as_deref(),
+            Greater => node.right.as_deref(),
+        };
+        Some(data)
+    }
+}
+
+/// A cursor over the elements of a [`BinarySearchTree`] in order
+///
+/// The cursor points either at an element or at the "ghost" position, which
+/// sits after the largest and before the smallest element. Moving past either
+/// end lands on the ghost, and moving on from the ghost wraps around.
+///
+/// The position is tracked by sorted index and resolved through the subtree
+/// sizes, so every move costs `O(height)` and needs no key comparisons.
+pub struct Cursor<'a, T>
+where
+    T: Ord,
+{
+    tree: &'a BinarySearchTree<T>,
+    /// sorted position of the current element, `tree.len()` for the ghost
+    index: usize,
+    current: Option<&'a T>,
+}
+
+impl<'a, T> Cursor<'a, T>
+where
+    T: Ord,
+{
+    fn new(tree: &'a BinarySearchTree<T>, index: usize) -> Self {
+        let index = index.min(tree.size);
+
+        Self {
+            tree,
+            index,
+            current: tree.select(index),
+        }
+    }
+
+    /// Returns the element the cursor points at, `None` at the ghost position
+    pub fn current(&self) -> Option<&'a T> {
+        self.current
+    }
+
+    /// Returns the sorted position of the current element, `None` at the
+    /// ghost position
+    pub fn index(&self) -> Option<usize> {
+        self.current.map(|_| self.index)
+    }
+
+    /// Moves the cursor to the next element
+    pub fn move_next(&mut self) {
+        *self = Self::new(self.tree, next_index(self.index, self.tree.size));
+    }
+
+    /// Moves the cursor to the previous element
+    pub fn move_prev(&mut self) {
+        *self = Self::new(self.tree, prev_index(self.index, self.tree.size));
+    }
+
+    /// Returns the next element without moving the cursor
+    pub fn peek_next(&self) -> Option<&'a T> {
+        self.tree.select(next_index(self.index, self.tree.size))
+    }
+
+    /// Returns the previous element without moving the cursor
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        self.tree.select(prev_index(self.index, self.tree.size))
+    }
+}
+
+/// A cursor over the elements of a [`BinarySearchTree`] which can remove
+/// and insert elements
+///
+/// Positions work like [`Cursor`]. Edits are made at the current position
+/// and cost `O(height)` like the moves.
+pub struct CursorMut<'a, T>
+where
+    T: Ord,
+{
+    tree: &'a mut BinarySearchTree<T>,
+    /// sorted position of the current element, `tree.len()` for the ghost
+    index: usize,
+}
+
+impl<T> CursorMut<'_, T>
+where
+    T: Ord,
+{
+    /// Returns the element the cursor points at, `None` at the ghost position
+    pub fn current(&self) -> Option<&T> {
+        self.tree.select(self.index)
+    }
+
+    /// Returns the sorted position of the current element, `None` at the
+    /// ghost position
+    pub fn index(&self) -> Option<usize> {
+        (self.index < self.tree.size).then_some(self.index)
+    }
+
+    /// Moves the cursor to the next element
+    pub fn move_next(&mut self) {
+        self.index = next_index(self.index, self.tree.size);
+    }
+
+    /// Moves the cursor to the previous element
+    pub fn move_prev(&mut self) {
+        self.index = prev_index(self.index, self.tree.size);
+    }
+
+    /// Returns the next element without moving the cursor
+    pub fn peek_next(&self) -> Option<&T> {
+        self.tree.select(next_index(self.index, self.tree.size))
+    }
+
+    /// Returns the previous element without moving the cursor
+    pub fn peek_prev(&self) -> Option<&T> {
+        self.tree.select(prev_index(self.index, self.tree.size))
+    }
+
+    /// Removes the current element and moves the cursor to the next one
+    ///
+    /// Returns `None` and leaves the tree unchanged at the ghost position.
+    pub fn remove_current(&mut self) -> Option<T> {
+        self.tree.take_at(self.index)
+    }
+
+    /// Inserts `data` into the tree, keeping the cursor on the current element
+    ///
+    /// The element goes wherever the ordering puts it, which is not necessarily
+    /// next to the cursor. Returns whether it was inserted, see
+    /// [`BinarySearchTree::insert`].
+    pub fn insert(&mut self, data: T) -> bool {
+        // an element inserted before the current one shifts its position
+        let shifts = self.current().is_none_or(|current| data < *current);
+
+        let inserted = self.tree.insert(data);
+        if inserted && shifts {
+            self.index += 1;
+        }
+        inserted
+    }
+
+    /// Returns a read-only cursor at the same position
+    pub fn as_cursor(&self) -> Cursor<'_, T> {
+        Cursor::new(self.tree, self.index)
+    }
+}
+
+/// Index after `index` in a tree of `len` elements, where `len` is the ghost
+fn next_index(index: usize, len: usize) -> usize {
+    if index >= len {
+        0
+    } else {
+        index + 1
+    }
+}
+
+/// Index before `index` in a tree of `len` elements, where `len` is the ghost
+fn prev_index(index: usize, len: usize) -> usize {
+    if index == 0 {
+        len
+    } else {
+        index - 1
+    }
+}
+
+/// Where the smallest remaining element of a merge comes from
+enum Merged<'a, T> {
+    Left(&'a T),
+    Right(&'a T),
+    Both(&'a T),
+}
+
+/// Walks two trees in order side by side, pairing up equal elements
+struct MergeIterator<'a, T>
+where
+    T: Ord,
+{
+    left: Peekable<BinarySearchTreeIterator<'a, T>>,
+    right: Peekable<BinarySearchTreeIterator<'a, T>>,
+}
+
+impl<'a, T> MergeIterator<'a, T>
+where
+    T: Ord,
+{
+    fn new(left: &'a BinarySearchTree<T>, right: &'a BinarySearchTree<T>) -> Self {
+        Self {
+            left: left.iter().peekable(),
+            right: right.iter().peekable(),
+        }
+    }
+}
+
+impl<'a, T> Iterator for MergeIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = Merged<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.left.peek(), self.right.peek()) {
+            (Some(left), Some(right)) => match left.cmp(right) {
+                Less => self.left.next().map(Merged::Left),
+                Greater => self.right.next().map(Merged::Right),
+                Equal => {
+                    self.right.next();
+                    self.left.next().map(Merged::Both)
+                }
+            },
+            (Some(_), None) => self.left.next().map(Merged::Left),
+            (None, Some(_)) => self.right.next().map(Merged::Right),
+            (None, None) => None,
+        }
+    }
+}
+
+/// An iterator over the union of two [`BinarySearchTree`]s, see
+/// [`BinarySearchTree::union`]
+pub struct BinarySearchTreeUnion<'a, T>(MergeIterator<'a, T>)
+where
+    T: Ord;
+
+impl<'a, T> Iterator for BinarySearchTreeUnion<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|merged| match merged {
+            Merged::Left(data) | Merged::Right(data) | Merged::Both(data) => data,
+        })
+    }
+}
+
+/// An iterator over the intersection of two [`BinarySearchTree`]s, see
+/// [`BinarySearchTree::intersection`]
+pub struct BinarySearchTreeIntersection<'a, T>(MergeIterator<'a, T>)
+where
+    T: Ord;
+
+impl<'a, T> Iterator for BinarySearchTreeIntersection<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.find_map(|merged| match merged {
+            Merged::Both(data) => Some(data),
+            _ => None,
+        })
+    }
+}
+
+/// An iterator over the difference of two [`BinarySearchTree`]s, see
+/// [`BinarySearchTree::difference`]
+pub struct BinarySearchTreeDifference<'a, T>(MergeIterator<'a, T>)
+where
+    T: Ord;
+
+impl<'a, T> Iterator for BinarySearchTreeDifference<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.find_map(|merged| match merged {
+            Merged::Left(data) => Some(data),
+            _ => None,
+        })
+    }
+}
+
+/// An iterator over the symmetric difference of two [`BinarySearchTree`]s,
+/// see [`BinarySearchTree::symmetric_difference`]
+pub struct BinarySearchTreeSymmetricDifference<'a, T>(MergeIterator<'a, T>)
+where
+    T: Ord;
+
+impl<'a, T> Iterator for BinarySearchTreeSymmetricDifference<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.find_map(|merged| match merged {
+            Merged::Left(data) | Merged::Right(data) => Some(data),
+            Merged::Both(_) => None,
+        })
+    }
+}
+
+struct BinarySearchTreeRangeIterator<'a, T, R>
+where
+    T: Ord,
+    R: RangeBounds<T>,
+{
+    stack: Vec<&'a BinarySearchTree<T>>,
+    range: R,
+}
+
+impl<'a, T, R> BinarySearchTreeRangeIterator<'a, T, R>
+where
+    T: Ord,
+    R: RangeBounds<T>,
+{
+    fn new(tree: &'a BinarySearchTree<T>, range: R) -> Self {
+        let mut iter = Self {
+            stack: Vec::new(),
+            range,
+        };
+
+        iter.stack_push_left(Some(tree));
+        iter
+    }
+
+    fn after_start(&self, data: &T) -> bool {
+        match self.range.start_bound() {
+            Included(start) => data >= start,
+            Excluded(start) => data > start,
+            Unbounded => true,
+        }
+    }
+
+    fn before_end(&self, data: &T) -> bool {
+        match self.range.end_bound() {
+            Included(end) => data <= end,
+            Excluded(end) => data < end,
+            Unbounded => true,
+        }
+    }
+
+    /// Pushes the left spine of `node`, skipping every subtree which lies
+    /// entirely before the start of the range
+    fn stack_push_left(&mut self, mut node: Option<&'a BinarySearchTree<T>>) {
+        while let Some(current) = node {
+            match &current.data {
+                Some(data) if self.after_start(data) => {
+                    self.stack.push(current);
+                    node = current.left.as_deref();
+                }
+                // data and its left subtree are before the start
+                Some(_) => node = current.right.as_deref(),
+                None => break,
+            }
+        }
+    }
+}
+
+impl<'a, T, R> Iterator for BinarySearchTreeRangeIterator<'a, T, R>
+where
+    T: Ord,
+    R: RangeBounds<T>,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let data = node.data.as_ref()?;
+
+        if !self.before_end(data) {
+            // every remaining element is past the end
+            self.stack.clear();
+            return None;
+        }
+
+        self.stack_push_left(node.right.as_deref());
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::shuffled;
+
+    fn prequel_tree() -> BinarySearchTree<u32> {
+        let mut tree = BinarySearchTree::new();
+        tree.insert(16);
+        tree.insert(7);
+        tree.insert(28);
+        tree.insert(3);
+        tree.insert(21);
+        tree.insert(36);
+        tree.insert(70);
+        tree
+    }
+
+    /// Ordered by `id` only, so equal elements can still differ by `name`
+    #[derive(Debug)]
+    struct User {
+        id: u32,
+        name: &'static str,
+    }
+
+    impl PartialEq for User {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl Eq for User {}
+
+    impl PartialOrd for User {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for User {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.id.cmp(&other.id)
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let tree = prequel_tree();
+
+        assert!(tree.search(&16));
+        assert!(tree.search(&7));
+        assert!(tree.search(&28));
+        assert!(tree.search(&3));
+        assert!(tree.search(&21));
+        assert!(tree.search(&36));
+        assert!(tree.search(&70));
+
+        assert!(!tree.search(&1));
+        assert!(!tree.search(&90));
+    }
+
+    #[test]
+    fn test_iterator() {
+        let tree = prequel_tree();
+        let mut iter = tree.iter();
+
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next(), Some(&7));
+        assert_eq!(iter.next(), Some(&16));
+        assert_eq!(iter.next(), Some(&21));
+        assert_eq!(iter.next(), Some(&28));
+        assert_eq!(iter.next(), Some(&36));
+        assert_eq!(iter.next(), Some(&70));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree = prequel_tree();
+
+        // leaf
+        assert!(tree.remove(&3));
+        // single child
+        assert!(tree.remove(&36));
+        // two children
+        assert!(tree.remove(&28));
+        // root with two children
+        assert!(tree.remove(&16));
+
+        assert!(!tree.remove(&16));
+        assert!(!tree.remove(&90));
+
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&7, &21, &70]);
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let mut tree = prequel_tree();
+
+        for data in [16, 7, 28, 3, 21, 36, 70] {
+            assert!(tree.remove(&data));
+        }
+
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.min(), None);
+
+        tree.insert(5);
+        assert!(tree.search(&5));
+    }
+
+    #[test]
+    fn test_len() {
+        let mut tree = BinarySearchTree::new();
+        assert_eq!(tree.len(), 0);
+        assert!(tree.is_empty());
+
+        tree.insert(16);
+        tree.insert(7);
+        tree.insert(28);
+        assert_eq!(tree.len(), 3);
+        assert!(!tree.is_empty());
+
+        assert!(!tree.remove(&90));
+        assert_eq!(tree.len(), 3);
+
+        assert!(tree.remove(&16));
+        assert_eq!(tree.len(), 2);
+
+        assert!(tree.remove(&7));
+        assert!(tree.remove(&28));
+        assert_eq!(tree.len(), 0);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_height() {
+        let mut tree = BinarySearchTree::new();
+        assert_eq!(tree.height(), 0);
+
+        tree.insert(1);
+        assert_eq!(tree.height(), 1);
+
+        assert_eq!(prequel_tree().height(), 4);
+
+        // sorted insertion degenerates into a linked list
+        for data in 2..=10 {
+            tree.insert(data);
+        }
+        assert_eq!(tree.height(), 10);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut tree = prequel_tree();
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.height(), 0);
+        assert!(!tree.search(&16));
+        assert_eq!(tree.iter().next(), None);
+
+        tree.insert(42);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.min(), Some(&42));
+    }
+
+    #[test]
+    fn test_contains_borrowed() {
+        let mut tree = BinarySearchTree::new();
+        tree.insert(String::from("oak"));
+        tree.insert(String::from("elm"));
+        tree.insert(String::from("pine"));
+
+        assert!(tree.contains("oak"));
+        assert!(tree.contains("elm"));
+        assert!(tree.contains("pine"));
+        assert!(!tree.contains("birch"));
+
+        assert!(prequel_tree().contains(&21));
+    }
+
+    #[test]
+    fn test_floor_ceiling() {
+        let tree = prequel_tree();
+
+        assert_eq!(tree.floor(&16), Some(&16));
+        assert_eq!(tree.floor(&20), Some(&16));
+        assert_eq!(tree.floor(&35), Some(&28));
+        assert_eq!(tree.floor(&100), Some(&70));
+        assert_eq!(tree.floor(&2), None);
+
+        assert_eq!(tree.ceiling(&16), Some(&16));
+        assert_eq!(tree.ceiling(&17), Some(&21));
+        assert_eq!(tree.ceiling(&4), Some(&7));
+        assert_eq!(tree.ceiling(&0), Some(&3));
+        assert_eq!(tree.ceiling(&71), None);
+
+        let empty = BinarySearchTree::new();
+        assert_eq!(empty.floor(&1), None);
+        assert_eq!(empty.ceiling(&1), None);
+    }
+
+    #[test]
+    fn test_successor_predecessor() {
+        let tree = prequel_tree();
+
+        assert_eq!(tree.successor(&3), Some(&7));
+        assert_eq!(tree.successor(&7), Some(&16));
+        assert_eq!(tree.successor(&16), Some(&21));
+        assert_eq!(tree.successor(&36), Some(&70));
+        assert_eq!(tree.successor(&70), None);
+        assert_eq!(tree.successor(&22), Some(&28));
+
+        assert_eq!(tree.predecessor(&70), Some(&36));
+        assert_eq!(tree.predecessor(&21), Some(&16));
+        assert_eq!(tree.predecessor(&16), Some(&7));
+        assert_eq!(tree.predecessor(&3), None);
+        assert_eq!(tree.predecessor(&22), Some(&21));
+    }
+
+    #[test]
+    fn test_range() {
+        let tree = prequel_tree();
+
+        assert_eq!(tree.range(7..28).collect::<Vec<_>>(), vec![&7, &16, &21]);
+        assert_eq!(tree.range(8..=28).collect::<Vec<_>>(), vec![&16, &21, &28]);
+        assert_eq!(tree.range(30..).collect::<Vec<_>>(), vec![&36, &70]);
+        assert_eq!(tree.range(..7).collect::<Vec<_>>(), vec![&3]);
+        assert_eq!(tree.range(..).count(), 7);
+        assert_eq!(
+            tree.range((Excluded(16), Excluded(36))).collect::<Vec<_>>(),
+            vec![&21, &28]
+        );
+
+        assert_eq!(tree.range(40..70).next(), None);
+        assert_eq!(tree.range(71..).next(), None);
+        assert_eq!(BinarySearchTree::<u32>::new().range(..).next(), None);
+    }
+
+    #[test]
+    fn test_select_rank() {
+        let mut tree = prequel_tree();
+        let sorted = [3, 7, 16, 21, 28, 36, 70];
+
+        for (k, data) in sorted.iter().enumerate() {
+            assert_eq!(tree.select(k), Some(data));
+            assert_eq!(tree.rank(data), k);
+        }
+        assert_eq!(tree.select(7), None);
+        assert_eq!(tree.rank(&0), 0);
+        assert_eq!(tree.rank(&20), 3);
+        assert_eq!(tree.rank(&100), 7);
+
+        // sizes stay consistent after removals
+        tree.remove(&16);
+        tree.remove(&3);
+        assert_eq!(tree.select(0), Some(&7));
+        assert_eq!(tree.select(2), Some(&28));
+        assert_eq!(tree.rank(&70), 4);
+
+        assert_eq!(BinarySearchTree::<u32>::new().select(0), None);
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let tree = prequel_tree();
+
+        let mut borrowed = Vec::new();
+        for data in &tree {
+            borrowed.push(*data);
+        }
+        assert_eq!(borrowed, vec![3, 7, 16, 21, 28, 36, 70]);
+
+        let owned: Vec<u32> = tree.into_iter().collect();
+        assert_eq!(owned, vec![3, 7, 16, 21, 28, 36, 70]);
+
+        let mut tree = BinarySearchTree::new();
+        tree.insert(String::from("b"));
+        tree.insert(String::from("a"));
+        assert_eq!(
+            tree.into_iter().collect::<Vec<_>>(),
+            vec![String::from("a"), String::from("b")]
+        );
+        assert_eq!(BinarySearchTree::<u32>::new().into_iter().next(), None);
+    }
+
+    #[test]
+    fn test_double_ended_iterator() {
+        let tree = prequel_tree();
+
+        assert_eq!(
+            tree.iter().rev().collect::<Vec<_>>(),
+            vec![&70, &36, &28, &21, &16, &7, &3]
+        );
+
+        // both ends meet in the middle without yielding an element twice
+        let mut iter = tree.iter();
+        assert_eq!(iter.len(), 7);
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next_back(), Some(&70));
+        assert_eq!(iter.next_back(), Some(&36));
+        assert_eq!(iter.next(), Some(&7));
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next_back(), Some(&28));
+        assert_eq!(iter.next(), Some(&16));
+        assert_eq!(iter.next_back(), Some(&21));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        assert_eq!(BinarySearchTree::<u32>::new().iter().next_back(), None);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut tree = prequel_tree();
+
+        let drained: Vec<u32> = tree.drain().collect();
+        assert_eq!(drained, vec![3, 7, 16, 21, 28, 36, 70]);
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+
+        tree.insert(1);
+        let mut drain = tree.drain();
+        assert_eq!(drain.next(), Some(1));
+        assert_eq!(drain.next(), None);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_from_iterator_extend() {
+        let mut tree: BinarySearchTree<u32> = vec![16, 7, 28, 3].into_iter().collect();
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&3, &7, &16, &28]);
+
+        tree.extend(vec![21, 36]);
+        tree.extend(&[70]);
+        assert_eq!(tree.len(), 7);
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            prequel_tree().iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_clone_debug_eq() {
+        let tree = prequel_tree();
+
+        let mut cloned = tree.clone();
+        assert_eq!(tree, cloned);
+        cloned.remove(&16);
+        assert_ne!(tree, cloned);
+        assert_eq!(tree.len(), 7);
+
+        // equal elements inserted in a different order give a different shape
+        let reordered: BinarySearchTree<u32> = vec![3, 7, 16, 21, 28, 36, 70].into_iter().collect();
+        assert_ne!(tree.height(), reordered.height());
+        assert_eq!(tree, reordered);
+
+        assert_eq!(format!("{:?}", tree), "{3, 7, 16, 21, 28, 36, 70}");
+        assert_eq!(format!("{:?}", BinarySearchTree::<u32>::new()), "{}");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let tree = prequel_tree();
+
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(json, "[3,7,16,21,28,36,70]");
+
+        let deserialized: BinarySearchTree<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, tree);
+        assert_eq!(deserialized.len(), 7);
+        assert_eq!(deserialized.height(), 3);
+
+        let unsorted: BinarySearchTree<u32> = serde_json::from_str("[5,1,3]").unwrap();
+        assert_eq!(unsorted.iter().collect::<Vec<_>>(), vec![&1, &3, &5]);
+
+        // the policy is not serialized
+        let mut set = BinarySearchTree::with_duplicate_policy(DuplicatePolicy::Reject);
+        set.extend([2, 1]);
+        let json = serde_json::to_string(&set).unwrap();
+        let deserialized: BinarySearchTree<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.duplicate_policy(), DuplicatePolicy::Allow);
+    }
+
+    #[test]
+    fn test_pretty_string() {
+        let tree = prequel_tree();
+
+        let expected = concat!(
+            "        ┌── 70\n",
+            "    ┌── 36\n",
+            "┌── 28\n",
+            "│   └── 21\n",
+            "16\n",
+            "└── 7\n",
+            "    └── 3\n",
+        );
+        assert_eq!(tree.to_pretty_string(), expected);
+        assert_eq!(BinarySearchTree::<u32>::new().to_pretty_string(), "");
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let tree: BinarySearchTree<u32> = vec![2, 1, 3].into_iter().collect();
+
+        let expected = concat!(
+            "digraph BinarySearchTree {\n",
+            "    n0 [label=\"2\"];\n",
+            "    n1 [label=\"1\"];\n",
+            "    n0 -> n1 [label=\"L\"];\n",
+            "    n2 [label=\"3\"];\n",
+            "    n0 -> n2 [label=\"R\"];\n",
+            "}\n",
+        );
+        assert_eq!(tree.to_dot(), expected);
+
+        let mut quoted = BinarySearchTree::new();
+        quoted.insert(String::from("say \"hi\""));
+        assert!(quoted.to_dot().contains(r#"[label="say \"hi\""]"#));
+
+        assert_eq!(
+            BinarySearchTree::<u32>::new().to_dot(),
+            "digraph BinarySearchTree {\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_to_dot_deep_tree() {
+        let dot = skewed_tree(200_000).to_dot();
+
+        // the edges are written from the bottom up, once their subtree is
+        assert!(dot.contains("    n199999 [label=\"199999\"];\n    n199998 -> n199999"));
+        assert!(dot.ends_with("    n1 -> n2 [label=\"R\"];\n    n0 -> n1 [label=\"R\"];\n}\n"));
+    }
+
+    #[test]
+    fn test_pretty_deep_tree() {
+        // a deep tree on a small stack, the output grows with the square of
+        // the depth
+        let pretty = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| skewed_tree(2_000).to_pretty_string())
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let lines: Vec<_> = pretty.lines().collect();
+        assert_eq!(lines.len(), 2_000);
+        assert_eq!(lines[0], format!("{}┌── 1999", "    ".repeat(1_998)));
+        assert_eq!(lines[1_999], "0");
+    }
+
+    #[test]
+    fn test_deep_insert_search() {
+        let mut tree = BinarySearchTree::new();
+
+        for data in 0..10_000 {
+            tree.insert(data);
+        }
+        assert_eq!(tree.len(), 10_000);
+        assert!(tree.search(&0));
+        assert!(tree.search(&9_999));
+        assert!(!tree.contains(&10_000));
+    }
+
+    /// Builds the right-skewed spine of `0..n` directly, inserting this many
+    /// sorted elements one by one would take quadratic time
+    fn skewed_tree(n: u32) -> BinarySearchTree<u32> {
+        let mut tree = BinarySearchTree::new();
+
+        for data in (0..n).rev() {
+            let mut node = BinarySearchTree::new();
+            node.data = Some(data);
+            node.size = tree.size + 1;
+            if tree.data.is_some() {
+                node.right = Some(Box::new(tree));
+            }
+            tree = node;
+        }
+        tree
+    }
+
+    #[test]
+    fn test_drop_deep_tree() {
+        let tree = skewed_tree(1_000_000);
+        assert_eq!(tree.len(), 1_000_000);
+        assert_eq!(tree.min(), Some(&0));
+
+        drop(tree);
+    }
+
+    #[test]
+    fn test_height_deep_tree() {
+        assert_eq!(skewed_tree(1_000_000).height(), 1_000_000);
+    }
+
+    #[test]
+    fn test_split_off_deep_tree() {
+        let mut tree = skewed_tree(200_000);
+
+        let greater_or_equal = tree.split_off(&150_000);
+        assert_eq!(tree.len(), 150_000);
+        assert_eq!(greater_or_equal.len(), 50_000);
+        assert_eq!(tree.select(149_999), Some(&149_999));
+        assert_eq!(tree.rank(&100_000), 100_000);
+        assert_eq!(greater_or_equal.select(0), Some(&150_000));
+        assert_eq!(greater_or_equal.select(49_999), Some(&199_999));
+    }
+
+    #[test]
+    fn test_diagnostics_deep_tree() {
+        let mut tree = skewed_tree(200_000);
+        assert!(!tree.is_balanced());
+        assert!(tree.is_valid_bst());
+        assert!(BinarySearchTree::from_sorted_iter(0..200_000).is_balanced());
+
+        // break the order at the bottom of the spine
+        let mut node = &mut tree;
+        while node.right.is_some() {
+            node = node.right.as_deref_mut().unwrap();
+        }
+        node.data = Some(0);
+        assert!(!tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_stats_deep_tree() {
+        let stats = skewed_tree(200_000).stats();
+        assert_eq!(stats.height, 200_000);
+        assert_eq!(stats.diameter, 200_000);
+        assert_eq!(stats.leaves, 1);
+    }
+
+    #[test]
+    fn test_clone_deep_tree() {
+        let tree = skewed_tree(200_000);
+        let clone = tree.clone();
+
+        assert_eq!(clone.len(), 200_000);
+        assert_eq!(clone.to_bytes(), tree.to_bytes());
+        assert_eq!(clone.rank(&150_000), 150_000);
+    }
+
+    #[test]
+    fn test_pop_deep_tree() {
+        let mut tree = skewed_tree(200_000);
+
+        assert_eq!(tree.pop_max(), Some(199_999));
+        assert_eq!(tree.pop_max(), Some(199_998));
+        assert_eq!(tree.pop_min(), Some(0));
+        assert_eq!(tree.len(), 199_997);
+        assert_eq!(tree.select(199_996), Some(&199_997));
+    }
+
+    #[test]
+    fn test_remove_deep_tree() {
+        let mut tree = skewed_tree(200_000);
+
+        assert!(tree.remove(&199_999));
+        assert!(!tree.remove(&199_999));
+        assert_eq!(tree.take(&150_000), Some(150_000));
+        assert_eq!(tree.len(), 199_998);
+        assert_eq!(tree.select(150_000), Some(&150_001));
+        assert_eq!(tree.rank(&199_998), 199_997);
+    }
+
+    #[test]
+    fn test_cursor_remove_deep_tree() {
+        let mut tree = skewed_tree(200_000);
+
+        let mut cursor = tree.cursor_back_mut();
+        assert_eq!(cursor.remove_current(), Some(199_999));
+        cursor.move_prev();
+        assert_eq!(cursor.remove_current(), Some(199_998));
+        assert_eq!(tree.len(), 199_998);
+        assert_eq!(tree.select(199_997), Some(&199_997));
+    }
+
+    #[test]
+    fn test_rebalance() {
+        let mut tree: BinarySearchTree<u32> = (1..=15).collect();
+        assert_eq!(tree.height(), 15);
+
+        tree.rebalance();
+        assert_eq!(tree.height(), 4);
+        assert_eq!(tree.len(), 15);
+        assert!(tree.iter().copied().eq(1..=15));
+        for k in 0..15 {
+            assert_eq!(tree.select(k), Some(&(k as u32 + 1)));
+        }
+
+        let mut tree: BinarySearchTree<u32> = (1..=10).rev().collect();
+        tree.rebalance();
+        assert_eq!(tree.height(), 4);
+        assert!(tree.iter().copied().eq(1..=10));
+        assert_eq!(tree.rank(&7), 6);
+
+        let mut tree = prequel_tree();
+        tree.rebalance();
+        assert_eq!(tree, prequel_tree());
+        assert_eq!(tree.height(), 3);
+
+        let mut empty = BinarySearchTree::<u32>::new();
+        empty.rebalance();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_from_sorted() {
+        let tree = BinarySearchTree::from_sorted_iter(1..=15);
+        assert_eq!(tree.len(), 15);
+        assert_eq!(tree.height(), 4);
+        assert!(tree.iter().copied().eq(1..=15));
+        assert_eq!(tree.select(9), Some(&10));
+
+        let tree = BinarySearchTree::from_sorted_slice(&[3, 7, 16, 21, 28, 36, 70]);
+        assert_eq!(tree, prequel_tree());
+        assert_eq!(tree.height(), 3);
+        assert!(tree.search(&21));
+
+        let empty = BinarySearchTree::<u32>::from_sorted_slice(&[]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_is_balanced() {
+        assert!(BinarySearchTree::<u32>::new().is_balanced());
+        assert!(BinarySearchTree::from_sorted_iter(1..=100).is_balanced());
+
+        assert!(prequel_tree().is_balanced());
+
+        let mut tree: BinarySearchTree<u32> = (1..=3).collect();
+        assert!(!tree.is_balanced());
+        tree.rebalance();
+        assert!(tree.is_balanced());
+    }
+
+    #[test]
+    fn test_is_valid_bst() {
+        let mut tree = prequel_tree();
+        assert!(tree.is_valid_bst());
+        assert!(BinarySearchTree::<u32>::new().is_valid_bst());
+
+        tree.remove(&16);
+        tree.remove(&36);
+        assert!(tree.is_valid_bst());
+
+        // break the ordering deep inside the right subtree
+        tree.right.as_mut().unwrap().right.as_mut().unwrap().data = Some(1);
+        assert!(!tree.is_valid_bst());
+
+        // break a subtree size
+        let mut tree = prequel_tree();
+        tree.left.as_mut().unwrap().size = 5;
+        assert!(!tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_duplicate_policy() {
+        let mut tree = BinarySearchTree::new();
+        assert_eq!(tree.duplicate_policy(), DuplicatePolicy::Allow);
+
+        assert!(tree.insert(5));
+        assert!(tree.insert(3));
+        assert!(tree.insert(5));
+        assert!(tree.insert(5));
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.count(&5), 3);
+        assert_eq!(tree.count(&4), 0);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&3, &5, &5, &5]);
+
+        assert!(tree.remove(&5));
+        assert_eq!(tree.count(&5), 2);
+        assert!(tree.is_valid_bst());
+
+        let mut tree = BinarySearchTree::with_duplicate_policy(DuplicatePolicy::Reject);
+        assert!(tree.insert(5));
+        assert!(tree.insert(3));
+        assert!(!tree.insert(5));
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.count(&5), 1);
+
+        tree.extend(vec![3, 4, 5]);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&3, &4, &5]);
+
+        // the policy survives restructuring of the root
+        assert!(tree.remove(&5));
+        tree.rebalance();
+        let _ = tree.drain();
+        assert!(tree.insert(1));
+        assert!(!tree.insert(1));
+    }
+
+    #[test]
+    fn test_append() {
+        let mut tree = prequel_tree();
+        let mut other: BinarySearchTree<u32> = vec![1, 20, 21, 99].into_iter().collect();
+
+        tree.append(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(tree.len(), 11);
+        assert!(tree.is_balanced());
+        assert!(tree.is_valid_bst());
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&1, &3, &7, &16, &20, &21, &21, &28, &36, &70, &99]
+        );
+
+        let mut set = BinarySearchTree::with_duplicate_policy(DuplicatePolicy::Reject);
+        set.extend(vec![1, 2, 3]);
+        let mut other: BinarySearchTree<u32> = vec![2, 3, 4].into_iter().collect();
+        set.append(&mut other);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![&1, &2, &3, &4]);
+        assert_eq!(set.duplicate_policy(), DuplicatePolicy::Reject);
+        assert!(!set.insert(4));
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut tree = prequel_tree();
+
+        let greater_or_equal = tree.split_off(&21);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&3, &7, &16]);
+        assert_eq!(
+            greater_or_equal.iter().collect::<Vec<_>>(),
+            vec![&21, &28, &36, &70]
+        );
+        assert!(tree.is_valid_bst());
+        assert!(greater_or_equal.is_valid_bst());
+        assert_eq!(tree.len(), 3);
+        assert_eq!(greater_or_equal.len(), 4);
+
+        let mut tree = prequel_tree();
+        let everything = tree.split_off(&0);
+        assert!(tree.is_empty());
+        assert!(tree.is_valid_bst());
+        assert_eq!(everything, prequel_tree());
+
+        let mut tree = prequel_tree();
+        assert!(tree.split_off(&71).is_empty());
+        assert_eq!(tree, prequel_tree());
+
+        let mut set = BinarySearchTree::with_duplicate_policy(DuplicatePolicy::Reject);
+        set.extend(1..=5);
+        let mut upper = set.split_off(&3);
+        assert!(!upper.insert(4));
+        assert!(set.insert(4));
+
+        // splitting at every key relinks both sides of a bushy tree
+        for key in 0..=41 {
+            let mut tree: BinarySearchTree<u64> = shuffled(41).into_iter().collect();
+            let greater_or_equal = tree.split_off(&key);
+            assert!(tree.is_valid_bst());
+            assert!(greater_or_equal.is_valid_bst());
+            assert_eq!(tree.len() as u64, key.min(41));
+        }
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a = prequel_tree();
+        let b: BinarySearchTree<u32> = vec![21, 1, 16, 99, 70].into_iter().collect();
+
+        assert_eq!(
+            a.union(&b).collect::<Vec<_>>(),
+            vec![&1, &3, &7, &16, &21, &28, &36, &70, &99]
+        );
+        assert_eq!(a.intersection(&b).collect::<Vec<_>>(), vec![&16, &21, &70]);
+        assert_eq!(a.difference(&b).collect::<Vec<_>>(), vec![&3, &7, &28, &36]);
+        assert_eq!(b.difference(&a).collect::<Vec<_>>(), vec![&1, &99]);
+        assert_eq!(
+            a.symmetric_difference(&b).collect::<Vec<_>>(),
+            vec![&1, &3, &7, &28, &36, &99]
+        );
+
+        let empty = BinarySearchTree::new();
+        assert_eq!(a.union(&empty).count(), 7);
+        assert_eq!(a.intersection(&empty).next(), None);
+        assert_eq!(empty.difference(&a).next(), None);
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut tree = prequel_tree();
+        let mut visited = Vec::new();
+
+        tree.retain(|data| {
+            visited.push(*data);
+            data % 2 == 0
+        });
+        assert_eq!(visited, vec![3, 7, 16, 21, 28, 36, 70]);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&16, &28, &36, &70]);
+        assert_eq!(tree.len(), 4);
+        assert!(tree.is_valid_bst());
+
+        tree.retain(|_| false);
+        assert!(tree.is_empty());
+        assert!(tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_pop_min_max() {
+        let mut tree = prequel_tree();
+
+        assert_eq!(tree.pop_min(), Some(3));
+        assert_eq!(tree.pop_max(), Some(70));
+        assert_eq!(tree.pop_min(), Some(7));
+        // the root is the minimum now
+        assert_eq!(tree.pop_min(), Some(16));
+        assert_eq!(tree.pop_max(), Some(36));
+        assert_eq!(tree.len(), 2);
+        assert!(tree.is_valid_bst());
+
+        assert_eq!(tree.pop_max(), Some(28));
+        assert_eq!(tree.pop_max(), Some(21));
+        assert_eq!(tree.pop_max(), None);
+        assert_eq!(tree.pop_min(), None);
+        assert!(tree.is_empty());
+        assert!(tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_take() {
+        let mut tree = BinarySearchTree::new();
+        tree.insert(String::from("oak"));
+        tree.insert(String::from("elm"));
+        tree.insert(String::from("pine"));
+
+        let taken: Option<String> = tree.take(&String::from("oak"));
+        assert_eq!(taken.as_deref(), Some("oak"));
+        assert_eq!(tree.take(&String::from("oak")), None);
+        assert_eq!(tree.len(), 2);
+        assert!(tree.is_valid_bst());
+
+        let mut tree = prequel_tree();
+        assert_eq!(tree.take(&28), Some(28));
+        assert_eq!(tree.take(&3), Some(3));
+        assert_eq!(tree.take(&99), None);
+        assert_eq!(tree.len(), 5);
+        assert!(tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_traversal_orders() {
+        let tree = prequel_tree();
+
+        assert_eq!(
+            tree.iter_preorder().collect::<Vec<_>>(),
+            vec![&16, &7, &3, &28, &21, &36, &70]
+        );
+        assert_eq!(
+            tree.iter_postorder().collect::<Vec<_>>(),
+            vec![&3, &7, &21, &70, &36, &28, &16]
+        );
+        assert_eq!(
+            tree.iter_level_order().collect::<Vec<_>>(),
+            vec![&16, &7, &28, &3, &21, &36, &70]
+        );
+
+        let empty = BinarySearchTree::<u32>::new();
+        assert_eq!(empty.iter_preorder().next(), None);
+        assert_eq!(empty.iter_postorder().next(), None);
+        assert_eq!(empty.iter_level_order().next(), None);
+    }
+
+    #[test]
+    fn test_lowest_common_ancestor() {
+        let tree = prequel_tree();
+
+        assert_eq!(tree.lowest_common_ancestor(&3, &70), Some(&16));
+        assert_eq!(tree.lowest_common_ancestor(&21, &70), Some(&28));
+        assert_eq!(tree.lowest_common_ancestor(&70, &21), Some(&28));
+        assert_eq!(tree.lowest_common_ancestor(&36, &70), Some(&36));
+        assert_eq!(tree.lowest_common_ancestor(&3, &3), Some(&3));
+
+        assert_eq!(tree.lowest_common_ancestor(&21, &22), None);
+        assert_eq!(tree.lowest_common_ancestor(&1, &2), None);
+        assert_eq!(BinarySearchTree::new().lowest_common_ancestor(&1, &2), None);
+    }
+
+    #[test]
+    fn test_depth_and_path() {
+        let tree = prequel_tree();
+
+        assert_eq!(tree.depth_of(&16), Some(0));
+        assert_eq!(tree.depth_of(&7), Some(1));
+        assert_eq!(tree.depth_of(&21), Some(2));
+        assert_eq!(tree.depth_of(&70), Some(3));
+        assert_eq!(tree.depth_of(&22), None);
+
+        assert_eq!(
+            tree.path_to(&70).collect::<Vec<_>>(),
+            vec![&16, &28, &36, &70]
+        );
+        assert_eq!(tree.path_to(&16).collect::<Vec<_>>(), vec![&16]);
+        // the key is absent, the path ends where it would be inserted
+        assert_eq!(tree.path_to(&22).collect::<Vec<_>>(), vec![&16, &28, &21]);
+
+        assert_eq!(BinarySearchTree::new().path_to(&1).next(), None);
+    }
+
+    #[test]
+    fn test_to_bytes_round_trip() {
+        let tree = prequel_tree();
+        let bytes = tree.to_bytes();
+
+        // policy, 7 nodes with a marker and 4 bytes each, 8 null markers
+        assert_eq!(bytes.len(), 1 + 7 * 5 + 8);
+
+        let decoded = BinarySearchTree::<u32>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, tree);
+        assert_eq!(decoded.to_pretty_string(), tree.to_pretty_string());
+        assert_eq!(decoded.rank(&28), 4);
+        assert!(decoded.is_valid_bst());
+
+        let empty = BinarySearchTree::<u32>::with_duplicate_policy(DuplicatePolicy::Reject);
+        let decoded = BinarySearchTree::<u32>::from_bytes(&empty.to_bytes()).unwrap();
+        assert!(decoded.is_empty());
+        assert_eq!(decoded.duplicate_policy(), DuplicatePolicy::Reject);
+
+        // deep trees are encoded and decoded without recursion
+        let skewed: BinarySearchTree<u32> = (0..20_000).collect();
+        let bytes = skewed.to_bytes();
+        let decoded = BinarySearchTree::<u32>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), 20_000);
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_from_bytes_errors() {
+        let bytes = prequel_tree().to_bytes();
+
+        assert_eq!(
+            BinarySearchTree::<u32>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            BinarySearchTree::<u32>::from_bytes(&[bytes.as_slice(), &[0]].concat()),
+            Err(DecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            BinarySearchTree::<u32>::from_bytes(&[2, 0]),
+            Err(DecodeError::InvalidValue)
+        );
+
+        // policy, then 2 with 1 as its left child, then with 1 as its right child
+        let left = [0, 1, 2, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0];
+        let right = [0, 1, 2, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0];
+        assert!(BinarySearchTree::<u32>::from_bytes(&left).is_ok());
+        assert_eq!(
+            BinarySearchTree::<u32>::from_bytes(&right),
+            Err(DecodeError::InvalidOrder)
+        );
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let node = mem::size_of::<BinarySearchTree<u32>>();
+
+        assert_eq!(BinarySearchTree::<u32>::new().memory_usage(), 0);
+        assert_eq!(prequel_tree().memory_usage(), 6 * node);
+
+        let mut tree = prequel_tree();
+        tree.remove(&16);
+        assert_eq!(tree.memory_usage(), 5 * node);
+    }
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(
+            prequel_tree().stats(),
+            BinarySearchTreeStats {
+                nodes: 7,
+                leaves: 3,
+                height: 4,
+                width: 3,
+                diameter: 6,
+            }
+        );
+
+        let skewed: BinarySearchTree<u32> = (0..5).collect();
+        let stats = skewed.stats();
+        assert_eq!((stats.leaves, stats.height, stats.width), (1, 5, 1));
+        assert_eq!(stats.diameter, 5);
+
+        assert_eq!(
+            BinarySearchTree::<u32>::new().stats(),
+            BinarySearchTreeStats::default()
+        );
+    }
+
+    #[test]
+    fn test_mirror() {
+        let tree = prequel_tree();
+        let mut mirrored = prequel_tree();
+
+        mirrored.mirror();
+        assert!(mirrored.is_mirror_of(&tree));
+        assert!(tree.is_mirror_of(&mirrored));
+        assert!(!tree.is_mirror_of(&prequel_tree()));
+        assert!(!mirrored.is_valid_bst());
+        assert_eq!(
+            mirrored.iter().collect::<Vec<_>>(),
+            vec![&70, &36, &28, &21, &16, &7, &3]
+        );
+
+        mirrored.mirror();
+        assert!(mirrored.is_valid_bst());
+        assert!(mirrored.search(&21));
+
+        let single: BinarySearchTree<u32> = vec![1].into_iter().collect();
+        assert!(single.is_mirror_of(&single));
+        assert!(BinarySearchTree::<u32>::new().is_mirror_of(&BinarySearchTree::new()));
+    }
+
+    #[test]
+    fn test_cursor() {
+        let tree = prequel_tree();
+
+        let mut cursor = tree.cursor_at(&20);
+        assert_eq!(cursor.current(), Some(&21));
+        assert_eq!(cursor.index(), Some(3));
+        assert_eq!(cursor.peek_prev(), Some(&16));
+        assert_eq!(cursor.peek_next(), Some(&28));
+
+        cursor.move_next();
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&70));
+
+        // past the end is the ghost, then it wraps around
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.index(), None);
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&3));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&70));
+
+        assert_eq!(tree.cursor_back().current(), Some(&70));
+        assert_eq!(tree.cursor_at(&71).current(), None);
+        assert_eq!(
+            BinarySearchTree::<u32>::new().cursor_front().current(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cursor_mut() {
+        let mut tree = prequel_tree();
+
+        let mut cursor = tree.cursor_at_mut(&16);
+        assert_eq!(cursor.remove_current(), Some(16));
+        assert_eq!(cursor.current(), Some(&21));
+        assert_eq!(cursor.remove_current(), Some(21));
+        assert_eq!(cursor.current(), Some(&28));
+
+        // inserting before the cursor keeps it on the same element
+        assert!(cursor.insert(1));
+        assert_eq!(cursor.current(), Some(&28));
+        assert!(cursor.insert(99));
+        assert_eq!(cursor.current(), Some(&28));
+        assert_eq!(cursor.peek_prev(), Some(&7));
+
+        cursor.move_prev();
+        assert_eq!(cursor.as_cursor().current(), Some(&7));
+
+        let mut cursor = tree.cursor_back_mut();
+        assert_eq!(cursor.remove_current(), Some(99));
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.remove_current(), None);
+
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&1, &3, &7, &28, &36, &70]
+        );
+        assert!(tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let tree = prequel_tree();
+
+        assert_eq!(
+            tree.iter_from(&21).collect::<Vec<_>>(),
+            vec![&21, &28, &36, &70]
+        );
+        assert_eq!(tree.iter_from(&22).collect::<Vec<_>>(), vec![&28, &36, &70]);
+        assert_eq!(tree.iter_from(&0).count(), 7);
+        assert_eq!(tree.iter_from(&71).next(), None);
+
+        // resume a paginated scan after the last element of the previous page
+        let page: Vec<_> = tree.iter().take(3).collect();
+        let next_page: Vec<_> = tree.iter_from(page[2]).skip(1).take(3).collect();
+        assert_eq!(next_page, vec![&21, &28, &36]);
+    }
+
+    #[test]
+    fn test_count_range() {
+        let tree = prequel_tree();
+
+        assert_eq!(tree.count_range(..), 7);
+        assert_eq!(tree.count_range(7..28), 3);
+        assert_eq!(tree.count_range(7..=28), 4);
+        assert_eq!(tree.count_range((Excluded(7), Excluded(28))), 2);
+        assert_eq!(tree.count_range(30..), 2);
+        assert_eq!(tree.count_range(..=3), 1);
+        assert_eq!(tree.count_range(40..70), 0);
+        assert_eq!(tree.count_range((Included(50), Included(10))), 0);
+
+        for (start, end) in [(0, 100), (3, 4), (16, 36), (22, 23)] {
+            assert_eq!(tree.count_range(start..end), tree.range(start..end).count());
+        }
+
+        let mut tree = BinarySearchTree::new();
+        tree.extend(vec![2, 2, 2, 1, 3]);
+        assert_eq!(tree.count_range(2..=2), 3);
+    }
+
+    #[test]
+    fn test_nearest() {
+        let tree = prequel_tree();
+
+        assert_eq!(tree.nearest(&16), Some(&16));
+        assert_eq!(tree.nearest(&19), Some(&21));
+        assert_eq!(tree.nearest(&10), Some(&7));
+        assert_eq!(tree.nearest(&0), Some(&3));
+        assert_eq!(tree.nearest(&1000), Some(&70));
+        // 5 is as close to 3 as to 7
+        assert_eq!(tree.nearest(&5), Some(&3));
+
+        let distance = |a: &u32, b: &u32| a.abs_diff(*b);
+        assert_eq!(tree.nearest_by(&5, NearestTie::Larger, distance), Some(&7));
+        assert_eq!(tree.nearest_by(&5, NearestTie::Smaller, distance), Some(&3));
+
+        assert_eq!(BinarySearchTree::<u32>::new().nearest(&5), None);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut tree = BinarySearchTree::new();
+        tree.insert(User {
+            id: 2,
+            name: "ferris",
+        });
+        tree.insert(User {
+            id: 1,
+            name: "corro",
+        });
+
+        let probe = User { id: 2, name: "" };
+        assert_eq!(tree.get(&probe).map(|user| user.name), Some("ferris"));
+        assert_eq!(tree.get(&User { id: 3, name: "" }), None);
+
+        let tree = prequel_tree();
+        assert_eq!(tree.get(&36), Some(&36));
+        assert_eq!(BinarySearchTree::<u32>::new().get(&36), None);
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut tree = prequel_tree();
+        assert_eq!(tree.try_insert(21), Ok(()));
+        assert_eq!(tree.count(&21), 2);
+
+        let mut tree = BinarySearchTree::with_duplicate_policy(DuplicatePolicy::Reject);
+        tree.extend([16, 7, 28]);
+        assert_eq!(tree.try_insert(3), Ok(()));
+
+        let error = tree.try_insert(7).unwrap_err();
+        assert_eq!(error.kind(), InsertErrorKind::Duplicate);
+        assert_eq!(error.to_string(), "an equal element is already stored");
+        assert_eq!(error.into_value(), 7);
+        assert_eq!(tree.len(), 4);
+        assert!(tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut tree = BinarySearchTree::new();
+
+        assert!(tree
+            .replace(User {
+                id: 1,
+                name: "corro"
+            })
+            .is_none());
+        assert!(tree
+            .replace(User {
+                id: 2,
+                name: "ferris"
+            })
+            .is_none());
+
+        let replaced = tree.replace(User {
+            id: 2,
+            name: "crab",
+        });
+        assert_eq!(replaced.map(|user| user.name), Some("ferris"));
+        assert_eq!(tree.len(), 2);
+        assert_eq!(
+            tree.get(&User { id: 2, name: "" }).map(|user| user.name),
+            Some("crab")
+        );
+
+        // replace never keeps a duplicate, even when duplicates are allowed
+        let mut tree = prequel_tree();
+        assert_eq!(tree.replace(21), Some(21));
+        assert_eq!(tree.count(&21), 1);
+        assert_eq!(tree.replace(22), None);
+        assert_eq!(tree.len(), 8);
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut tree = BinarySearchTree::new();
+        tree.insert(User {
+            id: 1,
+            name: "corro",
+        });
+
+        let user = tree.get_or_insert_with(User { id: 1, name: "" }, || User {
+            id: 1,
+            name: "unused",
+        });
+        assert_eq!(user.name, "corro");
+
+        let user = tree.get_or_insert_with(User { id: 2, name: "" }, || User {
+            id: 2,
+            name: "ferris",
+        });
+        assert_eq!(user.name, "ferris");
+        assert_eq!(tree.len(), 2);
+
+        let mut tree = prequel_tree();
+        assert_eq!(*tree.get_or_insert_with(22, || 22), 22);
+        assert_eq!(*tree.get_or_insert_with(22, || unreachable!()), 22);
+        assert_eq!(tree.len(), 8);
+        assert_eq!(tree.rank(&28), 5);
+        assert!(tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_into_sorted_vec() {
+        let sorted = prequel_tree().into_sorted_vec();
+        assert_eq!(sorted, vec![3, 7, 16, 21, 28, 36, 70]);
+        assert_eq!(sorted.capacity(), 7);
+
+        let tree: BinarySearchTree<String> =
+            ["b", "c", "a"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(tree.into_sorted_vec(), vec!["a", "b", "c"]);
+        assert!(BinarySearchTree::<u32>::new().into_sorted_vec().is_empty());
+    }
+
+    #[test]
+    fn test_std_conversions() {
+        let tree = BinarySearchTree::from(vec![28, 7, 16, 3, 70, 21, 36]);
+        assert_eq!(tree, prequel_tree());
+        assert!(tree.is_balanced());
+
+        let tree = BinarySearchTree::from(BTreeSet::from([28, 7, 16, 3, 70, 21, 36]));
+        assert_eq!(tree.height(), 3);
+        assert_eq!(Vec::from(tree), vec![3, 7, 16, 21, 28, 36, 70]);
+
+        let tree = BinarySearchTree::from([2, 1, 2]);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(BTreeSet::from(tree), BTreeSet::from([1, 2]));
+    }
+
+    #[test]
+    fn test_subtree() {
+        let mut tree = prequel_tree();
+
+        let subtree = tree.subtree(&28).unwrap();
+        assert_eq!(subtree.len(), 4);
+        assert_eq!(subtree.min(), Some(&21));
+        assert_eq!(subtree.iter().collect::<Vec<_>>(), vec![&21, &28, &36, &70]);
+        assert!(!subtree.contains(&16));
+        assert!(tree.subtree(&22).is_none());
+
+        let detached = tree.split_subtree(&28).unwrap();
+        assert_eq!(
+            detached.iter().collect::<Vec<_>>(),
+            vec![&21, &28, &36, &70]
+        );
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&3, &7, &16]);
+        assert_eq!(tree.len(), 3);
+        assert!(tree.is_valid_bst());
+        assert!(tree.split_subtree(&28).is_none());
+
+        let everything = tree.split_subtree(&16).unwrap();
+        assert_eq!(everything.len(), 3);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_index() {
+        let tree = prequel_tree();
+
+        assert_eq!(tree[0], 3);
+        assert_eq!(tree[3], 21);
+        assert_eq!(tree[6], 70);
+        for (position, data) in tree.iter().enumerate() {
+            assert_eq!(&tree[position], data);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is 7 but the index is 7")]
+    fn test_index_out_of_bounds() {
+        let _ = prequel_tree()[7];
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_iter() {
+        use rayon::prelude::*;
+
+        let tree = BinarySearchTree::from_sorted_iter(0..10_000u64);
+
+        assert_eq!(tree.par_iter().count(), 10_000);
+        assert_eq!(tree.par_iter().sum::<u64>(), (0..10_000u64).sum::<u64>());
+        assert_eq!(tree.par_iter().max(), Some(&9_999));
+        assert_eq!(
+            prequel_tree().par_iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &28, &36, &70]
+        );
+
+        let doubled: Vec<u32> = prequel_tree()
+            .into_par_iter()
+            .map(|data| data * 2)
+            .collect();
+        assert_eq!(doubled, vec![6, 14, 32, 42, 56, 72, 140]);
+
+        assert_eq!(BinarySearchTree::<u32>::new().par_iter().count(), 0);
     }
 }
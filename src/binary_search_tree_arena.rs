@@ -0,0 +1,650 @@
+use crate::binary_search_tree::{InsertError, InsertErrorKind};
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    collections::BTreeSet,
+    fmt, mem,
+};
+
+/// Marks a missing child, or the end of the free list
+const NIL: u32 = u32::MAX;
+
+/// A binary search tree which stores its nodes in a single `Vec`
+///
+/// Children are linked by `u32` indices instead of boxes, so inserting only
+/// allocates when the vector grows and neighbouring nodes share cache lines.
+/// Slots of removed nodes are kept on a free list and reused by later
+/// insertions.
+///
+/// Like [`crate::BinarySearchTree`] with [`crate::DuplicatePolicy::Allow`],
+/// equal elements are all stored, each later one to the right of the others.
+#[derive(Clone)]
+pub struct BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    nodes: Vec<Slot<T>>,
+    root: u32,
+    /// head of the list of vacant slots, threaded through `Slot::Vacant`
+    free: u32,
+    len: usize,
+}
+
+#[derive(Clone)]
+enum Slot<T> {
+    Occupied(Node<T>),
+    Vacant { next_free: u32 },
+}
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: u32,
+    right: u32,
+}
+
+/// Where the index of a node is stored
+#[derive(Clone, Copy)]
+enum Link {
+    Root,
+    Left(u32),
+    Right(u32),
+}
+
+impl<T> BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty tree with room for `capacity` nodes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            nodes: Vec::with_capacity(capacity),
+            root: NIL,
+            free: NIL,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements, keeping the allocated node storage
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = NIL;
+        self.free = NIL;
+        self.len = 0;
+    }
+
+    /// Returns `true` if the tree contains an element equal to `key`
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut index = self.root;
+
+        while index != NIL {
+            let node = self.node(index);
+
+            index = match key.cmp(node.data.borrow()) {
+                Equal => return Some(&node.data),
+                Less => node.left,
+                Greater => node.right,
+            };
+        }
+        None
+    }
+
+    /// Inserts `data` into the tree, it is always stored so this returns `true`
+    pub fn insert(&mut self, data: T) -> bool {
+        let mut link = Link::Root;
+
+        loop {
+            let index = self.link(link);
+            if index == NIL {
+                break;
+            }
+
+            link = if data < self.node(index).data {
+                Link::Left(index)
+            } else {
+                Link::Right(index)
+            };
+        }
+
+        let index = self.allocate(data);
+        *self.link_mut(link) = index;
+        self.len += 1;
+        true
+    }
+
+    /// Inserts `data` into the tree, handing it back inside the error if the
+    /// node buffer can't grow
+    ///
+    /// Fails with [`InsertErrorKind::AllocationFailed`] when there is no
+    /// vacant slot and reserving a new one fails, or the tree already holds
+    /// `u32::MAX` nodes, one for every index below the `u32::MAX` that marks
+    /// an empty link.
+    pub fn try_insert(&mut self, data: T) -> Result<(), InsertError<T>> {
+        if self.free == NIL
+            && (self.nodes.len() >= NIL as usize || self.nodes.try_reserve(1).is_err())
+        {
+            return Err(InsertError::new(InsertErrorKind::AllocationFailed, data));
+        }
+
+        self.insert(data);
+        Ok(())
+    }
+
+    /// Removes `data` from the tree, returning whether it was present
+    pub fn remove(&mut self, data: &T) -> bool {
+        self.take(data).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take(&mut self, key: &T) -> Option<T> {
+        let mut link = Link::Root;
+
+        let index = loop {
+            let index = self.link(link);
+            if index == NIL {
+                return None;
+            }
+
+            link = match key.cmp(&self.node(index).data) {
+                Equal => break index,
+                Less => Link::Left(index),
+                Greater => Link::Right(index),
+            };
+        };
+
+        let Node { left, right, .. } = *self.node(index);
+        let replacement = if left == NIL {
+            right
+        } else if right == NIL {
+            left
+        } else {
+            // two children: the in-order successor takes the place of the node
+            let mut successor_link = Link::Right(index);
+            while self.node(self.link(successor_link)).left != NIL {
+                successor_link = Link::Left(self.link(successor_link));
+            }
+
+            let successor = self.link(successor_link);
+            *self.link_mut(successor_link) = self.node(successor).right;
+
+            // read the children again, unlinking may have changed `right`
+            let Node { left, right, .. } = *self.node(index);
+            let successor_node = self.node_mut(successor);
+            successor_node.left = left;
+            successor_node.right = right;
+            successor
+        };
+
+        *self.link_mut(link) = replacement;
+        self.len -= 1;
+        Some(self.deallocate(index))
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        let mut index = self.root;
+        let mut min = None;
+
+        while index != NIL {
+            let node = self.node(index);
+            min = Some(&node.data);
+            index = node.left;
+        }
+        min
+    }
+
+    pub fn max(&self) -> Option<&T> {
+        let mut index = self.root;
+        let mut max = None;
+
+        while index != NIL {
+            let node = self.node(index);
+            max = Some(&node.data);
+            index = node.right;
+        }
+        max
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut stack = vec![(self.root, 1)];
+
+        while let Some((index, depth)) = stack.pop() {
+            if index != NIL {
+                let node = self.node(index);
+                height = height.max(depth);
+                stack.push((node.left, depth + 1));
+                stack.push((node.right, depth + 1));
+            }
+        }
+        height
+    }
+
+    /// Returns the number of heap bytes used by the node buffer
+    ///
+    /// Counts the whole capacity, including vacant and spare slots. Heap memory
+    /// owned by the elements themselves is not included.
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<Slot<T>>()
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> BinarySearchTreeArenaIterator<'_, T> {
+        BinarySearchTreeArenaIterator::new(self)
+    }
+
+    /// Consumes the tree, returning its elements in order
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut order = Vec::with_capacity(self.len);
+        let mut stack = Vec::new();
+        let mut index = self.root;
+
+        while index != NIL || !stack.is_empty() {
+            while index != NIL {
+                stack.push(index);
+                index = self.node(index).left;
+            }
+
+            let next = stack.pop().expect("checked by the loop");
+            order.push(next);
+            index = self.node(next).right;
+        }
+
+        let mut slots: Vec<Option<T>> = mem::take(&mut self.nodes)
+            .into_iter()
+            .map(|slot| match slot {
+                Slot::Occupied(node) => Some(node.data),
+                Slot::Vacant { .. } => None,
+            })
+            .collect();
+
+        order
+            .into_iter()
+            .map(|index| {
+                slots[index as usize]
+                    .take()
+                    .expect("index of an occupied slot")
+            })
+            .collect()
+    }
+
+    /// Builds a height-balanced tree from elements which are already sorted
+    ///
+    /// Each element keeps its sorted position as its slot index.
+    fn from_sorted_vec(sorted: Vec<T>) -> Self {
+        debug_assert!(sorted.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(sorted.len() < NIL as usize, "BinarySearchTreeArena is full");
+
+        let mut tree = Self::with_capacity(sorted.len());
+        tree.len = sorted.len();
+        tree.nodes.extend(sorted.into_iter().map(|data| {
+            Slot::Occupied(Node {
+                data,
+                left: NIL,
+                right: NIL,
+            })
+        }));
+
+        // link the middle of every range to the middles of its two halves
+        let mut ranges = vec![(0, tree.len, Link::Root)];
+
+        while let Some((start, end, link)) = ranges.pop() {
+            if start == end {
+                continue;
+            }
+
+            let middle = start + (end - start) / 2;
+            *tree.link_mut(link) = middle as u32;
+
+            ranges.push((start, middle, Link::Left(middle as u32)));
+            ranges.push((middle + 1, end, Link::Right(middle as u32)));
+        }
+
+        tree
+    }
+
+    fn node(&self, index: u32) -> &Node<T> {
+        match &self.nodes[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    fn node_mut(&mut self, index: u32) -> &mut Node<T> {
+        match &mut self.nodes[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    fn link(&self, link: Link) -> u32 {
+        match link {
+            Link::Root => self.root,
+            Link::Left(index) => self.node(index).left,
+            Link::Right(index) => self.node(index).right,
+        }
+    }
+
+    fn link_mut(&mut self, link: Link) -> &mut u32 {
+        match link {
+            Link::Root => &mut self.root,
+            Link::Left(index) => &mut self.node_mut(index).left,
+            Link::Right(index) => &mut self.node_mut(index).right,
+        }
+    }
+
+    /// Stores `data` in a vacant slot, or a new one, and returns its index
+    fn allocate(&mut self, data: T) -> u32 {
+        let node = Slot::Occupied(Node {
+            data,
+            left: NIL,
+            right: NIL,
+        });
+
+        if self.free == NIL {
+            let index = self.nodes.len();
+            assert!(index < NIL as usize, "BinarySearchTreeArena is full");
+
+            self.nodes.push(node);
+            index as u32
+        } else {
+            let index = self.free;
+
+            match mem::replace(&mut self.nodes[index as usize], node) {
+                Slot::Vacant { next_free } => self.free = next_free,
+                Slot::Occupied(_) => unreachable!("free list points to an occupied slot"),
+            }
+            index
+        }
+    }
+
+    /// Moves the data out of the slot at `index` and puts the slot on the free list
+    fn deallocate(&mut self, index: u32) -> T {
+        let vacant = Slot::Vacant {
+            next_free: self.free,
+        };
+        self.free = index;
+
+        match mem::replace(&mut self.nodes[index as usize], vacant) {
+            Slot::Occupied(node) => node.data,
+            Slot::Vacant { .. } => unreachable!("deallocating a vacant slot"),
+        }
+    }
+}
+
+impl<T> Default for BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for BinarySearchTreeArena<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+/// Sorts the elements and builds a height-balanced tree
+impl<T> From<Vec<T>> for BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    fn from(mut elements: Vec<T>) -> Self {
+        elements.sort();
+        Self::from_sorted_vec(elements)
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    fn from(elements: [T; N]) -> Self {
+        Self::from(Vec::from(elements))
+    }
+}
+
+impl<T> From<BTreeSet<T>> for BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    fn from(set: BTreeSet<T>) -> Self {
+        Self::from_sorted_vec(set.into_iter().collect())
+    }
+}
+
+impl<T> From<BinarySearchTreeArena<T>> for Vec<T>
+where
+    T: Ord,
+{
+    fn from(tree: BinarySearchTreeArena<T>) -> Self {
+        tree.into_sorted_vec()
+    }
+}
+
+impl<T> Extend<T> for BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+
+        self.nodes.reserve(iter.size_hint().0);
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`BinarySearchTreeArena`] in order
+pub struct BinarySearchTreeArenaIterator<'a, T>
+where
+    T: Ord,
+{
+    tree: &'a BinarySearchTreeArena<T>,
+    stack: Vec<u32>,
+}
+
+impl<'a, T> BinarySearchTreeArenaIterator<'a, T>
+where
+    T: Ord,
+{
+    fn new(tree: &'a BinarySearchTreeArena<T>) -> Self {
+        let mut iter = Self {
+            tree,
+            stack: Vec::new(),
+        };
+
+        iter.stack_push_left(tree.root);
+        iter
+    }
+
+    fn stack_push_left(&mut self, mut index: u32) {
+        while index != NIL {
+            self.stack.push(index);
+            index = self.tree.node(index).left;
+        }
+    }
+}
+
+impl<'a, T> Iterator for BinarySearchTreeArenaIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.tree.node(self.stack.pop()?);
+
+        self.stack_push_left(node.right);
+        Some(&node.data)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BinarySearchTreeArena<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = BinarySearchTreeArenaIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn prequel_tree() -> BinarySearchTreeArena<u32> {
+        let mut tree = BinarySearchTreeArena::new();
+        tree.insert(16);
+        tree.insert(7);
+        tree.insert(28);
+        tree.insert(3);
+        tree.insert(21);
+        tree.insert(36);
+        tree.insert(70);
+        tree
+    }
+
+    #[test]
+    fn test_search() {
+        let tree = prequel_tree();
+
+        for data in [16, 7, 28, 3, 21, 36, 70] {
+            assert!(tree.contains(&data));
+        }
+        assert!(!tree.contains(&1));
+        assert!(!tree.contains(&90));
+
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.height(), 4);
+        assert_eq!(tree.min(), Some(&3));
+        assert_eq!(tree.max(), Some(&70));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &28, &36, &70]
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree = prequel_tree();
+
+        // leaf, single child, two children with the successor as right child,
+        // and the root
+        assert!(tree.remove(&3));
+        assert!(tree.remove(&36));
+        assert!(tree.remove(&28));
+        assert!(tree.remove(&16));
+        assert!(!tree.remove(&16));
+
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&7, &21, &70]);
+
+        // a successor deeper in the right subtree
+        let mut tree: BinarySearchTreeArena<u32> =
+            vec![10, 5, 20, 15, 25, 12].into_iter().collect();
+        assert_eq!(tree.take(&10), Some(10));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&5, &12, &15, &20, &25]
+        );
+    }
+
+    #[test]
+    fn test_std_conversions() {
+        let tree = BinarySearchTreeArena::from(vec![28, 7, 16, 3, 70, 21, 36]);
+        assert_eq!(tree.height(), 3);
+        assert!(tree.contains(&21));
+
+        let mut tree = BinarySearchTreeArena::from(BTreeSet::from([2, 1, 3]));
+        tree.remove(&2);
+        tree.insert(0);
+        assert_eq!(Vec::from(tree), vec![0, 1, 3]);
+        assert!(BinarySearchTreeArena::<u32>::from([])
+            .into_sorted_vec()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut tree = prequel_tree();
+
+        assert_eq!(tree.try_insert(22), Ok(()));
+        assert_eq!(tree.try_insert(22), Ok(()));
+        assert_eq!(tree.len(), 9);
+        assert_eq!(tree.iter().filter(|&&data| data == 22).count(), 2);
+    }
+
+    #[test]
+    fn test_slots_are_reused() {
+        let mut tree = prequel_tree();
+
+        tree.remove(&21);
+        tree.remove(&3);
+        tree.insert(4);
+        tree.insert(22);
+        tree.insert(23);
+        assert_eq!(tree.nodes.len(), 8);
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&4, &7, &16, &22, &23, &28, &36, &70]
+        );
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.min(), None);
+        assert_eq!(tree.height(), 0);
+        // the buffer is kept for reuse
+        assert!(tree.memory_usage() >= 8 * std::mem::size_of::<Slot<u32>>());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::binary_search_tree::BinarySearchTree;
+use std::{cmp::Ordering, fmt};
+
+/// An ordering between two elements, used by [`BinarySearchTreeBy`]
+///
+/// Implemented for every `Fn(&T, &T) -> Ordering` and for [`ByKey`].
+pub trait Comparator<T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+impl<T, F> Comparator<T> for F
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+/// Orders elements by the key extracted from them, see
+/// [`BinarySearchTreeBy::new_by_key`]
+#[derive(Clone)]
+pub struct ByKey<F>(F);
+
+impl<T, K, F> Comparator<T> for ByKey<F>
+where
+    F: Fn(&T) -> K,
+    K: Ord,
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.0)(a).cmp(&(self.0)(b))
+    }
+}
+
+/// A binary search tree ordered by a caller-supplied comparator instead of
+/// the `Ord` impl of its elements
+///
+/// Shares its nodes with [`BinarySearchTree`], so equal elements are stored
+/// next to each other in insertion order like under
+/// [`crate::DuplicatePolicy::Allow`].
+#[derive(Clone)]
+pub struct BinarySearchTreeBy<T, C> {
+    tree: BinarySearchTree<Unordered<T>>,
+    compare: C,
+}
+
+/// Element wrapper which satisfies the `Ord` bound of [`BinarySearchTree`]
+///
+/// The tree is only ever searched through the `_by` helpers with the
+/// comparator, so this ordering is never consulted.
+#[derive(Clone)]
+struct Unordered<T>(T);
+
+impl<T> PartialEq for Unordered<T> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<T> Eq for Unordered<T> {}
+
+impl<T> PartialOrd for Unordered<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Unordered<T> {
+    fn cmp(&self, _other: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl<T, C> BinarySearchTreeBy<T, C>
+where
+    C: Fn(&T, &T) -> Ordering,
+{
+    /// Creates an empty tree ordered by `compare`
+    pub fn new_by(compare: C) -> Self {
+        Self {
+            tree: BinarySearchTree::new(),
+            compare,
+        }
+    }
+}
+
+impl<T, K, F> BinarySearchTreeBy<T, ByKey<F>>
+where
+    F: Fn(&T) -> K,
+    K: Ord,
+{
+    /// Creates an empty tree ordered by the key `key` extracts from each
+    /// element
+    ///
+    /// The key is recomputed on every comparison, so it should be cheap.
+    pub fn new_by_key(key: F) -> Self {
+        Self {
+            tree: BinarySearchTree::new(),
+            compare: ByKey(key),
+        }
+    }
+}
+
+impl<T, C> BinarySearchTreeBy<T, C>
+where
+    C: Comparator<T>,
+{
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Removes all elements, keeping the comparator
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Inserts `data`, after any elements the comparator considers equal
+    pub fn insert(&mut self, data: T) {
+        let compare = &self.compare;
+
+        // equal elements go to the right, so never stop at one
+        let (_, path) = self
+            .tree
+            .search_path_by(|stored| compare.compare(&data, &stored.0).then(Ordering::Greater));
+
+        self.tree.insert_at_path(&path, Unordered(data));
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get(&self, key: &T) -> Option<&T> {
+        self.tree
+            .get_by(|stored| self.compare.compare(key, &stored.0))
+            .map(|stored| &stored.0)
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove(&mut self, key: &T) -> bool {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take(&mut self, key: &T) -> Option<T> {
+        let compare = &self.compare;
+
+        self.tree
+            .take_by(&mut |stored| compare.compare(key, &stored.0))
+            .map(|stored| stored.0)
+    }
+
+    /// Returns the smallest element according to the comparator
+    pub fn min(&self) -> Option<&T> {
+        self.tree.min().map(|stored| &stored.0)
+    }
+
+    /// Returns the largest element according to the comparator
+    pub fn max(&self) -> Option<&T> {
+        self.tree.max().map(|stored| &stored.0)
+    }
+
+    /// Returns the number of heap bytes used by the nodes of the tree, see
+    /// [`BinarySearchTree::memory_usage`]
+    pub fn memory_usage(&self) -> usize {
+        self.tree.memory_usage()
+    }
+
+    /// Returns a new iterator over the elements in comparator order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        self.tree.iter().map(|stored| &stored.0)
+    }
+}
+
+impl<T, C> fmt::Debug for BinarySearchTreeBy<T, C>
+where
+    T: fmt::Debug,
+    C: Comparator<T>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, C> Extend<T> for BinarySearchTreeBy<T, C>
+where
+    C: Comparator<T>,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Has no `Ord` impl at all
+    #[derive(Debug, Clone, PartialEq)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    fn point(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_new_by() {
+        let mut tree = BinarySearchTreeBy::new_by(|a: &u32, b: &u32| b.cmp(a));
+        tree.extend([16, 7, 28, 3, 21, 7]);
+
+        assert_eq!(tree.len(), 6);
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&28, &21, &16, &7, &7, &3]
+        );
+        assert_eq!(tree.min(), Some(&28));
+        assert_eq!(tree.max(), Some(&3));
+        assert!(tree.contains(&21));
+        assert!(tree.remove(&7));
+        assert!(tree.remove(&7));
+        assert!(!tree.remove(&7));
+        assert_eq!(format!("{:?}", tree), "{28, 21, 16, 3}");
+    }
+
+    #[test]
+    fn test_types_without_ord() {
+        let mut by_x = BinarySearchTreeBy::new_by(|a: &Point, b: &Point| a.x.total_cmp(&b.x));
+        by_x.extend([point(2.5, 0.0), point(-1.0, 4.0), point(0.5, 1.0)]);
+        assert_eq!(by_x.get(&point(0.5, 99.0)), Some(&point(0.5, 1.0)));
+        assert_eq!(by_x.take(&point(-1.0, 0.0)), Some(point(-1.0, 4.0)));
+        assert_eq!(by_x.iter().map(|p| p.x).collect::<Vec<_>>(), vec![0.5, 2.5]);
+    }
+
+    #[test]
+    fn test_new_by_key() {
+        let mut by_len = BinarySearchTreeBy::new_by_key(|s: &&str| s.len());
+        by_len.extend(["ferris", "oak", "corro", "ash"]);
+        assert_eq!(
+            by_len.iter().collect::<Vec<_>>(),
+            vec![&"oak", &"ash", &"corro", &"ferris"]
+        );
+        assert_eq!(by_len.get(&"elm"), Some(&"oak"));
+    }
+}
@@ -0,0 +1,672 @@
+use crate::binary_search_tree::{BinarySearchTree, BinarySearchTreeIntoIterator};
+use std::{borrow::Borrow, cmp::Ordering, collections::BTreeMap, fmt};
+
+/// A key-value map built on top of [`BinarySearchTree`]
+///
+/// Every entry is stored as a single tree element ordered by its key only, so
+/// the map reuses the node logic of the tree and each key is stored once.
+#[derive(Clone)]
+pub struct BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    tree: BinarySearchTree<MapEntry<K, V>>,
+}
+
+/// A key-value pair which compares by its key only
+#[derive(Clone)]
+struct MapEntry<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: Ord, V> PartialEq for MapEntry<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> Eq for MapEntry<K, V> {}
+
+impl<K: Ord, V> PartialOrd for MapEntry<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for MapEntry<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K, V> BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    pub fn new() -> Self {
+        Self {
+            tree: BinarySearchTree::new(),
+        }
+    }
+
+    /// Returns the number of entries in the map
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns `true` if the map contains no entries
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Removes all entries
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Inserts `value` under `key`, returning the value previously stored
+    /// under an equal key
+    ///
+    /// Like `BTreeMap`, the stored key is kept when its value is replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(entry) = self.tree.get_mut_by(|entry| key.cmp(&entry.key)) {
+            return Some(std::mem::replace(&mut entry.value, value));
+        }
+
+        self.tree.insert(MapEntry { key, value });
+        None
+    }
+
+    /// Returns the value stored under `key`, inserting the result of `f` if
+    /// there is none
+    pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> V,
+    {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Returns `true` if the map contains an entry for `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value stored under `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Returns the stored key and value for `key`
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.tree
+            .get_by(|entry| key.cmp(entry.key.borrow()))
+            .map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.tree
+            .get_mut_by(|entry| key.cmp(entry.key.borrow()))
+            .map(|entry| &mut entry.value)
+    }
+
+    /// Removes the entry for `key`, returning its value
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes the entry for `key`, returning the stored key and value
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.tree
+            .take_by(&mut |entry| key.cmp(entry.key.borrow()))
+            .map(|entry| (entry.key, entry.value))
+    }
+
+    /// Returns the entry with the smallest key
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.tree.min().map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Returns the entry with the largest key
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.tree.max().map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Returns the number of heap bytes used by the nodes of the map, see
+    /// [`BinarySearchTree::memory_usage`]
+    pub fn memory_usage(&self) -> usize {
+        self.tree.memory_usage()
+    }
+
+    /// Returns a new iterator over the entries in key order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator {
+        self.tree.iter().map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Returns a new iterator over the entries in key order, with mutable
+    /// references to the values
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.tree
+            .iter_mut()
+            .map(|entry| (&entry.key, &mut entry.value))
+    }
+
+    /// Returns a new iterator over the keys in order
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator {
+        self.tree.iter().map(|entry| &entry.key)
+    }
+
+    /// Returns a new iterator over the values in key order
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator {
+        self.tree.iter().map(|entry| &entry.value)
+    }
+
+    /// Returns a new iterator over mutable references to the values in key order
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.tree.iter_mut().map(|entry| &mut entry.value)
+    }
+
+    /// Returns the entry for `key`, for in-place manipulation
+    ///
+    /// The key is compared only once per node on the way down; inserting into
+    /// a vacant entry follows the recorded path instead of searching again.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let (found, path) = self.tree.search_path_by(|entry| key.cmp(&entry.key));
+
+        if found {
+            Entry::Occupied(OccupiedEntry {
+                entry: self.tree.get_at_path_mut(&path),
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                key,
+                path,
+                tree: &mut self.tree,
+            })
+        }
+    }
+}
+
+/// A view into a single entry of a [`BinarySearchTreeMap`], which is either
+/// occupied or vacant
+pub enum Entry<'a, K, V>
+where
+    K: Ord,
+{
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// An entry whose key is present in the map
+pub struct OccupiedEntry<'a, K, V> {
+    entry: &'a mut MapEntry<K, V>,
+}
+
+/// An entry whose key is absent from the map
+pub struct VacantEntry<'a, K, V>
+where
+    K: Ord,
+{
+    key: K,
+    path: Vec<Ordering>,
+    tree: &'a mut BinarySearchTree<MapEntry<K, V>>,
+}
+
+impl<'a, K, V> Entry<'a, K, V>
+where
+    K: Ord,
+{
+    /// Returns the key of this entry
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant and returns the value
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the result of `default` if the entry is vacant and returns the
+    /// value
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Inserts `V::default()` if the entry is vacant and returns the value
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `modify` on the value if the entry is occupied
+    pub fn and_modify<F>(mut self, modify: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        if let Entry::Occupied(entry) = &mut self {
+            modify(entry.get_mut());
+        }
+
+        self
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    /// Returns the stored key
+    pub fn key(&self) -> &K {
+        &self.entry.key
+    }
+
+    /// Returns the stored value
+    pub fn get(&self) -> &V {
+        &self.entry.value
+    }
+
+    /// Returns a mutable reference to the stored value
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.entry.value
+    }
+
+    /// Converts the entry into a mutable reference bound to the map
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.entry.value
+    }
+
+    /// Replaces the stored value, returning the old one
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(&mut self.entry.value, value)
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V>
+where
+    K: Ord,
+{
+    /// Returns the key which would be inserted
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes back ownership of the key
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` under the entry's key and returns a reference to it
+    pub fn insert(self, value: V) -> &'a mut V {
+        let entry = MapEntry {
+            key: self.key,
+            value,
+        };
+
+        &mut self.tree.insert_at_path(&self.path, entry).value
+    }
+}
+
+impl<K, V> Default for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for BinarySearchTreeMap<K, V>
+where
+    K: Ord + fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Two maps are equal when they hold equal entries, regardless of their shape
+impl<K, V> PartialEq for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<K, V> Eq for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+    V: Eq,
+{
+}
+
+impl<K, V> FromIterator<(K, V)> for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+/// Sorts the entries and builds a height-balanced map, for equal keys the last
+/// value wins like in [`BTreeMap::from`]
+impl<K, V> From<Vec<(K, V)>> for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn from(entries: Vec<(K, V)>) -> Self {
+        let mut entries: Vec<MapEntry<K, V>> = entries
+            .into_iter()
+            .map(|(key, value)| MapEntry { key, value })
+            .collect();
+        // stable, so equal keys keep their order and the last one can win
+        entries.sort();
+
+        let mut unique: Vec<MapEntry<K, V>> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match unique.last_mut() {
+                Some(last) if last.key == entry.key => *last = entry,
+                _ => unique.push(entry),
+            }
+        }
+
+        Self {
+            tree: BinarySearchTree::from_sorted_iter(unique),
+        }
+    }
+}
+
+impl<K, V, const N: usize> From<[(K, V); N]> for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn from(entries: [(K, V); N]) -> Self {
+        Self::from(Vec::from(entries))
+    }
+}
+
+/// Builds a height-balanced map in `O(n)`, the entries are already sorted
+impl<K, V> From<BTreeMap<K, V>> for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn from(map: BTreeMap<K, V>) -> Self {
+        Self {
+            tree: BinarySearchTree::from_sorted_iter(
+                map.into_iter().map(|(key, value)| MapEntry { key, value }),
+            ),
+        }
+    }
+}
+
+impl<K, V> From<BinarySearchTreeMap<K, V>> for Vec<(K, V)>
+where
+    K: Ord,
+{
+    fn from(map: BinarySearchTreeMap<K, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<K, V> From<BinarySearchTreeMap<K, V>> for BTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn from(map: BinarySearchTreeMap<K, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<K, V> Extend<(K, V)> for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K, V> IntoIterator for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    type Item = (K, V);
+    type IntoIter = BinarySearchTreeMapIntoIterator<K, V>;
+
+    /// Consumes the map, returning an iterator over its entries in key order
+    fn into_iter(self) -> Self::IntoIter {
+        BinarySearchTreeMapIntoIterator {
+            inner: self.tree.into_iter(),
+        }
+    }
+}
+
+/// An owning iterator over the entries of a [`BinarySearchTreeMap`] in key order
+pub struct BinarySearchTreeMapIntoIterator<K, V>
+where
+    K: Ord,
+{
+    inner: BinarySearchTreeIntoIterator<MapEntry<K, V>>,
+}
+
+impl<K, V> Iterator for BinarySearchTreeMapIntoIterator<K, V>
+where
+    K: Ord,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|entry| (entry.key, entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn prequel_map() -> BinarySearchTreeMap<u32, &'static str> {
+        let mut map = BinarySearchTreeMap::new();
+        map.insert(16, "sixteen");
+        map.insert(7, "seven");
+        map.insert(28, "twenty-eight");
+        map.insert(3, "three");
+        map.insert(21, "twenty-one");
+        map
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut map = prequel_map();
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get(&7), Some(&"seven"));
+        assert_eq!(map.get(&8), None);
+        assert!(map.contains_key(&28));
+
+        assert_eq!(map.insert(7, "SEVEN"), Some("seven"));
+        assert_eq!(map.get(&7), Some(&"SEVEN"));
+        assert_eq!(map.len(), 5);
+
+        if let Some(value) = map.get_mut(&3) {
+            *value = "THREE";
+        }
+        assert_eq!(map.get_key_value(&3), Some((&3, &"THREE")));
+
+        assert_eq!(map.remove(&16), Some("sixteen"));
+        assert_eq!(map.remove(&16), None);
+        assert_eq!(map.remove_entry(&21), Some((21, "twenty-one")));
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.first_key_value(), Some((&3, &"THREE")));
+        assert_eq!(map.last_key_value(), Some((&28, &"twenty-eight")));
+    }
+
+    #[test]
+    fn test_borrowed_keys() {
+        let mut map = BinarySearchTreeMap::new();
+        map.insert(String::from("oak"), 1);
+        map.insert(String::from("elm"), 2);
+
+        assert_eq!(map.get("oak"), Some(&1));
+        assert!(map.contains_key("elm"));
+        assert_eq!(map.remove("elm"), Some(2));
+        assert!(!map.contains_key("elm"));
+    }
+
+    #[test]
+    fn test_iterators() {
+        let mut map = prequel_map();
+
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![
+                (&3, &"three"),
+                (&7, &"seven"),
+                (&16, &"sixteen"),
+                (&21, &"twenty-one"),
+                (&28, &"twenty-eight")
+            ]
+        );
+        assert_eq!(
+            map.keys().rev().collect::<Vec<_>>(),
+            vec![&28, &21, &16, &7, &3]
+        );
+        assert_eq!(map.values().next(), Some(&"three"));
+
+        for value in map.values_mut() {
+            *value = "x";
+        }
+        for (key, value) in map.iter_mut() {
+            if key % 2 == 0 {
+                *value = "even";
+            }
+        }
+        assert_eq!(
+            map.values().collect::<Vec<_>>(),
+            vec![&"x", &"x", &"even", &"x", &"even"]
+        );
+
+        let owned: Vec<(u32, &str)> = map.into_iter().collect();
+        assert_eq!(owned[0], (3, "x"));
+        assert_eq!(owned.len(), 5);
+    }
+
+    #[test]
+    fn test_collect_debug_eq() {
+        let map: BinarySearchTreeMap<u32, u32> =
+            vec![(2, 20), (1, 10), (2, 22)].into_iter().collect();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(format!("{:?}", map), "{1: 10, 2: 22}");
+        assert_eq!(map.clone(), map);
+        assert_ne!(map, BinarySearchTreeMap::new());
+    }
+
+    #[test]
+    fn test_std_conversions() {
+        let map = BinarySearchTreeMap::from([(2, "b"), (1, "a"), (2, "B"), (3, "c")]);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&2), Some(&"B"));
+
+        let std_map = BTreeMap::from(map.clone());
+        assert_eq!(BinarySearchTreeMap::from(std_map), map);
+        assert_eq!(Vec::from(map), vec![(1, "a"), (2, "B"), (3, "c")]);
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut counts = BinarySearchTreeMap::new();
+
+        for word in "the oak and the elm and the ash".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+
+        assert_eq!(
+            counts.iter().collect::<Vec<_>>(),
+            vec![
+                (&"and", &2),
+                (&"ash", &1),
+                (&"elm", &1),
+                (&"oak", &1),
+                (&"the", &3)
+            ]
+        );
+
+        counts
+            .entry("oak")
+            .and_modify(|count| *count += 10)
+            .or_insert(0);
+        counts
+            .entry("fir")
+            .and_modify(|count| *count += 10)
+            .or_insert(7);
+        assert_eq!(counts.get("oak"), Some(&11));
+        assert_eq!(counts.get("fir"), Some(&7));
+        assert_eq!(*counts.entry("yew").or_insert_with(|| 4), 4);
+        assert_eq!(*counts.entry("yew").or_default(), 4);
+        assert_eq!(counts.entry("pine").key(), &"pine");
+        assert!(!counts.contains_key("pine"));
+
+        // subtree sizes stay correct after inserting through vacant entries
+        assert_eq!(counts.len(), 7);
+        assert_eq!(counts.tree.select(6).map(|entry| entry.key), Some("yew"));
+        assert!(counts.tree.is_valid_bst());
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut map = prequel_map();
+
+        assert_eq!(*map.get_or_insert_with(7, || unreachable!()), "seven");
+        assert_eq!(*map.get_or_insert_with(8, || "eight"), "eight");
+        assert_eq!(map.len(), 6);
+    }
+}
@@ -0,0 +1,666 @@
+use std::{fmt, mem};
+
+/// Number of entries a bucket holds before it bursts into a trie node
+const BURST_LIMIT: usize = 256;
+
+/// Number of hash slots in each bucket
+const SLOTS: usize = 16;
+
+#[derive(Clone)]
+enum Node<V> {
+    Inner(Box<Inner<V>>),
+    Bucket(Bucket<V>),
+}
+
+#[derive(Clone)]
+struct Inner<V> {
+    /// value of the key ending at this node
+    value: Option<V>,
+    /// the child for each byte following this prefix, sorted by byte
+    children: Vec<(u8, Node<V>)>,
+}
+
+impl<V> Inner<V> {
+    fn position(&self, byte: u8) -> Result<usize, usize> {
+        self.children
+            .binary_search_by_key(&byte, |&(child_byte, _)| child_byte)
+    }
+
+    fn child(&self, byte: u8) -> Option<&Node<V>> {
+        let position = self.position(byte).ok()?;
+        Some(&self.children[position].1)
+    }
+
+    fn child_mut(&mut self, byte: u8) -> Option<&mut Node<V>> {
+        let position = self.position(byte).ok()?;
+        Some(&mut self.children[position].1)
+    }
+
+    /// Returns the child for `byte`, adding an empty bucket if there is none
+    fn child_or_insert(&mut self, byte: u8) -> &mut Node<V> {
+        let position = match self.position(byte) {
+            Ok(position) => position,
+            Err(position) => {
+                let bucket = Node::Bucket(Bucket::new());
+                self.children.insert(position, (byte, bucket));
+                position
+            }
+        };
+        &mut self.children[position].1
+    }
+}
+
+/// The keys below a prefix with that prefix cut off, in a small hash table
+/// whose slots each keep their suffixes back to back in one buffer
+#[derive(Clone)]
+struct Bucket<V> {
+    /// no slots until the first entry, so empty buckets don't allocate
+    slots: Vec<Slot<V>>,
+    len: usize,
+}
+
+#[derive(Clone)]
+struct Slot<V> {
+    /// each suffix as its LEB128 length followed by its bytes
+    bytes: Vec<u8>,
+    /// the value of each suffix, in the same order
+    values: Vec<V>,
+}
+
+/// A suffix found in a slot, with its index and the range of its encoding
+struct Found {
+    index: usize,
+    start: usize,
+    end: usize,
+}
+
+impl<V> Slot<V> {
+    /// Returns the suffixes of the slot with their values, in slot order
+    fn entries(&self) -> impl Iterator<Item = (&[u8], &V)> {
+        let mut position = 0;
+        self.values.iter().map(move |value| {
+            let len = read_len(&self.bytes, &mut position);
+            let suffix = &self.bytes[position..position + len];
+            position += len;
+            (suffix, value)
+        })
+    }
+
+    fn find(&self, suffix: &[u8]) -> Option<Found> {
+        let mut position = 0;
+        for index in 0..self.values.len() {
+            let start = position;
+            let len = read_len(&self.bytes, &mut position);
+            let end = position + len;
+            if &self.bytes[position..end] == suffix {
+                return Some(Found { index, start, end });
+            }
+            position = end;
+        }
+        None
+    }
+}
+
+impl<V> Bucket<V> {
+    fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+
+    fn get(&self, suffix: &[u8]) -> Option<&V> {
+        let slot = self.slots.get(slot_of(suffix))?;
+        Some(&slot.values[slot.find(suffix)?.index])
+    }
+
+    fn get_mut(&mut self, suffix: &[u8]) -> Option<&mut V> {
+        let slot = self.slots.get_mut(slot_of(suffix))?;
+        let index = slot.find(suffix)?.index;
+        Some(&mut slot.values[index])
+    }
+
+    fn insert(&mut self, suffix: &[u8], value: V) -> Option<V> {
+        if self.slots.is_empty() {
+            self.slots.resize_with(SLOTS, || Slot {
+                bytes: Vec::new(),
+                values: Vec::new(),
+            });
+        }
+
+        let slot = &mut self.slots[slot_of(suffix)];
+        if let Some(found) = slot.find(suffix) {
+            return Some(mem::replace(&mut slot.values[found.index], value));
+        }
+        write_len(&mut slot.bytes, suffix.len());
+        slot.bytes.extend_from_slice(suffix);
+        slot.values.push(value);
+        self.len += 1;
+        None
+    }
+
+    fn remove(&mut self, suffix: &[u8]) -> Option<V> {
+        let slot = self.slots.get_mut(slot_of(suffix))?;
+        let Found { index, start, end } = slot.find(suffix)?;
+        slot.bytes.drain(start..end);
+        self.len -= 1;
+        Some(slot.values.remove(index))
+    }
+
+    /// Returns the entries of the bucket sorted by suffix
+    fn sorted(&self) -> Vec<(&[u8], &V)> {
+        let mut entries: Vec<_> = self.slots.iter().flat_map(Slot::entries).collect();
+        entries.sort_unstable_by_key(|&(suffix, _)| suffix);
+        entries
+    }
+
+    /// Turns the bucket into a trie node whose children hold its suffixes
+    /// without their first byte
+    fn burst(self) -> Inner<V> {
+        let mut inner = Inner {
+            value: None,
+            children: Vec::new(),
+        };
+
+        for slot in self.slots {
+            let mut position = 0;
+            for value in slot.values {
+                let len = read_len(&slot.bytes, &mut position);
+                let suffix = &slot.bytes[position..position + len];
+                position += len;
+
+                match suffix.split_first() {
+                    None => inner.value = Some(value),
+                    Some((&byte, rest)) => {
+                        let Node::Bucket(bucket) = inner.child_or_insert(byte) else {
+                            unreachable!("only buckets are added while bursting");
+                        };
+                        bucket.insert(rest, value);
+                    }
+                }
+            }
+        }
+        inner
+    }
+}
+
+fn write_len(bytes: &mut Vec<u8>, mut len: usize) {
+    while len >= 0x80 {
+        bytes.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    bytes.push(len as u8);
+}
+
+fn read_len(bytes: &[u8], position: &mut usize) -> usize {
+    let mut len = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*position];
+        *position += 1;
+        len |= usize::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return len;
+        }
+        shift += 7;
+    }
+}
+
+/// Returns the slot of `suffix`, from its 64 bit FNV-1a hash
+fn slot_of(suffix: &[u8]) -> usize {
+    let hash = suffix
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    (hash % SLOTS as u64) as usize
+}
+
+/// Bursts `node` if it's a bucket over the limit, and then the buckets it
+/// bursts into, which may still be over it when their keys share more bytes
+fn burst_if_full<V>(node: &mut Node<V>) {
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        match node {
+            Node::Bucket(bucket) if bucket.len > BURST_LIMIT => {
+                let bucket = mem::replace(bucket, Bucket::new());
+                *node = Node::Inner(Box::new(bucket.burst()));
+                stack.push(node);
+            }
+            Node::Bucket(_) => {}
+            Node::Inner(inner) => {
+                stack.extend(inner.children.iter_mut().map(|(_, child)| child));
+            }
+        }
+    }
+}
+
+/// Releases the nodes under `node` one at a time, so that dropping deep
+/// chains of bursts can't overflow the stack
+fn drop_iteratively<V>(node: Node<V>) {
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        if let Node::Inner(mut inner) = node {
+            stack.extend(
+                mem::take(&mut inner.children)
+                    .into_iter()
+                    .map(|(_, child)| child),
+            );
+        }
+    }
+}
+
+/// A map keyed by byte strings that keeps the keys below a prefix together
+/// in a bucket, and only grows trie nodes where a bucket gets too full
+///
+/// Each bucket is a small hash table whose slots store their key suffixes
+/// back to back in one buffer, so scanning a slot reads contiguous memory
+/// and a short key costs a few bytes rather than a node per byte. A bucket
+/// reaching more than a few hundred keys bursts into a trie node with one
+/// bucket for each byte the keys continue with. Lookups walk the few trie
+/// nodes above a bucket and then scan a single slot.
+///
+/// Buckets aren't kept sorted, so iterating in key order sorts each bucket
+/// as it's reached. Removing keys doesn't merge buckets back.
+#[derive(Clone)]
+pub struct BurstTrie<V> {
+    root: Node<V>,
+    len: usize,
+}
+
+impl<V> BurstTrie<V> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::Bucket(Bucket::new()),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the trie contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all keys
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Stores `value` under `key`, returning the value previously stored there
+    pub fn insert<K>(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        let mut node = &mut self.root;
+        let mut depth = 0;
+
+        let old = loop {
+            match node {
+                Node::Inner(inner) => match key.get(depth) {
+                    None => break inner.value.replace(value),
+                    Some(&byte) => {
+                        node = inner.child_or_insert(byte);
+                        depth += 1;
+                    }
+                },
+                Node::Bucket(bucket) => {
+                    let old = bucket.insert(&key[depth..], value);
+                    burst_if_full(node);
+                    break old;
+                }
+            }
+        };
+
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Returns `true` if a value is stored under `key`
+    pub fn contains_key<K>(&self, key: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get<K>(&self, key: K) -> Option<&V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        let mut node = &self.root;
+        let mut depth = 0;
+
+        loop {
+            match node {
+                Node::Inner(inner) => match key.get(depth) {
+                    None => return inner.value.as_ref(),
+                    Some(&byte) => {
+                        node = inner.child(byte)?;
+                        depth += 1;
+                    }
+                },
+                Node::Bucket(bucket) => return bucket.get(&key[depth..]),
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut<K>(&mut self, key: K) -> Option<&mut V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        let mut node = &mut self.root;
+        let mut depth = 0;
+
+        loop {
+            match node {
+                Node::Inner(inner) => match key.get(depth) {
+                    None => return inner.value.as_mut(),
+                    Some(&byte) => {
+                        node = inner.child_mut(byte)?;
+                        depth += 1;
+                    }
+                },
+                Node::Bucket(bucket) => return bucket.get_mut(&key[depth..]),
+            }
+        }
+    }
+
+    /// Removes `key` from the trie, returning the value stored under it
+    pub fn remove<K>(&mut self, key: K) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        let mut node = &mut self.root;
+        let mut depth = 0;
+
+        let value = loop {
+            match node {
+                Node::Inner(inner) => match key.get(depth) {
+                    None => break inner.value.take(),
+                    Some(&byte) => {
+                        node = inner.child_mut(byte)?;
+                        depth += 1;
+                    }
+                },
+                Node::Bucket(bucket) => break bucket.remove(&key[depth..]),
+            }
+        }?;
+
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Returns the number of buckets, which hold all the keys not ending at
+    /// a trie node
+    pub fn bucket_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Inner(inner) => stack.extend(inner.children.iter().map(|(_, child)| child)),
+                Node::Bucket(_) => count += 1,
+            }
+        }
+        count
+    }
+
+    /// Returns an iterator over the entries in lexicographic order of their
+    /// keys
+    pub fn iter(&self) -> BurstTrieIterator<'_, V> {
+        BurstTrieIterator {
+            key: Vec::new(),
+            stack: vec![(0, None, &self.root)],
+            bucket: Vec::new().into_iter(),
+        }
+    }
+
+    /// Returns `true` if no bucket is over the limit, every suffix is in the
+    /// slot its hash picks, the children of each trie node are sorted by
+    /// distinct bytes and `len` matches the number of values
+    pub fn is_valid(&self) -> bool {
+        let mut values = 0;
+        let mut stack = vec![&self.root];
+
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Inner(inner) => {
+                    if !inner.children.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+                        return false;
+                    }
+                    values += usize::from(inner.value.is_some());
+                    stack.extend(inner.children.iter().map(|(_, child)| child));
+                }
+                Node::Bucket(bucket) => {
+                    let placed = bucket.slots.iter().enumerate().all(|(index, slot)| {
+                        slot.entries().all(|(suffix, _)| slot_of(suffix) == index)
+                    });
+                    let counted: usize = bucket.slots.iter().map(|slot| slot.values.len()).sum();
+                    if !placed || counted != bucket.len || bucket.len > BURST_LIMIT {
+                        return false;
+                    }
+                    values += bucket.len;
+                }
+            }
+        }
+        values == self.len
+    }
+}
+
+impl<V> Default for BurstTrie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Drop for BurstTrie<V> {
+    fn drop(&mut self) {
+        drop_iteratively(mem::replace(&mut self.root, Node::Bucket(Bucket::new())));
+    }
+}
+
+impl<V> fmt::Debug for BurstTrie<V>
+where
+    V: fmt::Debug,
+{
+    /// Formats the entries in key order, like a map, showing the keys as
+    /// strings with invalid UTF-8 replaced
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter()
+                    .map(|(key, value)| (String::from_utf8_lossy(&key).into_owned(), value)),
+            )
+            .finish()
+    }
+}
+
+impl<V> PartialEq for BurstTrie<V>
+where
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<V> Eq for BurstTrie<V> where V: Eq {}
+
+impl<K, V> FromIterator<(K, V)> for BurstTrie<V>
+where
+    K: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<K, V> Extend<(K, V)> for BurstTrie<V>
+where
+    K: AsRef<[u8]>,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the entries of a [`BurstTrie`] in lexicographic order of
+/// their keys
+pub struct BurstTrieIterator<'a, V> {
+    /// prefix of the last node visited
+    key: Vec<u8>,
+    /// nodes left to visit, with the length of the key above them and the
+    /// byte leading to them, the next one on top
+    stack: Vec<(usize, Option<u8>, &'a Node<V>)>,
+    /// the sorted entries left in the bucket under `key`
+    bucket: std::vec::IntoIter<(&'a [u8], &'a V)>,
+}
+
+impl<'a, V> Iterator for BurstTrieIterator<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((suffix, value)) = self.bucket.next() {
+                let mut key = self.key.clone();
+                key.extend_from_slice(suffix);
+                return Some((key, value));
+            }
+
+            let (depth, byte, node) = self.stack.pop()?;
+            self.key.truncate(depth);
+            self.key.extend(byte);
+
+            match node {
+                Node::Inner(inner) => {
+                    let depth = self.key.len();
+                    self.stack.extend(
+                        inner
+                            .children
+                            .iter()
+                            .rev()
+                            .map(|(byte, child)| (depth, Some(*byte), child)),
+                    );
+                    if let Some(value) = &inner.value {
+                        return Some((self.key.clone(), value));
+                    }
+                }
+                Node::Bucket(bucket) => self.bucket = bucket.sorted().into_iter(),
+            }
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a BurstTrie<V> {
+    type Item = (Vec<u8>, &'a V);
+    type IntoIter = BurstTrieIterator<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel_words;
+
+    /// Returns `count` distinct words over a small alphabet, so that many of
+    /// them share prefixes
+    fn words(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|number| {
+                let mut word = String::new();
+                let mut rest = number;
+                loop {
+                    word.push(char::from(b"abcde"[rest % 5]));
+                    rest /= 5;
+                    if rest == 0 {
+                        break word;
+                    }
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let mut trie: BurstTrie<u32> = prequel_words();
+
+        assert_eq!(trie.len(), 7);
+        assert_eq!(trie.bucket_count(), 1);
+        assert_eq!(trie.get("ten"), Some(&1));
+        assert_eq!(trie.get("te"), None);
+        assert_eq!(trie.insert("ten", 10), Some(1));
+        assert_eq!(trie.insert("", 99), None);
+        *trie.get_mut("to").unwrap() += 20;
+
+        assert_eq!(trie.get(b"ten"), Some(&10));
+        assert_eq!(trie.get(""), Some(&99));
+        assert_eq!(trie.get("to"), Some(&22));
+        assert_eq!(
+            format!("{:?}", trie),
+            r#"{"": 99, "i": 5, "in": 4, "inn": 3, "tea": 0, "team": 6, "ten": 10, "to": 22}"#
+        );
+        assert!(trie.is_valid());
+    }
+
+    #[test]
+    fn test_burst() {
+        let words = words(5_000);
+        let mut trie: BurstTrie<usize> = words.iter().zip(0..).collect();
+
+        assert_eq!(trie.len(), words.len());
+        assert!(trie.bucket_count() > 1);
+        assert!(trie.is_valid());
+        for (index, word) in words.iter().enumerate() {
+            assert_eq!(trie.get(word), Some(&index));
+        }
+        assert!(!trie.contains_key("abcdeabcde"));
+
+        let mut sorted = words.clone();
+        sorted.sort_unstable();
+        assert!(trie
+            .iter()
+            .map(|(key, _)| String::from_utf8(key).unwrap())
+            .eq(sorted));
+
+        for (index, word) in words.iter().enumerate().step_by(2) {
+            assert_eq!(trie.remove(word), Some(index));
+        }
+        assert_eq!(trie.remove(&words[0]), None);
+        assert_eq!(trie.len(), words.len() / 2);
+        assert!(trie.is_valid());
+        assert_eq!(trie.get(&words[1]), Some(&1));
+    }
+
+    #[test]
+    fn test_long_shared_prefix() {
+        // every burst moves all the keys one byte down into a single bucket
+        let prefix = "a".repeat(1_000);
+        let mut trie: BurstTrie<usize> = (0..=BURST_LIMIT)
+            .map(|number| (format!("{prefix}{number}"), number))
+            .collect();
+
+        assert!(trie.is_valid());
+        assert_eq!(trie.get(format!("{prefix}7")), Some(&7));
+        trie.insert(&prefix, 0);
+        assert_eq!(trie.iter().next(), Some((prefix.into_bytes(), &0)));
+    }
+}
@@ -0,0 +1,169 @@
+//! A minimal binary encoding for tree elements, independent of serde
+//!
+//! Integers are stored little-endian with their full width, `usize` and
+//! `isize` as 64 bits, and strings as a `u32` byte length followed by UTF-8.
+
+use std::{error::Error, fmt};
+
+/// An element which can be written to and read back from a byte stream
+pub trait Codec: Sized {
+    /// Appends the encoding of `self` to `out`
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Reads one element from the front of `input`, advancing it
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// Why a byte stream could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The stream ended in the middle of a value
+    UnexpectedEnd,
+    /// A marker, tag or element had an invalid value
+    InvalidValue,
+    /// The decoded tree does not hold its elements in search order
+    InvalidOrder,
+    /// Bytes were left over after the tree was decoded
+    TrailingBytes,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            DecodeError::UnexpectedEnd => "unexpected end of input",
+            DecodeError::InvalidValue => "invalid value in input",
+            DecodeError::InvalidOrder => "elements are not in search order",
+            DecodeError::TrailingBytes => "trailing bytes after the encoded tree",
+        };
+        f.write_str(message)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Splits the first `len` bytes off `input`
+pub(crate) fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < len {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+macro_rules! impl_codec_for_int {
+    ($($int:ty),*) => {
+        $(
+            impl Codec for $int {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                    let bytes = take(input, std::mem::size_of::<$int>())?;
+                    Ok(<$int>::from_le_bytes(bytes.try_into().expect("length was checked")))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Codec for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        usize::try_from(u64::decode(input)?).map_err(|_| DecodeError::InvalidValue)
+    }
+}
+
+impl Codec for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        isize::try_from(i64::decode(input)?).map_err(|_| DecodeError::InvalidValue)
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+impl Codec for char {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u32).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        char::from_u32(u32::decode(input)?).ok_or(DecodeError::InvalidValue)
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let len =
+            u32::try_from(self.len()).expect("strings longer than u32::MAX bytes can't be encoded");
+        len.encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = u32::decode(input)? as usize;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidValue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip<T: Codec + PartialEq + fmt::Debug>(value: T) {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+
+        let mut input = out.as_slice();
+        assert_eq!(T::decode(&mut input), Ok(value));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(0xABu8);
+        round_trip(-12345i32);
+        round_trip(u128::MAX);
+        round_trip(usize::MAX);
+        round_trip(true);
+        round_trip('🦀');
+        round_trip(String::from("ferris"));
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(
+            u32::decode(&mut &[1, 2][..]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(bool::decode(&mut &[2][..]), Err(DecodeError::InvalidValue));
+        assert_eq!(
+            String::decode(&mut &[2, 0, 0, 0, 0xFF, 0xFF][..]),
+            Err(DecodeError::InvalidValue)
+        );
+    }
+}
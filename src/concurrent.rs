@@ -0,0 +1,12 @@
+//! Lock-free trees shared between threads, behind the `concurrent` feature
+//!
+//! Every operation takes `&self`, so a tree can be put in an `Arc`, or
+//! borrowed by scoped threads, and updated from all of them at once. Unlinked
+//! nodes are freed through epoch-based reclamation once no thread can still
+//! be reading them.
+
+mod bst;
+mod skip_list_map;
+
+pub use bst::Bst;
+pub use skip_list_map::{SkipListMap, SkipListMapIterator};
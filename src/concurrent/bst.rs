@@ -0,0 +1,565 @@
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned, Shared};
+use std::{
+    cmp::Ordering,
+    fmt,
+    sync::atomic::Ordering::{AcqRel, Acquire, Relaxed},
+};
+
+/// Set on the edge to a leaf whose removal has started
+const FLAG: usize = 1;
+
+/// Set on the edge to the sibling of a leaf being removed, so that nothing
+/// is inserted below it before its parent is bypassed
+const TAG: usize = 2;
+
+/// A key, or the key of a sentinel, greater than every element
+#[derive(Clone)]
+enum Key<T> {
+    Element(T),
+    Infinity,
+}
+
+impl<T: Ord> Key<T> {
+    /// Orders `data` against `self`, elements always being below sentinels
+    fn cmp_element(&self, data: &T) -> Ordering {
+        match self {
+            Key::Element(key) => key.cmp(data),
+            Key::Infinity => Ordering::Greater,
+        }
+    }
+}
+
+/// An internal node routing searches, or a leaf holding an element when both
+/// children are null
+struct Node<T> {
+    key: Key<T>,
+    left: Atomic<Node<T>>,
+    right: Atomic<Node<T>>,
+}
+
+impl<T: Ord> Node<T> {
+    fn leaf(key: Key<T>) -> Self {
+        Self {
+            key,
+            left: Atomic::null(),
+            right: Atomic::null(),
+        }
+    }
+
+    /// Returns the edge a search for `data` follows
+    fn child(&self, data: &T) -> &Atomic<Node<T>> {
+        if self.key.cmp_element(data) == Ordering::Greater {
+            &self.left
+        } else {
+            &self.right
+        }
+    }
+
+    fn element(&self) -> Option<&T> {
+        match &self.key {
+            Key::Element(key) => Some(key),
+            Key::Infinity => None,
+        }
+    }
+}
+
+/// The end of a search: the leaf reached, its parent, and above them the
+/// lowest edge that is not tagged, from `ancestor` to `successor`
+struct Seek<'g, T> {
+    ancestor: &'g Node<T>,
+    successor: Shared<'g, Node<T>>,
+    parent: &'g Node<T>,
+    leaf: Shared<'g, Node<T>>,
+    /// the edge from `parent` to `leaf`, marks included
+    leaf_edge: Shared<'g, Node<T>>,
+}
+
+/// A lock-free binary search tree behaving like a set, for many threads at
+/// once
+///
+/// The tree is external: elements live in the leaves and internal nodes only
+/// route searches, so an insertion swaps one leaf for a small subtree and a
+/// removal bypasses a leaf together with its parent, each with a single
+/// compare-and-swap. Following Natarajan and Mittal, a removal first flags
+/// the edge to its leaf, which is where it takes effect, then tags the edge to
+/// the sibling and swings the edge above the parent to the sibling. Threads
+/// which run into marked edges help finishing the removal, so no thread waits
+/// on another. The tree is not rebalanced.
+pub struct Bst<T>
+where
+    T: Ord,
+{
+    /// sentinel whose left child is the second sentinel, with every element
+    /// in the left subtree of the latter
+    root: Node<T>,
+}
+
+impl<T> Bst<T>
+where
+    T: Ord + Clone + Send + 'static,
+{
+    pub fn new() -> Self {
+        let inner = Node {
+            key: Key::Infinity,
+            left: Atomic::new(Node::leaf(Key::Infinity)),
+            right: Atomic::new(Node::leaf(Key::Infinity)),
+        };
+
+        Self {
+            root: Node {
+                key: Key::Infinity,
+                left: Atomic::new(inner),
+                right: Atomic::new(Node::leaf(Key::Infinity)),
+            },
+        }
+    }
+
+    /// Walks down to the leaf where `data` is or would be
+    fn seek<'g>(&'g self, data: &T, guard: &'g Guard) -> Seek<'g, T> {
+        let successor = self.root.left.load(Acquire, guard);
+        // SAFETY: the sentinels are never removed
+        let inner = unsafe { successor.deref() };
+        let leaf_edge = inner.left.load(Acquire, guard);
+
+        let mut seek = Seek {
+            ancestor: &self.root,
+            successor,
+            parent: inner,
+            leaf: leaf_edge.with_tag(0),
+            leaf_edge,
+        };
+
+        loop {
+            // SAFETY: nodes reached while pinned are only freed after unpinning
+            let leaf = unsafe { seek.leaf.deref() };
+            let next_edge = leaf.child(data).load(Acquire, guard);
+            if next_edge.is_null() {
+                return seek;
+            }
+
+            if seek.leaf_edge.tag() & TAG == 0 {
+                seek.ancestor = seek.parent;
+                seek.successor = seek.leaf;
+            }
+            seek.parent = leaf;
+            seek.leaf = next_edge.with_tag(0);
+            seek.leaf_edge = next_edge;
+        }
+    }
+
+    /// Returns `true` if an element equal to `data` is stored
+    pub fn contains(&self, data: &T) -> bool {
+        let guard = &epoch::pin();
+        let seek = self.seek(data, guard);
+
+        // SAFETY: protected by the guard
+        let leaf = unsafe { seek.leaf.deref() };
+        seek.leaf_edge.tag() & FLAG == 0 && leaf.element() == Some(data)
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&self, data: T) -> bool {
+        let guard = &epoch::pin();
+        let mut new_leaf = Owned::new(Node::leaf(Key::Element(data)));
+
+        loop {
+            let data = new_leaf.element().expect("leaves hold elements");
+            let seek = self.seek(data, guard);
+            // SAFETY: protected by the guard
+            let leaf = unsafe { seek.leaf.deref() };
+
+            if leaf.element() == Some(data) {
+                if seek.leaf_edge.tag() & FLAG == 0 {
+                    return false;
+                }
+                // the element is already removed, finish that before adding it
+                self.cleanup(data, &seek, guard);
+                continue;
+            }
+
+            // the new internal node routes between the leaf found and the new one
+            let (key, new_leaf_is_left) = match leaf.key.cmp_element(data) {
+                Ordering::Greater => (leaf.key.clone(), true),
+                _ => (Key::Element(data.clone()), false),
+            };
+            let edge = seek.parent.child(data);
+            let inner = Owned::new(Node {
+                key,
+                left: Atomic::null(),
+                right: Atomic::null(),
+            });
+
+            let new_leaf_shared = new_leaf.into_shared(guard);
+            let (left, right) = if new_leaf_is_left {
+                (new_leaf_shared, seek.leaf)
+            } else {
+                (seek.leaf, new_leaf_shared)
+            };
+            inner.left.store(left, Relaxed);
+            inner.right.store(right, Relaxed);
+
+            match edge.compare_exchange(seek.leaf, inner, AcqRel, Acquire, guard) {
+                Ok(_) => return true,
+                Err(failure) => {
+                    // SAFETY: the new leaf was never published
+                    new_leaf = unsafe { new_leaf_shared.into_owned() };
+
+                    let current = failure.current;
+                    if current.with_tag(0) == seek.leaf && current.tag() != 0 {
+                        let data = new_leaf.element().expect("leaves hold elements");
+                        self.cleanup(data, &seek, guard);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes `data` from the tree, returning whether it was present
+    pub fn remove(&self, data: &T) -> bool {
+        let guard = &epoch::pin();
+        let mut flagged = None;
+
+        loop {
+            let seek = self.seek(data, guard);
+
+            if let Some(leaf) = flagged {
+                // a helping thread may have finished the removal already
+                if seek.leaf != leaf || self.cleanup(data, &seek, guard) {
+                    return true;
+                }
+                continue;
+            }
+
+            // SAFETY: protected by the guard
+            let leaf = unsafe { seek.leaf.deref() };
+            if leaf.element() != Some(data) {
+                return false;
+            }
+            if seek.leaf_edge.tag() & FLAG != 0 {
+                // a concurrent removal has already taken effect
+                self.cleanup(data, &seek, guard);
+                return false;
+            }
+
+            let edge = seek.parent.child(data);
+            let flagged_leaf = seek.leaf.with_tag(FLAG);
+            match edge.compare_exchange(seek.leaf, flagged_leaf, AcqRel, Acquire, guard) {
+                Ok(_) => {
+                    flagged = Some(seek.leaf);
+                    if self.cleanup(data, &seek, guard) {
+                        return true;
+                    }
+                }
+                Err(failure) => {
+                    let current = failure.current;
+                    if current.with_tag(0) == seek.leaf && current.tag() != 0 {
+                        self.cleanup(data, &seek, guard);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bypasses `seek.parent` and the flagged leaf under it, returning whether
+    /// this thread did it
+    fn cleanup(&self, data: &T, seek: &Seek<'_, T>, guard: &Guard) -> bool {
+        let parent = seek.parent;
+        let (child_edge, mut sibling_edge) = if parent.key.cmp_element(data) == Ordering::Greater {
+            (&parent.left, &parent.right)
+        } else {
+            (&parent.right, &parent.left)
+        };
+
+        // the flagged leaf may be the other child, the one towards `data` is then kept
+        if child_edge.load(Acquire, guard).tag() & FLAG == 0 {
+            sibling_edge = child_edge;
+        }
+
+        // once tagged, the edge to the kept node can no longer change
+        let sibling = sibling_edge.fetch_or(TAG, AcqRel, guard);
+        let swung = seek
+            .ancestor
+            .child(data)
+            .compare_exchange(
+                seek.successor,
+                sibling.with_tag(sibling.tag() & FLAG),
+                AcqRel,
+                Acquire,
+                guard,
+            )
+            .is_ok();
+
+        if swung {
+            // SAFETY: this thread has just unlinked the nodes
+            unsafe { Self::retire(data, seek, sibling.with_tag(0), guard) };
+        }
+        swung
+    }
+
+    /// Frees the nodes a cleanup has unlinked: the internal nodes on the path
+    /// from `seek.successor` to `seek.parent`, and the flagged leaf hanging
+    /// from each of them
+    ///
+    /// # Safety
+    ///
+    /// The calling thread must have just replaced the path with `kept`.
+    unsafe fn retire(data: &T, seek: &Seek<'_, T>, kept: Shared<'_, Node<T>>, guard: &Guard) {
+        let mut node = seek.successor;
+
+        loop {
+            // SAFETY: protected by the guard, and the path is marked so it
+            // kept its shape since the search
+            let inner = unsafe { node.deref() };
+            let (towards, away) = if inner.key.cmp_element(data) == Ordering::Greater {
+                (&inner.left, &inner.right)
+            } else {
+                (&inner.right, &inner.left)
+            };
+            let towards = towards.load(Acquire, guard).with_tag(0);
+            let away = away.load(Acquire, guard).with_tag(0);
+
+            // SAFETY: no thread can reach these nodes anymore
+            unsafe { guard.defer_destroy(node) };
+            if std::ptr::eq(inner, seek.parent) {
+                let removed = if towards == kept { away } else { towards };
+                unsafe { guard.defer_destroy(removed) };
+                return;
+            }
+            unsafe { guard.defer_destroy(away) };
+            node = towards;
+        }
+    }
+
+    /// Returns the elements in order
+    ///
+    /// While other threads update the tree, the result may or may not reflect
+    /// each of their changes, but it is always sorted and free of duplicates:
+    /// a removal can move a subtree the traversal still has to visit under a
+    /// wider range of keys, so the elements are sorted once collected.
+    pub fn to_vec(&self) -> Vec<T> {
+        let guard = &epoch::pin();
+        let mut elements = Vec::new();
+        let mut stack = vec![self.root.left.load(Acquire, guard)];
+
+        while let Some(edge) = stack.pop() {
+            // SAFETY: protected by the guard
+            let node = unsafe { edge.with_tag(0).deref() };
+            let (left, right) = (
+                node.left.load(Acquire, guard),
+                node.right.load(Acquire, guard),
+            );
+
+            if left.is_null() {
+                if let (Some(data), 0) = (node.element(), edge.tag() & FLAG) {
+                    elements.push(data.clone());
+                }
+            } else {
+                stack.push(right);
+                stack.push(left);
+            }
+        }
+        elements.sort_unstable();
+        elements.dedup();
+        elements
+    }
+
+    /// Returns the number of elements, counted by a traversal which is as
+    /// consistent as [`Self::to_vec`]
+    pub fn len(&self) -> usize {
+        self.to_vec().len()
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Bst<T>
+where
+    T: Ord,
+{
+    fn drop(&mut self) {
+        // SAFETY: `&mut self` means no other thread is using the tree
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut stack = vec![
+                self.root.left.load(Relaxed, guard),
+                self.root.right.load(Relaxed, guard),
+            ];
+
+            while let Some(edge) = stack.pop() {
+                let node = edge.with_tag(0).into_owned();
+                for child in [&node.left, &node.right] {
+                    let child = child.load(Relaxed, guard);
+                    if !child.is_null() {
+                        stack.push(child);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for Bst<T>
+where
+    T: Ord + Clone + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Bst<T>
+where
+    T: Ord + Clone + Send + 'static + fmt::Debug,
+{
+    /// Formats a snapshot of the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.to_vec()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for Bst<T>
+where
+    T: Ord + Clone + Send + 'static,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let tree = Self::new();
+        for data in iter {
+            tree.insert(data);
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering::SeqCst},
+        thread,
+    };
+
+    #[test]
+    fn test_single_thread() {
+        let tree: Bst<u32> = prequel();
+
+        assert!(!tree.insert(21));
+        assert!(tree.insert(22));
+        assert!(tree.contains(&22));
+        assert!(!tree.contains(&23));
+        assert!(tree.remove(&16));
+        assert!(!tree.remove(&16));
+        assert!(tree.remove(&70));
+        assert_eq!(tree.to_vec(), vec![3, 7, 21, 22, 28, 36]);
+        assert_eq!(tree.len(), 6);
+
+        for data in [3, 7, 21, 22, 28, 36] {
+            assert!(tree.remove(&data));
+        }
+        assert!(tree.is_empty());
+        assert!(tree.insert(1));
+        assert_eq!(format!("{tree:?}"), "{1}");
+    }
+
+    #[test]
+    fn test_disjoint_threads() {
+        let tree = Bst::new();
+        let keys = |thread: u32| (0..2_000).map(move |i| i * 7919 % 2_003 * 4 + thread);
+
+        thread::scope(|scope| {
+            for thread in 0..4 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for key in keys(thread) {
+                        assert!(tree.insert(key));
+                    }
+                    for key in keys(thread).step_by(2) {
+                        assert!(tree.remove(&key));
+                    }
+                });
+            }
+        });
+
+        let elements = tree.to_vec();
+        assert_eq!(elements.len(), 4_000);
+        assert!(elements.windows(2).all(|pair| pair[0] < pair[1]));
+        for thread in 0..4 {
+            for (i, key) in keys(thread).enumerate() {
+                assert_eq!(tree.contains(&key), i % 2 == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_contended_keys() {
+        let tree = Bst::new();
+        let inserted = AtomicUsize::new(0);
+        let removed = AtomicUsize::new(0);
+
+        // every thread fights over the same few keys
+        thread::scope(|scope| {
+            for thread in 0..4u64 {
+                let (tree, inserted, removed) = (&tree, &inserted, &removed);
+                scope.spawn(move || {
+                    for i in 0..5_000u64 {
+                        let key = (i * 31 + thread * 17) % 16;
+                        if (i + thread) % 2 == 0 {
+                            if tree.insert(key) {
+                                inserted.fetch_add(1, SeqCst);
+                            }
+                        } else if tree.remove(&key) {
+                            removed.fetch_add(1, SeqCst);
+                        }
+                    }
+                });
+            }
+        });
+
+        let elements = tree.to_vec();
+        assert_eq!(inserted.load(SeqCst) - removed.load(SeqCst), elements.len());
+        assert!(elements.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_snapshot_while_updating() {
+        let tree: Bst<u64> = (0..64).collect();
+        let done = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for thread in 0..6u64 {
+                let (tree, done) = (&tree, &done);
+                scope.spawn(move || {
+                    for i in 0..20_000u64 {
+                        let key = (i * 37 + thread * 11) % 64;
+                        match (i + thread) % 3 {
+                            0 => {
+                                tree.insert(key);
+                            }
+                            1 => {
+                                tree.remove(&key);
+                            }
+                            _ => {
+                                tree.contains(&key);
+                            }
+                        }
+                    }
+                    done.fetch_add(1, SeqCst);
+                });
+            }
+
+            for _ in 0..2 {
+                let (tree, done) = (&tree, &done);
+                scope.spawn(move || {
+                    while done.load(SeqCst) < 6 {
+                        let elements = tree.to_vec();
+                        assert!(elements.windows(2).all(|pair| pair[0] < pair[1]));
+                        assert!(elements.iter().all(|&key| key < 64));
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,517 @@
+use crate::treap::XorShift;
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned, Shared};
+use std::{
+    borrow::Borrow,
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{
+        AtomicU64, AtomicUsize,
+        Ordering::{AcqRel, Acquire, Relaxed},
+    },
+};
+
+/// Set on the links out of a node once its removal has started
+const MARK: usize = 1;
+
+/// Levels a node can appear on, enough for far more than `u32::MAX` entries
+const MAX_LEVELS: usize = 32;
+
+type Tower<K, V> = [Atomic<Node<K, V>>];
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    /// next node on each level this node is on, from the bottom one up
+    tower: Box<Tower<K, V>>,
+    /// levels the node is linked on, plus one while it is being inserted
+    refs: AtomicUsize,
+}
+
+impl<K, V> Node<K, V> {
+    /// Drops a reference to `node`, freeing it once it is unreachable
+    ///
+    /// # Safety
+    ///
+    /// The reference, from a link or from the inserting thread, must be held
+    /// by the caller and given up.
+    unsafe fn release(node: Shared<'_, Self>, guard: &Guard) {
+        // SAFETY: the reference released keeps the node alive until now
+        unsafe {
+            if node.deref().refs.fetch_sub(1, AcqRel) == 1 {
+                guard.defer_destroy(node);
+            }
+        }
+    }
+}
+
+/// The nodes around a key on every level: the tower of the last node before
+/// it, and the first node not before it
+struct Position<'g, K, V> {
+    preds: [&'g Tower<K, V>; MAX_LEVELS],
+    succs: [Shared<'g, Node<K, V>>; MAX_LEVELS],
+}
+
+/// A lock-free ordered map made of linked lists stacked on top of each
+/// other, for many threads at once
+///
+/// Like [`crate::SkipList`], every entry is on the bottom level and each
+/// entry on a level is also on the one above with probability one half.
+/// Entries are linked in with a compare-and-swap on the bottom level, which
+/// is where an insertion takes effect, then on the levels above. A removal
+/// marks the links out of the node from the top level down, taking effect
+/// when it marks the bottom one, and searches unlink every marked node they
+/// step over, so no thread waits on another.
+///
+/// Values are cloned out of the map, as another thread may remove an entry
+/// at any time. Iteration is weakly consistent: it yields the entries
+/// in order, and sees each concurrent update or not.
+pub struct SkipListMap<K, V>
+where
+    K: Ord,
+{
+    /// first node on each level, from the bottom one up
+    head: [Atomic<Node<K, V>>; MAX_LEVELS],
+    len: AtomicUsize,
+    /// drawn from and advanced by every insertion
+    seed: AtomicU64,
+}
+
+impl<K, V> SkipListMap<K, V>
+where
+    K: Ord + Send + 'static,
+    V: Send + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            head: std::array::from_fn(|_| Atomic::null()),
+            len: AtomicUsize::new(0),
+            seed: AtomicU64::new(XorShift::from_entropy().next_u64()),
+        }
+    }
+
+    /// Returns the number of entries, which may be stale while other threads
+    /// update the map
+    pub fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+
+    /// Returns `true` if the map contains no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Draws the number of levels of a new node, each one above the bottom
+    /// with probability one half
+    fn random_levels(&self) -> usize {
+        // mixes successive seeds, as every thread draws from the same sequence
+        let mut bits = self.seed.fetch_add(0x9E37_79B9_7F4A_7C15, Relaxed);
+        bits = (bits ^ (bits >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        bits = (bits ^ (bits >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        bits ^= bits >> 31;
+        (1 + bits.trailing_ones() as usize).min(MAX_LEVELS)
+    }
+
+    /// Finds the nodes around `key` on every level, unlinking the marked
+    /// nodes on the way
+    fn search<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Position<'g, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        'retry: loop {
+            let mut position = Position {
+                preds: [&self.head[..]; MAX_LEVELS],
+                succs: [Shared::null(); MAX_LEVELS],
+            };
+            let mut pred: &'g Tower<K, V> = &self.head;
+
+            for level in (0..MAX_LEVELS).rev() {
+                let mut curr = pred[level].load(Acquire, guard);
+                if curr.tag() == MARK {
+                    // the predecessor is being removed, so its links are stale
+                    continue 'retry;
+                }
+
+                // SAFETY: nodes reached while pinned are only freed after unpinning
+                while let Some(node) = unsafe { curr.as_ref() } {
+                    let succ = node.tower[level].load(Acquire, guard);
+                    if succ.tag() == MARK {
+                        let unlinked = succ.with_tag(0);
+                        if pred[level]
+                            .compare_exchange(curr, unlinked, AcqRel, Acquire, guard)
+                            .is_err()
+                        {
+                            continue 'retry;
+                        }
+                        // SAFETY: the link just removed held a reference
+                        unsafe { Node::release(curr, guard) };
+                        curr = unlinked;
+                    } else if node.key.borrow() < key {
+                        pred = &node.tower;
+                        curr = succ;
+                    } else {
+                        break;
+                    }
+                }
+
+                position.preds[level] = pred;
+                position.succs[level] = curr;
+            }
+            return position;
+        }
+    }
+
+    /// Returns the node holding `key`, if any
+    fn find<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g Node<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let position = self.search(key, guard);
+        // SAFETY: protected by the guard
+        unsafe { position.succs[0].as_ref() }.filter(|node| node.key.borrow() == key)
+    }
+
+    /// Returns `true` if the map contains `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key, &epoch::pin()).is_some()
+    }
+
+    /// Returns a clone of the value of `key`, if any
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        V: Clone,
+    {
+        let guard = &epoch::pin();
+        self.find(key, guard).map(|node| node.value.clone())
+    }
+
+    /// Inserts `key` with `value`, returning `false` and leaving the map
+    /// unchanged if it already contains `key`
+    pub fn insert(&self, key: K, value: V) -> bool {
+        let guard = &epoch::pin();
+        let levels = self.random_levels();
+        let node = Owned::new(Node {
+            key,
+            value,
+            tower: (0..levels).map(|_| Atomic::null()).collect(),
+            refs: AtomicUsize::new(1),
+        })
+        .into_shared(guard);
+        // SAFETY: the node stays alive while this thread holds a reference
+        let new = unsafe { node.deref() };
+
+        let mut position = loop {
+            let position = self.search(&new.key, guard);
+            // SAFETY: protected by the guard
+            if let Some(found) = unsafe { position.succs[0].as_ref() } {
+                if found.key == new.key {
+                    // SAFETY: the node was never published
+                    drop(unsafe { node.into_owned() });
+                    return false;
+                }
+            }
+
+            for (link, succ) in new.tower.iter().zip(position.succs) {
+                link.store(succ, Relaxed);
+            }
+            new.refs.fetch_add(1, Relaxed);
+            self.len.fetch_add(1, Relaxed);
+            if position.preds[0][0]
+                .compare_exchange(position.succs[0], node, AcqRel, Acquire, guard)
+                .is_ok()
+            {
+                break position;
+            }
+            new.refs.fetch_sub(1, Relaxed);
+            self.len.fetch_sub(1, Relaxed);
+        };
+
+        'levels: for level in 1..levels {
+            loop {
+                let link = &new.tower[level];
+                // the links out of the node only change when it gets marked
+                if link
+                    .compare_exchange(
+                        link.load(Acquire, guard).with_tag(0),
+                        position.succs[level],
+                        AcqRel,
+                        Acquire,
+                        guard,
+                    )
+                    .is_err()
+                {
+                    break 'levels;
+                }
+
+                new.refs.fetch_add(1, Relaxed);
+                if position.preds[level][level]
+                    .compare_exchange(position.succs[level], node, AcqRel, Acquire, guard)
+                    .is_ok()
+                {
+                    break;
+                }
+                new.refs.fetch_sub(1, Relaxed);
+                position = self.search(&new.key, guard);
+            }
+        }
+
+        // a removal may have passed the levels before they were linked
+        if new.tower[levels - 1].load(Acquire, guard).tag() == MARK {
+            self.search(&new.key, guard);
+        }
+        // SAFETY: gives up the reference taken on allocation
+        unsafe { Node::release(node, guard) };
+        true
+    }
+
+    /// Removes `key` from the map, returning a clone of its value if it was
+    /// present
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        V: Clone,
+    {
+        let guard = &epoch::pin();
+        let node = self.find(key, guard)?;
+
+        for link in node.tower[1..].iter().rev() {
+            link.fetch_or(MARK, AcqRel, guard);
+        }
+        if node.tower[0].fetch_or(MARK, AcqRel, guard).tag() == MARK {
+            // a concurrent removal got there first
+            return None;
+        }
+        self.len.fetch_sub(1, Relaxed);
+
+        let value = node.value.clone();
+        self.search(key, guard);
+        Some(value)
+    }
+
+    /// Returns an iterator over clones of the entries in order
+    ///
+    /// The iterator keeps the current thread pinned, which holds back the
+    /// freeing of removed nodes until it is dropped.
+    pub fn iter(&self) -> SkipListMapIterator<'_, K, V> {
+        let guard = epoch::pin();
+        let next = self.head[0].load(Acquire, &guard).as_raw();
+        SkipListMapIterator {
+            guard,
+            next,
+            map: PhantomData,
+        }
+    }
+}
+
+/// Iterator over clones of the entries of a [`SkipListMap`], in order
+pub struct SkipListMapIterator<'a, K, V>
+where
+    K: Ord,
+{
+    guard: Guard,
+    next: *const Node<K, V>,
+    map: PhantomData<&'a SkipListMap<K, V>>,
+}
+
+impl<K, V> Iterator for SkipListMapIterator<'_, K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // SAFETY: the node was reached through the guard, pinned ever since
+            let node = unsafe { self.next.as_ref() }?;
+            let next = node.tower[0].load(Acquire, &self.guard);
+            self.next = next.with_tag(0).as_raw();
+
+            // removed nodes still link to the rest of the level
+            if next.tag() != MARK {
+                return Some((node.key.clone(), node.value.clone()));
+            }
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a SkipListMap<K, V>
+where
+    K: Ord + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    type Item = (K, V);
+    type IntoIter = SkipListMapIterator<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V> Drop for SkipListMap<K, V>
+where
+    K: Ord,
+{
+    fn drop(&mut self) {
+        // SAFETY: `&mut self` means no other thread is using the map
+        unsafe {
+            let guard = epoch::unprotected();
+
+            // a node is freed on the lowest level it is linked on, once every
+            // link to it has been counted
+            for level in (0..MAX_LEVELS).rev() {
+                let mut curr = self.head[level].load(Relaxed, guard);
+                while let Some(node) = curr.as_ref() {
+                    let next = node.tower[level].load(Relaxed, guard).with_tag(0);
+                    if node.refs.fetch_sub(1, Relaxed) == 1 {
+                        drop(curr.into_owned());
+                    }
+                    curr = next;
+                }
+            }
+        }
+    }
+}
+
+impl<K, V> Default for SkipListMap<K, V>
+where
+    K: Ord + Send + 'static,
+    V: Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for SkipListMap<K, V>
+where
+    K: Ord + Clone + Send + 'static + fmt::Debug,
+    V: Clone + Send + 'static + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for SkipListMap<K, V>
+where
+    K: Ord + Send + 'static,
+    V: Send + 'static,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let map = Self::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::PREQUEL;
+    use std::thread;
+
+    fn prequel_map() -> SkipListMap<u32, String> {
+        PREQUEL
+            .into_iter()
+            .map(|key| (key, key.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_single_thread() {
+        let map = prequel_map();
+
+        assert_eq!(map.len(), 7);
+        assert!(!map.insert(21, "twenty-one".to_string()));
+        assert_eq!(map.get(&21).as_deref(), Some("21"));
+        assert!(map.insert(22, "22".to_string()));
+        assert!(map.contains_key(&22));
+        assert!(!map.contains_key(&23));
+        assert_eq!(map.remove(&16).as_deref(), Some("16"));
+        assert_eq!(map.remove(&16), None);
+        assert_eq!(
+            map.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            vec![3, 7, 21, 22, 28, 36, 70]
+        );
+
+        for key in [3, 7, 21, 22, 28, 36, 70] {
+            assert!(map.remove(&key).is_some());
+        }
+        assert!(map.is_empty());
+        assert!(map.insert(1, "one".to_string()));
+        assert_eq!(format!("{map:?}"), r#"{1: "one"}"#);
+    }
+
+    #[test]
+    fn test_disjoint_threads() {
+        let map = SkipListMap::new();
+        let keys = |thread: u32| (0..2_000).map(move |i| i * 7919 % 2_003 * 4 + thread);
+
+        thread::scope(|scope| {
+            for thread in 0..4 {
+                let map = &map;
+                scope.spawn(move || {
+                    for key in keys(thread) {
+                        assert!(map.insert(key, key * 2));
+                    }
+                    for key in keys(thread).step_by(2) {
+                        assert_eq!(map.remove(&key), Some(key * 2));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(map.len(), 4_000);
+        let entries: Vec<_> = map.iter().collect();
+        assert_eq!(entries.len(), 4_000);
+        assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for thread in 0..4 {
+            for (i, key) in keys(thread).enumerate() {
+                assert_eq!(map.get(&key), (i % 2 == 1).then_some(key * 2));
+            }
+        }
+    }
+
+    #[test]
+    fn test_iterate_while_updating() {
+        let map = SkipListMap::new();
+
+        thread::scope(|scope| {
+            for thread in 0..3u64 {
+                let map = &map;
+                scope.spawn(move || {
+                    for i in 0..5_000u64 {
+                        let key = (i * 31 + thread * 17) % 64;
+                        if (i + thread) % 2 == 0 {
+                            map.insert(key, i);
+                        } else {
+                            map.remove(&key);
+                        }
+                    }
+                });
+            }
+
+            // snapshots stay sorted and free of duplicates under contention
+            let map = &map;
+            scope.spawn(move || {
+                for _ in 0..200 {
+                    let keys: Vec<_> = map.iter().map(|(key, _)| key).collect();
+                    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+                }
+            });
+        });
+
+        assert_eq!(map.iter().count(), map.len());
+    }
+}
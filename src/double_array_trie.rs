@@ -0,0 +1,379 @@
+use std::{error::Error, fmt};
+
+/// `check` of the units no node uses
+const FREE: u32 = u32::MAX;
+
+/// Code of the transition to the unit holding the value of a key ending at a
+/// node, the bytes having the codes from 1 to 256
+const END: u32 = 0;
+
+/// Why a [`DoubleArrayTrie`] could not be built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoubleArrayError {
+    /// The keys are not in strictly increasing order
+    UnsortedKeys,
+    /// The trie needs more units than `u32` can index
+    TooLarge,
+}
+
+impl fmt::Display for DoubleArrayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            DoubleArrayError::UnsortedKeys => "keys are not sorted and distinct",
+            DoubleArrayError::TooLarge => "too many nodes for a double array",
+        };
+        f.write_str(message)
+    }
+}
+
+impl Error for DoubleArrayError {}
+
+/// One slot of the double array
+#[derive(Clone, Copy)]
+struct Unit {
+    /// offset of the children of the node here, or for the unit reached by
+    /// `END`, the index of its value
+    base: u32,
+    /// the unit of the parent, or `FREE`
+    check: u32,
+}
+
+/// A read-only trie whose transitions are two array reads, for dictionaries
+/// that are looked up far more often than they change
+///
+/// Each node is a unit of one array, and the child of the node at `s` for the
+/// code `c` of a byte is the unit at `base[s] + c`, which really belongs to it
+/// if its `check` is `s`. Building packs the children of all nodes into the
+/// free units of the array, so the array is barely larger than the number of
+/// nodes, and walking a key takes a constant number of reads per byte without
+/// any search among children.
+///
+/// [`DoubleArrayTrie::common_prefix_search`] finds every key that starts a
+/// text in one walk, which is the lookup of tokenizers and morphological
+/// analyzers.
+#[derive(Clone)]
+pub struct DoubleArrayTrie<V> {
+    units: Vec<Unit>,
+    /// the values in the order of their keys
+    values: Vec<V>,
+}
+
+impl<V> DoubleArrayTrie<V> {
+    /// Builds the trie of `entries`, whose keys must be sorted and distinct
+    ///
+    /// The nodes are placed from the root down, each one at the lowest base
+    /// where all its children fit in free units.
+    pub fn build<K, I>(entries: I) -> Result<Self, DoubleArrayError>
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        for (key, value) in entries {
+            if keys
+                .last()
+                .is_some_and(|last: &K| last.as_ref() >= key.as_ref())
+            {
+                return Err(DoubleArrayError::UnsortedKeys);
+            }
+            keys.push(key);
+            values.push(value);
+        }
+
+        let mut builder = Builder {
+            units: vec![Unit {
+                base: 0,
+                check: FREE,
+            }],
+            first_free: 1,
+        };
+        // nodes left to place the children of, with the range of their keys
+        // and the number of bytes they stand for
+        let mut stack = vec![(0, 0, keys.len(), 0)];
+        let mut codes = Vec::new();
+
+        while let Some((unit, start, end, depth)) = stack.pop() {
+            codes.clear();
+            for key in &keys[start..end] {
+                let code = key
+                    .as_ref()
+                    .get(depth)
+                    .map_or(END, |&byte| u32::from(byte) + 1);
+                if codes.last() != Some(&code) {
+                    codes.push(code);
+                }
+            }
+            if codes.is_empty() {
+                continue;
+            }
+
+            let base = builder.place(unit, &codes)?;
+            builder.units[unit as usize].base = base;
+
+            let mut first = start;
+            while first < end {
+                let key = keys[first].as_ref();
+                let Some(&byte) = key.get(depth) else {
+                    // a shorter key sorts before the keys it is a prefix of
+                    builder.units[(base + END) as usize].base = first as u32;
+                    first += 1;
+                    continue;
+                };
+
+                let mut last = first + 1;
+                while keys[last..end]
+                    .first()
+                    .is_some_and(|key| key.as_ref()[depth] == byte)
+                {
+                    last += 1;
+                }
+                stack.push((base + u32::from(byte) + 1, first, last, depth + 1));
+                first = last;
+            }
+        }
+
+        Ok(Self {
+            units: builder.units,
+            values,
+        })
+    }
+
+    /// Returns the number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the trie contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the number of units in the array, used or free
+    pub fn unit_count(&self) -> usize {
+        self.units.len()
+    }
+
+    /// Returns the unit reached from `unit` by `code`
+    fn step(&self, unit: u32, code: u32) -> Option<u32> {
+        let next = self.units[unit as usize].base.checked_add(code)?;
+        let found = self.units.get(next as usize)?;
+        (found.check == unit).then_some(next)
+    }
+
+    /// Returns the value of the key ending at `unit`
+    fn value(&self, unit: u32) -> Option<&V> {
+        let end = self.step(unit, END)?;
+        Some(&self.values[self.units[end as usize].base as usize])
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get<K>(&self, key: K) -> Option<&V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let unit = key
+            .as_ref()
+            .iter()
+            .try_fold(0, |unit, &byte| self.step(unit, u32::from(byte) + 1))?;
+        self.value(unit)
+    }
+
+    /// Returns `true` if a value is stored under `key`
+    pub fn contains_key<K>(&self, key: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the keys that `text` starts with, as their lengths, with their
+    /// values, from the shortest
+    pub fn common_prefix_search<K>(&self, text: K) -> Vec<(usize, &V)>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut found = Vec::new();
+        let mut unit = 0;
+
+        for (len, &byte) in text.as_ref().iter().enumerate() {
+            if let Some(value) = self.value(unit) {
+                found.push((len, value));
+            }
+            match self.step(unit, u32::from(byte) + 1) {
+                Some(next) => unit = next,
+                None => return found,
+            }
+        }
+        if let Some(value) = self.value(unit) {
+            found.push((text.as_ref().len(), value));
+        }
+        found
+    }
+
+    /// Returns `true` if every used unit is the child of its `check` for a
+    /// valid code, and the units reached by the end code hold each value
+    /// exactly once
+    pub fn is_valid(&self) -> bool {
+        let mut seen = vec![false; self.values.len()];
+
+        for (index, unit) in self.units.iter().enumerate().skip(1) {
+            if unit.check == FREE {
+                continue;
+            }
+            let Some(parent) = self.units.get(unit.check as usize) else {
+                return false;
+            };
+            let parent_is_root = unit.check == 0;
+            if !parent_is_root && parent.check == FREE {
+                return false;
+            }
+
+            let Some(code) = (index as u32).checked_sub(parent.base) else {
+                return false;
+            };
+            if code > 256 {
+                return false;
+            }
+            if code == END {
+                let Some(seen) = seen.get_mut(unit.base as usize) else {
+                    return false;
+                };
+                if *seen {
+                    return false;
+                }
+                *seen = true;
+            }
+        }
+        seen.into_iter().all(|seen| seen)
+    }
+}
+
+struct Builder {
+    units: Vec<Unit>,
+    /// no unit before this one is free, except the root's
+    first_free: usize,
+}
+
+impl Builder {
+    /// Finds the lowest base putting every code of `codes` on a free unit,
+    /// and claims those units for the children of `parent`
+    fn place(&mut self, parent: u32, codes: &[u32]) -> Result<u32, DoubleArrayError> {
+        let is_free =
+            |units: &[Unit], index: usize| units.get(index).is_none_or(|unit| unit.check == FREE);
+
+        // the first code has to land on a free unit, so the search starts
+        // where it would land on the first free one
+        let mut base = self.first_free.saturating_sub(codes[0] as usize).max(1);
+        while !codes
+            .iter()
+            .all(|&code| is_free(&self.units, base + code as usize))
+        {
+            base += 1;
+        }
+
+        let last = base + *codes.last().expect("codes is not empty") as usize;
+        if last >= FREE as usize {
+            return Err(DoubleArrayError::TooLarge);
+        }
+        if last >= self.units.len() {
+            self.units.resize(
+                last + 1,
+                Unit {
+                    base: 0,
+                    check: FREE,
+                },
+            );
+        }
+        for &code in codes {
+            self.units[base + code as usize].check = parent;
+        }
+        while self.first_free < self.units.len() && !is_free(&self.units, self.first_free) {
+            self.first_free += 1;
+        }
+        Ok(base as u32)
+    }
+}
+
+impl<V> fmt::Debug for DoubleArrayTrie<V> {
+    /// Formats the number of keys and units
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DoubleArrayTrie")
+            .field("len", &self.values.len())
+            .field("units", &self.units.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel_words;
+
+    fn prequel_trie() -> DoubleArrayTrie<u32> {
+        let mut entries: Vec<_> = prequel_words();
+        entries.sort_unstable();
+        DoubleArrayTrie::build(entries).unwrap()
+    }
+
+    #[test]
+    fn test_build_get() {
+        let trie = prequel_trie();
+
+        assert!(trie.is_valid());
+        assert_eq!(trie.len(), 7);
+        assert_eq!(trie.get("ten"), Some(&1));
+        assert_eq!(trie.get(b"team"), Some(&6));
+        assert_eq!(trie.get("te"), None);
+        assert_eq!(trie.get("tens"), None);
+        assert_eq!(trie.get(""), None);
+        assert!(trie.contains_key("i"));
+
+        assert_eq!(
+            DoubleArrayTrie::build([("b", 0), ("a", 1)]).unwrap_err(),
+            DoubleArrayError::UnsortedKeys
+        );
+        assert!(DoubleArrayTrie::build([("a", 0), ("a", 1)]).is_err());
+        let empty = DoubleArrayTrie::<u32>::build(Vec::<(&str, u32)>::new()).unwrap();
+        assert!(empty.is_empty() && empty.is_valid());
+        assert_eq!(empty.get("a"), None);
+    }
+
+    #[test]
+    fn test_common_prefix_search() {
+        let trie = prequel_trie();
+
+        assert_eq!(
+            trie.common_prefix_search("inner"),
+            vec![(1, &5), (2, &4), (3, &3)]
+        );
+        assert_eq!(trie.common_prefix_search("teams"), vec![(3, &0), (4, &6)]);
+        assert!(trie.common_prefix_search("tx").is_empty());
+        assert!(trie.common_prefix_search("").is_empty());
+
+        let with_empty = DoubleArrayTrie::build([("", 'e'), ("a", 'a')]).unwrap();
+        assert_eq!(
+            with_empty.common_prefix_search("ab"),
+            vec![(0, &'e'), (1, &'a')]
+        );
+    }
+
+    #[test]
+    fn test_large_dictionary() {
+        let mut keys: Vec<String> = (0..20_000_u32)
+            .map(|number| format!("{}", number * 7919 % 100_003))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let trie = DoubleArrayTrie::build(keys.iter().zip(0..)).unwrap();
+
+        assert!(trie.is_valid());
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(trie.get(key), Some(&index));
+        }
+        assert_eq!(trie.get("100003"), None);
+        // the children of the nodes fill the array densely
+        let used = trie.units.iter().filter(|unit| unit.check != FREE).count();
+        assert!(used * 10 > trie.unit_count() * 9);
+    }
+}
@@ -0,0 +1,888 @@
+//! A persistent 2-3 finger tree, after Hinze and Paterson
+//!
+//! Elements are measured into a [`Monoid`], and every node caches the
+//! combined measure of what lies below it. Splitting at the point where a
+//! monotone predicate over the running measure turns true then takes
+//! `O(log n)`, which turns the same structure into an indexed sequence (with
+//! [`Size`]), a priority queue (with a maximum), or an ordered sequence (with
+//! the last key), depending on the measure.
+
+use std::{fmt, sync::Arc};
+
+/// An associative combination with an identity, used to summarize the
+/// elements of a [`FingerTree`]
+pub trait Monoid: Clone {
+    /// The measure of an empty sequence
+    fn identity() -> Self;
+
+    /// The measure of `self` followed by `other`
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// An element which has a measure in the monoid `M`
+pub trait Measured<M>
+where
+    M: Monoid,
+{
+    fn measure(&self) -> M;
+}
+
+/// Counts the elements, making a [`FingerTree`] an indexed sequence
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Size(pub usize);
+
+impl Monoid for Size {
+    fn identity() -> Self {
+        Size(0)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Size(self.0 + other.0)
+    }
+}
+
+impl<T> Measured<Size> for T {
+    fn measure(&self) -> Size {
+        Size(1)
+    }
+}
+
+/// An element, or a node of two or three items one level further down
+enum Item<T, M> {
+    Leaf(Arc<T>),
+    Branch(Arc<Branch<T, M>>),
+}
+
+type Items<T, M> = Vec<Item<T, M>>;
+
+struct Branch<T, M> {
+    /// combined measure of the children
+    measure: M,
+    children: Vec<Item<T, M>>,
+}
+
+enum Tree<T, M> {
+    Empty,
+    Single(Item<T, M>),
+    Deep(Arc<Deep<T, M>>),
+}
+
+struct Deep<T, M> {
+    /// combined measure of the whole tree
+    measure: M,
+    /// one to four items at each end, the fingers
+    prefix: Vec<Item<T, M>>,
+    /// a tree of branches over the items of this level
+    middle: Tree<T, M>,
+    suffix: Vec<Item<T, M>>,
+}
+
+impl<T, M> Clone for Item<T, M> {
+    fn clone(&self) -> Self {
+        match self {
+            Item::Leaf(data) => Item::Leaf(Arc::clone(data)),
+            Item::Branch(branch) => Item::Branch(Arc::clone(branch)),
+        }
+    }
+}
+
+impl<T, M> Clone for Tree<T, M> {
+    fn clone(&self) -> Self {
+        match self {
+            Tree::Empty => Tree::Empty,
+            Tree::Single(item) => Tree::Single(item.clone()),
+            Tree::Deep(deep) => Tree::Deep(Arc::clone(deep)),
+        }
+    }
+}
+
+fn measure_all<T: Measured<M>, M: Monoid>(items: &[Item<T, M>]) -> M {
+    items.iter().fold(M::identity(), |measure, item| {
+        measure.combine(&item.measure())
+    })
+}
+
+/// Splits `items` at the first one whose measure, added to `measure`, makes
+/// `predicate` true, or at the last one
+fn split_items<T, M, F>(
+    items: &[Item<T, M>],
+    predicate: &F,
+    measure: &M,
+) -> (Items<T, M>, Item<T, M>, Items<T, M>)
+where
+    T: Measured<M>,
+    M: Monoid,
+    F: Fn(&M) -> bool,
+{
+    let mut measure = measure.clone();
+
+    for (i, item) in items.iter().enumerate() {
+        measure = measure.combine(&item.measure());
+        if predicate(&measure) || i + 1 == items.len() {
+            return (items[..i].to_vec(), item.clone(), items[i + 1..].to_vec());
+        }
+    }
+    unreachable!("splitting an empty digit")
+}
+
+/// Like [`split_items`], returning the measure before the found item instead
+fn find_item<'a, T, M, F>(items: &'a [Item<T, M>], predicate: &F, measure: M) -> (M, &'a Item<T, M>)
+where
+    T: Measured<M>,
+    M: Monoid,
+    F: Fn(&M) -> bool,
+{
+    let mut measure = measure;
+
+    for (i, item) in items.iter().enumerate() {
+        let next = measure.combine(&item.measure());
+        if predicate(&next) || i + 1 == items.len() {
+            return (measure, item);
+        }
+        measure = next;
+    }
+    unreachable!("searching an empty digit")
+}
+
+/// Groups the two to twelve `items` into branches of three, and two at the end
+fn branches<T: Measured<M>, M: Monoid>(items: Vec<Item<T, M>>) -> Vec<Item<T, M>> {
+    let mut items = items.into_iter();
+    let mut remaining = items.len();
+    let mut branches = Vec::new();
+
+    while remaining > 0 {
+        let take = match remaining {
+            2 | 4 => 2,
+            _ => 3,
+        };
+        branches.push(Item::branch(items.by_ref().take(take).collect()));
+        remaining -= take;
+    }
+    branches
+}
+
+impl<T, M> Item<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    fn branch(children: Vec<Self>) -> Self {
+        Item::Branch(Arc::new(Branch {
+            measure: measure_all(&children),
+            children,
+        }))
+    }
+
+    fn measure(&self) -> M {
+        match self {
+            Item::Leaf(data) => <T as Measured<M>>::measure(data),
+            Item::Branch(branch) => branch.measure.clone(),
+        }
+    }
+
+    fn children(&self) -> &[Self] {
+        match self {
+            Item::Branch(branch) => &branch.children,
+            Item::Leaf(_) => unreachable!("leaves only sit on the top level"),
+        }
+    }
+
+    /// Returns the element under `self` at which `predicate` turns true
+    fn find<F: Fn(&M) -> bool>(&self, predicate: &F, measure: M) -> &T {
+        match self {
+            Item::Leaf(data) => data,
+            Item::Branch(branch) => {
+                let (measure, child) = find_item(&branch.children, predicate, measure);
+                child.find(predicate, measure)
+            }
+        }
+    }
+}
+
+impl<T, M> Tree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    fn deep(prefix: Vec<Item<T, M>>, middle: Self, suffix: Vec<Item<T, M>>) -> Self {
+        let measure = measure_all(&prefix)
+            .combine(&middle.measure())
+            .combine(&measure_all(&suffix));
+
+        Tree::Deep(Arc::new(Deep {
+            measure,
+            prefix,
+            middle,
+            suffix,
+        }))
+    }
+
+    fn from_items(items: Vec<Item<T, M>>) -> Self {
+        items
+            .into_iter()
+            .fold(Tree::Empty, |tree, item| tree.push_back(item))
+    }
+
+    fn measure(&self) -> M {
+        match self {
+            Tree::Empty => M::identity(),
+            Tree::Single(item) => item.measure(),
+            Tree::Deep(deep) => deep.measure.clone(),
+        }
+    }
+
+    fn push_front(&self, item: Item<T, M>) -> Self {
+        match self {
+            Tree::Empty => Tree::Single(item),
+            Tree::Single(other) => Self::deep(vec![item], Tree::Empty, vec![other.clone()]),
+            // a full finger keeps one item and sends three down as a branch
+            Tree::Deep(deep) if deep.prefix.len() == 4 => {
+                let mut prefix = deep.prefix.clone();
+                let pushed = prefix.split_off(1);
+                prefix.insert(0, item);
+
+                let middle = deep.middle.push_front(Item::branch(pushed));
+                Self::deep(prefix, middle, deep.suffix.clone())
+            }
+            Tree::Deep(deep) => {
+                let mut prefix = Vec::with_capacity(deep.prefix.len() + 1);
+                prefix.push(item);
+                prefix.extend(deep.prefix.iter().cloned());
+
+                Self::deep(prefix, deep.middle.clone(), deep.suffix.clone())
+            }
+        }
+    }
+
+    fn push_back(&self, item: Item<T, M>) -> Self {
+        match self {
+            Tree::Empty => Tree::Single(item),
+            Tree::Single(other) => Self::deep(vec![other.clone()], Tree::Empty, vec![item]),
+            Tree::Deep(deep) if deep.suffix.len() == 4 => {
+                let mut pushed = deep.suffix.clone();
+                let mut suffix = pushed.split_off(3);
+                suffix.push(item);
+
+                let middle = deep.middle.push_back(Item::branch(pushed));
+                Self::deep(deep.prefix.clone(), middle, suffix)
+            }
+            Tree::Deep(deep) => {
+                let mut suffix = deep.suffix.clone();
+                suffix.push(item);
+
+                Self::deep(deep.prefix.clone(), deep.middle.clone(), suffix)
+            }
+        }
+    }
+
+    fn front(&self) -> Option<&Item<T, M>> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(item) => Some(item),
+            Tree::Deep(deep) => deep.prefix.first(),
+        }
+    }
+
+    fn back(&self) -> Option<&Item<T, M>> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(item) => Some(item),
+            Tree::Deep(deep) => deep.suffix.last(),
+        }
+    }
+
+    /// Splits off the first item
+    fn view_front(&self) -> Option<(Item<T, M>, Self)> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(item) => Some((item.clone(), Tree::Empty)),
+            Tree::Deep(deep) => {
+                let rest = Self::deep_left(
+                    deep.prefix[1..].to_vec(),
+                    deep.middle.clone(),
+                    deep.suffix.clone(),
+                );
+                Some((deep.prefix[0].clone(), rest))
+            }
+        }
+    }
+
+    /// Splits off the last item
+    fn view_back(&self) -> Option<(Self, Item<T, M>)> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(item) => Some((Tree::Empty, item.clone())),
+            Tree::Deep(deep) => {
+                let (last, suffix) = deep.suffix.split_last().expect("fingers are not empty");
+                let rest =
+                    Self::deep_right(deep.prefix.clone(), deep.middle.clone(), suffix.to_vec());
+                Some((rest, last.clone()))
+            }
+        }
+    }
+
+    /// Builds a tree from a prefix which may be empty, borrowing a branch
+    /// from the middle to refill it
+    fn deep_left(prefix: Vec<Item<T, M>>, middle: Self, suffix: Vec<Item<T, M>>) -> Self {
+        if !prefix.is_empty() {
+            return Self::deep(prefix, middle, suffix);
+        }
+
+        match middle.view_front() {
+            None => Self::from_items(suffix),
+            Some((branch, middle)) => Self::deep(branch.children().to_vec(), middle, suffix),
+        }
+    }
+
+    /// Builds a tree from a suffix which may be empty, borrowing a branch
+    /// from the middle to refill it
+    fn deep_right(prefix: Vec<Item<T, M>>, middle: Self, suffix: Vec<Item<T, M>>) -> Self {
+        if !suffix.is_empty() {
+            return Self::deep(prefix, middle, suffix);
+        }
+
+        match middle.view_back() {
+            None => Self::from_items(prefix),
+            Some((middle, branch)) => Self::deep(prefix, middle, branch.children().to_vec()),
+        }
+    }
+
+    /// Concatenates `left`, the `items` and `right`
+    fn append(left: &Self, items: Vec<Item<T, M>>, right: &Self) -> Self {
+        match (left, right) {
+            (Tree::Empty, _) => items
+                .into_iter()
+                .rev()
+                .fold(right.clone(), |tree, item| tree.push_front(item)),
+            (_, Tree::Empty) => items
+                .into_iter()
+                .fold(left.clone(), |tree, item| tree.push_back(item)),
+            (Tree::Single(item), _) => {
+                Self::append(&Tree::Empty, items, right).push_front(item.clone())
+            }
+            (_, Tree::Single(item)) => {
+                Self::append(left, items, &Tree::Empty).push_back(item.clone())
+            }
+            // the facing fingers go one level down, grouped into branches
+            (Tree::Deep(left), Tree::Deep(right)) => {
+                let mut inner = left.suffix.clone();
+                inner.extend(items);
+                inner.extend(right.prefix.iter().cloned());
+
+                let middle = Self::append(&left.middle, branches(inner), &right.middle);
+                Self::deep(left.prefix.clone(), middle, right.suffix.clone())
+            }
+        }
+    }
+
+    /// Splits the non-empty tree around the item at which `predicate`, over
+    /// `measure` followed by the items, turns true
+    fn split<F: Fn(&M) -> bool>(&self, predicate: &F, measure: &M) -> (Self, Item<T, M>, Self) {
+        let deep = match self {
+            Tree::Empty => unreachable!("splitting an empty tree"),
+            Tree::Single(item) => return (Tree::Empty, item.clone(), Tree::Empty),
+            Tree::Deep(deep) => deep,
+        };
+
+        let prefix_measure = measure.combine(&measure_all(&deep.prefix));
+        if predicate(&prefix_measure) {
+            let (left, item, right) = split_items(&deep.prefix, predicate, measure);
+            let right = Self::deep_left(right, deep.middle.clone(), deep.suffix.clone());
+            return (Self::from_items(left), item, right);
+        }
+
+        let middle_measure = prefix_measure.combine(&deep.middle.measure());
+        if predicate(&middle_measure) {
+            let (middle_left, branch, middle_right) = deep.middle.split(predicate, &prefix_measure);
+            let before = prefix_measure.combine(&middle_left.measure());
+            let (left, item, right) = split_items(branch.children(), predicate, &before);
+
+            let left = Self::deep_right(deep.prefix.clone(), middle_left, left);
+            let right = Self::deep_left(right, middle_right, deep.suffix.clone());
+            return (left, item, right);
+        }
+
+        let (left, item, right) = split_items(&deep.suffix, predicate, &middle_measure);
+        let left = Self::deep_right(deep.prefix.clone(), deep.middle.clone(), left);
+        (left, item, Self::from_items(right))
+    }
+
+    /// Returns the measure before the item at which `predicate` turns true,
+    /// and the item, in the non-empty tree
+    fn find<F: Fn(&M) -> bool>(&self, predicate: &F, measure: M) -> (M, &Item<T, M>) {
+        let deep = match self {
+            Tree::Empty => unreachable!("searching an empty tree"),
+            Tree::Single(item) => return (measure, item),
+            Tree::Deep(deep) => deep,
+        };
+
+        let prefix_measure = measure.combine(&measure_all(&deep.prefix));
+        if predicate(&prefix_measure) {
+            return find_item(&deep.prefix, predicate, measure);
+        }
+
+        let middle_measure = prefix_measure.combine(&deep.middle.measure());
+        if predicate(&middle_measure) {
+            let (measure, branch) = deep.middle.find(predicate, prefix_measure);
+            return find_item(branch.children(), predicate, measure);
+        }
+
+        find_item(&deep.suffix, predicate, middle_measure)
+    }
+}
+
+/// A persistent sequence of elements measured in the monoid `M`
+///
+/// Both ends sit in short fingers at the top of the tree, so pushing and
+/// popping at either end takes `O(1)` amortized, over the updates of a single
+/// version, and [`Self::append`] and [`Self::split`] take `O(log n)`. Every
+/// update returns a new tree sharing most of its nodes with `self`, so
+/// cloning is `O(1)` and old versions stay valid.
+pub struct FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    tree: Tree<T, M>,
+}
+
+impl<T, M> FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    pub fn new() -> Self {
+        Self { tree: Tree::Empty }
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        matches!(self.tree, Tree::Empty)
+    }
+
+    /// Returns the combined measure of all the elements
+    pub fn measure(&self) -> M {
+        self.tree.measure()
+    }
+
+    /// Returns the first element
+    pub fn front(&self) -> Option<&T> {
+        let mut item = self.tree.front()?;
+
+        while let Item::Branch(branch) = item {
+            item = branch.children.first().expect("branches are not empty");
+        }
+        item.leaf()
+    }
+
+    /// Returns the last element
+    pub fn back(&self) -> Option<&T> {
+        let mut item = self.tree.back()?;
+
+        while let Item::Branch(branch) = item {
+            item = branch.children.last().expect("branches are not empty");
+        }
+        item.leaf()
+    }
+
+    /// Returns a new tree with `data` added before the first element
+    pub fn push_front(&self, data: T) -> Self {
+        Self {
+            tree: self.tree.push_front(Item::Leaf(Arc::new(data))),
+        }
+    }
+
+    /// Returns a new tree with `data` added after the last element
+    pub fn push_back(&self, data: T) -> Self {
+        Self {
+            tree: self.tree.push_back(Item::Leaf(Arc::new(data))),
+        }
+    }
+
+    /// Returns the first element along with a new tree holding the others
+    pub fn pop_front(&self) -> Option<(&T, Self)> {
+        let front = self.front()?;
+        let (_, tree) = self.tree.view_front()?;
+
+        Some((front, Self { tree }))
+    }
+
+    /// Returns the last element along with a new tree holding the others
+    pub fn pop_back(&self) -> Option<(&T, Self)> {
+        let back = self.back()?;
+        let (tree, _) = self.tree.view_back()?;
+
+        Some((back, Self { tree }))
+    }
+
+    /// Returns a new tree holding the elements of `self` followed by those of
+    /// `other`
+    pub fn append(&self, other: &Self) -> Self {
+        Self {
+            tree: Tree::append(&self.tree, Vec::new(), &other.tree),
+        }
+    }
+
+    /// Splits the sequence before the first element at which `predicate`,
+    /// applied to the measure of the elements up to and including it, turns
+    /// true
+    ///
+    /// `predicate` must be monotone: false on some prefix of the sequence and
+    /// true from there on. If it is never true, everything goes to the left.
+    pub fn split<F>(&self, predicate: F) -> (Self, Self)
+    where
+        F: Fn(&M) -> bool,
+    {
+        if self.is_empty() || !predicate(&self.measure()) {
+            return (self.clone(), Self::new());
+        }
+
+        let (left, item, right) = self.tree.split(&predicate, &M::identity());
+        (
+            Self { tree: left },
+            Self {
+                tree: right.push_front(item),
+            },
+        )
+    }
+
+    /// Returns the first element at which the monotone `predicate`, applied
+    /// to the measure of the elements up to and including it, turns true
+    pub fn find<F>(&self, predicate: F) -> Option<&T>
+    where
+        F: Fn(&M) -> bool,
+    {
+        if self.is_empty() || !predicate(&self.measure()) {
+            return None;
+        }
+
+        let (measure, item) = self.tree.find(&predicate, M::identity());
+        Some(item.find(&predicate, measure))
+    }
+
+    /// Returns a new iterator which iterates over the elements from front to
+    /// back
+    pub fn iter(&self) -> FingerTreeIterator<'_, T, M> {
+        FingerTreeIterator {
+            stack: vec![Piece::Tree(&self.tree)],
+        }
+    }
+}
+
+impl<T, M> Item<T, M> {
+    fn leaf(&self) -> Option<&T> {
+        match self {
+            Item::Leaf(data) => Some(data),
+            Item::Branch(_) => None,
+        }
+    }
+}
+
+impl<T, M> FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid + PartialEq,
+{
+    /// Returns `true` if every finger holds one to four items, every branch
+    /// two or three, all elements sit at the bottom, and every cached measure
+    /// is right
+    pub fn is_valid(&self) -> bool {
+        fn check_item<T: Measured<M>, M: Monoid + PartialEq>(
+            item: &Item<T, M>,
+            depth: usize,
+        ) -> Option<M> {
+            match item {
+                Item::Leaf(data) => (depth == 0).then(|| <T as Measured<M>>::measure(data)),
+                Item::Branch(branch) => {
+                    if depth == 0 || !(2..=3).contains(&branch.children.len()) {
+                        return None;
+                    }
+
+                    let measure = check_items(&branch.children, depth - 1)?;
+                    (measure == branch.measure).then_some(measure)
+                }
+            }
+        }
+
+        fn check_items<T: Measured<M>, M: Monoid + PartialEq>(
+            items: &[Item<T, M>],
+            depth: usize,
+        ) -> Option<M> {
+            items.iter().try_fold(M::identity(), |measure, item| {
+                Some(measure.combine(&check_item(item, depth)?))
+            })
+        }
+
+        fn check_tree<T: Measured<M>, M: Monoid + PartialEq>(
+            tree: &Tree<T, M>,
+            depth: usize,
+        ) -> Option<M> {
+            match tree {
+                Tree::Empty => Some(M::identity()),
+                Tree::Single(item) => check_item(item, depth),
+                Tree::Deep(deep) => {
+                    let fingers_ok = [&deep.prefix, &deep.suffix]
+                        .iter()
+                        .all(|finger| (1..=4).contains(&finger.len()));
+                    if !fingers_ok {
+                        return None;
+                    }
+
+                    let measure = check_items(&deep.prefix, depth)?
+                        .combine(&check_tree(&deep.middle, depth + 1)?)
+                        .combine(&check_items(&deep.suffix, depth)?);
+                    (measure == deep.measure).then_some(measure)
+                }
+            }
+        }
+
+        check_tree(&self.tree, 0).is_some()
+    }
+}
+
+impl<T> FingerTree<T, Size> {
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.measure().0
+    }
+
+    /// Returns the element at `index`, counting from the front
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.find(|size| size.0 > index)
+    }
+
+    /// Splits the sequence into the elements before `index` and the others
+    pub fn split_at(&self, index: usize) -> (Self, Self) {
+        self.split(|size| size.0 > index)
+    }
+}
+
+impl<T, M> Clone for FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl<T, M> Default for FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, M> fmt::Debug for FingerTree<T, M>
+where
+    T: Measured<M> + fmt::Debug,
+    M: Monoid,
+{
+    /// Formats the elements from front to back, like a list
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements in the same order,
+/// regardless of their shape
+impl<T, M> PartialEq for FingerTree<T, M>
+where
+    T: Measured<M> + PartialEq,
+    M: Monoid,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T, M> Eq for FingerTree<T, M>
+where
+    T: Measured<M> + Eq,
+    M: Monoid,
+{
+}
+
+impl<T, M> FromIterator<T> for FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T, M> Extend<T> for FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.tree = self.tree.push_back(Item::Leaf(Arc::new(data)));
+        }
+    }
+}
+
+/// A part of a [`FingerTree`] the iterator still has to walk
+enum Piece<'a, T, M> {
+    Tree(&'a Tree<T, M>),
+    Item(&'a Item<T, M>),
+}
+
+/// An iterator over references to the elements of a [`FingerTree`] from
+/// front to back
+pub struct FingerTreeIterator<'a, T, M> {
+    stack: Vec<Piece<'a, T, M>>,
+}
+
+impl<'a, T, M> Iterator for FingerTreeIterator<'a, T, M> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.pop()? {
+                Piece::Tree(Tree::Empty) => {}
+                Piece::Tree(Tree::Single(item)) => self.stack.push(Piece::Item(item)),
+                Piece::Tree(Tree::Deep(deep)) => {
+                    self.stack.extend(deep.suffix.iter().rev().map(Piece::Item));
+                    self.stack.push(Piece::Tree(&deep.middle));
+                    self.stack.extend(deep.prefix.iter().rev().map(Piece::Item));
+                }
+                Piece::Item(Item::Leaf(data)) => return Some(data),
+                Piece::Item(Item::Branch(branch)) => {
+                    self.stack
+                        .extend(branch.children.iter().rev().map(Piece::Item));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T, M> IntoIterator for &'a FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    type Item = &'a T;
+    type IntoIter = FingerTreeIterator<'a, T, M>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Max(Option<u32>);
+
+    impl Monoid for Max {
+        fn identity() -> Self {
+            Max(None)
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Max(self.0.max(other.0))
+        }
+    }
+
+    /// Tasks measured by their priority
+    #[derive(Debug, PartialEq)]
+    struct Task(u32);
+
+    impl Measured<Max> for Task {
+        fn measure(&self) -> Max {
+            Max(Some(self.0))
+        }
+    }
+
+    #[test]
+    fn test_deque_ends() {
+        let tree: FingerTree<u32, Size> = prequel();
+
+        assert_eq!(tree.len(), 7);
+        assert_eq!((tree.front(), tree.back()), (Some(&16), Some(&70)));
+        assert_eq!(tree.get(3), Some(&3));
+        assert_eq!(tree.get(7), None);
+
+        let pushed = tree.push_front(1).push_back(99);
+        let (front, rest) = pushed.pop_front().unwrap();
+        let (back, rest) = rest.pop_back().unwrap();
+        assert_eq!((front, back), (&1, &99));
+        assert_eq!(rest, tree);
+        // the old versions are untouched
+        assert_eq!(tree.len(), 7);
+        assert_eq!(pushed.len(), 9);
+
+        let mut tree = FingerTree::<u32, Size>::new();
+        for data in 0..500 {
+            tree = tree.push_front(data).push_back(data);
+        }
+        assert!(tree.is_valid());
+        assert!(tree.iter().copied().eq((0..500).rev().chain(0..500)));
+        while let Some((_, rest)) = tree.pop_front() {
+            tree = rest;
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.pop_back(), None);
+    }
+
+    #[test]
+    fn test_split_append() {
+        let tree: FingerTree<usize, Size> = (0..1000).collect();
+        assert!(tree.is_valid());
+
+        for index in [0, 1, 4, 57, 500, 999, 1000] {
+            let (left, right) = tree.split_at(index);
+
+            assert!(left.is_valid() && right.is_valid());
+            assert_eq!(left.len(), index);
+            assert!(left.iter().copied().eq(0..index));
+            assert!(right.iter().copied().eq(index..1000));
+
+            let joined = left.append(&right);
+            assert!(joined.is_valid());
+            assert_eq!(joined, tree);
+        }
+
+        let doubled = tree.append(&tree);
+        assert_eq!(doubled.len(), 2000);
+        assert_eq!(doubled.get(1500), Some(&500));
+        assert!(doubled.is_valid());
+    }
+
+    #[test]
+    fn test_priority_queue() {
+        let queue: FingerTree<Task, Max> = [3, 9, 1, 9, 4].into_iter().map(Task).collect();
+        let highest = queue.measure();
+
+        assert_eq!(highest, Max(Some(9)));
+        assert_eq!(queue.find(|max| *max == highest), Some(&Task(9)));
+
+        // take out the first task with the highest priority
+        let (before, rest) = queue.split(|max| *max == highest);
+        let (task, after) = rest.pop_front().unwrap();
+        let queue = before.append(&after);
+
+        assert_eq!(task, &Task(9));
+        assert!(queue.is_valid());
+        assert_eq!(
+            queue.iter().map(|task| task.0).collect::<Vec<_>>(),
+            vec![3, 1, 9, 4]
+        );
+        assert_eq!(queue.find(|max| *max == Max(Some(10))), None);
+    }
+}
@@ -0,0 +1,557 @@
+use crate::treap::XorShift;
+use std::{
+    fmt, mem,
+    ops::{Bound, Index, IndexMut, RangeBounds},
+};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    /// no greater than the priority of the parent
+    priority: u64,
+    left: Link<T>,
+    right: Link<T>,
+    /// number of nodes in the subtree rooted here
+    size: usize,
+    /// the subtree is to be read backwards, which is still to be pushed into
+    /// the children of this node
+    reversed: bool,
+}
+
+impl<T> Node<T> {
+    fn new(data: T, priority: u64) -> Box<Self> {
+        Box::new(Self {
+            data,
+            priority,
+            left: None,
+            right: None,
+            size: 1,
+            reversed: false,
+        })
+    }
+
+    fn update_size(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+
+    /// Swaps the children of a reversed node, handing the reversal down to them
+    fn push_down(&mut self) {
+        if mem::take(&mut self.reversed) {
+            mem::swap(&mut self.left, &mut self.right);
+
+            for child in [&mut self.left, &mut self.right].into_iter().flatten() {
+                child.reversed = !child.reversed;
+            }
+        }
+    }
+
+    /// Returns the children in sequence order, for a node read backwards when
+    /// `reversed` holds
+    fn children(&self, reversed: bool) -> (&Link<T>, &Link<T>) {
+        if reversed {
+            (&self.right, &self.left)
+        } else {
+            (&self.left, &self.right)
+        }
+    }
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// Splits `link` into its first `at` elements and the rest
+fn split<T>(link: Link<T>, at: usize) -> (Link<T>, Link<T>) {
+    let Some(mut node) = link else {
+        return (None, None);
+    };
+    node.push_down();
+
+    let left_size = size(&node.left);
+    if at <= left_size {
+        let (left, middle) = split(node.left.take(), at);
+        node.left = middle;
+        node.update_size();
+        (left, Some(node))
+    } else {
+        let (middle, right) = split(node.right.take(), at - left_size - 1);
+        node.right = middle;
+        node.update_size();
+        (Some(node), right)
+    }
+}
+
+/// Joins two treaps, the elements of `left` coming first
+fn merge<T>(left: Link<T>, right: Link<T>) -> Link<T> {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority >= right.priority {
+                left.push_down();
+                left.right = merge(left.right.take(), Some(right));
+                left.update_size();
+                Some(left)
+            } else {
+                right.push_down();
+                right.left = merge(Some(left), right.left.take());
+                right.update_size();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// A sequence stored in a treap keyed by position, a balanced alternative to
+/// `Vec` when elements are often inserted or removed in the middle
+///
+/// No key is stored: the position of an element is the number of elements in
+/// the subtrees to its left, found from the subtree sizes on the way down.
+/// Inserting and removing at any position, splitting, appending and
+/// reversing a range all split and merge the treap in `O(log n)` expected
+/// time. A reversal only flags the root of the range, and the flag is pushed
+/// down whenever a later operation passes through.
+#[derive(Clone)]
+pub struct ImplicitTreap<T> {
+    root: Link<T>,
+    rng: XorShift,
+}
+
+impl<T> ImplicitTreap<T> {
+    /// Creates an empty sequence drawing priorities from a randomly seeded
+    /// generator
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            rng: XorShift::from_entropy(),
+        }
+    }
+
+    /// Creates an empty sequence whose priorities are generated from `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            root: None,
+            rng: XorShift::with_seed(seed),
+        }
+    }
+
+    /// Returns the number of elements in the sequence
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    /// Returns `true` if the sequence contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        fn height<T>(link: &Link<T>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    /// Returns the element at `index`
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+        let mut reversed = false;
+
+        loop {
+            reversed ^= node.reversed;
+            let (left, right) = node.children(reversed);
+            let left_size = size(left);
+
+            if index == left_size {
+                return Some(&node.data);
+            } else if index < left_size {
+                node = left.as_deref()?;
+            } else {
+                index -= left_size + 1;
+                node = right.as_deref()?;
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the element at `index`
+    pub fn get_mut(&mut self, mut index: usize) -> Option<&mut T> {
+        let mut node = self.root.as_deref_mut()?;
+
+        loop {
+            node.push_down();
+            let left_size = size(&node.left);
+
+            if index == left_size {
+                return Some(&mut node.data);
+            } else if index < left_size {
+                node = node.left.as_deref_mut()?;
+            } else {
+                index -= left_size + 1;
+                node = node.right.as_deref_mut()?;
+            }
+        }
+    }
+
+    /// Returns the first element
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// Returns the last element
+    pub fn last(&self) -> Option<&T> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    /// Inserts `data` at `index`, shifting the elements after it one place
+    /// to the right
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length.
+    pub fn insert(&mut self, index: usize, data: T) {
+        let len = self.len();
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+
+        let (left, right) = split(self.root.take(), index);
+        let node = Node::new(data, self.rng.next_u64());
+        self.root = merge(merge(left, Some(node)), right);
+    }
+
+    /// Appends `data` after the last element
+    pub fn push(&mut self, data: T) {
+        let node = Node::new(data, self.rng.next_u64());
+        self.root = merge(self.root.take(), Some(node));
+    }
+
+    /// Removes and returns the element at `index`, shifting the elements
+    /// after it one place to the left
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(
+            index < len,
+            "removal index (is {index}) should be < len (is {len})"
+        );
+
+        let (left, rest) = split(self.root.take(), index);
+        let (middle, right) = split(rest, 1);
+        self.root = merge(left, right);
+        middle.expect("index is in bounds").data
+    }
+
+    /// Removes and returns the last element
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len();
+        (len > 0).then(|| self.remove(len - 1))
+    }
+
+    /// Moves the elements from `at` on into a new sequence, whose generator is
+    /// seeded from this one's
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is greater than the length.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let len = self.len();
+        assert!(
+            at <= len,
+            "`at` split index (is {at}) should be <= len (is {len})"
+        );
+
+        let (left, right) = split(self.root.take(), at);
+        self.root = left;
+        Self {
+            root: right,
+            rng: XorShift::with_seed(self.rng.next_u64()),
+        }
+    }
+
+    /// Moves all the elements of `other` after the last element of `self`,
+    /// leaving `other` empty
+    pub fn append(&mut self, other: &mut Self) {
+        self.root = merge(self.root.take(), other.root.take());
+    }
+
+    /// Reverses the order of the elements in `range`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range starts after it ends or ends after the last element.
+    pub fn reverse<R>(&mut self, range: R)
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        };
+        assert!(start <= end, "range starts at {start} but ends at {end}");
+        assert!(
+            end <= len,
+            "range end index {end} out of range for length {len}"
+        );
+
+        let (left, rest) = split(self.root.take(), start);
+        let (mut middle, right) = split(rest, end - start);
+        if let Some(middle) = &mut middle {
+            middle.reversed = !middle.reversed;
+        }
+        self.root = merge(merge(left, middle), right);
+    }
+
+    /// Returns a new iterator which iterates over the sequence in order
+    pub fn iter(&self) -> ImplicitTreapIterator<'_, T> {
+        let mut iter = ImplicitTreapIterator {
+            stack: Vec::new(),
+            remaining: self.len(),
+        };
+
+        iter.stack_push_left(self.root.as_deref(), false);
+        iter
+    }
+
+    /// Returns `true` if the priorities are in heap order and every subtree
+    /// size is correct
+    pub fn is_valid(&self) -> bool {
+        fn check<T>(link: &Link<T>, max_priority: u64) -> Option<usize> {
+            let Some(node) = link else {
+                return Some(0);
+            };
+
+            if node.priority > max_priority {
+                return None;
+            }
+
+            let left = check(&node.left, node.priority)?;
+            let right = check(&node.right, node.priority)?;
+
+            (node.size == 1 + left + right).then_some(node.size)
+        }
+
+        check(&self.root, u64::MAX).is_some()
+    }
+}
+
+impl<T> Default for ImplicitTreap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for ImplicitTreap<T>
+where
+    T: fmt::Debug,
+{
+    /// Formats the elements in order, like a list
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> PartialEq for ImplicitTreap<T>
+where
+    T: PartialEq,
+{
+    /// Compares the elements only, whatever the priorities and shapes
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for ImplicitTreap<T> where T: Eq {}
+
+/// Indexes the elements by position, like [`ImplicitTreap::get`]
+///
+/// # Panics
+///
+/// Panics if `index` is out of bounds.
+impl<T> Index<usize> for ImplicitTreap<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        let len = self.len();
+        self.get(index).unwrap_or_else(|| {
+            panic!("index out of bounds: the len is {len} but the index is {index}")
+        })
+    }
+}
+
+impl<T> IndexMut<usize> for ImplicitTreap<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len();
+        self.get_mut(index).unwrap_or_else(|| {
+            panic!("index out of bounds: the len is {len} but the index is {index}")
+        })
+    }
+}
+
+impl<T> FromIterator<T> for ImplicitTreap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut treap = Self::new();
+        treap.extend(iter);
+        treap
+    }
+}
+
+impl<T> Extend<T> for ImplicitTreap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.push(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of an [`ImplicitTreap`] in
+/// order
+pub struct ImplicitTreapIterator<'a, T> {
+    /// nodes still to visit, each with whether it is read backwards
+    stack: Vec<(&'a Node<T>, bool)>,
+    remaining: usize,
+}
+
+impl<'a, T> ImplicitTreapIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>, mut reversed: bool) {
+        while let Some(current) = node {
+            reversed ^= current.reversed;
+            self.stack.push((current, reversed));
+            node = current.children(reversed).0.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for ImplicitTreapIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, reversed) = self.stack.pop()?;
+
+        self.stack_push_left(node.children(reversed).1.as_deref(), reversed);
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for ImplicitTreapIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a ImplicitTreap<T> {
+    type Item = &'a T;
+    type IntoIter = ImplicitTreapIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::PREQUEL;
+
+    fn prequel_sequence() -> ImplicitTreap<u32> {
+        let mut treap = ImplicitTreap::with_seed(42);
+        treap.extend(PREQUEL);
+        treap
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut treap = prequel_sequence();
+
+        assert_eq!(treap.len(), 7);
+        assert_eq!((treap.first(), treap.last()), (Some(&16), Some(&70)));
+        assert_eq!(treap[3], 3);
+        assert_eq!(treap.get(7), None);
+
+        treap.insert(3, 5);
+        treap.insert(0, 1);
+        treap.insert(9, 99);
+        assert_eq!(treap.remove(5), 3);
+        treap[1] += 1;
+        assert_eq!(format!("{treap:?}"), "[1, 17, 7, 28, 5, 21, 36, 70, 99]");
+        assert_eq!(treap.pop(), Some(99));
+        assert!(treap.is_valid());
+
+        // middle insertions stay logarithmic
+        let mut treap = ImplicitTreap::with_seed(7);
+        for i in 0..2000 {
+            treap.insert(treap.len() / 2, i);
+        }
+        assert!(treap.height() < 60);
+        assert!(treap.is_valid());
+    }
+
+    #[test]
+    fn test_split_append() {
+        let mut treap = prequel_sequence();
+        let mut tail = treap.split_off(3);
+
+        assert_eq!(treap.iter().collect::<Vec<_>>(), vec![&16, &7, &28]);
+        assert_eq!(tail.iter().collect::<Vec<_>>(), vec![&3, &21, &36, &70]);
+        assert!(treap.is_valid() && tail.is_valid());
+
+        treap.append(&mut tail);
+        assert!(tail.is_empty());
+        assert_eq!(treap, prequel_sequence());
+        assert!(treap.split_off(7).is_empty());
+    }
+
+    #[test]
+    fn test_reverse_against_vec() {
+        let mut treap = ImplicitTreap::with_seed(3);
+        let mut model = Vec::new();
+        let mut rng = XorShift::with_seed(11);
+
+        for i in 0..3000u32 {
+            let len = model.len();
+            let at = rng.next_u64() as usize % (len + 1);
+            match rng.next_u64() % 4 {
+                0 | 1 => {
+                    treap.insert(at, i);
+                    model.insert(at, i);
+                }
+                2 if at < len => assert_eq!(treap.remove(at), model.remove(at)),
+                _ => {
+                    let end = at + rng.next_u64() as usize % (len - at + 1);
+                    treap.reverse(at..end);
+                    model[at..end].reverse();
+                }
+            }
+
+            if i % 100 == 0 {
+                assert!(treap.iter().eq(model.iter()));
+                assert!(treap.is_valid());
+            }
+        }
+
+        treap.reverse(..);
+        model.reverse();
+        assert!(treap.iter().eq(model.iter()));
+        assert!((0..model.len()).step_by(17).all(|i| treap[i] == model[i]));
+    }
+}
@@ -1,5 +1,85 @@
 #![allow(dead_code)]
 
+pub mod aa_tree;
+pub mod aho_corasick;
+pub mod avl_tree;
+pub mod b_plus_tree;
+pub mod binary_search_multiset;
 pub mod binary_search_tree;
+pub mod binary_search_tree_arena;
+pub mod binary_search_tree_by;
+pub mod binary_search_tree_map;
+pub mod burst_trie;
+pub mod codec;
+#[cfg(feature = "concurrent")]
+pub mod concurrent;
+pub mod double_array_trie;
+pub mod finger_tree;
+pub mod implicit_treap;
+pub mod louds_trie;
+pub mod lsm;
+pub mod order_statistic_tree;
+pub mod patricia_trie;
+pub mod persistent;
+pub mod radix_trie;
+pub mod red_black_tree;
+pub mod rope;
+pub mod scapegoat_tree;
+pub mod sequence_trie;
+pub mod skip_list;
+pub mod sorted;
+pub mod splay_tree;
+pub mod suffix_automaton;
+pub mod suffix_tree;
+pub mod ternary_search_tree;
+#[cfg(test)]
+mod test_util;
+pub mod threaded_binary_search_tree;
+pub mod treap;
+pub mod trie;
+pub mod two_three_four_tree;
+pub mod two_three_tree;
+pub mod veb_tree;
+pub mod weight_balanced_tree;
+pub mod y_fast_trie;
+pub mod zip_tree;
 
-pub use binary_search_tree::BinarySearchTree;
+pub use aa_tree::AaTree;
+pub use aho_corasick::{AhoCorasick, Match};
+pub use avl_tree::AvlTree;
+pub use b_plus_tree::BPlusTree;
+pub use binary_search_multiset::BinarySearchMultiSet;
+pub use binary_search_tree::{
+    BinarySearchTree, DuplicatePolicy, InsertError, InsertErrorKind, NearestTie,
+};
+pub use binary_search_tree_arena::BinarySearchTreeArena;
+pub use binary_search_tree_by::BinarySearchTreeBy;
+pub use binary_search_tree_map::BinarySearchTreeMap;
+pub use burst_trie::BurstTrie;
+pub use double_array_trie::{DoubleArrayError, DoubleArrayTrie};
+pub use finger_tree::FingerTree;
+pub use implicit_treap::ImplicitTreap;
+pub use louds_trie::LoudsTrie;
+pub use lsm::{LsmMap, LsmSet};
+pub use order_statistic_tree::OrderStatisticTree;
+pub use patricia_trie::{Cidr, CidrError, PatriciaTrie};
+pub use radix_trie::RadixTrie;
+pub use red_black_tree::RedBlackTree;
+pub use rope::Rope;
+pub use scapegoat_tree::ScapegoatTree;
+pub use sequence_trie::SequenceTrie;
+pub use skip_list::SkipList;
+pub use sorted::{SortedMap, SortedSet};
+pub use splay_tree::{AccessStats, SplayTree};
+pub use suffix_automaton::SuffixAutomaton;
+pub use suffix_tree::SuffixTree;
+pub use ternary_search_tree::TernarySearchTree;
+pub use threaded_binary_search_tree::ThreadedBinarySearchTree;
+pub use treap::Treap;
+pub use trie::Trie;
+pub use two_three_four_tree::TwoThreeFourTree;
+pub use two_three_tree::TwoThreeTree;
+pub use veb_tree::{IntegerKey, VebTree};
+pub use weight_balanced_tree::WeightBalancedTree;
+pub use y_fast_trie::YFastTrie;
+pub use zip_tree::ZipTree;
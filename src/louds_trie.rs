@@ -0,0 +1,374 @@
+use crate::Trie;
+use std::fmt;
+
+/// Number of bits counted by each entry of a rank directory
+const BLOCK_BITS: usize = 512;
+const BLOCK_WORDS: usize = BLOCK_BITS / 64;
+
+/// A sequence of bits with a directory of the ones counted before each block
+/// of 512, for `rank` in constant time and `select` by binary search
+#[derive(Clone, Default)]
+struct BitVector {
+    words: Vec<u64>,
+    len: usize,
+    /// number of ones before each block, and then in all of them
+    ranks: Vec<u32>,
+}
+
+impl BitVector {
+    fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if bit {
+            *self.words.last_mut().expect("pushed above") |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    /// Fills the rank directory once all the bits are pushed
+    fn index(&mut self) {
+        let mut ones = 0;
+        self.ranks = Vec::with_capacity(self.words.len() / BLOCK_WORDS + 2);
+        for block in self.words.chunks(BLOCK_WORDS) {
+            self.ranks.push(ones);
+            ones += block.iter().map(|word| word.count_ones()).sum::<u32>();
+        }
+        self.ranks.push(ones);
+    }
+
+    fn get(&self, position: usize) -> bool {
+        self.words[position / 64] >> (position % 64) & 1 == 1
+    }
+
+    /// Returns the number of ones before `position`
+    fn rank1(&self, position: usize) -> usize {
+        let word = position / 64;
+        let block = word / BLOCK_WORDS;
+        let mut ones = self.ranks[block] as usize;
+        for &full in &self.words[block * BLOCK_WORDS..word] {
+            ones += full.count_ones() as usize;
+        }
+        if !position.is_multiple_of(64) {
+            ones += (self.words[word] & ((1 << (position % 64)) - 1)).count_ones() as usize;
+        }
+        ones
+    }
+
+    /// Returns the position of the zero with `index` zeros before it
+    fn select0(&self, index: usize) -> usize {
+        let zeros_before = |block: usize| block * BLOCK_BITS - self.ranks[block] as usize;
+        // the last block with at most `index` zeros before it
+        let (mut block, mut high) = (0, self.ranks.len() - 1);
+        while high - block > 1 {
+            let middle = (block + high) / 2;
+            if zeros_before(middle) <= index {
+                block = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        let mut remaining = index - zeros_before(block);
+        for (offset, &word) in self.words[block * BLOCK_WORDS..].iter().enumerate() {
+            let zeros = !word;
+            let count = zeros.count_ones() as usize;
+            if remaining < count {
+                let mut zeros = zeros;
+                for _ in 0..remaining {
+                    zeros &= zeros - 1;
+                }
+                return (block * BLOCK_WORDS + offset) * 64 + zeros.trailing_zeros() as usize;
+            }
+            remaining -= count;
+        }
+        unreachable!("fewer zeros than asked for")
+    }
+
+    fn heap_size(&self) -> usize {
+        self.words.len() * 8 + self.ranks.len() * 4
+    }
+}
+
+/// A read-only trie stored in a succinct level-order unary degree sequence
+///
+/// The nodes are numbered in breadth-first order, and each one is written as
+/// a one bit for every child followed by a zero, after a `10` for a virtual
+/// super root. Those two bits per node, with the byte leading to each node
+/// and one bit marking the nodes where keys end, are all the structure there
+/// is, against several words per node for a [`Trie`]. Moving to the children
+/// of a node takes a `select` and a `rank` on the bits, so lookups stay at a
+/// few dozen operations per byte.
+///
+/// It is built by [`Trie::freeze`], once a dictionary is complete.
+#[derive(Clone)]
+pub struct LoudsTrie<V> {
+    louds: BitVector,
+    /// the byte leading to each node but the root, by node number minus one
+    labels: Vec<u8>,
+    /// whether a key ends at each node, by node number
+    terminal: BitVector,
+    /// the values of the terminal nodes, in node order
+    values: Vec<V>,
+}
+
+impl<V> LoudsTrie<V> {
+    /// Returns the number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the trie contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the number of nodes, including the root
+    pub fn node_count(&self) -> usize {
+        self.labels.len() + 1
+    }
+
+    /// Returns the number of bytes allocated for the structure of the trie,
+    /// without the values
+    pub fn structure_size(&self) -> usize {
+        self.louds.heap_size() + self.labels.len() + self.terminal.heap_size()
+    }
+
+    /// Returns the numbers of the first child of `node` and of the node after
+    /// its last child
+    fn children(&self, node: usize) -> (usize, usize) {
+        // the children of node `n` are written after the `n`th zero
+        let start = self.louds.select0(node) + 1;
+        let end = self.louds.select0(node + 1);
+        let first = self.louds.rank1(start);
+        (first, first + end - start)
+    }
+
+    fn child(&self, node: usize, byte: u8) -> Option<usize> {
+        let (first, end) = self.children(node);
+        let index = self.labels[first - 1..end - 1].binary_search(&byte).ok()?;
+        Some(first + index)
+    }
+
+    /// Returns the number of the node standing for `prefix`
+    fn find(&self, prefix: &[u8]) -> Option<usize> {
+        prefix
+            .iter()
+            .try_fold(0, |node, &byte| self.child(node, byte))
+    }
+
+    fn value(&self, node: usize) -> Option<&V> {
+        if self.terminal.get(node) {
+            Some(&self.values[self.terminal.rank1(node)])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get<K>(&self, key: K) -> Option<&V>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.value(self.find(key.as_ref())?)
+    }
+
+    /// Returns `true` if a value is stored under `key`
+    pub fn contains_key<K>(&self, key: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns `true` if some stored key starts with `prefix`
+    pub fn contains_prefix<K>(&self, prefix: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        // frozen from a trie, every node leads to a value
+        !self.is_empty() && self.find(prefix.as_ref()).is_some()
+    }
+
+    /// Returns an iterator over the entries in lexicographic order of their
+    /// keys
+    pub fn iter(&self) -> LoudsTrieIterator<'_, V> {
+        LoudsTrieIterator {
+            trie: self,
+            key: Vec::new(),
+            stack: vec![(0, 0)],
+        }
+    }
+
+    /// Returns `true` if the bits hold one one per node and one zero more, the
+    /// children of each node are sorted by distinct bytes and there is a
+    /// value for every terminal node
+    pub fn is_valid(&self) -> bool {
+        let nodes = self.node_count();
+        let ones = self.louds.rank1(self.louds.len);
+        if ones != nodes
+            || self.louds.len != 2 * nodes + 1
+            || self.terminal.len != nodes
+            || self.terminal.rank1(nodes) != self.values.len()
+        {
+            return false;
+        }
+
+        (0..nodes).all(|node| {
+            let (first, end) = self.children(node);
+            end <= nodes
+                && self.labels[first - 1..end - 1]
+                    .windows(2)
+                    .all(|pair| pair[0] < pair[1])
+        })
+    }
+}
+
+impl<V> From<&Trie<V>> for LoudsTrie<V>
+where
+    V: Clone,
+{
+    fn from(trie: &Trie<V>) -> Self {
+        let mut degrees = vec![0];
+        let mut labels = Vec::new();
+        let mut terminal = BitVector::default();
+        let mut values = Vec::new();
+
+        let root = trie.get([]);
+        terminal.push(root.is_some());
+        values.extend(root.cloned());
+        trie.for_each_breadth_first(|parent, byte, value| {
+            degrees[parent] += 1;
+            degrees.push(0);
+            labels.push(byte);
+            terminal.push(value.is_some());
+            values.extend(value.cloned());
+        });
+
+        let mut louds = BitVector::default();
+        louds.push(true);
+        louds.push(false);
+        for degree in degrees {
+            for _ in 0..degree {
+                louds.push(true);
+            }
+            louds.push(false);
+        }
+        louds.index();
+        terminal.index();
+
+        Self {
+            louds,
+            labels,
+            terminal,
+            values,
+        }
+    }
+}
+
+impl<V> fmt::Debug for LoudsTrie<V>
+where
+    V: fmt::Debug,
+{
+    /// Formats the entries in key order, like a map, showing the keys as
+    /// strings with invalid UTF-8 replaced
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter()
+                    .map(|(key, value)| (String::from_utf8_lossy(&key).into_owned(), value)),
+            )
+            .finish()
+    }
+}
+
+/// An iterator over the entries of a [`LoudsTrie`] in lexicographic order of
+/// their keys
+pub struct LoudsTrieIterator<'a, V> {
+    trie: &'a LoudsTrie<V>,
+    /// key of the last node visited
+    key: Vec<u8>,
+    /// nodes left to visit, with the length of the key above them, the next
+    /// one on top
+    stack: Vec<(usize, usize)>,
+}
+
+impl<'a, V> Iterator for LoudsTrieIterator<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, depth) = self.stack.pop()?;
+            self.key.truncate(depth);
+            if node != 0 {
+                self.key.push(self.trie.labels[node - 1]);
+            }
+
+            let (first, end) = self.trie.children(node);
+            let depth = self.key.len();
+            self.stack
+                .extend((first..end).rev().map(|child| (child, depth)));
+
+            if let Some(value) = self.trie.value(node) {
+                return Some((self.key.clone(), value));
+            }
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a LoudsTrie<V> {
+    type Item = (Vec<u8>, &'a V);
+    type IntoIter = LoudsTrieIterator<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel_words;
+
+    #[test]
+    fn test_freeze() {
+        let trie: Trie<u32> = prequel_words();
+        let frozen = trie.freeze();
+
+        assert!(frozen.is_valid());
+        assert_eq!(frozen.len(), 7);
+        assert_eq!(frozen.node_count(), 10);
+        assert_eq!(frozen.get("ten"), Some(&1));
+        assert_eq!(frozen.get(b"team"), Some(&6));
+        assert_eq!(frozen.get("te"), None);
+        assert_eq!(frozen.get(""), None);
+        assert!(frozen.contains_prefix("te"));
+        assert!(!frozen.contains_prefix("tx"));
+        assert!(frozen.iter().eq(trie.iter()));
+        assert_eq!(format!("{:?}", frozen), format!("{:?}", trie));
+
+        let empty = Trie::<u32>::new().freeze();
+        assert!(empty.is_valid() && empty.is_empty());
+        assert_eq!(empty.get(""), None);
+    }
+
+    #[test]
+    fn test_large_dictionary() {
+        let mut trie = Trie::new();
+        for number in 0..20_000_u32 {
+            trie.insert(format!("{}", number * 7919 % 100_003), number);
+        }
+        trie.insert("", u32::MAX);
+        let frozen = trie.freeze();
+
+        assert!(frozen.is_valid());
+        assert_eq!(frozen.len(), trie.len());
+        for (key, value) in trie.iter() {
+            assert_eq!(frozen.get(&key), Some(value));
+        }
+        assert_eq!(frozen.get("100003"), None);
+        assert!(frozen.iter().eq(trie.iter()));
+        // the bits and labels take a couple of bytes a node
+        assert!(frozen.structure_size() < 2 * frozen.node_count());
+    }
+}
@@ -0,0 +1,623 @@
+//! Log-structured merge maps and sets, kept entirely in memory
+//!
+//! Writes go to a small balanced tree, the memtable. Once it is full it is
+//! frozen into an immutable sorted run, and when too many runs pile up they
+//! are merged into one. Removals are writes too: they store a tombstone which
+//! hides older values until a compaction drops both. Every write touches only
+//! the memtable, which keeps writes cheap under heavy ingestion, while reads
+//! check the memtable and then the runs from newest to oldest.
+
+use crate::{two_three_tree::TwoThreeTreeIterator, TwoThreeTree};
+use std::{borrow::Borrow, cmp::Ordering, collections::BinaryHeap, fmt, mem, slice};
+
+/// Number of entries the memtable of [`LsmMap::new`] holds before it is
+/// frozen into a run
+const DEFAULT_MEMTABLE_CAPACITY: usize = 1024;
+
+/// Once there are more runs than this, they are all merged into one
+const MAX_RUNS: usize = 8;
+
+/// A sorted run of entries, `None` standing for a tombstone
+type Run<K, V> = Vec<(K, Option<V>)>;
+
+/// The next entry of one of the sources of a [`Merge`]
+struct Head<K, V> {
+    key: K,
+    value: V,
+    source: usize,
+}
+
+/// Heads are popped by smallest key first, then newest source first
+impl<K: Ord, V> Ord for Head<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .cmp(&self.key)
+            .then(self.source.cmp(&other.source))
+    }
+}
+
+impl<K: Ord, V> PartialOrd for Head<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> PartialEq for Head<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, V> Eq for Head<K, V> {}
+
+/// K-way merge of sorted sources, later sources taking precedence when keys
+/// are equal
+struct Merge<K, V, I> {
+    sources: Vec<I>,
+    heap: BinaryHeap<Head<K, V>>,
+}
+
+impl<K, V, I> Merge<K, V, I>
+where
+    K: Ord,
+    I: Iterator<Item = (K, V)>,
+{
+    fn new(sources: Vec<I>) -> Self {
+        let mut merge = Self {
+            heap: BinaryHeap::with_capacity(sources.len()),
+            sources,
+        };
+
+        for source in 0..merge.sources.len() {
+            merge.advance(source);
+        }
+        merge
+    }
+
+    fn advance(&mut self, source: usize) {
+        if let Some((key, value)) = self.sources[source].next() {
+            self.heap.push(Head { key, value, source });
+        }
+    }
+}
+
+impl<K, V, I> Iterator for Merge<K, V, I>
+where
+    K: Ord,
+    I: Iterator<Item = (K, V)>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let head = self.heap.pop()?;
+        self.advance(head.source);
+
+        // older entries under the same key are shadowed
+        while self.heap.peek().is_some_and(|next| next.key == head.key) {
+            let shadowed = self.heap.pop().expect("peeked above");
+            self.advance(shadowed.source);
+        }
+        Some((head.key, head.value))
+    }
+}
+
+/// An ordered map split into a mutable memtable and immutable sorted runs
+///
+/// Writes are blind: [`Self::insert`] and [`Self::remove`] don't look for an
+/// older value, so they neither return it nor keep an exact length. Iteration
+/// merges the memtable and all the runs on the fly.
+#[derive(Clone)]
+pub struct LsmMap<K, V>
+where
+    K: Ord,
+{
+    memtable: TwoThreeTree<K, Option<V>>,
+    memtable_capacity: usize,
+    /// oldest first
+    runs: Vec<Run<K, V>>,
+}
+
+impl<K, V> LsmMap<K, V>
+where
+    K: Ord,
+{
+    pub fn new() -> Self {
+        Self::with_memtable_capacity(DEFAULT_MEMTABLE_CAPACITY)
+    }
+
+    /// Creates an empty map whose memtable is frozen into a run once it holds
+    /// `capacity` entries
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_memtable_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "memtable capacity must be positive");
+
+        Self {
+            memtable: TwoThreeTree::new(),
+            memtable_capacity: capacity,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if the map contains no live entries
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Removes all entries, runs included
+    pub fn clear(&mut self) {
+        self.memtable.clear();
+        self.runs.clear();
+    }
+
+    /// Returns the number of immutable runs
+    pub fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Returns `true` if the map contains a live value for `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the newest value stored under `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if let Some(entry) = self.memtable.get(key) {
+            return entry.as_ref();
+        }
+
+        self.runs.iter().rev().find_map(|run| {
+            run.binary_search_by(|(stored_key, _)| stored_key.borrow().cmp(key))
+                .ok()
+                .map(|index| run[index].1.as_ref())
+        })?
+    }
+
+    /// Stores `value` under `key`, shadowing any older value
+    pub fn insert(&mut self, key: K, value: V) {
+        self.write(key, Some(value));
+    }
+
+    /// Stores a tombstone under `key`, hiding any older value
+    pub fn remove(&mut self, key: K) {
+        self.write(key, None);
+    }
+
+    fn write(&mut self, key: K, entry: Option<V>) {
+        self.memtable.insert(key, entry);
+
+        if self.memtable.len() >= self.memtable_capacity {
+            self.flush();
+        }
+    }
+
+    /// Freezes the memtable into a new run, merging all the runs into one
+    /// when there are too many
+    pub fn flush(&mut self) {
+        if !self.memtable.is_empty() {
+            let run = mem::take(&mut self.memtable).into_sorted_vec();
+            self.runs.push(run);
+        }
+
+        if self.runs.len() > MAX_RUNS {
+            self.merge_runs();
+        }
+    }
+
+    /// Flushes the memtable and merges everything into a single run without
+    /// tombstones
+    pub fn compact(&mut self) {
+        self.flush();
+        self.merge_runs();
+    }
+
+    /// Merges all the runs into one, where nothing is left for tombstones to
+    /// hide
+    fn merge_runs(&mut self) {
+        let runs = mem::take(&mut self.runs)
+            .into_iter()
+            .map(Vec::into_iter)
+            .collect();
+        let run: Run<K, V> = Merge::new(runs)
+            .filter(|(_, entry)| entry.is_some())
+            .collect();
+
+        if !run.is_empty() {
+            self.runs.push(run);
+        }
+    }
+
+    /// Returns an iterator over the live entries in key order
+    pub fn iter(&self) -> LsmMapIterator<'_, K, V> {
+        let mut sources: Vec<Source<'_, K, V>> = self
+            .runs
+            .iter()
+            .map(|run| Source::Run(run.iter()))
+            .collect();
+        sources.push(Source::Memtable(self.memtable.iter()));
+
+        LsmMapIterator {
+            merge: Merge::new(sources),
+        }
+    }
+
+    /// Returns an iterator over the keys of the live entries in order
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the live values in key order
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns `true` if every run is sorted with unique keys and the memtable
+    /// is below its capacity
+    pub fn is_valid(&self) -> bool {
+        let runs_ok = self
+            .runs
+            .iter()
+            .all(|run| !run.is_empty() && run.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        runs_ok
+            && self.runs.len() <= MAX_RUNS
+            && self.memtable.len() < self.memtable_capacity
+            && self.memtable.is_valid()
+    }
+}
+
+impl<K, V> Default for LsmMap<K, V>
+where
+    K: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for LsmMap<K, V>
+where
+    K: Ord + fmt::Debug,
+    V: fmt::Debug,
+{
+    /// Formats the live entries in key order, like a map
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Two maps are equal when they hold equal live entries, however these are
+/// spread over memtables and runs
+impl<K, V> PartialEq for LsmMap<K, V>
+where
+    K: Ord,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K, V> Eq for LsmMap<K, V>
+where
+    K: Ord,
+    V: Eq,
+{
+}
+
+impl<K, V> FromIterator<(K, V)> for LsmMap<K, V>
+where
+    K: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V> Extend<(K, V)> for LsmMap<K, V>
+where
+    K: Ord,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// Where an [`LsmMapIterator`] reads entries from
+enum Source<'a, K, V> {
+    Memtable(TwoThreeTreeIterator<'a, K, Option<V>>),
+    Run(slice::Iter<'a, (K, Option<V>)>),
+}
+
+impl<'a, K, V> Iterator for Source<'a, K, V> {
+    type Item = (&'a K, &'a Option<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Source::Memtable(iter) => iter.next(),
+            Source::Run(iter) => iter.next().map(|(key, entry)| (key, entry)),
+        }
+    }
+}
+
+/// An iterator over the live entries of an [`LsmMap`] in key order
+pub struct LsmMapIterator<'a, K, V> {
+    merge: Merge<&'a K, &'a Option<V>, Source<'a, K, V>>,
+}
+
+impl<'a, K, V> Iterator for LsmMapIterator<'a, K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // skip the keys whose newest entry is a tombstone
+        self.merge
+            .by_ref()
+            .find_map(|(key, entry)| entry.as_ref().map(|value| (key, value)))
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a LsmMap<K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = LsmMapIterator<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An ordered set split into a mutable memtable and immutable sorted runs,
+/// see [`LsmMap`]
+#[derive(Clone)]
+pub struct LsmSet<T>
+where
+    T: Ord,
+{
+    map: LsmMap<T, ()>,
+}
+
+impl<T> LsmSet<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { map: LsmMap::new() }
+    }
+
+    /// Creates an empty set whose memtable is frozen into a run once it holds
+    /// `capacity` elements
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_memtable_capacity(capacity: usize) -> Self {
+        Self {
+            map: LsmMap::with_memtable_capacity(capacity),
+        }
+    }
+
+    /// Returns `true` if the set contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all elements, runs included
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Returns the number of immutable runs
+    pub fn run_count(&self) -> usize {
+        self.map.run_count()
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Adds `data` to the set
+    pub fn insert(&mut self, data: T) {
+        self.map.insert(data, ());
+    }
+
+    /// Stores a tombstone for `data`, removing it from the set
+    pub fn remove(&mut self, data: T) {
+        self.map.remove(data);
+    }
+
+    /// Freezes the memtable into a new run, merging all the runs into one
+    /// when there are too many
+    pub fn flush(&mut self) {
+        self.map.flush();
+    }
+
+    /// Flushes the memtable and merges everything into a single run without
+    /// tombstones
+    pub fn compact(&mut self) {
+        self.map.compact();
+    }
+
+    /// Returns an iterator over the elements in order
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.map.keys()
+    }
+
+    /// Returns `true` if every run is sorted with unique elements and the
+    /// memtable is below its capacity
+    pub fn is_valid(&self) -> bool {
+        self.map.is_valid()
+    }
+}
+
+impl<T> Default for LsmSet<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for LsmSet<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two sets are equal when they hold equal elements, regardless of their layout
+impl<T> PartialEq for LsmSet<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<T> Eq for LsmSet<T> where T: Ord {}
+
+impl<T> FromIterator<T> for LsmSet<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T> Extend<T> for LsmSet<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{shuffled, PREQUEL};
+    use std::collections::BTreeMap;
+
+    fn prequel_map() -> LsmMap<u32, u32> {
+        let mut map = LsmMap::with_memtable_capacity(4);
+        for key in PREQUEL {
+            map.insert(key, key * 10);
+        }
+        map
+    }
+
+    #[test]
+    fn test_newest_entry_wins() {
+        let mut map = prequel_map();
+
+        // the first four entries were frozen into a run
+        assert_eq!(map.run_count(), 1);
+        assert_eq!(map.get(&16), Some(&160));
+        assert_eq!(map.get(&70), Some(&700));
+
+        map.insert(16, 1);
+        map.remove(7);
+        map.remove(99);
+        assert_eq!(map.get(&16), Some(&1));
+        assert_eq!(map.get(&7), None);
+        assert!(!map.contains_key(&99));
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![
+                (&3, &30),
+                (&16, &1),
+                (&21, &210),
+                (&28, &280),
+                (&36, &360),
+                (&70, &700)
+            ]
+        );
+        assert!(map.is_valid());
+
+        map.compact();
+        assert_eq!(map.run_count(), 1);
+        assert_eq!(map.runs[0].len(), 6);
+        assert_eq!(map.get(&7), None);
+        assert_eq!(map.keys().count(), 6);
+    }
+
+    #[test]
+    fn test_matches_btree_map() {
+        let mut map = LsmMap::with_memtable_capacity(16);
+        let mut expected = BTreeMap::new();
+
+        for (count, key) in shuffled(1009).into_iter().enumerate() {
+            map.insert(key % 300, count);
+            expected.insert(key % 300, count);
+
+            if count % 3 == 0 {
+                map.remove(key % 97);
+                expected.remove(&(key % 97));
+            }
+            assert!(map.run_count() <= MAX_RUNS);
+        }
+
+        assert!(map.is_valid());
+        assert!(map.iter().eq(expected.iter()));
+        assert!((0..300).all(|key| map.get(&key) == expected.get(&key)));
+
+        map.compact();
+        assert!(map.iter().eq(expected.iter()));
+        assert_eq!(map.runs[0].len(), expected.len());
+    }
+
+    #[test]
+    fn test_set() {
+        let mut set: LsmSet<u32> = LsmSet::with_memtable_capacity(2);
+        set.extend(PREQUEL);
+        set.remove(28);
+        set.insert(7);
+
+        assert!(set.is_valid());
+        assert!(set.contains(&7));
+        assert!(!set.contains(&28));
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &36, &70]
+        );
+
+        set.clear();
+        assert!(set.is_empty());
+        assert_eq!(set.run_count(), 0);
+    }
+}
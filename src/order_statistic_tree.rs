@@ -0,0 +1,572 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{self, Equal, Greater, Less},
+    fmt, mem,
+    ops::Index,
+};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+    /// number of nodes on the longest path from this node down to a leaf
+    height: usize,
+    /// number of nodes in the subtree rooted here
+    size: usize,
+}
+
+impl<T> Node<T> {
+    fn new(data: T) -> Box<Self> {
+        Box::new(Self {
+            data,
+            left: None,
+            right: None,
+            height: 1,
+            size: 1,
+        })
+    }
+
+    fn update(&mut self) {
+        self.height = 1 + height(&self.left).max(height(&self.right));
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+
+    /// Height of the right subtree minus height of the left subtree
+    fn balance_factor(&self) -> isize {
+        height(&self.right) as isize - height(&self.left) as isize
+    }
+}
+
+fn height<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.height)
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// An AVL tree behaving like a set whose nodes also count their subtree, so
+/// that elements can be found and removed by their position in sorted order
+///
+/// [`Self::select`], [`Self::rank`] and [`Self::remove_kth`] all run in
+/// `O(log n)`, which makes the tree a good fit for sliding-window medians and
+/// running percentiles.
+#[derive(Clone)]
+pub struct OrderStatisticTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+}
+
+impl<T> OrderStatisticTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        height(&self.root)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return Some(&current.data),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Returns the `k`-th smallest element, counting from zero
+    pub fn select(&self, k: usize) -> Option<&T> {
+        let mut k = k;
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            let left_size = size(&current.left);
+
+            match k.cmp(&left_size) {
+                Equal => return Some(&current.data),
+                Less => node = current.left.as_deref(),
+                Greater => {
+                    // skip the left subtree and the current node
+                    k -= left_size + 1;
+                    node = current.right.as_deref();
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the number of elements strictly less than `key`
+    ///
+    /// `key` does not need to be stored in the tree.
+    pub fn rank<Q>(&self, key: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut rank = 0;
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            match key.cmp(current.data.borrow()) {
+                Equal => return rank + size(&current.left),
+                Less => node = current.left.as_deref(),
+                Greater => {
+                    rank += size(&current.left) + 1;
+                    node = current.right.as_deref();
+                }
+            }
+        }
+        rank
+    }
+
+    /// Returns the lower median, the element at position `(len - 1) / 2`
+    pub fn median(&self) -> Option<&T> {
+        self.select(self.len().checked_sub(1)? / 2)
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        Self::insert_into(&mut self.root, data)
+    }
+
+    fn insert_into(link: &mut Link<T>, data: T) -> bool {
+        let Some(node) = link else {
+            *link = Some(Node::new(data));
+            return true;
+        };
+
+        let inserted = match data.cmp(&node.data) {
+            Equal => false,
+            Less => Self::insert_into(&mut node.left, data),
+            Greater => Self::insert_into(&mut node.right, data),
+        };
+
+        if inserted {
+            Self::rebalance(link);
+        }
+        inserted
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        Self::take_from(&mut self.root, &mut |node| key.cmp(node.data.borrow()))
+    }
+
+    /// Removes and returns the `k`-th smallest element, counting from zero
+    pub fn remove_kth(&mut self, k: usize) -> Option<T> {
+        let mut k = k;
+
+        Self::take_from(&mut self.root, &mut |node| {
+            let left_size = size(&node.left);
+            let direction = k.cmp(&left_size);
+
+            if direction == Greater {
+                k -= left_size + 1;
+            }
+            direction
+        })
+    }
+
+    /// Removes the node `locate` leads to, `locate` telling on which side of
+    /// each node on the way the wanted one lies
+    fn take_from<F>(link: &mut Link<T>, locate: &mut F) -> Option<T>
+    where
+        F: FnMut(&Node<T>) -> Ordering,
+    {
+        let node = link.as_mut()?;
+
+        let data = match locate(node) {
+            Less => Self::take_from(&mut node.left, locate)?,
+            Greater => Self::take_from(&mut node.right, locate)?,
+            Equal => match (node.left.is_some(), node.right.is_some()) {
+                // replace with the in-order successor, the leftmost of the right
+                (true, true) => {
+                    let successor = Self::take_min(&mut node.right);
+                    mem::replace(&mut node.data, successor)
+                }
+                _ => {
+                    let node = link.take().expect("matched above");
+                    let Node {
+                        data, left, right, ..
+                    } = *node;
+
+                    *link = left.or(right);
+                    return Some(data);
+                }
+            },
+        };
+
+        Self::rebalance(link);
+        Some(data)
+    }
+
+    /// Removes and returns the smallest element under the non-empty `link`
+    fn take_min(link: &mut Link<T>) -> T {
+        let node = link.as_mut().expect("link is not empty");
+
+        if node.left.is_some() {
+            let min = Self::take_min(&mut node.left);
+            Self::rebalance(link);
+            return min;
+        }
+
+        let node = link.take().expect("link is not empty");
+        *link = node.right;
+        node.data
+    }
+
+    /// Restores the height, the size and the AVL balance of the node at
+    /// `link`, whose subtrees are balanced and differ in height by at most two
+    fn rebalance(link: &mut Link<T>) {
+        let Some(node) = link else {
+            return;
+        };
+
+        node.update();
+
+        match node.balance_factor() {
+            // left heavy
+            -2 => {
+                if node
+                    .left
+                    .as_ref()
+                    .is_some_and(|left| left.balance_factor() > 0)
+                {
+                    Self::rotate_left(&mut node.left);
+                }
+                Self::rotate_right(link);
+            }
+            // right heavy
+            2 => {
+                if node
+                    .right
+                    .as_ref()
+                    .is_some_and(|right| right.balance_factor() < 0)
+                {
+                    Self::rotate_right(&mut node.right);
+                }
+                Self::rotate_left(link);
+            }
+            _ => {}
+        }
+    }
+
+    /// Lifts the left child of the node at `link` into its place
+    fn rotate_right(link: &mut Link<T>) {
+        let mut node = link.take().expect("rotating an empty link");
+        let mut left = node
+            .left
+            .take()
+            .expect("rotating right without a left child");
+
+        node.left = left.right.take();
+        node.update();
+        left.right = Some(node);
+        left.update();
+
+        *link = Some(left);
+    }
+
+    /// Lifts the right child of the node at `link` into its place
+    fn rotate_left(link: &mut Link<T>) {
+        let mut node = link.take().expect("rotating an empty link");
+        let mut right = node
+            .right
+            .take()
+            .expect("rotating left without a right child");
+
+        node.right = right.left.take();
+        node.update();
+        right.left = Some(node);
+        right.update();
+
+        *link = Some(right);
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        self.select(0)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        self.select(self.len().checked_sub(1)?)
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> OrderStatisticTreeIterator<'_, T> {
+        let mut iter = OrderStatisticTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len(),
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the elements are in order, every stored height and
+    /// size is correct and no node is out of balance
+    pub fn is_valid(&self) -> bool {
+        fn check<T: Ord>(
+            link: &Link<T>,
+            lower: Option<&T>,
+            upper: Option<&T>,
+        ) -> Option<(usize, usize)> {
+            let Some(node) = link else {
+                return Some((0, 0));
+            };
+
+            if lower.is_some_and(|lower| node.data <= *lower)
+                || upper.is_some_and(|upper| node.data >= *upper)
+            {
+                return None;
+            }
+
+            let (left_height, left_size) = check(&node.left, lower, Some(&node.data))?;
+            let (right_height, right_size) = check(&node.right, Some(&node.data), upper)?;
+
+            (left_height.abs_diff(right_height) <= 1
+                && node.height == 1 + left_height.max(right_height)
+                && node.size == 1 + left_size + right_size)
+                .then_some((node.height, node.size))
+        }
+
+        check(&self.root, None, None).is_some()
+    }
+}
+
+impl<T> Default for OrderStatisticTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for OrderStatisticTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements, regardless of their shape
+impl<T> PartialEq for OrderStatisticTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for OrderStatisticTree<T> where T: Ord {}
+
+/// Indexes the elements in sorted order, like [`OrderStatisticTree::select`]
+///
+/// # Panics
+///
+/// Panics if `index` is not less than the length of the tree.
+impl<T> Index<usize> for OrderStatisticTree<T>
+where
+    T: Ord,
+{
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.select(index).unwrap_or_else(|| {
+            panic!(
+                "index out of bounds: the len is {} but the index is {index}",
+                self.len()
+            )
+        })
+    }
+}
+
+impl<T> FromIterator<T> for OrderStatisticTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for OrderStatisticTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of an [`OrderStatisticTree`]
+/// in order
+pub struct OrderStatisticTreeIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> OrderStatisticTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for OrderStatisticTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for OrderStatisticTreeIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a OrderStatisticTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = OrderStatisticTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+
+    #[test]
+    fn test_select_rank_index() {
+        let tree: OrderStatisticTree<u32> = prequel();
+
+        assert_eq!(tree.select(0), Some(&3));
+        assert_eq!(tree[4], 28);
+        assert_eq!(tree.select(7), None);
+        assert_eq!(tree.rank(&28), 4);
+        assert_eq!(tree.rank(&29), 5);
+        assert_eq!(tree.median(), Some(&21));
+        assert_eq!((tree.min(), tree.max()), (Some(&3), Some(&70)));
+        assert!(tree.is_valid());
+
+        let tree: OrderStatisticTree<u32> = (0..1024).collect();
+        assert_eq!(tree.height(), 11);
+        assert!((0..1024).all(|k| tree[k] == k as u32 && tree.rank(&tree[k]) == k));
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is 7 but the index is 7")]
+    fn test_index_out_of_bounds() {
+        let _ = prequel::<OrderStatisticTree<u32>>()[7];
+    }
+
+    #[test]
+    fn test_remove_kth_sliding_median() {
+        let mut tree: OrderStatisticTree<u32> = prequel();
+
+        assert_eq!(tree.remove_kth(2), Some(16));
+        assert_eq!(tree.remove_kth(5), Some(70));
+        assert_eq!(tree.remove_kth(5), None);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&3, &7, &21, &28, &36]);
+        assert!(tree.is_valid());
+
+        // medians of every window of 5 over a pseudo-random sequence
+        let values: Vec<u64> = (0..200).map(|i| i * 7919 % 1009).collect();
+        let mut window = OrderStatisticTree::new();
+        for (i, &value) in values.iter().enumerate() {
+            window.insert(value);
+            if i >= 5 {
+                assert!(window.remove(&values[i - 5]));
+            }
+            if i >= 4 {
+                let mut sorted = values[i + 1 - 5..=i].to_vec();
+                sorted.sort();
+                assert_eq!(window.median(), Some(&sorted[2]));
+            }
+        }
+        assert!(window.is_valid());
+
+        while window.remove_kth(0).is_some() {}
+        assert!(window.is_empty());
+    }
+}
@@ -0,0 +1,595 @@
+use std::{
+    error::Error,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+/// An IPv4 or IPv6 network, an address and how many of its leading bits are
+/// fixed, written `10.0.0.0/8` or `2001:db8::/32`
+///
+/// The bits after the prefix are cleared, so `10.1.2.3/8` is the same network
+/// as `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+/// Why a [`Cidr`] could not be made or parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CidrError {
+    /// The address part is not an IPv4 or IPv6 address
+    InvalidAddress,
+    /// The prefix length is not a number or is longer than the address
+    InvalidPrefixLength,
+}
+
+impl fmt::Display for CidrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            CidrError::InvalidAddress => "invalid IP address",
+            CidrError::InvalidPrefixLength => "invalid prefix length",
+        };
+        f.write_str(message)
+    }
+}
+
+impl Error for CidrError {}
+
+/// Returns the number of bits in addresses of the family of `addr`
+fn width(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Returns the bits of `addr`, the first one being the most significant of
+/// the result whatever the family
+fn to_bits(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u128::from(u32::from(addr)) << 96,
+        IpAddr::V6(addr) => u128::from(addr),
+    }
+}
+
+/// Returns `bits` with all but the first `len` cleared
+fn mask(bits: u128, len: u8) -> u128 {
+    bits & !u128::MAX.checked_shr(len.into()).unwrap_or(0)
+}
+
+/// Returns bit `index` of `bits`, counting from the most significant
+fn bit(bits: u128, index: u8) -> usize {
+    ((bits >> (127 - index)) & 1) as usize
+}
+
+impl Cidr {
+    /// Creates the network of the first `prefix_len` bits of `addr`
+    pub fn new<A>(addr: A, prefix_len: u8) -> Result<Self, CidrError>
+    where
+        A: Into<IpAddr>,
+    {
+        let addr = addr.into();
+        if prefix_len > width(addr) {
+            return Err(CidrError::InvalidPrefixLength);
+        }
+
+        let bits = mask(to_bits(addr), prefix_len);
+        let addr = match addr {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from((bits >> 96) as u32)),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(bits)),
+        };
+        Ok(Self { addr, prefix_len })
+    }
+
+    /// Returns the first address of the network
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns how many leading bits of the address are fixed
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns `true` if `ip` belongs to this network
+    pub fn contains<A>(&self, ip: A) -> bool
+    where
+        A: Into<IpAddr>,
+    {
+        let ip = ip.into();
+        ip.is_ipv4() == self.addr.is_ipv4()
+            && mask(to_bits(ip), self.prefix_len) == to_bits(self.addr)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Parses `address/length`, or a lone address as a network of that address
+/// only
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr.parse().map_err(|_| CidrError::InvalidAddress)?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .map_err(|_| CidrError::InvalidPrefixLength)?,
+            None => width(addr),
+        };
+        Self::new(addr, prefix_len)
+    }
+}
+
+type Link<V> = Option<Box<Node<V>>>;
+
+#[derive(Clone)]
+struct Node<V> {
+    /// the prefix this node stands for, the bits after `len` cleared
+    bits: u128,
+    len: u8,
+    value: Option<V>,
+    /// the subtrees whose prefixes continue with a 0 and a 1 bit after `len`
+    children: [Link<V>; 2],
+}
+
+impl<V> Node<V> {
+    fn new(bits: u128, len: u8, value: Option<V>) -> Box<Self> {
+        Box::new(Self {
+            bits,
+            len,
+            value,
+            children: [None, None],
+        })
+    }
+
+    /// Returns how many leading bits of this prefix and `bits` agree, at most
+    /// `len` and the length of this prefix
+    fn common_len(&self, bits: u128, len: u8) -> u8 {
+        let differing = (self.bits ^ bits).leading_zeros() as u8;
+        differing.min(self.len).min(len)
+    }
+}
+
+/// A map from IP networks to values answering longest-prefix matches, as a
+/// routing table or an access control list
+///
+/// Networks are stored in a binary trie over the bits of their addresses,
+/// with IPv4 and IPv6 in separate tries. Like a Patricia trie, a node is only
+/// kept for a stored network or where two branches fork, and each node
+/// records its whole prefix so that runs of bits with no fork are skipped in
+/// one comparison. Every operation takes at most one step per address bit,
+/// 32 or 128, however many networks are stored.
+#[derive(Clone)]
+pub struct PatriciaTrie<V> {
+    v4: Link<V>,
+    v6: Link<V>,
+    len: usize,
+}
+
+impl<V> PatriciaTrie<V> {
+    pub fn new() -> Self {
+        Self {
+            v4: None,
+            v6: None,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of networks in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the trie contains no networks
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all networks
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    fn root(&self, addr: IpAddr) -> &Link<V> {
+        match addr {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => &self.v6,
+        }
+    }
+
+    fn root_mut(&mut self, addr: IpAddr) -> &mut Link<V> {
+        match addr {
+            IpAddr::V4(_) => &mut self.v4,
+            IpAddr::V6(_) => &mut self.v6,
+        }
+    }
+
+    /// Stores `value` under `cidr`, returning the value previously stored
+    /// there
+    pub fn insert(&mut self, cidr: Cidr, value: V) -> Option<V> {
+        let (bits, len) = (to_bits(cidr.addr), cidr.prefix_len);
+        let old = Self::insert_into(self.root_mut(cidr.addr), bits, len, value);
+
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    fn insert_into(link: &mut Link<V>, bits: u128, len: u8, value: V) -> Option<V> {
+        let Some(node) = link else {
+            *link = Some(Node::new(bits, len, Some(value)));
+            return None;
+        };
+
+        let common = node.common_len(bits, len);
+        if common == node.len {
+            if len == node.len {
+                return node.value.replace(value);
+            }
+            return Self::insert_into(&mut node.children[bit(bits, node.len)], bits, len, value);
+        }
+
+        // the new network forks off the path to `node`, or lies on it
+        let old = link.take().expect("checked above");
+        let mut fork = if common == len {
+            Node::new(bits, len, Some(value))
+        } else {
+            let mut fork = Node::new(mask(bits, common), common, None);
+            fork.children[bit(bits, common)] = Some(Node::new(bits, len, Some(value)));
+            fork
+        };
+        let side = bit(old.bits, common);
+        fork.children[side] = Some(old);
+        *link = Some(fork);
+        None
+    }
+
+    /// Returns the value stored under exactly `cidr`
+    pub fn get(&self, cidr: Cidr) -> Option<&V> {
+        let (bits, len) = (to_bits(cidr.addr), cidr.prefix_len);
+        let mut link = self.root(cidr.addr);
+
+        while let Some(node) = link {
+            if node.common_len(bits, len) < node.len {
+                return None;
+            }
+            if node.len == len {
+                return node.value.as_ref();
+            }
+            link = &node.children[bit(bits, node.len)];
+        }
+        None
+    }
+
+    /// Removes `cidr` from the trie, returning the value stored under it
+    ///
+    /// A node left without a value and with fewer than two children is
+    /// removed, or replaced by its child.
+    pub fn remove(&mut self, cidr: Cidr) -> Option<V> {
+        let (bits, len) = (to_bits(cidr.addr), cidr.prefix_len);
+        let value = Self::remove_from(self.root_mut(cidr.addr), bits, len)?;
+
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn remove_from(link: &mut Link<V>, bits: u128, len: u8) -> Option<V> {
+        let node = link.as_mut()?;
+        if node.common_len(bits, len) < node.len {
+            return None;
+        }
+
+        let value = if node.len == len {
+            node.value.take()?
+        } else {
+            Self::remove_from(&mut node.children[bit(bits, node.len)], bits, len)?
+        };
+
+        if node.value.is_none() {
+            match &mut node.children {
+                [None, None] => *link = None,
+                [child @ Some(_), None] | [None, child @ Some(_)] => *link = child.take(),
+                _ => {}
+            }
+        }
+        Some(value)
+    }
+
+    /// Returns the value of the most specific network containing `ip`
+    pub fn longest_match<A>(&self, ip: A) -> Option<&V>
+    where
+        A: Into<IpAddr>,
+    {
+        self.longest_match_entry(ip).map(|(_, value)| value)
+    }
+
+    /// Returns the most specific network containing `ip` and its value
+    pub fn longest_match_entry<A>(&self, ip: A) -> Option<(Cidr, &V)>
+    where
+        A: Into<IpAddr>,
+    {
+        let ip = ip.into();
+        let (bits, width) = (to_bits(ip), width(ip));
+        let mut link = self.root(ip);
+        let mut best = None;
+
+        while let Some(node) = link {
+            if node.common_len(bits, width) < node.len {
+                break;
+            }
+            if let Some(value) = &node.value {
+                best = Some((node.len, value));
+            }
+            if node.len == width {
+                break;
+            }
+            link = &node.children[bit(bits, node.len)];
+        }
+
+        best.map(|(len, value)| (Cidr::new(ip, len).expect("len fits the address"), value))
+    }
+
+    /// Returns an iterator over the networks and their values, IPv4 ones
+    /// first, each family in address order with a network before the ones it
+    /// contains
+    pub fn iter(&self) -> PatriciaTrieIterator<'_, V> {
+        let mut stack = Vec::new();
+        stack.extend(self.v6.as_deref().map(|node| (false, node)));
+        stack.extend(self.v4.as_deref().map(|node| (true, node)));
+
+        PatriciaTrieIterator { stack }
+    }
+
+    /// Returns `true` if every node extends the prefix of its parent on the
+    /// side of its child slot, every node holds a value or has two children
+    /// and `len` matches the number of values
+    pub fn is_valid(&self) -> bool {
+        let values = Self::check(&self.v4, 32).zip(Self::check(&self.v6, 128));
+        values.is_some_and(|(v4, v6)| v4 + v6 == self.len)
+    }
+
+    /// Returns the number of values in the trie at `root`, if its nodes are
+    /// valid for addresses of `width` bits
+    fn check(root: &Link<V>, width: u8) -> Option<usize> {
+        let mut values = 0;
+        let mut stack = Vec::new();
+        stack.extend(root.as_deref().map(|node| (node, None)));
+
+        while let Some((node, parent)) = stack.pop() {
+            let extends_parent = parent.is_none_or(|(parent, side): (&Node<V>, usize)| {
+                node.len > parent.len
+                    && node.common_len(parent.bits, parent.len) == parent.len
+                    && bit(node.bits, parent.len) == side
+            });
+            let has_two_children = node.children.iter().all(Option::is_some);
+
+            if !extends_parent
+                || node.len > width
+                || mask(node.bits, node.len) != node.bits
+                || (node.value.is_none() && !has_two_children)
+            {
+                return None;
+            }
+
+            values += usize::from(node.value.is_some());
+            for (side, child) in node.children.iter().enumerate() {
+                stack.extend(child.as_deref().map(|child| (child, Some((node, side)))));
+            }
+        }
+        Some(values)
+    }
+}
+
+impl<V> Default for PatriciaTrie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> fmt::Debug for PatriciaTrie<V>
+where
+    V: fmt::Debug,
+{
+    /// Formats the networks in order, like a map
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(cidr, value)| (cidr.to_string(), value)))
+            .finish()
+    }
+}
+
+impl<V> FromIterator<(Cidr, V)> for PatriciaTrie<V> {
+    fn from_iter<I: IntoIterator<Item = (Cidr, V)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<V> Extend<(Cidr, V)> for PatriciaTrie<V> {
+    fn extend<I: IntoIterator<Item = (Cidr, V)>>(&mut self, iter: I) {
+        for (cidr, value) in iter {
+            self.insert(cidr, value);
+        }
+    }
+}
+
+/// An iterator over the networks of a [`PatriciaTrie`] and their values
+pub struct PatriciaTrieIterator<'a, V> {
+    /// nodes left to visit and whether they are IPv4, the next one on top
+    stack: Vec<(bool, &'a Node<V>)>,
+}
+
+impl<'a, V> Iterator for PatriciaTrieIterator<'a, V> {
+    type Item = (Cidr, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (is_v4, node) = self.stack.pop()?;
+            for child in node.children.iter().rev() {
+                self.stack
+                    .extend(child.as_deref().map(|child| (is_v4, child)));
+            }
+
+            if let Some(value) = &node.value {
+                let addr = if is_v4 {
+                    IpAddr::V4(Ipv4Addr::from((node.bits >> 96) as u32))
+                } else {
+                    IpAddr::V6(Ipv6Addr::from(node.bits))
+                };
+                return Some((Cidr::new(addr, node.len).expect("a stored network"), value));
+            }
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a PatriciaTrie<V> {
+    type Item = (Cidr, &'a V);
+    type IntoIter = PatriciaTrieIterator<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    fn routing_table() -> PatriciaTrie<&'static str> {
+        [
+            ("0.0.0.0/0", "default"),
+            ("10.0.0.0/8", "private"),
+            ("10.1.0.0/16", "office"),
+            ("10.1.2.0/24", "lab"),
+            ("192.168.1.0/24", "home"),
+            ("2001:db8::/32", "docs"),
+            ("2001:db8:1::/48", "docs-site"),
+        ]
+        .into_iter()
+        .map(|(network, hop)| (cidr(network), hop))
+        .collect()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        assert_eq!(cidr("10.1.2.3/8"), cidr("10.0.0.0/8"));
+        assert_eq!(cidr("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert_eq!(cidr("::1").prefix_len(), 128);
+        assert_eq!(cidr("1.2.3.4").prefix_len(), 32);
+        assert_eq!(cidr("0.0.0.0/0").addr(), ip("0.0.0.0"));
+        assert!(cidr("10.0.0.0/8").contains(ip("10.200.0.1")));
+        assert!(!cidr("10.0.0.0/8").contains(ip("11.0.0.1")));
+        assert!(!cidr("0.0.0.0/0").contains(ip("::1")));
+        assert_eq!(
+            "10.0.0.0/33".parse::<Cidr>(),
+            Err(CidrError::InvalidPrefixLength)
+        );
+        assert_eq!(
+            "10.0.0.0/x".parse::<Cidr>(),
+            Err(CidrError::InvalidPrefixLength)
+        );
+        assert_eq!("10.0.0/8".parse::<Cidr>(), Err(CidrError::InvalidAddress));
+        assert_eq!(
+            Cidr::new(Ipv6Addr::LOCALHOST, 129),
+            Err(CidrError::InvalidPrefixLength)
+        );
+    }
+
+    #[test]
+    fn test_longest_match() {
+        let table = routing_table();
+
+        assert_eq!(table.len(), 7);
+        assert!(table.is_valid());
+        assert_eq!(table.longest_match(ip("10.1.2.3")), Some(&"lab"));
+        assert_eq!(table.longest_match(ip("10.1.3.3")), Some(&"office"));
+        assert_eq!(
+            table.longest_match(Ipv4Addr::new(10, 2, 0, 1)),
+            Some(&"private")
+        );
+        assert_eq!(table.longest_match(ip("8.8.8.8")), Some(&"default"));
+        assert_eq!(table.longest_match(ip("2001:db8:1::5")), Some(&"docs-site"));
+        assert_eq!(table.longest_match(ip("2001:db8:2::5")), Some(&"docs"));
+        assert_eq!(table.longest_match(ip("2001:db9::")), None);
+        assert_eq!(
+            table.longest_match_entry(ip("192.168.1.77")),
+            Some((cidr("192.168.1.0/24"), &"home"))
+        );
+        assert_eq!(table.get(cidr("10.1.0.0/16")), Some(&"office"));
+        assert_eq!(table.get(cidr("10.1.0.0/17")), None);
+        assert_eq!(
+            table
+                .iter()
+                .map(|(network, _)| network.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "0.0.0.0/0",
+                "10.0.0.0/8",
+                "10.1.0.0/16",
+                "10.1.2.0/24",
+                "192.168.1.0/24",
+                "2001:db8::/32",
+                "2001:db8:1::/48",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut table = routing_table();
+
+        assert_eq!(table.insert(cidr("10.1.2.0/24"), "lab-2"), Some("lab"));
+        assert_eq!(table.insert(cidr("10.1.2.128/25"), "rack"), None);
+        assert_eq!(table.longest_match(ip("10.1.2.200")), Some(&"rack"));
+        assert_eq!(table.longest_match(ip("10.1.2.100")), Some(&"lab-2"));
+
+        assert_eq!(table.remove(cidr("10.1.0.0/16")), Some("office"));
+        assert_eq!(table.remove(cidr("10.1.0.0/16")), None);
+        assert_eq!(table.remove(cidr("10.0.0.0/9")), None);
+        assert_eq!(table.longest_match(ip("10.1.3.3")), Some(&"private"));
+        assert_eq!(table.remove(cidr("0.0.0.0/0")), Some("default"));
+        assert_eq!(table.longest_match(ip("8.8.8.8")), None);
+        assert!(table.is_valid());
+
+        // host routes and many siblings
+        for host in 0..=255u8 {
+            table.insert(
+                Cidr::new(Ipv4Addr::new(172, 16, 0, host), 32).unwrap(),
+                "host",
+            );
+        }
+        assert!(table.is_valid());
+        assert_eq!(table.len(), 6 + 256);
+        for host in (0..=255u8).step_by(2) {
+            assert_eq!(
+                table.remove(Cidr::new(Ipv4Addr::new(172, 16, 0, host), 32).unwrap()),
+                Some("host")
+            );
+        }
+        assert!(table.is_valid());
+        assert_eq!(table.longest_match(ip("172.16.0.3")), Some(&"host"));
+        assert_eq!(table.longest_match(ip("172.16.0.4")), None);
+    }
+}
@@ -0,0 +1,652 @@
+//! Persistent trees, where every update returns a new version and leaves the
+//! old one intact
+//!
+//! Versions share all the nodes an update did not touch, so keeping many of
+//! them around (snapshots, undo stacks) costs one path per update.
+
+mod red_black_map;
+
+pub use red_black_map::{RedBlackMap, RedBlackMapIterator};
+
+use std::{borrow::Borrow, cmp::Ordering, collections::BTreeSet, fmt, mem, sync::Arc};
+
+type Link<T> = Option<Arc<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+    /// number of elements stored in this subtree
+    size: usize,
+}
+
+impl<T> Node<T> {
+    fn new(data: T, left: Link<T>, right: Link<T>) -> Arc<Self> {
+        let size = 1 + subtree_size(&left) + subtree_size(&right);
+
+        Arc::new(Self {
+            data,
+            left,
+            right,
+            size,
+        })
+    }
+}
+
+fn subtree_size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// A persistent binary search tree behaving like a set
+///
+/// `insert` and `remove` copy the path from the root to the changed node and
+/// share everything else with `self`, so cloning is O(1) and the tree can be
+/// sent across threads when `T` can.
+///
+/// The tree can also be used as a copy-on-write mutable tree: `insert_in_place`
+/// and `remove_in_place` update nodes owned only by `self` directly and copy
+/// just the shared nodes on their path, so taking a snapshot with `clone` is
+/// cheap even for large trees that keep changing.
+pub struct BinarySearchTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+}
+
+impl<T> BinarySearchTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        subtree_size(&self.root)
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            match key.cmp(current.data.borrow()) {
+                Ordering::Equal => return Some(&current.data),
+                Ordering::Less => node = current.left.as_deref(),
+                Ordering::Greater => node = current.right.as_deref(),
+            }
+        }
+
+        None
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+
+        Some(&node.data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+
+        Some(&node.data)
+    }
+
+    /// Returns the number of heap bytes used by the nodes of this version
+    ///
+    /// Nodes shared with other versions are counted too, so the sum over
+    /// several versions overstates their combined footprint.
+    pub fn memory_usage(&self) -> usize {
+        // each node lives in its own `Arc` allocation next to the two counters
+        self.len() * (mem::size_of::<Node<T>>() + 2 * mem::size_of::<usize>())
+    }
+
+    /// Returns `true` if both trees are the same version, i.e. share their root
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns a new iterator over the elements in order
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            remaining: self.len(),
+        };
+        iter.push_left(self.root.as_deref());
+        iter
+    }
+}
+
+impl<T> BinarySearchTree<T>
+where
+    T: Ord + Clone,
+{
+    /// Returns a new version of the tree which also holds `data`
+    ///
+    /// If an equal element is already stored the new version shares the whole
+    /// tree with `self`.
+    pub fn insert(&self, data: T) -> Self {
+        let mut path = Vec::new();
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            let turn = data.cmp(&node.data);
+            if turn == Ordering::Equal {
+                return self.clone();
+            }
+
+            path.push((node, turn));
+            link = match turn {
+                // data < stored_data
+                Ordering::Less => &node.left,
+                // data > stored_data
+                _ => &node.right,
+            };
+        }
+
+        Self {
+            root: Self::copy_path(path, Some(Node::new(data, None, None))),
+        }
+    }
+
+    /// Returns a new version of the tree without the element equal to `key`
+    ///
+    /// If there is no such element the new version shares the whole tree with
+    /// `self`.
+    pub fn remove<Q>(&self, key: &Q) -> Self
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut path = Vec::new();
+        let mut link = &self.root;
+
+        let target = loop {
+            let Some(node) = link else {
+                return self.clone();
+            };
+
+            let turn = key.cmp(node.data.borrow());
+            if turn == Ordering::Equal {
+                break node;
+            }
+
+            path.push((node, turn));
+            link = match turn {
+                Ordering::Less => &node.left,
+                _ => &node.right,
+            };
+        };
+
+        let replacement = match (&target.left, &target.right) {
+            (None, None) => None,
+            (Some(child), None) | (None, Some(child)) => Some(Arc::clone(child)),
+            // replace with the in-order successor, the leftmost of the right
+            (Some(left), Some(right)) => {
+                let mut successor_path = Vec::new();
+                let mut successor = right;
+
+                while let Some(next) = &successor.left {
+                    successor_path.push((successor, Ordering::Less));
+                    successor = next;
+                }
+
+                let right = Self::copy_path(successor_path, successor.right.clone());
+                Some(Node::new(
+                    successor.data.clone(),
+                    Some(Arc::clone(left)),
+                    right,
+                ))
+            }
+        };
+
+        Self {
+            root: Self::copy_path(path, replacement),
+        }
+    }
+
+    /// Inserts `data` into this version, returning whether it was inserted
+    ///
+    /// Only the nodes on the path which are shared with another version are
+    /// copied, the rest are updated in place.
+    pub fn insert_in_place(&mut self, data: T) -> bool {
+        if self.contains(&data) {
+            return false;
+        }
+
+        let mut link = &mut self.root;
+
+        while link.is_some() {
+            let node = Arc::make_mut(link.as_mut().expect("checked by the loop"));
+            node.size += 1;
+
+            link = if data < node.data {
+                &mut node.left
+            } else {
+                &mut node.right
+            };
+        }
+
+        *link = Some(Node::new(data, None, None));
+        true
+    }
+
+    /// Removes the element equal to `key` from this version and returns it
+    ///
+    /// Like [`Self::insert_in_place`], shared nodes on the path are copied
+    /// and the rest are updated in place.
+    pub fn remove_in_place<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if !self.contains(key) {
+            return None;
+        }
+
+        let mut link = &mut self.root;
+
+        loop {
+            let node = link.as_ref().expect("key is present");
+
+            let turn = key.cmp(node.data.borrow());
+            if turn == Ordering::Equal {
+                break;
+            }
+
+            let node = Arc::make_mut(link.as_mut().expect("key is present"));
+            node.size -= 1;
+
+            link = match turn {
+                Ordering::Less => &mut node.left,
+                _ => &mut node.right,
+            };
+        }
+
+        let target = link.take().expect("key is present");
+        let Node {
+            data, left, right, ..
+        } = Arc::unwrap_or_clone(target);
+
+        *link = match (left, right) {
+            (None, None) => None,
+            (Some(child), None) | (None, Some(child)) => Some(child),
+            // replace with the in-order successor, the leftmost of the right
+            (Some(left), Some(right)) => {
+                let mut right = Some(right);
+                let successor = Self::pop_min_in_place(&mut right);
+                Some(Node::new(successor, Some(left), right))
+            }
+        };
+
+        Some(data)
+    }
+
+    /// Removes and returns the smallest element under the non-empty `link`
+    fn pop_min_in_place(mut link: &mut Link<T>) -> T {
+        while link.as_ref().is_some_and(|node| node.left.is_some()) {
+            let node = Arc::make_mut(link.as_mut().expect("checked by the loop"));
+            node.size -= 1;
+            link = &mut node.left;
+        }
+
+        let min = link.take().expect("link is not empty");
+        let Node { data, right, .. } = Arc::unwrap_or_clone(min);
+        *link = right;
+        data
+    }
+
+    /// Rebuilds the nodes along `path`, bottom-up, so that the last turn leads
+    /// to `link`
+    ///
+    /// The subtrees hanging off the path are shared, not copied.
+    fn copy_path(path: Vec<(&Arc<Node<T>>, Ordering)>, mut link: Link<T>) -> Link<T> {
+        for (node, turn) in path.into_iter().rev() {
+            link = Some(match turn {
+                Ordering::Less => Node::new(node.data.clone(), link, node.right.clone()),
+                _ => Node::new(node.data.clone(), node.left.clone(), link),
+            });
+        }
+
+        link
+    }
+}
+
+impl<T> BinarySearchTree<T>
+where
+    T: Ord,
+{
+    /// Builds a height-balanced tree from sorted elements without duplicates
+    fn from_sorted_vec(sorted: Vec<T>) -> Self {
+        debug_assert!(sorted.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let len = sorted.len();
+        Self {
+            root: Self::build_balanced(&mut sorted.into_iter(), len),
+        }
+    }
+
+    /// Builds a balanced subtree out of the next `len` elements of `iter`
+    fn build_balanced<I>(iter: &mut I, len: usize) -> Link<T>
+    where
+        I: Iterator<Item = T>,
+    {
+        if len == 0 {
+            return None;
+        }
+
+        let left_len = len / 2;
+        let left = Self::build_balanced(iter, left_len);
+        let data = iter.next().expect("iter holds len elements");
+        let right = Self::build_balanced(iter, len - left_len - 1);
+
+        Some(Node::new(data, left, right))
+    }
+}
+
+impl<T> Clone for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    /// O(1), the clone shares every node with `self`
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<T> Default for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Releases nodes iteratively, so that dropping the last version of a deep,
+/// skewed tree can't overflow the stack
+impl<T> Drop for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn drop(&mut self) {
+        let mut stack: Vec<Arc<Node<T>>> = self.root.take().into_iter().collect();
+
+        while let Some(node) = stack.pop() {
+            // nodes still shared with another version are left alone
+            if let Ok(mut node) = Arc::try_unwrap(node) {
+                stack.extend(node.left.take());
+                stack.extend(node.right.take());
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for BinarySearchTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T> PartialEq for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || (self.len() == other.len() && self.iter().eq(other.iter()))
+    }
+}
+
+impl<T> Eq for BinarySearchTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for BinarySearchTree<T>
+where
+    T: Ord + Clone,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::new(), |tree, data| tree.insert(data))
+    }
+}
+
+/// Sorts the elements and builds a height-balanced tree, keeping the first of
+/// equal elements
+impl<T> From<Vec<T>> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn from(mut elements: Vec<T>) -> Self {
+        elements.sort();
+        elements.dedup();
+        Self::from_sorted_vec(elements)
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn from(elements: [T; N]) -> Self {
+        Self::from(Vec::from(elements))
+    }
+}
+
+impl<T> From<BTreeSet<T>> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn from(set: BTreeSet<T>) -> Self {
+        Self::from_sorted_vec(set.into_iter().collect())
+    }
+}
+
+/// Clones the elements, since other versions may still share them
+impl<T> From<BinarySearchTree<T>> for Vec<T>
+where
+    T: Ord + Clone,
+{
+    fn from(tree: BinarySearchTree<T>) -> Self {
+        tree.iter().cloned().collect()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BinarySearchTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An in-order iterator over a persistent [`BinarySearchTree`]
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+
+    #[test]
+    fn test_versions_are_independent() {
+        let v1: BinarySearchTree<u32> = prequel();
+        let v2 = v1.insert(10);
+        let v3 = v2.remove(&16).remove(&3);
+
+        assert_eq!(
+            v1.iter().copied().collect::<Vec<_>>(),
+            vec![3, 7, 16, 21, 28, 36, 70]
+        );
+        assert_eq!(
+            v2.iter().copied().collect::<Vec<_>>(),
+            vec![3, 7, 10, 16, 21, 28, 36, 70]
+        );
+        assert_eq!(
+            v3.iter().copied().collect::<Vec<_>>(),
+            vec![7, 10, 21, 28, 36, 70]
+        );
+        assert_eq!(v3.len(), 6);
+        assert!(v1.contains(&16));
+        assert!(!v3.contains(&16));
+        assert_eq!(v3.min(), Some(&7));
+        assert_eq!(v3.max(), Some(&70));
+        assert_eq!(v3.memory_usage() * 7, v1.memory_usage() * 6);
+    }
+
+    #[test]
+    fn test_structural_sharing() {
+        let v1: BinarySearchTree<u32> = prequel();
+
+        // no-op updates share the whole tree
+        assert!(v1.insert(28).ptr_eq(&v1));
+        assert!(v1.remove(&29).ptr_eq(&v1));
+
+        // inserting on the left leaves the right subtree shared
+        let v2 = v1.insert(1);
+        let (Some(a), Some(b)) = (&v1.root, &v2.root) else {
+            panic!("both versions are non-empty");
+        };
+        assert!(!Arc::ptr_eq(a, b));
+        assert!(Arc::ptr_eq(
+            a.right.as_ref().unwrap(),
+            b.right.as_ref().unwrap()
+        ));
+
+        assert_eq!(v1.clone(), v1);
+        assert_ne!(v1, v2);
+        assert_eq!(v2.remove(&1), v1);
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut tree: BinarySearchTree<u32> = prequel();
+        let snapshot = tree.clone();
+
+        assert!(tree.insert_in_place(10));
+        assert!(!tree.insert_in_place(10));
+        assert_eq!(tree.remove_in_place(&16), Some(16));
+        assert_eq!(tree.remove_in_place(&16), None);
+        assert_eq!(tree.remove_in_place(&3), Some(3));
+
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            vec![7, 10, 21, 28, 36, 70]
+        );
+        assert_eq!(tree.len(), 6);
+        assert_eq!(snapshot, prequel());
+
+        // untouched subtrees are still shared with the snapshot
+        let (Some(a), Some(b)) = (&tree.root, &snapshot.root) else {
+            panic!("both versions are non-empty");
+        };
+        let (Some(a), Some(b)) = (&a.right, &b.right) else {
+            panic!("both roots have a right child");
+        };
+        assert!(Arc::ptr_eq(
+            a.right.as_ref().unwrap(),
+            b.right.as_ref().unwrap()
+        ));
+
+        // once unshared, nodes are updated in place
+        drop(snapshot);
+        let root = Arc::as_ptr(tree.root.as_ref().unwrap());
+        assert!(tree.insert_in_place(1));
+        assert_eq!(Arc::as_ptr(tree.root.as_ref().unwrap()), root);
+        assert_eq!(tree.min(), Some(&1));
+    }
+
+    #[test]
+    fn test_std_conversions() {
+        let tree = BinarySearchTree::from(vec![28, 7, 16, 3, 70, 21, 36, 7]);
+        assert_eq!(tree, prequel());
+        assert_eq!(tree.len(), 7);
+
+        let tree = BinarySearchTree::from(BTreeSet::from([2, 1, 3]));
+        assert_eq!(tree.root.as_ref().map(|root| root.data), Some(2));
+        assert_eq!(Vec::from(tree.insert(0)), vec![0, 1, 2, 3]);
+        assert_eq!(Vec::from(tree), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_deep_tree_drop() {
+        // ascending inserts degenerate into a list
+        let tree: BinarySearchTree<u32> = (0..3_000).collect();
+        let older = tree.remove(&0);
+        assert_eq!(tree.len(), 3_000);
+        assert_eq!(older.len(), 2_999);
+        drop(tree);
+        assert_eq!(older.min(), Some(&1));
+    }
+}
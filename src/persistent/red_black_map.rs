@@ -0,0 +1,654 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    fmt,
+    sync::Arc,
+};
+
+type Link<K, V> = Option<Arc<Node<K, V>>>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Color {
+    Red,
+    Black,
+}
+
+use Color::{Black, Red};
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    color: Color,
+    left: Link<K, V>,
+    right: Link<K, V>,
+    /// number of entries stored in this subtree
+    size: usize,
+}
+
+impl<K, V> Node<K, V> {
+    fn new(color: Color, left: Link<K, V>, (key, value): (K, V), right: Link<K, V>) -> Link<K, V> {
+        let size = 1 + size(&left) + size(&right);
+
+        Some(Arc::new(Self {
+            key,
+            value,
+            color,
+            left,
+            right,
+            size,
+        }))
+    }
+}
+
+impl<K, V> Node<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    fn entry(&self) -> (K, V) {
+        (self.key.clone(), self.value.clone())
+    }
+
+    /// Copies this node with another color, sharing both children
+    fn recolor(&self, color: Color) -> Link<K, V> {
+        Node::new(color, self.left.clone(), self.entry(), self.right.clone())
+    }
+}
+
+fn size<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// Returns the node at `link` if it is red
+fn red<K, V>(link: &Link<K, V>) -> Option<&Arc<Node<K, V>>> {
+    link.as_ref().filter(|node| node.color == Red)
+}
+
+/// Returns `true` if there is a black node at `link`, not just a leaf
+fn is_black_node<K, V>(link: &Link<K, V>) -> bool {
+    link.as_ref().is_some_and(|node| node.color == Black)
+}
+
+/// A persistent ordered map kept balanced as a red-black tree
+///
+/// `insert` and `remove` return a new version in `O(log n)`, copying only the
+/// path to the changed entry and the few nodes the rebalancing recolors or
+/// rotates, and sharing everything else with `self`. Versions are immutable
+/// and share nodes through `Arc`, so a snapshot can be cloned in O(1) and read
+/// from other threads while a writer keeps deriving newer versions.
+///
+/// Insertion follows Okasaki's functional balancing and removal follows
+/// Kahrs', both of which only rebuild nodes instead of mutating them.
+pub struct RedBlackMap<K, V>
+where
+    K: Ord,
+{
+    root: Link<K, V>,
+}
+
+impl<K, V> RedBlackMap<K, V>
+where
+    K: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Returns the number of entries in the map
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    /// Returns `true` if the map contains no entries
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        fn height<K, V>(link: &Link<K, V>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    /// Returns `true` if an entry is stored under `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Returns the stored key equal to `key` and its value
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            match key.cmp(current.key.borrow()) {
+                Equal => return Some((&current.key, &current.value)),
+                Less => node = current.left.as_deref(),
+                Greater => node = current.right.as_deref(),
+            }
+        }
+        None
+    }
+
+    /// Returns the entry with the smallest key
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    /// Returns the entry with the largest key
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    /// Returns `true` if both maps are the same version, i.e. share their root
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns a new iterator over the entries in key order
+    pub fn iter(&self) -> RedBlackMapIterator<'_, K, V> {
+        let mut iter = RedBlackMapIterator {
+            stack: Vec::new(),
+            remaining: self.len(),
+        };
+        iter.push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the keys are in order, the root is black, no red
+    /// node has a red child, every path from the root to a leaf has the same
+    /// number of black nodes and every size is correct
+    pub fn is_valid(&self) -> bool {
+        fn black_height<K: Ord, V>(
+            link: &Link<K, V>,
+            lower: Option<&K>,
+            upper: Option<&K>,
+        ) -> Option<usize> {
+            let Some(node) = link else {
+                return Some(1);
+            };
+
+            let in_order = lower.is_none_or(|lower| *lower < node.key)
+                && upper.is_none_or(|upper| node.key < *upper);
+            let red_red =
+                node.color == Red && (red(&node.left).is_some() || red(&node.right).is_some());
+            if !in_order || red_red || node.size != 1 + size(&node.left) + size(&node.right) {
+                return None;
+            }
+
+            let left = black_height(&node.left, lower, Some(&node.key))?;
+            let right = black_height(&node.right, Some(&node.key), upper)?;
+            (left == right).then_some(left + usize::from(node.color == Black))
+        }
+
+        red(&self.root).is_none() && black_height(&self.root, None, None).is_some()
+    }
+}
+
+impl<K, V> RedBlackMap<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Returns a new version of the map with `value` stored under `key`
+    ///
+    /// Like `BTreeMap`, the stored key is kept when its value is replaced.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        Self {
+            root: blacken(Self::insert_into(&self.root, key, value)),
+        }
+    }
+
+    /// Returns a new version of the map without the entry stored under `key`
+    ///
+    /// If there is no such entry the new version shares the whole map with
+    /// `self`.
+    pub fn remove<Q>(&self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // the rebalancing below relies on the subtree losing an entry
+        if !self.contains_key(key) {
+            return self.clone();
+        }
+
+        Self {
+            root: blacken(Self::remove_from(&self.root, key)),
+        }
+    }
+
+    /// Returns a copy of the subtree at `link` holding the new entry, whose
+    /// root may be red with a red child
+    fn insert_into(link: &Link<K, V>, key: K, value: V) -> Link<K, V> {
+        let Some(node) = link else {
+            return Node::new(Red, None, (key, value), None);
+        };
+
+        match key.cmp(&node.key) {
+            Less => {
+                let left = Self::insert_into(&node.left, key, value);
+                match node.color {
+                    Black => balance(left, node.entry(), node.right.clone()),
+                    Red => Node::new(Red, left, node.entry(), node.right.clone()),
+                }
+            }
+            Greater => {
+                let right = Self::insert_into(&node.right, key, value);
+                match node.color {
+                    Black => balance(node.left.clone(), node.entry(), right),
+                    Red => Node::new(Red, node.left.clone(), node.entry(), right),
+                }
+            }
+            Equal => Node::new(
+                node.color,
+                node.left.clone(),
+                (node.key.clone(), value),
+                node.right.clone(),
+            ),
+        }
+    }
+
+    /// Returns a copy of the subtree at `link` without `key`, which must be
+    /// stored there
+    ///
+    /// Removing from a black subtree leaves it one black node short, which
+    /// `balance_left` and `balance_right` make up for on the way back up.
+    fn remove_from<Q>(link: &Link<K, V>, key: &Q) -> Link<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node = link.as_ref().expect("key is present");
+
+        match key.cmp(node.key.borrow()) {
+            Less => {
+                let left = Self::remove_from(&node.left, key);
+                if is_black_node(&node.left) {
+                    balance_left(left, node.entry(), node.right.clone())
+                } else {
+                    Node::new(Red, left, node.entry(), node.right.clone())
+                }
+            }
+            Greater => {
+                let right = Self::remove_from(&node.right, key);
+                if is_black_node(&node.right) {
+                    balance_right(node.left.clone(), node.entry(), right)
+                } else {
+                    Node::new(Red, node.left.clone(), node.entry(), right)
+                }
+            }
+            Equal => append(&node.left, &node.right),
+        }
+    }
+}
+
+/// Rebuilds a black node over `left` and `right`, rotating away a red node
+/// with a red child below it
+fn balance<K, V>(left: Link<K, V>, entry: (K, V), right: Link<K, V>) -> Link<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    if let (Some(l), Some(r)) = (red(&left), red(&right)) {
+        return Node::new(Red, l.recolor(Black), entry, r.recolor(Black));
+    }
+
+    if let Some(l) = red(&left) {
+        if let Some(ll) = red(&l.left) {
+            let right = Node::new(Black, l.right.clone(), entry, right);
+            return Node::new(Red, ll.recolor(Black), l.entry(), right);
+        }
+        if let Some(lr) = red(&l.right) {
+            let left = Node::new(Black, l.left.clone(), l.entry(), lr.left.clone());
+            let right = Node::new(Black, lr.right.clone(), entry, right);
+            return Node::new(Red, left, lr.entry(), right);
+        }
+    }
+
+    if let Some(r) = red(&right) {
+        if let Some(rr) = red(&r.right) {
+            let left = Node::new(Black, left, entry, r.left.clone());
+            return Node::new(Red, left, r.entry(), rr.recolor(Black));
+        }
+        if let Some(rl) = red(&r.left) {
+            let left = Node::new(Black, left, entry, rl.left.clone());
+            let right = Node::new(Black, rl.right.clone(), r.entry(), r.right.clone());
+            return Node::new(Red, left, rl.entry(), right);
+        }
+    }
+
+    Node::new(Black, left, entry, right)
+}
+
+/// Rebuilds a node whose `left` subtree is one black node short
+fn balance_left<K, V>(left: Link<K, V>, entry: (K, V), right: Link<K, V>) -> Link<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    if let Some(l) = red(&left) {
+        return Node::new(Red, l.recolor(Black), entry, right);
+    }
+
+    let r = right.as_ref().expect("the taller side is not empty");
+    match r.color {
+        Black => balance(left, entry, r.recolor(Red)),
+        Red => {
+            let rl = r.left.as_ref().expect("a red node has black children");
+            let left = Node::new(Black, left, entry, rl.left.clone());
+            let right = balance(rl.right.clone(), r.entry(), redden(&r.right));
+            Node::new(Red, left, rl.entry(), right)
+        }
+    }
+}
+
+/// Rebuilds a node whose `right` subtree is one black node short
+fn balance_right<K, V>(left: Link<K, V>, entry: (K, V), right: Link<K, V>) -> Link<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    if let Some(r) = red(&right) {
+        return Node::new(Red, left, entry, r.recolor(Black));
+    }
+
+    let l = left.as_ref().expect("the taller side is not empty");
+    match l.color {
+        Black => balance(l.recolor(Red), entry, right),
+        Red => {
+            let lr = l.right.as_ref().expect("a red node has black children");
+            let left = balance(redden(&l.left), l.entry(), lr.left.clone());
+            let right = Node::new(Black, lr.right.clone(), entry, right);
+            Node::new(Red, left, lr.entry(), right)
+        }
+    }
+}
+
+/// Joins the two children of a removed node, all of `left` being less than
+/// all of `right`, into one subtree of the same black height
+fn append<K, V>(left: &Link<K, V>, right: &Link<K, V>) -> Link<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    let (Some(a), Some(b)) = (left, right) else {
+        return left.clone().or_else(|| right.clone());
+    };
+
+    match (a.color, b.color) {
+        (Red, Red) => {
+            let middle = append(&a.right, &b.left);
+            if let Some(m) = red(&middle) {
+                let left = Node::new(Red, a.left.clone(), a.entry(), m.left.clone());
+                let right = Node::new(Red, m.right.clone(), b.entry(), b.right.clone());
+                Node::new(Red, left, m.entry(), right)
+            } else {
+                let right = Node::new(Red, middle, b.entry(), b.right.clone());
+                Node::new(Red, a.left.clone(), a.entry(), right)
+            }
+        }
+        (Black, Black) => {
+            let middle = append(&a.right, &b.left);
+            if let Some(m) = red(&middle) {
+                let left = Node::new(Black, a.left.clone(), a.entry(), m.left.clone());
+                let right = Node::new(Black, m.right.clone(), b.entry(), b.right.clone());
+                Node::new(Red, left, m.entry(), right)
+            } else {
+                let right = Node::new(Black, middle, b.entry(), b.right.clone());
+                balance_left(a.left.clone(), a.entry(), right)
+            }
+        }
+        (Black, Red) => Node::new(Red, append(left, &b.left), b.entry(), b.right.clone()),
+        (Red, Black) => Node::new(Red, a.left.clone(), a.entry(), append(&a.right, right)),
+    }
+}
+
+/// Returns the subtree at `link` with a black root
+fn blacken<K, V>(link: Link<K, V>) -> Link<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    match red(&link) {
+        Some(node) => node.recolor(Black),
+        None => link,
+    }
+}
+
+/// Returns the black node at `link` turned red
+fn redden<K, V>(link: &Link<K, V>) -> Link<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    let node = link.as_ref().expect("a black node");
+    debug_assert_eq!(node.color, Black);
+    node.recolor(Red)
+}
+
+impl<K, V> Clone for RedBlackMap<K, V>
+where
+    K: Ord,
+{
+    /// O(1), the clone shares every node with `self`
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<K, V> Default for RedBlackMap<K, V>
+where
+    K: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for RedBlackMap<K, V>
+where
+    K: Ord + fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> PartialEq for RedBlackMap<K, V>
+where
+    K: Ord,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || (self.len() == other.len() && self.iter().eq(other.iter()))
+    }
+}
+
+impl<K, V> Eq for RedBlackMap<K, V>
+where
+    K: Ord,
+    V: Eq,
+{
+}
+
+impl<K, V> FromIterator<(K, V)> for RedBlackMap<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::new(), |map, (key, value)| map.insert(key, value))
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a RedBlackMap<K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = RedBlackMapIterator<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a persistent [`RedBlackMap`] in key order
+pub struct RedBlackMapIterator<'a, K, V> {
+    stack: Vec<&'a Node<K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> RedBlackMapIterator<'a, K, V> {
+    fn push_left(&mut self, mut node: Option<&'a Node<K, V>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for RedBlackMapIterator<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for RedBlackMapIterator<'_, K, V> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel_entries;
+    use std::thread;
+
+    #[test]
+    fn test_versions_are_independent() {
+        let v1: RedBlackMap<u32, u32> = prequel_entries();
+        let v2 = v1.insert(10, 100).insert(21, 0);
+        let v3 = v2.remove(&16).remove(&3);
+
+        assert_eq!(v1.len(), 7);
+        assert_eq!(v1.get(&21), Some(&210));
+        assert_eq!(v2.get(&21), Some(&0));
+        assert_eq!(v2.len(), 8);
+        assert_eq!(
+            v3.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec![7, 10, 21, 28, 36, 70]
+        );
+        assert!(v1.contains_key(&16) && !v3.contains_key(&16));
+        assert_eq!(v3.first_key_value(), Some((&7, &70)));
+        assert_eq!(v3.last_key_value(), Some((&70, &700)));
+        assert!(v1.is_valid() && v2.is_valid() && v3.is_valid());
+
+        // removing a missing key shares the whole map
+        assert!(v3.remove(&16).ptr_eq(&v3));
+        assert_eq!(v2.remove(&10).insert(21, 210), v1);
+    }
+
+    #[test]
+    fn test_balance_and_sharing() {
+        let mut versions = vec![RedBlackMap::new()];
+        for key in 0..1_000u32 {
+            let next = versions.last().unwrap().insert(key * 7_919 % 1_000, key);
+            versions.push(next);
+        }
+        let full = versions.last().unwrap().clone();
+        assert!(full.is_valid());
+        assert!(full.height() <= 2 * 10);
+
+        for (len, version) in versions.iter().enumerate().step_by(97) {
+            assert_eq!(version.len(), len);
+            assert!(version.is_valid());
+        }
+
+        let mut map = full.clone();
+        for key in (0..1_000).step_by(2) {
+            map = map.remove(&key);
+            assert!(map.is_valid());
+        }
+        assert!(map.iter().map(|(key, _)| *key).eq((1..1_000).step_by(2)));
+        assert_eq!(full.len(), 1_000);
+
+        // an update only copies nodes near one path
+        let updated = full.insert(500, 0);
+        let shared = |a: &Link<u32, u32>, b: &Link<u32, u32>| match (a, b) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        };
+        let (Some(a), Some(b)) = (&full.root, &updated.root) else {
+            panic!("both versions are non-empty");
+        };
+        assert!(shared(&a.left, &b.left) || shared(&a.right, &b.right));
+    }
+
+    #[test]
+    fn test_snapshot_reads_while_writing() {
+        let mut map: RedBlackMap<u32, u32> = (0..500).map(|key| (key, key)).collect();
+        let snapshot = map.clone();
+
+        thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                (0..500).all(|key| snapshot.get(&key) == Some(&key)) && snapshot.len() == 500
+            });
+
+            for key in 0..500 {
+                map = map.insert(key, key + 1).remove(&(key / 2));
+            }
+            assert!(reader.join().unwrap());
+        });
+
+        assert!(map.is_valid());
+        assert_eq!(map.len(), 250);
+        assert_eq!(map.get(&499), Some(&500));
+        assert_eq!(snapshot.get(&0), Some(&0));
+    }
+}
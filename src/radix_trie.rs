@@ -0,0 +1,509 @@
+use std::{fmt, mem};
+
+/// A map keyed by byte strings, like [`crate::Trie`], whose nodes hold whole
+/// fragments of the keys
+///
+/// A chain of nodes with one child and no value is merged into a single node
+/// labelled with every byte along it, so a node stands either for a stored
+/// key or for a prefix where stored keys branch off. `n` keys take at most
+/// `2n` nodes however long they are, which for long sparse keys such as file
+/// paths and URLs is far fewer than the one node per byte of a plain trie.
+#[derive(Clone)]
+pub struct RadixTrie<V> {
+    root: Node<V>,
+    len: usize,
+}
+
+#[derive(Clone)]
+struct Node<V> {
+    /// bytes of the key between the parent and this node, empty for the root
+    label: Vec<u8>,
+    value: Option<V>,
+    /// sorted by the first byte of their labels, which are all distinct
+    children: Vec<Node<V>>,
+}
+
+impl<V> Node<V> {
+    fn new(label: Vec<u8>, value: Option<V>) -> Self {
+        Self {
+            label,
+            value,
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns the position of the child whose label starts with `byte`, or
+    /// where it would be inserted
+    fn position(&self, byte: u8) -> Result<usize, usize> {
+        self.children
+            .binary_search_by_key(&byte, |child| child.label[0])
+    }
+
+    /// Merges the only child into this node, which holds no value
+    fn merge_child(&mut self) {
+        debug_assert!(self.value.is_none() && self.children.len() == 1);
+
+        let child = self.children.pop().expect("a single child");
+        self.label.extend(child.label);
+        self.value = child.value;
+        self.children = child.children;
+    }
+}
+
+/// Returns the length of the longest common prefix of `a` and `b`
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+impl<V> RadixTrie<V> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::new(Vec::new(), None),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the trie contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all keys
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Returns the number of nodes below the root
+    pub fn node_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![&self.root];
+
+        while let Some(node) = stack.pop() {
+            count += node.children.len();
+            stack.extend(&node.children);
+        }
+        count
+    }
+
+    /// Stores `value` under `key`, returning the value previously stored there
+    ///
+    /// A node whose label only partly matches `key` is split where they
+    /// differ.
+    pub fn insert<K>(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut rest = key.as_ref();
+        let mut node = &mut self.root;
+
+        while let Some(&byte) = rest.first() {
+            let position = match node.position(byte) {
+                Ok(position) => position,
+                Err(position) => {
+                    node.children
+                        .insert(position, Node::new(rest.to_vec(), Some(value)));
+                    self.len += 1;
+                    return None;
+                }
+            };
+
+            let child = &mut node.children[position];
+            let common = common_prefix(&child.label, rest);
+            if common < child.label.len() {
+                // the child keeps the label up to where the key leaves it
+                let suffix = child.label.split_off(common);
+                let lower = Node {
+                    label: suffix,
+                    value: child.value.take(),
+                    children: mem::take(&mut child.children),
+                };
+                child.children.push(lower);
+            }
+
+            rest = &rest[common..];
+            node = child;
+        }
+
+        let old = node.value.replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Returns `true` if a value is stored under `key`
+    pub fn contains_key<K>(&self, key: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get<K>(&self, key: K) -> Option<&V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut rest = key.as_ref();
+        let mut node = &self.root;
+
+        while let Some(&byte) = rest.first() {
+            node = &node.children[node.position(byte).ok()?];
+            rest = rest.strip_prefix(node.label.as_slice())?;
+        }
+        node.value.as_ref()
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut<K>(&mut self, key: K) -> Option<&mut V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut rest = key.as_ref();
+        let mut node = &mut self.root;
+
+        while let Some(&byte) = rest.first() {
+            let position = node.position(byte).ok()?;
+            node = &mut node.children[position];
+            rest = rest.strip_prefix(node.label.as_slice())?;
+        }
+        node.value.as_mut()
+    }
+
+    /// Returns `true` if some stored key starts with `prefix`
+    pub fn contains_prefix<K>(&self, prefix: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        !self.is_empty() && self.find_prefix(prefix.as_ref()).is_some()
+    }
+
+    /// Removes `key` from the trie, returning the value stored under it
+    ///
+    /// The node of `key` is removed if it has no children, or merged with
+    /// its only child, and so is its parent if that leaves it with a single
+    /// child and no value.
+    pub fn remove<K>(&mut self, key: K) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut rest = key.as_ref();
+        let mut path = Vec::new();
+        let mut node = &self.root;
+
+        while let Some(&byte) = rest.first() {
+            let position = node.position(byte).ok()?;
+            node = &node.children[position];
+            rest = rest.strip_prefix(node.label.as_slice())?;
+            path.push(position);
+        }
+        node.value.as_ref()?;
+
+        let Some((&last, above)) = path.split_last() else {
+            // the empty key is stored on the root, which is never removed
+            self.len -= 1;
+            return self.root.value.take();
+        };
+
+        let mut parent = &mut self.root;
+        for &position in above {
+            parent = &mut parent.children[position];
+        }
+
+        let target = &mut parent.children[last];
+        let value = target.value.take();
+        match target.children.len() {
+            0 => {
+                parent.children.remove(last);
+                let is_root = above.is_empty();
+                if !is_root && parent.value.is_none() && parent.children.len() == 1 {
+                    parent.merge_child();
+                }
+            }
+            1 => target.merge_child(),
+            _ => {}
+        }
+
+        self.len -= 1;
+        value
+    }
+
+    /// Returns an iterator over the entries in lexicographic order of their
+    /// keys
+    pub fn iter(&self) -> RadixTrieIterator<'_, V> {
+        self.iter_prefix([])
+    }
+
+    /// Returns an iterator over the keys in lexicographic order
+    pub fn keys(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values in lexicographic order of their
+    /// keys
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`, in
+    /// lexicographic order of their keys
+    pub fn iter_prefix<K>(&self, prefix: K) -> RadixTrieIterator<'_, V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let prefix = prefix.as_ref();
+        let start = self.find_prefix(prefix);
+
+        RadixTrieIterator {
+            key: start.map_or_else(Vec::new, |(depth, _)| prefix[..depth].to_vec()),
+            stack: start.into_iter().collect(),
+        }
+    }
+
+    /// Returns the highest node whose key starts with `prefix`, with the
+    /// length of the key above its label
+    fn find_prefix(&self, prefix: &[u8]) -> Option<(usize, &Node<V>)> {
+        let mut depth = 0;
+        let mut node = &self.root;
+
+        while depth < prefix.len() {
+            let rest = &prefix[depth..];
+            let child = &node.children[node.position(rest[0]).ok()?];
+
+            if child.label.starts_with(rest) {
+                return Some((depth, child));
+            }
+            if !rest.starts_with(&child.label) {
+                return None;
+            }
+            depth += child.label.len();
+            node = child;
+        }
+        Some((depth, node))
+    }
+
+    /// Returns `true` if only the root has an empty label, siblings start
+    /// with distinct bytes in order, every node other than the root holds a
+    /// value or has at least two children and `len` matches the number of
+    /// values
+    pub fn is_valid(&self) -> bool {
+        let mut values = 0;
+        let mut stack = vec![(&self.root, true)];
+
+        while let Some((node, is_root)) = stack.pop() {
+            let sorted = node
+                .children
+                .windows(2)
+                .all(|pair| pair[0].label.first() < pair[1].label.first());
+            let compressed = is_root || node.value.is_some() || node.children.len() >= 2;
+            if !sorted || !compressed || node.label.is_empty() != is_root {
+                return false;
+            }
+
+            values += usize::from(node.value.is_some());
+            stack.extend(node.children.iter().map(|child| (child, false)));
+        }
+        values == self.len
+    }
+}
+
+impl<V> Default for RadixTrie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Releases nodes one at a time, so that dropping a deeply nested trie can't
+/// overflow the stack
+impl<V> Drop for RadixTrie<V> {
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.root.children);
+
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+impl<V> fmt::Debug for RadixTrie<V>
+where
+    V: fmt::Debug,
+{
+    /// Formats the entries in key order, like a map, showing the keys as
+    /// strings with invalid UTF-8 replaced
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter()
+                    .map(|(key, value)| (String::from_utf8_lossy(&key).into_owned(), value)),
+            )
+            .finish()
+    }
+}
+
+impl<V> PartialEq for RadixTrie<V>
+where
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<V> Eq for RadixTrie<V> where V: Eq {}
+
+impl<K, V> FromIterator<(K, V)> for RadixTrie<V>
+where
+    K: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<K, V> Extend<(K, V)> for RadixTrie<V>
+where
+    K: AsRef<[u8]>,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the entries of a [`RadixTrie`] in lexicographic order of
+/// their keys, walking the nodes depth first
+pub struct RadixTrieIterator<'a, V> {
+    /// key of the last node visited
+    key: Vec<u8>,
+    /// nodes left to visit, with the length of the key above their labels,
+    /// the next one on top
+    stack: Vec<(usize, &'a Node<V>)>,
+}
+
+impl<'a, V> Iterator for RadixTrieIterator<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (depth, node) = self.stack.pop()?;
+            self.key.truncate(depth);
+            self.key.extend_from_slice(&node.label);
+
+            let depth = self.key.len();
+            self.stack
+                .extend(node.children.iter().rev().map(|child| (depth, child)));
+
+            if let Some(value) = &node.value {
+                return Some((self.key.clone(), value));
+            }
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a RadixTrie<V> {
+    type Item = (Vec<u8>, &'a V);
+    type IntoIter = RadixTrieIterator<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel_words;
+
+    fn keys(iter: RadixTrieIterator<'_, u32>) -> Vec<String> {
+        iter.map(|(key, _)| String::from_utf8(key).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let mut trie: RadixTrie<u32> = prequel_words();
+
+        assert_eq!(trie.len(), 7);
+        assert_eq!(trie.get("ten"), Some(&1));
+        assert_eq!(trie.get("te"), None);
+        assert_eq!(trie.get("teams"), None);
+        assert_eq!(trie.insert("ten", 10), Some(1));
+        assert_eq!(trie.insert("", 99), None);
+        *trie.get_mut("team").unwrap() += 20;
+
+        assert_eq!(trie.get(b"ten"), Some(&10));
+        assert_eq!(trie.get(""), Some(&99));
+        assert_eq!(trie.get("team"), Some(&26));
+        assert!(trie.contains_prefix("te"));
+        assert!(!trie.contains_prefix("tx"));
+        assert_eq!(
+            keys(trie.iter()),
+            vec!["", "i", "in", "inn", "tea", "team", "ten", "to"]
+        );
+        assert_eq!(keys(trie.iter_prefix("te")), vec!["tea", "team", "ten"]);
+        assert_eq!(keys(trie.iter_prefix("tea")), vec!["tea", "team"]);
+        assert!(trie.is_valid());
+    }
+
+    #[test]
+    fn test_remove_merges_nodes() {
+        let mut trie: RadixTrie<u32> = prequel_words();
+        let nodes = trie.node_count();
+
+        assert_eq!(trie.remove("te"), None);
+        assert_eq!(trie.remove("tea"), Some(0));
+        assert_eq!(trie.remove("tea"), None);
+        assert_eq!(trie.node_count(), nodes - 1);
+        assert_eq!(keys(trie.iter_prefix("te")), vec!["team", "ten"]);
+
+        assert_eq!(trie.remove("ten"), Some(1));
+        assert_eq!(trie.get("team"), Some(&6));
+        assert!(trie.is_valid());
+
+        for key in ["i", "in", "inn", "team", "to"] {
+            assert!(trie.remove(key).is_some());
+            assert!(trie.is_valid());
+        }
+        assert!(trie.is_empty());
+        assert_eq!(trie.node_count(), 0);
+        assert_eq!(trie, RadixTrie::new());
+    }
+
+    #[test]
+    fn test_long_sparse_keys() {
+        let paths: Vec<String> = (0..200)
+            .map(|i| {
+                format!(
+                    "/home/user/projects/trees/src/module_{}/file_{}.rs",
+                    i % 10,
+                    i
+                )
+            })
+            .collect();
+        let trie: RadixTrie<usize> = paths.iter().zip(0..).collect();
+
+        assert!(trie.is_valid());
+        assert_eq!(trie.len(), 200);
+        assert!(trie.node_count() < 2 * paths.len());
+        assert!(trie.node_count() * 10 < paths.iter().map(String::len).sum::<usize>());
+        assert_eq!(
+            trie.iter_prefix("/home/user/projects/trees/src/module_3/")
+                .count(),
+            20
+        );
+        assert_eq!(trie.get(&paths[123]), Some(&123));
+
+        let mut sorted = paths.clone();
+        sorted.sort();
+        assert!(trie.keys().eq(sorted.into_iter().map(String::into_bytes)));
+    }
+}
@@ -0,0 +1,903 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    fmt, mem,
+};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Red,
+    Black,
+}
+
+impl Color {
+    fn flip(self) -> Self {
+        match self {
+            Color::Red => Color::Black,
+            Color::Black => Color::Red,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+    /// color of the link from the parent to this node
+    color: Color,
+    /// number of nodes in the subtree rooted here
+    size: usize,
+}
+
+impl<T> Node<T> {
+    fn new(data: T, color: Color) -> Box<Self> {
+        Box::new(Self {
+            data,
+            left: None,
+            right: None,
+            color,
+            size: 1,
+        })
+    }
+
+    fn update_size(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn is_red<T>(link: &Link<T>) -> bool {
+    link.as_ref().is_some_and(|node| node.color == Color::Red)
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// Returns the number of black links on every path from `link` down to an
+/// empty link
+fn black_height<T>(mut link: &Link<T>) -> usize {
+    let mut height = 0;
+
+    while let Some(node) = link {
+        height += usize::from(node.color == Color::Black);
+        link = &node.left;
+    }
+    height
+}
+
+/// A self-balancing binary search tree behaving like a set
+///
+/// Implemented as a left-leaning red-black tree: red links always lean left,
+/// no path has two red links in a row and every path from the root to an empty
+/// link crosses the same number of black links, which bounds the height by
+/// `2 log2(n + 1)`. Insertions and removals both rebalance on the way back up.
+///
+/// Every node also stores the size of its subtree, so that [`Self::join`] and
+/// [`Self::split`] can hand out whole subtrees in `O(log n)`.
+#[derive(Clone)]
+pub struct RedBlackTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+    len: usize,
+}
+
+impl<T> RedBlackTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// Builds a tree in `O(n)` from elements yielded in ascending order
+    ///
+    /// The tree is laid out as a 2-3 tree of black height `log2(n + 1)`,
+    /// rounded down, with a 3-node wherever a 2-node would leave a subtree
+    /// too full for its height. Runs of equal elements are reduced to their
+    /// first one. The input must be sorted, otherwise the resulting tree
+    /// violates the ordering invariant (checked with a debug assertion).
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut sorted: Vec<T> = iter.into_iter().collect();
+        sorted.dedup();
+        debug_assert!(sorted.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let len = sorted.len();
+        let black_height = (len + 1).ilog2();
+        Self::from_root(Self::build(&mut sorted.into_iter(), len, black_height))
+    }
+
+    /// Builds a subtree with a black root and black height `height` out of
+    /// the next `len` elements of `iter`, consuming them in order
+    ///
+    /// `len` must lie between `2^height - 1` and `3^height - 1`, the sizes of
+    /// the 2-3 trees of that height made only of 2-nodes and only of 3-nodes.
+    fn build<I>(iter: &mut I, len: usize, height: u32) -> Link<T>
+    where
+        I: Iterator<Item = T>,
+    {
+        if height == 0 {
+            return None;
+        }
+
+        let most_below = 3usize.saturating_pow(height - 1) - 1;
+        let mut root;
+
+        if len - 1 - (len - 1) / 2 <= most_below {
+            let left_len = (len - 1) / 2;
+            let left = Self::build(iter, left_len, height - 1);
+            root = Node::new(iter.next().expect("len elements remain"), Color::Black);
+            root.left = left;
+            root.right = Self::build(iter, len - 1 - left_len, height - 1);
+        } else {
+            // a 3-node, its smaller element the red left child of the other
+            let left_len = (len - 2) / 3;
+            let middle_len = (len - 2 - left_len) / 2;
+            let left = Self::build(iter, left_len, height - 1);
+            let mut red = Node::new(iter.next().expect("len elements remain"), Color::Red);
+            red.left = left;
+            red.right = Self::build(iter, middle_len, height - 1);
+            red.update_size();
+
+            root = Node::new(iter.next().expect("len elements remain"), Color::Black);
+            root.left = Some(red);
+            root.right = Self::build(iter, len - 2 - left_len - middle_len, height - 1);
+        }
+
+        root.update_size();
+        Some(root)
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        fn height<T>(link: &Link<T>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return Some(&current.data),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        let mut inserted = false;
+
+        let mut root = Self::insert_into(self.root.take(), data, &mut inserted);
+        root.color = Color::Black;
+        self.root = Some(root);
+
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    fn insert_into(link: Link<T>, data: T, inserted: &mut bool) -> Box<Node<T>> {
+        let Some(mut node) = link else {
+            *inserted = true;
+            return Node::new(data, Color::Red);
+        };
+
+        match data.cmp(&node.data) {
+            Equal => {}
+            // data < stored_data
+            Less => node.left = Some(Self::insert_into(node.left.take(), data, inserted)),
+            // data > stored_data
+            Greater => node.right = Some(Self::insert_into(node.right.take(), data, inserted)),
+        }
+
+        Self::balance(node)
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // the top-down pass below assumes that `key` is present
+        if !self.contains(key) {
+            return None;
+        }
+
+        let mut root = self.root.take().expect("key is present");
+        if !is_red(&root.left) && !is_red(&root.right) {
+            root.color = Color::Red;
+        }
+
+        let mut removed = None;
+        self.root = Self::take_from(root, key, &mut removed);
+        if let Some(root) = &mut self.root {
+            root.color = Color::Black;
+        }
+
+        self.len -= 1;
+        removed
+    }
+
+    /// Removes `key`, which is present under `node`, storing it in `removed`
+    ///
+    /// On the way down a red link is pushed ahead of the search so that the
+    /// node finally removed is never a lone black one.
+    fn take_from<Q>(mut node: Box<Node<T>>, key: &Q, removed: &mut Option<T>) -> Link<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if key < node.data.borrow() {
+            if !is_red(&node.left) && !node.left.as_ref().is_some_and(|left| is_red(&left.left)) {
+                node = Self::move_red_left(node);
+            }
+
+            let left = node.left.take().expect("key is in the left subtree");
+            node.left = Self::take_from(left, key, removed);
+        } else {
+            if is_red(&node.left) {
+                node = Self::rotate_right(node);
+            }
+
+            if key == node.data.borrow() && node.right.is_none() {
+                *removed = Some(node.data);
+                return None;
+            }
+
+            if !is_red(&node.right) && !node.right.as_ref().is_some_and(|right| is_red(&right.left))
+            {
+                node = Self::move_red_right(node);
+            }
+
+            let right = node
+                .right
+                .take()
+                .expect("key is here or in the right subtree");
+            if key == node.data.borrow() {
+                // replace with the in-order successor, the leftmost of the right
+                let (right, successor) = Self::take_min(right);
+                *removed = Some(mem::replace(&mut node.data, successor));
+                node.right = right;
+            } else {
+                node.right = Self::take_from(right, key, removed);
+            }
+        }
+
+        Some(Self::balance(node))
+    }
+
+    /// Removes the smallest element under `node`, returning what is left of
+    /// the subtree along with the element
+    fn take_min(mut node: Box<Node<T>>) -> (Link<T>, T) {
+        // left-leaning, so a node without a left child is a leaf
+        if node.left.is_none() {
+            return (None, node.data);
+        }
+
+        if !is_red(&node.left) && !node.left.as_ref().is_some_and(|left| is_red(&left.left)) {
+            node = Self::move_red_left(node);
+        }
+
+        let left = node.left.take().expect("a left child still exists");
+        let (left, min) = Self::take_min(left);
+        node.left = left;
+
+        (Some(Self::balance(node)), min)
+    }
+
+    /// Restores the size and the left-leaning invariants on the way back up
+    fn balance(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        node.update_size();
+
+        if is_red(&node.right) && !is_red(&node.left) {
+            node = Self::rotate_left(node);
+        }
+        if is_red(&node.left) && node.left.as_ref().is_some_and(|left| is_red(&left.left)) {
+            node = Self::rotate_right(node);
+        }
+        if is_red(&node.left) && is_red(&node.right) {
+            Self::flip_colors(&mut node);
+        }
+        node
+    }
+
+    /// Makes the left child or one of its children red, assuming `node` is
+    /// red and both its children are black
+    fn move_red_left(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        Self::flip_colors(&mut node);
+
+        if node.right.as_ref().is_some_and(|right| is_red(&right.left)) {
+            let right = node.right.take().expect("checked above");
+            node.right = Some(Self::rotate_right(right));
+            node = Self::rotate_left(node);
+            Self::flip_colors(&mut node);
+        }
+        node
+    }
+
+    /// Makes the right child or one of its children red, assuming `node` is
+    /// red and both its children are black
+    fn move_red_right(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        Self::flip_colors(&mut node);
+
+        if node.left.as_ref().is_some_and(|left| is_red(&left.left)) {
+            node = Self::rotate_right(node);
+            Self::flip_colors(&mut node);
+        }
+        node
+    }
+
+    fn flip_colors(node: &mut Node<T>) {
+        node.color = node.color.flip();
+
+        for child in [&mut node.left, &mut node.right].into_iter().flatten() {
+            child.color = child.color.flip();
+        }
+    }
+
+    /// Turns the right-leaning link below `node` into a left-leaning one
+    fn rotate_left(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        let mut right = node
+            .right
+            .take()
+            .expect("rotating left without a right child");
+
+        node.right = right.left.take();
+        right.color = node.color;
+        node.color = Color::Red;
+        right.size = node.size;
+        node.update_size();
+        right.left = Some(node);
+        right
+    }
+
+    /// Turns the left-leaning link below `node` into a right-leaning one
+    fn rotate_right(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        let mut left = node
+            .left
+            .take()
+            .expect("rotating right without a left child");
+
+        node.left = left.right.take();
+        left.color = node.color;
+        node.color = Color::Red;
+        left.size = node.size;
+        node.update_size();
+        left.right = Some(node);
+        left
+    }
+
+    /// Joins `left`, `pivot` and `right` into one tree
+    ///
+    /// The tree with the smaller black height is hung off the side of the
+    /// other one at the matching black height, below a red node holding
+    /// `pivot`, then rebalanced on the way back up like after an insertion, in
+    /// `O(log n)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless every element of `left` is less than `pivot` and every
+    /// element of `right` is greater than it.
+    pub fn join(left: Self, pivot: T, right: Self) -> Self {
+        assert!(
+            left.max().is_none_or(|max| *max < pivot) && right.min().is_none_or(|min| pivot < *min),
+            "the pivot must lie between the joined trees"
+        );
+
+        let pivot = Node::new(pivot, Color::Red);
+        Self::from_root(Some(Self::join_links(left.root, pivot, right.root)))
+    }
+
+    /// Splits the tree at `key` into the elements less than it, the element
+    /// equal to it if there is one, and the elements greater than it
+    ///
+    /// The subtrees hanging off the search path for `key` are joined back
+    /// together on each side, in `O(log n)` overall.
+    pub fn split<Q>(self, key: &Q) -> (Self, Option<T>, Self)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (less, found, greater) = Self::split_link(self.root, key);
+        (Self::from_root(less), found, Self::from_root(greater))
+    }
+
+    /// Moves the elements of both trees into one, keeping the element of
+    /// `self` when both hold equal ones
+    ///
+    /// `self` is split at the root of `other`, and the unions of the two sides
+    /// are joined back with that root in between, in `O(m log(n / m + 1))` for
+    /// trees of `m <= n` elements.
+    pub fn union(self, other: Self) -> Self {
+        Self::from_root(Self::union_links(self.root, other.root))
+    }
+
+    /// Makes a tree of a subtree, whose root may be red
+    fn from_root(mut root: Link<T>) -> Self {
+        if let Some(root) = &mut root {
+            root.color = Color::Black;
+        }
+
+        Self {
+            len: size(&root),
+            root,
+        }
+    }
+
+    /// Joins two subtrees with `pivot`, a lone node, in between, returning a
+    /// subtree with a black root
+    fn join_links(mut left: Link<T>, mut pivot: Box<Node<T>>, mut right: Link<T>) -> Box<Node<T>> {
+        // blackening a root keeps a subtree valid on its own
+        for root in [&mut left, &mut right].into_iter().flatten() {
+            root.color = Color::Black;
+        }
+        let (left_height, right_height) = (black_height(&left), black_height(&right));
+
+        let mut root = match left_height.cmp(&right_height) {
+            Greater => Self::join_right(left, left_height, pivot, right, right_height),
+            Less => Self::join_left(left, left_height, pivot, right, right_height),
+            Equal => {
+                pivot.left = left;
+                pivot.right = right;
+                pivot.update_size();
+                pivot
+            }
+        };
+
+        root.color = Color::Black;
+        root
+    }
+
+    /// Hangs `pivot` and `right` off the right spine of `link`, whose black
+    /// height `height` is greater
+    fn join_right(
+        link: Link<T>,
+        height: usize,
+        mut pivot: Box<Node<T>>,
+        right: Link<T>,
+        right_height: usize,
+    ) -> Box<Node<T>> {
+        if height == right_height {
+            pivot.left = link;
+            pivot.right = right;
+            pivot.color = Color::Red;
+            pivot.update_size();
+            return pivot;
+        }
+
+        // left-leaning, so the nodes on the right spine are all black
+        let mut node = link.expect("the left subtree is the taller one");
+        let node_right = node.right.take();
+        node.right = Some(Self::join_right(
+            node_right,
+            height - 1,
+            pivot,
+            right,
+            right_height,
+        ));
+        Self::balance(node)
+    }
+
+    /// Hangs `left` and `pivot` off the left spine of `link`, whose black
+    /// height `height` is greater
+    fn join_left(
+        left: Link<T>,
+        left_height: usize,
+        mut pivot: Box<Node<T>>,
+        link: Link<T>,
+        height: usize,
+    ) -> Box<Node<T>> {
+        if height == left_height && !is_red(&link) {
+            pivot.left = left;
+            pivot.right = link;
+            pivot.color = Color::Red;
+            pivot.update_size();
+            return pivot;
+        }
+
+        let mut node = link.expect("the right subtree is the taller one");
+        let node_left = node.left.take();
+        let left_height_below = height - usize::from(node.color == Color::Black);
+        node.left = Some(Self::join_left(
+            left,
+            left_height,
+            pivot,
+            node_left,
+            left_height_below,
+        ));
+        Self::balance(node)
+    }
+
+    fn split_link<Q>(link: Link<T>, key: &Q) -> (Link<T>, Option<T>, Link<T>)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(mut node) = link else {
+            return (None, None, None);
+        };
+        let (left, right) = (node.left.take(), node.right.take());
+
+        match key.cmp(node.data.borrow()) {
+            Equal => (left, Some(node.data), right),
+            Less => {
+                let (less, found, greater) = Self::split_link(left, key);
+                (less, found, Some(Self::join_links(greater, node, right)))
+            }
+            Greater => {
+                let (less, found, greater) = Self::split_link(right, key);
+                (Some(Self::join_links(left, node, less)), found, greater)
+            }
+        }
+    }
+
+    fn union_links(link: Link<T>, other: Link<T>) -> Link<T> {
+        let Some(mut pivot) = other else {
+            return link;
+        };
+        if link.is_none() {
+            return Some(pivot);
+        }
+
+        let (other_left, other_right) = (pivot.left.take(), pivot.right.take());
+        let (less, found, greater) = Self::split_link(link, &pivot.data);
+        if let Some(found) = found {
+            pivot.data = found;
+        }
+
+        Some(Self::join_links(
+            Self::union_links(less, other_left),
+            pivot,
+            Self::union_links(greater, other_right),
+        ))
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> RedBlackTreeIterator<'_, T> {
+        let mut iter = RedBlackTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the elements are in order, every stored size is
+    /// correct and all the red-black invariants hold: the root is black, red
+    /// links lean left, no node has two red links in a row and every path has
+    /// the same black height
+    pub fn is_valid(&self) -> bool {
+        /// Returns the black height of the subtree
+        fn check<T: Ord>(
+            link: &Link<T>,
+            lower: Option<&T>,
+            upper: Option<&T>,
+            parent_red: bool,
+        ) -> Option<usize> {
+            let Some(node) = link else {
+                return Some(0);
+            };
+
+            let red = node.color == Color::Red;
+            if lower.is_some_and(|lower| node.data <= *lower)
+                || upper.is_some_and(|upper| node.data >= *upper)
+                || (red && parent_red)
+                || is_red(&node.right)
+                || node.size != 1 + size(&node.left) + size(&node.right)
+            {
+                return None;
+            }
+
+            let left = check(&node.left, lower, Some(&node.data), red)?;
+            let right = check(&node.right, Some(&node.data), upper, red)?;
+
+            (left == right).then_some(left + usize::from(!red))
+        }
+
+        !is_red(&self.root)
+            && check(&self.root, None, None, false).is_some()
+            && self.iter().count() == self.len
+    }
+}
+
+impl<T> Default for RedBlackTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for RedBlackTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements, regardless of their shape
+impl<T> PartialEq for RedBlackTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for RedBlackTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for RedBlackTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for RedBlackTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`RedBlackTree`] in order
+pub struct RedBlackTreeIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> RedBlackTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for RedBlackTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for RedBlackTreeIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a RedBlackTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = RedBlackTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{prequel, shuffled};
+
+    #[test]
+    fn test_insert_search() {
+        let mut tree: RedBlackTree<u32> = prequel();
+
+        assert_eq!(tree.len(), 7);
+        assert!(tree.contains(&21));
+        assert!(!tree.insert(21));
+        assert!(tree.insert(22));
+        assert_eq!(tree.get(&22), Some(&22));
+        assert_eq!((tree.min(), tree.max()), (Some(&3), Some(&70)));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &22, &28, &36, &70]
+        );
+        assert!(tree.is_valid());
+
+        let tree: RedBlackTree<u32> = (0..1023).collect();
+        assert!(tree.is_valid());
+        assert!(tree.height() <= 20);
+    }
+
+    #[test]
+    fn test_remove_from_both_ends() {
+        // removing the minimum or the maximum borrows red links down the
+        // left or the right spine
+        let mut tree: RedBlackTree<u32> = (0..1023).collect();
+        let mut height = black_height(&tree.root);
+
+        for i in 0..1023 {
+            let data = if i % 2 == 0 { i / 2 } else { 1022 - i / 2 };
+            assert_eq!(tree.take(&data), Some(data));
+            assert!(tree.is_valid());
+
+            // the black height only ever shrinks, at the root
+            let shrunk = black_height(&tree.root);
+            assert!(shrunk == height || shrunk + 1 == height);
+            height = shrunk;
+        }
+        assert!(tree.is_empty());
+        assert_eq!(height, 0);
+    }
+
+    #[test]
+    fn test_remove_inner_nodes() {
+        // the root has two children until the tree is nearly empty, so its
+        // successor is taken from the right subtree
+        let mut tree: RedBlackTree<u64> = shuffled(1009).into_iter().collect();
+
+        while let Some(root) = tree.root.as_ref().map(|node| node.data) {
+            assert!(tree.remove(&root));
+            assert!(!tree.contains(&root));
+            assert!(tree.is_valid());
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.min(), None);
+    }
+
+    #[test]
+    fn test_join_split() {
+        let (less, found, greater) = prequel::<RedBlackTree<u32>>().split(&21);
+
+        assert_eq!(less.iter().collect::<Vec<_>>(), vec![&3, &7, &16]);
+        assert_eq!(found, Some(21));
+        assert_eq!(greater.iter().collect::<Vec<_>>(), vec![&28, &36, &70]);
+        assert!(less.is_valid() && greater.is_valid());
+
+        let tree = RedBlackTree::join(less, 21, greater);
+        assert_eq!(tree, prequel());
+        assert!(tree.is_valid());
+
+        // trees of very different heights, on either side
+        let tree = RedBlackTree::join((0..3).collect(), 3, (4..1009).collect());
+        let tree = RedBlackTree::join(tree, 1009, (1010..1012).collect());
+        assert!(tree.iter().copied().eq(0..1012));
+        assert!(tree.is_valid());
+
+        for key in shuffled(1009).into_iter().step_by(37).chain([5000]) {
+            let (less, found, greater) = tree.clone().split(&key);
+            assert!(less.is_valid() && greater.is_valid());
+            assert_eq!(less.len() as u64, key.min(1012));
+            assert_eq!(found, (key < 1012).then_some(key));
+            assert_eq!(
+                less.len() + greater.len() + usize::from(found.is_some()),
+                1012
+            );
+        }
+    }
+
+    #[test]
+    fn test_union() {
+        let evens: RedBlackTree<u64> = (0..1000).step_by(2).collect();
+        let threes: RedBlackTree<u64> = shuffled(1009)
+            .into_iter()
+            .filter(|data| data % 3 == 0)
+            .collect();
+
+        let union = evens.union(threes);
+        assert!(union.is_valid());
+        assert!(union
+            .iter()
+            .copied()
+            .eq((0..1009).filter(|data| (data % 2 == 0 && *data < 1000) || data % 3 == 0)));
+        assert_eq!(union.clone().union(RedBlackTree::new()), union);
+    }
+
+    #[test]
+    fn test_from_sorted_iter() {
+        for len in [0, 1, 2, 3, 4, 7, 8, 26, 27, 100, 1009] {
+            let tree = RedBlackTree::from_sorted_iter(0..len);
+
+            assert_eq!(tree.len(), len as usize);
+            assert!(tree.is_valid());
+            assert!(tree.iter().copied().eq(0..len));
+        }
+
+        let tree = RedBlackTree::from_sorted_iter([3, 7, 7, 16, 21, 21, 21, 28, 36, 70]);
+        assert_eq!(tree, prequel());
+        assert!(tree.is_valid());
+        assert!(RedBlackTree::from_sorted_iter(0..1023).height() <= 11);
+    }
+}
@@ -0,0 +1,794 @@
+use std::{
+    cmp::Ordering,
+    fmt, iter, mem,
+    ops::{Add, Bound, RangeBounds},
+};
+
+/// Most bytes a leaf holds, so that edits copy little text
+const MAX_CHUNK: usize = 512;
+
+/// The sizes of a piece of text that the tree sums up in each node
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Metrics {
+    bytes: usize,
+    chars: usize,
+    /// number of line feeds
+    lines: usize,
+}
+
+impl Metrics {
+    fn of(text: &str) -> Self {
+        Self {
+            bytes: text.len(),
+            chars: text.chars().count(),
+            lines: text.bytes().filter(|&byte| byte == b'\n').count(),
+        }
+    }
+}
+
+impl Add for Metrics {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            bytes: self.bytes + other.bytes,
+            chars: self.chars + other.chars,
+            lines: self.lines + other.lines,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    metrics: Metrics,
+    height: u8,
+    kind: Kind,
+}
+
+#[derive(Clone)]
+enum Kind {
+    Leaf(String),
+    Branch(Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn leaf(text: String) -> Self {
+        Self {
+            metrics: Metrics::of(&text),
+            height: 0,
+            kind: Kind::Leaf(text),
+        }
+    }
+
+    fn branch(left: Node, right: Node) -> Self {
+        Self {
+            metrics: left.metrics + right.metrics,
+            height: left.height.max(right.height) + 1,
+            kind: Kind::Branch(Box::new(left), Box::new(right)),
+        }
+    }
+
+    fn into_children(self) -> (Node, Node) {
+        match self.kind {
+            Kind::Branch(left, right) => (*left, *right),
+            Kind::Leaf(_) => unreachable!("only branches are taller than leaves"),
+        }
+    }
+}
+
+/// Builds a tree holding `text` in leaves of at most `MAX_CHUNK` bytes
+fn build(text: &str) -> Node {
+    if text.len() <= MAX_CHUNK {
+        return Node::leaf(text.to_owned());
+    }
+
+    let mut middle = text.len() / 2;
+    while !text.is_char_boundary(middle) {
+        middle -= 1;
+    }
+    join(build(&text[..middle]), build(&text[middle..]))
+}
+
+/// Returns the tree of the text of `left` followed by the text of `right`
+///
+/// The taller tree is descended along its inner spine to a subtree about as
+/// tall as the other one, which is joined there and then rebalanced on the
+/// way back up, which takes time proportional to the difference in heights.
+fn join(left: Node, right: Node) -> Node {
+    if left.metrics.bytes == 0 {
+        right
+    } else if right.metrics.bytes == 0 {
+        left
+    } else if left.height > right.height + 1 {
+        let (outer, inner) = left.into_children();
+        balance(outer, join(inner, right))
+    } else if right.height > left.height + 1 {
+        let (inner, outer) = right.into_children();
+        balance(join(left, inner), outer)
+    } else {
+        concat(left, right)
+    }
+}
+
+/// Returns a branch over two trees of about the same height, or a single leaf
+/// if both are leaves that fit in one
+fn concat(left: Node, right: Node) -> Node {
+    match (left.kind, right.kind) {
+        (Kind::Leaf(mut left), Kind::Leaf(right)) if left.len() + right.len() <= MAX_CHUNK => {
+            left.push_str(&right);
+            Node::leaf(left)
+        }
+        (left_kind, right_kind) => Node::branch(
+            Node {
+                kind: left_kind,
+                ..left
+            },
+            Node {
+                kind: right_kind,
+                ..right
+            },
+        ),
+    }
+}
+
+/// Returns a branch over `left` and `right`, rotating once or twice if their
+/// heights differ by two
+fn balance(left: Node, right: Node) -> Node {
+    if left.height > right.height + 1 {
+        let (outer, inner) = left.into_children();
+        if inner.height > outer.height {
+            let (middle_left, middle_right) = inner.into_children();
+            Node::branch(
+                Node::branch(outer, middle_left),
+                Node::branch(middle_right, right),
+            )
+        } else {
+            Node::branch(outer, Node::branch(inner, right))
+        }
+    } else if right.height > left.height + 1 {
+        let (inner, outer) = right.into_children();
+        if inner.height > outer.height {
+            let (middle_left, middle_right) = inner.into_children();
+            Node::branch(
+                Node::branch(left, middle_left),
+                Node::branch(middle_right, outer),
+            )
+        } else {
+            Node::branch(Node::branch(left, inner), outer)
+        }
+    } else {
+        Node::branch(left, right)
+    }
+}
+
+/// Splits the tree into the first `at` chars and the rest
+fn split(node: Node, at: usize) -> (Node, Node) {
+    match node.kind {
+        Kind::Leaf(mut text) => {
+            let right = text.split_off(byte_of_char(&text, at));
+            (Node::leaf(text), Node::leaf(right))
+        }
+        Kind::Branch(left, right) => {
+            let left_chars = left.metrics.chars;
+            match at.cmp(&left_chars) {
+                Ordering::Less => {
+                    let (start, rest) = split(*left, at);
+                    (start, join(rest, *right))
+                }
+                Ordering::Equal => (*left, *right),
+                Ordering::Greater => {
+                    let (rest, end) = split(*right, at - left_chars);
+                    (join(*left, rest), end)
+                }
+            }
+        }
+    }
+}
+
+/// Returns the byte position of the char at `index` in `text`, or its length
+fn byte_of_char(text: &str, index: usize) -> usize {
+    text.char_indices()
+        .nth(index)
+        .map_or(text.len(), |(byte, _)| byte)
+}
+
+/// Returns the start and end of `range` over `len` chars
+///
+/// # Panics
+///
+/// Panics if the range starts after it ends or ends past `len`.
+fn bounds<R>(range: R, len: usize) -> (usize, usize)
+where
+    R: RangeBounds<usize>,
+{
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end + 1,
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    assert!(start <= end, "range starts at {start} but ends at {end}");
+    assert!(
+        end <= len,
+        "range end index {end} out of range for length {len}"
+    );
+    (start, end)
+}
+
+/// A text stored as a balanced tree of string chunks, for editing large
+/// documents
+///
+/// The leaves hold the text in order, in chunks of at most a few hundred
+/// bytes, and every node knows how many bytes and chars are below it. Edits
+/// split the tree at the chars they touch, in `O(log n)`, and join the pieces
+/// back with AVL rebalancing, so inserting into or removing from the middle
+/// of a long document never moves more than a chunk of text. Positions are
+/// counted in chars, as with an editor's cursor, and can be converted to and
+/// from byte positions and lines.
+///
+/// Lines are separated by line feeds, so a text has one more line than it has
+/// line feeds, the last one being empty if the text ends with a line feed.
+#[derive(Clone)]
+pub struct Rope {
+    root: Node,
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self {
+            root: Node::leaf(String::new()),
+        }
+    }
+
+    /// Returns the number of bytes of the text
+    pub fn len_bytes(&self) -> usize {
+        self.root.metrics.bytes
+    }
+
+    /// Returns the number of chars of the text
+    pub fn len_chars(&self) -> usize {
+        self.root.metrics.chars
+    }
+
+    /// Returns `true` if the text is empty
+    pub fn is_empty(&self) -> bool {
+        self.len_bytes() == 0
+    }
+
+    /// Inserts `text` before the char at `char_index`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `char_index` is greater than the number of chars.
+    pub fn insert(&mut self, char_index: usize, text: &str) {
+        let len = self.len_chars();
+        assert!(
+            char_index <= len,
+            "insertion index (is {char_index}) should be <= len (is {len})"
+        );
+        if text.is_empty() {
+            return;
+        }
+
+        let root = mem::replace(&mut self.root, Node::leaf(String::new()));
+        let (left, right) = split(root, char_index);
+        self.root = join(join(left, build(text)), right);
+    }
+
+    /// Removes the chars in `range`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range starts after it ends or ends after the last char.
+    pub fn remove<R>(&mut self, range: R)
+    where
+        R: RangeBounds<usize>,
+    {
+        let (start, end) = bounds(range, self.len_chars());
+        if start == end {
+            return;
+        }
+
+        let root = mem::replace(&mut self.root, Node::leaf(String::new()));
+        let (left, rest) = split(root, start);
+        let (_, right) = split(rest, end - start);
+        self.root = join(left, right);
+    }
+
+    /// Returns a copy of the chars in `range`
+    ///
+    /// Only the leaves overlapping the range are visited.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range starts after it ends or ends after the last char.
+    pub fn slice<R>(&self, range: R) -> String
+    where
+        R: RangeBounds<usize>,
+    {
+        let (start, end) = bounds(range, self.len_chars());
+        let mut slice = String::new();
+        // nodes left to copy from, with the number of chars before them
+        let mut stack = vec![(&self.root, 0)];
+
+        while let Some((node, offset)) = stack.pop() {
+            if offset >= end || offset + node.metrics.chars <= start {
+                continue;
+            }
+            match &node.kind {
+                Kind::Leaf(text) => {
+                    let from = byte_of_char(text, start.saturating_sub(offset));
+                    let to = byte_of_char(text, end - offset);
+                    slice.push_str(&text[from..to]);
+                }
+                Kind::Branch(left, right) => {
+                    stack.push((right, offset + left.metrics.chars));
+                    stack.push((left, offset));
+                }
+            }
+        }
+        slice
+    }
+
+    /// Returns the char at `char_index`
+    pub fn char_at(&self, char_index: usize) -> Option<char> {
+        if char_index >= self.len_chars() {
+            return None;
+        }
+        let (text, index) = self.leaf_of_char(char_index);
+        text.chars().nth(index)
+    }
+
+    /// Returns the leaf holding the char at `char_index`, which is at most the
+    /// number of chars, and the position of that char in it
+    fn leaf_of_char(&self, mut char_index: usize) -> (&str, usize) {
+        let mut node = &self.root;
+        loop {
+            match &node.kind {
+                Kind::Leaf(text) => return (text, char_index),
+                Kind::Branch(left, right) => {
+                    if char_index < left.metrics.chars {
+                        node = left;
+                    } else {
+                        char_index -= left.metrics.chars;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the byte position where the char at `char_index` starts, or
+    /// the number of bytes if `char_index` is the number of chars
+    ///
+    /// # Panics
+    ///
+    /// Panics if `char_index` is greater than the number of chars.
+    pub fn char_to_byte(&self, char_index: usize) -> usize {
+        let len = self.len_chars();
+        assert!(
+            char_index <= len,
+            "char index (is {char_index}) should be <= len (is {len})"
+        );
+
+        let mut node = &self.root;
+        let mut index = char_index;
+        let mut bytes = 0;
+        loop {
+            match &node.kind {
+                Kind::Leaf(text) => return bytes + byte_of_char(text, index),
+                Kind::Branch(left, right) => {
+                    if index < left.metrics.chars {
+                        node = left;
+                    } else {
+                        index -= left.metrics.chars;
+                        bytes += left.metrics.bytes;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the position of the char the byte at `byte_index` belongs to,
+    /// or the number of chars if `byte_index` is the number of bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics if `byte_index` is greater than the number of bytes.
+    pub fn byte_to_char(&self, byte_index: usize) -> usize {
+        let len = self.len_bytes();
+        assert!(
+            byte_index <= len,
+            "byte index (is {byte_index}) should be <= len (is {len})"
+        );
+
+        let mut node = &self.root;
+        let mut index = byte_index;
+        let mut chars = 0;
+        loop {
+            match &node.kind {
+                Kind::Leaf(text) => {
+                    let before = text
+                        .char_indices()
+                        .take_while(|&(start, _)| start <= index)
+                        .count();
+                    return chars + before.saturating_sub(usize::from(index < text.len()));
+                }
+                Kind::Branch(left, right) => {
+                    if index < left.metrics.bytes {
+                        node = left;
+                    } else {
+                        index -= left.metrics.bytes;
+                        chars += left.metrics.chars;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the number of lines of the text, which is one more than the
+    /// number of line feeds
+    pub fn len_lines(&self) -> usize {
+        self.root.metrics.lines + 1
+    }
+
+    /// Returns the position of the first char of the line at `line_index`,
+    /// or the number of chars if `line_index` is the number of lines
+    ///
+    /// The nodes are descended by their line feed counts down to the leaf
+    /// holding the line feed before the line.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `line_index` is greater than the number of lines.
+    pub fn line_to_char(&self, line_index: usize) -> usize {
+        let len = self.len_lines();
+        assert!(
+            line_index <= len,
+            "line index (is {line_index}) should be <= len (is {len})"
+        );
+        if line_index == 0 {
+            return 0;
+        } else if line_index == len {
+            return self.len_chars();
+        }
+
+        let mut node = &self.root;
+        // line feeds to pass, the last one ending the line before
+        let mut feeds = line_index;
+        let mut chars = 0;
+        loop {
+            match &node.kind {
+                Kind::Leaf(text) => {
+                    let (end, _) = text
+                        .match_indices('\n')
+                        .nth(feeds - 1)
+                        .expect("counted in the metrics");
+                    return chars + text[..end].chars().count() + 1;
+                }
+                Kind::Branch(left, right) => {
+                    if feeds <= left.metrics.lines {
+                        node = left;
+                    } else {
+                        feeds -= left.metrics.lines;
+                        chars += left.metrics.chars;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the index of the line holding the char at `char_index`, or the
+    /// last line if `char_index` is the number of chars
+    ///
+    /// # Panics
+    ///
+    /// Panics if `char_index` is greater than the number of chars.
+    pub fn char_to_line(&self, char_index: usize) -> usize {
+        let len = self.len_chars();
+        assert!(
+            char_index <= len,
+            "char index (is {char_index}) should be <= len (is {len})"
+        );
+
+        let mut node = &self.root;
+        let mut index = char_index;
+        let mut lines = 0;
+        loop {
+            match &node.kind {
+                Kind::Leaf(text) => {
+                    let feeds = text.chars().take(index).filter(|&c| c == '\n').count();
+                    return lines + feeds;
+                }
+                Kind::Branch(left, right) => {
+                    if index < left.metrics.chars {
+                        node = left;
+                    } else {
+                        index -= left.metrics.chars;
+                        lines += left.metrics.lines;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns an iterator over the lines of the text, without their line
+    /// feeds, yielding as many lines as [`Rope::len_lines`]
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        let mut chunks = self.chunks();
+        // the part of the current chunk not yielded yet
+        let mut rest = "";
+        let mut done = false;
+
+        iter::from_fn(move || {
+            if done {
+                return None;
+            }
+
+            let mut line = String::new();
+            loop {
+                if let Some(end) = rest.find('\n') {
+                    line.push_str(&rest[..end]);
+                    rest = &rest[end + 1..];
+                    return Some(line);
+                }
+                line.push_str(rest);
+                match chunks.next() {
+                    Some(chunk) => rest = chunk,
+                    None => {
+                        done = true;
+                        return Some(line);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Returns an iterator over the chunks of the text, in order, which
+    /// concatenate to the whole text
+    pub fn chunks(&self) -> impl Iterator<Item = &str> {
+        let mut stack = vec![&self.root];
+        iter::from_fn(move || loop {
+            let node = stack.pop()?;
+            match &node.kind {
+                Kind::Leaf(text) if text.is_empty() => {}
+                Kind::Leaf(text) => return Some(text.as_str()),
+                Kind::Branch(left, right) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        })
+    }
+
+    /// Returns an iterator over the chars of the text
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.chunks().flat_map(str::chars)
+    }
+
+    /// Returns `true` if every node is balanced like in an AVL tree and sums
+    /// up its children, and the leaves are non-empty chunks of at most the
+    /// chunk size, unless the text is empty
+    pub fn is_valid(&self) -> bool {
+        let mut stack = vec![&self.root];
+
+        while let Some(node) = stack.pop() {
+            match &node.kind {
+                Kind::Leaf(text) => {
+                    let sized = text.len() <= MAX_CHUNK
+                        && (!text.is_empty() || std::ptr::eq(node, &self.root));
+                    if !sized || node.height != 0 || node.metrics != Metrics::of(text) {
+                        return false;
+                    }
+                }
+                Kind::Branch(left, right) => {
+                    let balanced = left.height.abs_diff(right.height) <= 1
+                        && node.height == left.height.max(right.height) + 1;
+                    if !balanced || node.metrics != left.metrics + right.metrics {
+                        return false;
+                    }
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+        true
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        Self { root: build(text) }
+    }
+}
+
+impl From<String> for Rope {
+    fn from(text: String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl fmt::Debug for Rope {
+    /// Formats the text like a string, however it is split into chunks
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string(), f)
+    }
+}
+
+impl PartialEq for Rope {
+    fn eq(&self, other: &Self) -> bool {
+        self.len_bytes() == other.len_bytes()
+            && self
+                .chunks()
+                .flat_map(str::bytes)
+                .eq(other.chunks().flat_map(str::bytes))
+    }
+}
+
+impl Eq for Rope {}
+
+impl PartialEq<str> for Rope {
+    fn eq(&self, other: &str) -> bool {
+        self.len_bytes() == other.len() && self.chunks().flat_map(str::bytes).eq(other.bytes())
+    }
+}
+
+impl PartialEq<&str> for Rope {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_insert_remove_slice() {
+        let mut rope = Rope::from("tea to inn");
+
+        rope.insert(3, "m");
+        rope.insert(0, "¡");
+        rope.insert(rope.len_chars(), "!");
+        assert_eq!(rope, "¡team to inn!");
+        assert_eq!(rope.len_chars(), 13);
+        assert_eq!(rope.len_bytes(), 14);
+
+        rope.remove(5..8);
+        assert_eq!(rope, "¡team inn!");
+        rope.remove(..1);
+        rope.remove(8..);
+        assert_eq!(rope.to_string(), "team inn");
+        assert_eq!(rope.slice(2..6), "am i");
+        assert_eq!(rope.slice(..), "team inn");
+        assert_eq!(rope.slice(8..), "");
+        assert_eq!(rope.char_at(5), Some('i'));
+        assert_eq!(rope.char_at(8), None);
+        assert!(rope.is_valid());
+
+        rope.remove(..);
+        assert!(rope.is_empty());
+        assert!(rope.is_valid());
+    }
+
+    #[test]
+    fn test_large_edits() {
+        let line = "The quick brown fox jumps over the lazy dog. Ünïcödé — ✓\n";
+        let mut rope = Rope::new();
+        let mut expected = String::new();
+
+        for round in 0..200 {
+            let at = expected.chars().count() * round / 200;
+            let byte = expected
+                .char_indices()
+                .nth(at)
+                .map_or(expected.len(), |(byte, _)| byte);
+            rope.insert(at, line);
+            expected.insert_str(byte, line);
+        }
+        assert!(rope.is_valid());
+        assert!(rope.chunks().count() > 1);
+        assert_eq!(rope, expected.as_str());
+
+        for round in 0..100 {
+            let at = (round * 37) % (rope.len_chars() - 50);
+            rope.remove(at..at + 50);
+            let start = expected.char_indices().nth(at).unwrap().0;
+            let end = expected.char_indices().nth(at + 50).unwrap().0;
+            expected.replace_range(start..end, "");
+        }
+        assert!(rope.is_valid());
+        assert_eq!(rope.to_string(), expected);
+        assert_eq!(
+            rope.slice(1_000..1_100),
+            expected.chars().skip(1_000).take(100).collect::<String>()
+        );
+        assert!(rope.chars().eq(expected.chars()));
+    }
+
+    #[test]
+    fn test_index_conversion() {
+        let text = "añb✓c".repeat(300);
+        let rope = Rope::from(text.as_str());
+
+        assert!(rope.is_valid());
+        for (char_index, (byte_index, _)) in text.char_indices().enumerate() {
+            assert_eq!(rope.char_to_byte(char_index), byte_index);
+            assert_eq!(rope.byte_to_char(byte_index), char_index);
+        }
+        assert_eq!(rope.char_to_byte(rope.len_chars()), rope.len_bytes());
+        assert_eq!(rope.byte_to_char(rope.len_bytes()), rope.len_chars());
+        // the second byte of 'ñ' belongs to it
+        assert_eq!(rope.byte_to_char(2), 1);
+        assert_eq!(rope.byte_to_char(5), 3);
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut rope = Rope::from("tea\nten\n\nto");
+
+        assert_eq!(rope.len_lines(), 4);
+        assert_eq!(
+            rope.lines().collect::<Vec<_>>(),
+            vec!["tea", "ten", "", "to"]
+        );
+        assert_eq!(rope.line_to_char(0), 0);
+        assert_eq!(rope.line_to_char(1), 4);
+        assert_eq!(rope.line_to_char(3), 9);
+        assert_eq!(rope.line_to_char(4), rope.len_chars());
+        assert_eq!(rope.char_to_line(3), 0);
+        assert_eq!(rope.char_to_line(4), 1);
+        assert_eq!(rope.char_to_line(8), 2);
+        assert_eq!(rope.char_to_line(rope.len_chars()), 3);
+
+        rope.insert(rope.len_chars(), "\n");
+        assert_eq!(rope.len_lines(), 5);
+        assert_eq!(rope.lines().last(), Some(String::new()));
+        assert_eq!(Rope::new().lines().collect::<Vec<_>>(), vec![""]);
+    }
+
+    #[test]
+    fn test_lines_across_chunks() {
+        let text: String = (0..2_000).map(|line| format!("line {line} ✓\n")).collect();
+        let mut rope = Rope::from(text.as_str());
+        rope.remove(5..10);
+        let expected = rope.to_string();
+
+        assert!(rope.is_valid());
+        assert!(rope.lines().eq(expected.split('\n').map(str::to_owned)));
+        for (line, start) in [0]
+            .into_iter()
+            .chain(
+                expected
+                    .chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == '\n')
+                    .map(|(index, _)| index + 1),
+            )
+            .enumerate()
+        {
+            assert_eq!(rope.line_to_char(line), start);
+            assert_eq!(rope.char_to_line(start), line);
+        }
+    }
+}
@@ -0,0 +1,569 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{self, Equal, Greater, Less},
+    fmt, mem,
+};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+impl<T> Node<T> {
+    fn new(data: T) -> Box<Self> {
+        Box::new(Self {
+            data,
+            left: None,
+            right: None,
+        })
+    }
+}
+
+/// Counts the nodes below `link`, including itself
+fn count<T>(link: &Link<T>) -> usize {
+    let mut count = 0;
+    let mut stack: Vec<&Node<T>> = link.as_deref().into_iter().collect();
+
+    while let Some(node) = stack.pop() {
+        count += 1;
+        stack.extend(node.left.as_deref());
+        stack.extend(node.right.as_deref());
+    }
+    count
+}
+
+/// Moves the elements below `link` into a vector, in order
+fn into_sorted_vec<T>(link: Link<T>) -> Vec<T> {
+    let mut sorted = Vec::new();
+    let mut stack: Vec<Box<Node<T>>> = Vec::new();
+    let mut next = link;
+
+    loop {
+        while let Some(mut node) = next {
+            next = node.left.take();
+            stack.push(node);
+        }
+
+        let Some(mut node) = stack.pop() else {
+            return sorted;
+        };
+        next = node.right.take();
+        sorted.push(node.data);
+    }
+}
+
+/// Builds a perfectly balanced subtree from the next `len` sorted elements
+fn build_balanced<T>(sorted: &mut impl Iterator<Item = T>, len: usize) -> Link<T> {
+    if len == 0 {
+        return None;
+    }
+
+    let left = build_balanced(sorted, len / 2);
+    let mut node = Node::new(sorted.next().expect("len elements are left"));
+    node.left = left;
+    node.right = build_balanced(sorted, len - len / 2 - 1);
+    Some(node)
+}
+
+/// Detaches the smallest node below `node`, returning it and what remains
+fn pop_min<T>(mut node: Box<Node<T>>) -> (Box<Node<T>>, Link<T>) {
+    match node.left.take() {
+        None => {
+            let right = node.right.take();
+            (node, right)
+        }
+        Some(left) => {
+            let (min, rest) = pop_min(left);
+            node.left = rest;
+            (min, Some(node))
+        }
+    }
+}
+
+/// A binary search tree behaving like a set, kept balanced by occasionally
+/// rebuilding whole subtrees
+///
+/// Nodes hold nothing but the element and its children. When an insertion
+/// lands deeper than `log(len)` in base `1 / alpha`, the lowest ancestor
+/// whose child holds more than `alpha` of its elements is rebuilt perfectly
+/// balanced, and the whole tree is rebuilt once removals bring the length
+/// below `alpha` times its maximum. This gives `O(log n)` amortized updates
+/// and `O(log n)` worst case lookups.
+#[derive(Clone)]
+pub struct ScapegoatTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+    len: usize,
+    /// largest `len` since the whole tree was last rebuilt
+    max_len: usize,
+    alpha: f64,
+}
+
+impl<T> ScapegoatTree<T>
+where
+    T: Ord,
+{
+    /// Creates an empty tree with an alpha of `0.7`
+    pub fn new() -> Self {
+        Self::with_alpha(0.7)
+    }
+
+    /// Creates an empty tree with the given balance factor
+    ///
+    /// Lower values keep the tree shallower at the cost of more rebuilding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alpha` is not in `0.5..1.0`.
+    pub fn with_alpha(alpha: f64) -> Self {
+        assert!(
+            (0.5..1.0).contains(&alpha),
+            "alpha must be in 0.5..1.0, got {alpha}"
+        );
+
+        Self {
+            root: None,
+            len: 0,
+            max_len: 0,
+            alpha,
+        }
+    }
+
+    /// Returns the balance factor
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+        self.max_len = 0;
+    }
+
+    /// Returns the number of bytes allocated for the nodes
+    pub fn memory_usage(&self) -> usize {
+        self.len * mem::size_of::<Node<T>>()
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        fn height<T>(link: &Link<T>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return Some(&current.data),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        let (found, path) = self.search_path(|stored_data| data.cmp(stored_data));
+        if found {
+            return false;
+        }
+
+        *self.link_at_path(&path) = Some(Node::new(data));
+        self.len += 1;
+        self.max_len = self.max_len.max(self.len);
+
+        if path.len() > self.depth_bound(self.len) {
+            self.rebuild_scapegoat(&path);
+        }
+        true
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (found, path) = self.search_path(|stored_data| key.cmp(stored_data.borrow()));
+        if !found {
+            return None;
+        }
+
+        let link = self.link_at_path(&path);
+        let mut node = link.take().expect("found on the path");
+
+        *link = match (node.left.take(), node.right.take()) {
+            (None, child) | (child, None) => child,
+            // the in-order successor takes the place of the node
+            (Some(left), Some(right)) => {
+                let (mut min, rest) = pop_min(right);
+                min.left = Some(left);
+                min.right = rest;
+                Some(min)
+            }
+        };
+        self.len -= 1;
+
+        if (self.len as f64) < self.alpha * self.max_len as f64 {
+            self.rebuild(&[]);
+            self.max_len = self.len;
+        }
+        Some(node.data)
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> ScapegoatTreeIterator<'_, T> {
+        let mut iter = ScapegoatTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the elements are in search order and the tree is no
+    /// deeper than the balance factor allows
+    pub fn is_valid(&self) -> bool {
+        fn check<T: Ord>(link: &Link<T>, lower: Option<&T>, upper: Option<&T>) -> bool {
+            let Some(node) = link else {
+                return true;
+            };
+
+            lower.is_none_or(|lower| node.data > *lower)
+                && upper.is_none_or(|upper| node.data < *upper)
+                && check(&node.left, lower, Some(&node.data))
+                && check(&node.right, Some(&node.data), upper)
+        }
+
+        check(&self.root, None, None)
+            && count(&self.root) == self.len
+            && self.height() <= self.depth_bound(self.max_len) + 1
+    }
+
+    /// Deepest an insertion may land, counted in edges from the root, before
+    /// a rebuild is due
+    fn depth_bound(&self, len: usize) -> usize {
+        if len <= 1 {
+            return 0;
+        }
+
+        ((len as f64).ln() / (1.0 / self.alpha).ln()).floor() as usize
+    }
+
+    /// Returns whether an element for which `locate` reports `Equal` is
+    /// stored, and the directions taken from the root to it or to the empty
+    /// link where it would be
+    fn search_path<F>(&self, mut locate: F) -> (bool, Vec<Ordering>)
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        let mut path = Vec::new();
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            let direction = locate(&current.data);
+            node = match direction {
+                Equal => return (true, path),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+            path.push(direction);
+        }
+        (false, path)
+    }
+
+    /// Follows `path` from the root to a link
+    fn link_at_path(&mut self, path: &[Ordering]) -> &mut Link<T> {
+        let mut link = &mut self.root;
+
+        for &direction in path {
+            let node = link.as_mut().expect("path leads through nodes");
+            link = match direction {
+                Less => &mut node.left,
+                _ => &mut node.right,
+            };
+        }
+        link
+    }
+
+    /// Walks up from the node newly inserted at the end of `path`, rebuilding
+    /// the first ancestor that is out of balance
+    fn rebuild_scapegoat(&mut self, path: &[Ordering]) {
+        let mut child_len = 1;
+
+        for depth in (0..path.len()).rev() {
+            let parent = self
+                .link_at_path(&path[..depth])
+                .as_deref()
+                .expect("on the path");
+            let sibling = match path[depth] {
+                Less => &parent.right,
+                _ => &parent.left,
+            };
+            let parent_len = child_len + 1 + count(sibling);
+
+            if child_len as f64 > self.alpha * parent_len as f64 {
+                self.rebuild(&path[..depth]);
+                return;
+            }
+            child_len = parent_len;
+        }
+    }
+
+    /// Rebuilds the subtree at the end of `path` perfectly balanced
+    fn rebuild(&mut self, path: &[Ordering]) {
+        let link = self.link_at_path(path);
+        let sorted = into_sorted_vec(link.take());
+        let len = sorted.len();
+
+        *link = build_balanced(&mut sorted.into_iter(), len);
+    }
+}
+
+impl<T> Default for ScapegoatTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for ScapegoatTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T> PartialEq for ScapegoatTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for ScapegoatTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for ScapegoatTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for ScapegoatTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`ScapegoatTree`] in
+/// order
+pub struct ScapegoatTreeIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> ScapegoatTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for ScapegoatTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for ScapegoatTreeIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a ScapegoatTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = ScapegoatTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the elements in preorder, which pins down the shape
+    fn preorder(link: &Link<u32>) -> Vec<u32> {
+        let mut elements = Vec::new();
+        let mut stack: Vec<&Node<u32>> = link.as_deref().into_iter().collect();
+
+        while let Some(node) = stack.pop() {
+            elements.push(node.data);
+            stack.extend(node.right.as_deref());
+            stack.extend(node.left.as_deref());
+        }
+        elements
+    }
+
+    #[test]
+    fn test_removals_rebuild_below_alpha() {
+        let mut tree = ScapegoatTree::with_alpha(0.75);
+        tree.extend(0..16u32);
+        assert_eq!(tree.max_len, 16);
+
+        // no rebuild while at least 12 of the 16 elements are left
+        for data in 0..4 {
+            assert_eq!(tree.take(&data), Some(data));
+            assert!(!tree.remove(&data));
+            assert_eq!(tree.max_len, 16);
+            assert!(tree.is_valid());
+        }
+
+        // the next removal rebuilds the whole tree perfectly balanced
+        assert!(tree.remove(&4));
+        assert_eq!(tree.max_len, 11);
+        assert_eq!(
+            preorder(&tree.root),
+            preorder(&build_balanced(&mut (5..16), 11))
+        );
+        assert!(tree.is_valid());
+
+        // and the threshold follows the new length, 8.25 of 11
+        assert!(tree.remove(&15) && tree.remove(&14));
+        assert_eq!(tree.max_len, 11);
+        assert!(tree.remove(&13));
+        assert_eq!(tree.max_len, 8);
+        assert_eq!(
+            preorder(&tree.root),
+            preorder(&build_balanced(&mut (5..13), 8))
+        );
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            (5..13).collect::<Vec<_>>()
+        );
+
+        // insertions raise it again
+        tree.extend(13..20);
+        assert_eq!(tree.max_len, 15);
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_sorted_input_stays_shallow() {
+        for alpha in [0.5, 0.7, 0.9] {
+            let mut tree = ScapegoatTree::with_alpha(alpha);
+            tree.extend(0..2_000u32);
+            assert!(tree.is_valid(), "alpha {alpha}");
+
+            for data in (0..2_000).step_by(3) {
+                assert!(tree.remove(&data));
+            }
+            assert!(tree.is_valid(), "alpha {alpha}");
+            assert_eq!(tree.len(), 1_333);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "alpha must be in 0.5..1.0")]
+    fn test_invalid_alpha() {
+        ScapegoatTree::<u32>::with_alpha(1.0);
+    }
+}
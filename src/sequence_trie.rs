@@ -0,0 +1,612 @@
+use std::{borrow::Borrow, fmt, mem};
+
+/// A map keyed by sequences of tokens, sharing the nodes of common prefixes
+///
+/// This is the [`Trie`](crate::Trie) generalized from bytes to any ordered
+/// token type, for keys such as path segments, token streams or the numbers
+/// of an OID. A key is anything iterating over tokens, so `["api", "v1"]`,
+/// `vec![1, 3, 6]` or `path.split('/')` all work. Lookups take any sequence of
+/// tokens or references to them, and children are kept sorted by token, so
+/// they take `O(m log σ)` comparisons for a key of `m` tokens, `σ` being the
+/// number of distinct tokens following a prefix.
+pub struct SequenceTrie<K, V> {
+    root: Node<K, V>,
+    len: usize,
+}
+
+struct Node<K, V> {
+    value: Option<V>,
+    /// the child for each token following this prefix, sorted by token
+    children: Vec<(K, Node<K, V>)>,
+}
+
+impl<K, V> Node<K, V>
+where
+    K: Ord,
+{
+    fn new() -> Self {
+        Self {
+            value: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn child(&self, token: &K) -> Option<&Node<K, V>> {
+        let position = self.position(token).ok()?;
+        Some(&self.children[position].1)
+    }
+
+    fn child_mut(&mut self, token: &K) -> Option<&mut Node<K, V>> {
+        let position = self.position(token).ok()?;
+        Some(&mut self.children[position].1)
+    }
+
+    /// Returns the child for `token`, adding an empty one if there is none
+    fn child_or_insert(&mut self, token: K) -> &mut Node<K, V> {
+        let position = match self.position(&token) {
+            Ok(position) => position,
+            Err(position) => {
+                self.children.insert(position, (token, Node::new()));
+                position
+            }
+        };
+        &mut self.children[position].1
+    }
+
+    fn position(&self, token: &K) -> Result<usize, usize> {
+        self.children
+            .binary_search_by(|(child_token, _)| child_token.cmp(token))
+    }
+}
+
+impl<K, V> SequenceTrie<K, V>
+where
+    K: Ord,
+{
+    pub fn new() -> Self {
+        Self {
+            root: Node::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the trie contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all keys
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Stores `value` under `key`, returning the value previously stored there
+    pub fn insert<I>(&mut self, key: I, value: V) -> Option<V>
+    where
+        I: IntoIterator<Item = K>,
+    {
+        let mut node = &mut self.root;
+        for token in key {
+            node = node.child_or_insert(token);
+        }
+
+        let old = node.value.replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Returns `true` if a value is stored under `key`
+    pub fn contains_key<I>(&self, key: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get<I>(&self, key: I) -> Option<&V>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        self.find(key)?.value.as_ref()
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut<I>(&mut self, key: I) -> Option<&mut V>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let mut node = &mut self.root;
+        for token in key {
+            node = node.child_mut(token.borrow())?;
+        }
+        node.value.as_mut()
+    }
+
+    /// Returns `true` if some stored key starts with `prefix`
+    ///
+    /// Every key starts with the empty prefix, so it is only contained when
+    /// the trie is not empty.
+    pub fn contains_prefix<I>(&self, prefix: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        // nodes without a value are only kept while some key goes through them
+        !self.is_empty() && self.find(prefix).is_some()
+    }
+
+    /// Returns the longest stored key that `key` starts with, as its number of
+    /// tokens, with its value
+    ///
+    /// This is the lookup of a router, finding the most specific route
+    /// registered for a path.
+    pub fn longest_prefix<I>(&self, key: I) -> Option<(usize, &V)>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let mut node = &self.root;
+        let mut longest = node.value.as_ref().map(|value| (0, value));
+
+        for (depth, token) in key.into_iter().enumerate() {
+            let Some(child) = node.child(token.borrow()) else {
+                break;
+            };
+            node = child;
+            if let Some(value) = &node.value {
+                longest = Some((depth + 1, value));
+            }
+        }
+        longest
+    }
+
+    /// Removes `key` from the trie, returning the value stored under it
+    ///
+    /// The nodes that only led to `key` are removed with it.
+    pub fn remove<I>(&mut self, key: I) -> Option<V>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let key: Vec<I::Item> = key.into_iter().collect();
+
+        // the nodes below the last one still needed by another key are pruned
+        let mut node = &self.root;
+        let mut keep = 0;
+        for (depth, token) in key.iter().enumerate() {
+            if node.value.is_some() || node.children.len() > 1 {
+                keep = depth;
+            }
+            node = node.child(token.borrow())?;
+        }
+        node.value.as_ref()?;
+        let prune = node.children.is_empty();
+
+        let mut node = &mut self.root;
+        for token in &key[..keep] {
+            node = node.child_mut(token.borrow()).expect("checked above");
+        }
+
+        let value = if prune && keep < key.len() {
+            let position = node.position(key[keep].borrow()).expect("checked above");
+            let (_, mut branch) = node.children.remove(position);
+            let mut target = &mut branch;
+            for token in &key[keep + 1..] {
+                target = target.child_mut(token.borrow()).expect("checked above");
+            }
+            let value = target.value.take();
+            drop_iteratively(branch);
+            value
+        } else {
+            for token in &key[keep..] {
+                node = node.child_mut(token.borrow()).expect("checked above");
+            }
+            node.value.take()
+        };
+
+        self.len -= 1;
+        value
+    }
+
+    /// Returns an iterator over the entries in lexicographic order of their
+    /// keys
+    pub fn iter(&self) -> SequenceTrieIterator<'_, K, V> {
+        SequenceTrieIterator {
+            key: Vec::new(),
+            stack: vec![(0, None, &self.root)],
+        }
+    }
+
+    /// Returns an iterator over the keys in lexicographic order
+    pub fn keys(&self) -> impl Iterator<Item = Vec<&K>> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values in lexicographic order of their
+    /// keys
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`, in
+    /// lexicographic order of their keys
+    ///
+    /// The node for `prefix` is found once, then only the subtree below it is
+    /// visited, a key coming before the keys it is a prefix of.
+    pub fn iter_prefix<I>(&self, prefix: I) -> SequenceTrieIterator<'_, K, V>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        // the stored tokens of the prefix, which the yielded keys refer to
+        let mut key = Vec::new();
+        let mut node = Some(&self.root);
+        for token in prefix {
+            let Some(parent) = node else {
+                break;
+            };
+            node = parent.position(token.borrow()).ok().map(|position| {
+                let (token, child) = &parent.children[position];
+                key.push(token);
+                child
+            });
+        }
+
+        let depth = key.len();
+        SequenceTrieIterator {
+            key,
+            stack: node.map(|node| (depth, None, node)).into_iter().collect(),
+        }
+    }
+
+    /// Returns the node standing for `prefix`
+    fn find<I>(&self, prefix: I) -> Option<&Node<K, V>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let mut node = &self.root;
+        for token in prefix {
+            node = node.child(token.borrow())?;
+        }
+        Some(node)
+    }
+
+    /// Returns `true` if every node other than the root leads to a value,
+    /// the children of each node are sorted by distinct tokens and `len`
+    /// matches the number of values
+    pub fn is_valid(&self) -> bool {
+        let mut values = 0;
+        let mut stack = vec![(&self.root, true)];
+
+        while let Some((node, is_root)) = stack.pop() {
+            let sorted = node.children.windows(2).all(|pair| pair[0].0 < pair[1].0);
+            let leads_to_value = is_root || node.value.is_some() || !node.children.is_empty();
+            if !sorted || !leads_to_value {
+                return false;
+            }
+
+            values += usize::from(node.value.is_some());
+            stack.extend(node.children.iter().map(|(_, child)| (child, false)));
+        }
+        values == self.len
+    }
+}
+
+/// Releases the nodes under `node` one at a time, so that dropping very long
+/// keys can't overflow the stack
+fn drop_iteratively<K, V>(node: Node<K, V>) {
+    let mut stack = vec![node];
+
+    while let Some(mut node) = stack.pop() {
+        stack.extend(
+            mem::take(&mut node.children)
+                .into_iter()
+                .map(|(_, child)| child),
+        );
+    }
+}
+
+/// Copies the nodes under `node` one at a time, so that cloning very long keys
+/// can't overflow the stack
+fn clone_iteratively<K, V>(node: &Node<K, V>) -> Node<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    let mut stack = vec![(node, false)];
+    // the copies of the finished subtrees, siblings in the order of their tokens
+    let mut copies = Vec::new();
+
+    while let Some((node, children_done)) = stack.pop() {
+        if !children_done {
+            stack.push((node, true));
+            stack.extend(node.children.iter().rev().map(|(_, child)| (child, false)));
+            continue;
+        }
+
+        let children = copies.split_off(copies.len() - node.children.len());
+        copies.push(Node {
+            value: node.value.clone(),
+            children: node
+                .children
+                .iter()
+                .map(|(token, _)| token.clone())
+                .zip(children)
+                .collect(),
+        });
+    }
+    copies.pop().unwrap()
+}
+
+impl<K, V> Clone for SequenceTrie<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            root: clone_iteratively(&self.root),
+            len: self.len,
+        }
+    }
+}
+
+impl<K, V> Default for SequenceTrie<K, V>
+where
+    K: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for SequenceTrie<K, V> {
+    fn drop(&mut self) {
+        drop_iteratively(Node {
+            value: None,
+            children: mem::take(&mut self.root.children),
+        });
+    }
+}
+
+impl<K, V> fmt::Debug for SequenceTrie<K, V>
+where
+    K: Ord + fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> PartialEq for SequenceTrie<K, V>
+where
+    K: Ord,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<K, V> Eq for SequenceTrie<K, V>
+where
+    K: Ord,
+    V: Eq,
+{
+}
+
+impl<I, K, V> FromIterator<(I, V)> for SequenceTrie<K, V>
+where
+    I: IntoIterator<Item = K>,
+    K: Ord,
+{
+    fn from_iter<T: IntoIterator<Item = (I, V)>>(iter: T) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<I, K, V> Extend<(I, V)> for SequenceTrie<K, V>
+where
+    I: IntoIterator<Item = K>,
+    K: Ord,
+{
+    fn extend<T: IntoIterator<Item = (I, V)>>(&mut self, iter: T) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the entries of a [`SequenceTrie`] in lexicographic order
+/// of their keys, walking the nodes depth first
+pub struct SequenceTrieIterator<'a, K, V> {
+    /// tokens of the last node visited
+    key: Vec<&'a K>,
+    /// nodes left to visit, with the length of the key above them and the
+    /// token leading to them, the next one on top
+    stack: Vec<(usize, Option<&'a K>, &'a Node<K, V>)>,
+}
+
+impl<'a, K, V> Iterator for SequenceTrieIterator<'a, K, V> {
+    type Item = (Vec<&'a K>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (depth, token, node) = self.stack.pop()?;
+            self.key.truncate(depth);
+            self.key.extend(token);
+
+            let depth = self.key.len();
+            self.stack.extend(
+                node.children
+                    .iter()
+                    .rev()
+                    .map(|(token, child)| (depth, Some(token), child)),
+            );
+
+            if let Some(value) = &node.value {
+                return Some((self.key.clone(), value));
+            }
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a SequenceTrie<K, V>
+where
+    K: Ord,
+{
+    type Item = (Vec<&'a K>, &'a V);
+    type IntoIter = SequenceTrieIterator<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn routes() -> SequenceTrie<&'static str, u32> {
+        [
+            "",
+            "api",
+            "api/v1",
+            "api/v1/users",
+            "api/v2",
+            "static",
+            "api/v1/items",
+        ]
+        .into_iter()
+        .map(|path| path.split('/').filter(|segment| !segment.is_empty()))
+        .zip(0..)
+        .collect()
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let mut trie = routes();
+
+        assert_eq!(trie.len(), 7);
+        assert_eq!(trie.get(["api", "v1"]), Some(&2));
+        assert_eq!(trie.get(Vec::<&str>::new()), Some(&0));
+        assert_eq!(trie.get(["api", "v3"]), None);
+        assert_eq!(trie.insert(["api", "v2"], 20), Some(4));
+        *trie.get_mut("static".split('/')).unwrap() += 50;
+
+        assert_eq!(trie.get(&["api", "v2"]), Some(&20));
+        assert_eq!(trie.get(["static"]), Some(&55));
+        assert!(trie.contains_prefix(["api", "v1"]));
+        assert!(!trie.contains_prefix(["api", "v1", "users", "7"]));
+        assert!(trie.is_valid());
+
+        let oids: SequenceTrie<u32, &str> = [(vec![1, 3, 6, 1], "internet"), (vec![1, 3], "org")]
+            .into_iter()
+            .collect();
+        assert_eq!(oids.get([1, 3, 6, 1]), Some(&"internet"));
+        assert_eq!(
+            format!("{:?}", oids),
+            "{[1, 3]: \"org\", [1, 3, 6, 1]: \"internet\"}"
+        );
+    }
+
+    #[test]
+    fn test_prefix_queries() {
+        let trie = routes();
+        let keys = |iter: SequenceTrieIterator<'_, &str, u32>| {
+            iter.map(|(key, _)| key.into_iter().copied().collect::<Vec<_>>().join("/"))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            keys(trie.iter()),
+            vec![
+                "",
+                "api",
+                "api/v1",
+                "api/v1/items",
+                "api/v1/users",
+                "api/v2",
+                "static"
+            ]
+        );
+        assert_eq!(
+            keys(trie.iter_prefix(["api", "v1"])),
+            vec!["api/v1", "api/v1/items", "api/v1/users"]
+        );
+        assert!(trie.iter_prefix(["api", "v3"]).next().is_none());
+
+        assert_eq!(
+            trie.longest_prefix("api/v1/users/7".split('/')),
+            Some((3, &3))
+        );
+        assert_eq!(trie.longest_prefix("api/v3".split('/')), Some((1, &1)));
+        assert_eq!(trie.longest_prefix(["other"]), Some((0, &0)));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut trie = routes();
+
+        assert_eq!(trie.remove(["api", "v1", "users"]), Some(3));
+        assert_eq!(trie.remove(["api", "v1", "users"]), None);
+        assert!(!trie.contains_prefix(["api", "v1", "users"]));
+        assert_eq!(trie.remove(["api"]), Some(1));
+        assert!(trie.contains_key(["api", "v2"]));
+        assert!(trie.is_valid());
+
+        for key in [
+            &["api", "v1", "items"][..],
+            &["api", "v1"],
+            &["api", "v2"],
+            &["static"],
+            &[],
+        ] {
+            assert!(trie.remove(key).is_some());
+        }
+        assert!(trie.is_empty());
+        assert!(trie.root.children.is_empty());
+        assert!(trie.is_valid());
+    }
+
+    #[test]
+    fn test_clone() {
+        let trie = routes();
+        let mut clone = trie.clone();
+        assert_eq!(clone, trie);
+        assert!(clone.is_valid());
+
+        clone.insert(["api", "v3"], 7);
+        assert_eq!(clone.remove(["api", "v1"]), Some(2));
+        assert_eq!(trie.get(["api", "v1"]), Some(&2));
+        assert!(!trie.contains_key(["api", "v3"]));
+
+        // a long key makes a deep chain of nodes
+        let mut trie = SequenceTrie::new();
+        let long = vec![0; 1_000_000];
+        trie.insert(long.iter().copied(), 1);
+        trie.insert(long[..10].iter().copied(), 2);
+        trie.insert([1], 3);
+        let clone = trie.clone();
+        assert_eq!(clone.len(), 3);
+        assert_eq!(clone.get(&long), Some(&1));
+        assert_eq!(clone.get(&long[..10]), Some(&2));
+        assert_eq!(clone.get([1]), Some(&3));
+        assert!(clone.is_valid());
+    }
+}
@@ -0,0 +1,651 @@
+use crate::treap::XorShift;
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{self, Greater},
+    fmt, mem,
+};
+
+/// Marks the end of a level, or of the free list
+const NIL: u32 = u32::MAX;
+
+/// Levels a node can appear on, enough for far more than `u32::MAX` elements
+const MAX_LEVELS: usize = 32;
+
+/// A probabilistic ordered set made of linked lists stacked on top of each
+/// other
+///
+/// Every element is on the bottom level, and each element on a level is also
+/// on the one above with probability one half. Searches start on the top,
+/// sparsest level and drop down a level whenever the next element would
+/// overshoot, which takes `O(log n)` steps in expectation.
+///
+/// Every link also records its span, how many bottom-level steps it skips, so
+/// that elements can be found and removed by their position in `O(log n)` as
+/// well, as an indexed sorted list.
+///
+/// Nodes are kept in a single `Vec` and linked by `u32` indices, like
+/// [`crate::BinarySearchTreeArena`]. Seed it with [`Self::with_seed`] to get
+/// the same levels on every run.
+#[derive(Clone)]
+pub struct SkipList<T>
+where
+    T: Ord,
+{
+    nodes: Vec<Slot<T>>,
+    /// first node on each level, from the bottom one up
+    head: Vec<u32>,
+    /// span of each link in `head`
+    head_span: Vec<usize>,
+    /// head of the list of vacant slots, threaded through `Slot::Vacant`
+    free: u32,
+    len: usize,
+    rng: XorShift,
+}
+
+#[derive(Clone)]
+enum Slot<T> {
+    Occupied(Node<T>),
+    Vacant { next_free: u32 },
+}
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    /// next node on each level this node is on, from the bottom one up
+    next: Vec<u32>,
+    /// bottom-level steps to the node in `next` on each level, a link to
+    /// `NIL` reaching one step past the last node
+    span: Vec<usize>,
+}
+
+impl<T> SkipList<T>
+where
+    T: Ord,
+{
+    /// Creates an empty list drawing levels from a randomly seeded generator
+    pub fn new() -> Self {
+        Self::with_rng(XorShift::from_entropy())
+    }
+
+    /// Creates an empty list whose levels are generated from `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(XorShift::with_seed(seed))
+    }
+
+    fn with_rng(rng: XorShift) -> Self {
+        Self {
+            nodes: Vec::new(),
+            head: Vec::new(),
+            head_span: Vec::new(),
+            free: NIL,
+            len: 0,
+            rng,
+        }
+    }
+
+    /// Returns the number of elements in the list
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements, keeping the allocated node storage
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.head.clear();
+        self.head_span.clear();
+        self.free = NIL;
+        self.len = 0;
+    }
+
+    /// Returns the number of levels in use
+    pub fn levels(&self) -> usize {
+        self.head.len()
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let predecessors = self.predecessors(|stored_data, _| key.cmp(stored_data.borrow()));
+        let next = self.successor(&predecessors);
+
+        (next != NIL && self.node(next).data.borrow() == key).then(|| &self.node(next).data)
+    }
+
+    /// Returns the element at position `rank` in order, counting from zero
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        if rank >= self.len {
+            return None;
+        }
+
+        let predecessors = self.predecessors(|_, stored_rank| rank.cmp(&stored_rank));
+        Some(&self.node(self.successor(&predecessors)).data)
+    }
+
+    /// Returns the position of the element equal to `key` in order, counting
+    /// from zero
+    pub fn rank_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let predecessors = self.predecessors(|stored_data, _| key.cmp(stored_data.borrow()));
+        let next = self.successor(&predecessors);
+
+        (next != NIL && self.node(next).data.borrow() == key).then(|| predecessors[0].1)
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        let mut predecessors = self.predecessors(|stored_data, _| data.cmp(stored_data));
+        let next = self.successor(&predecessors);
+        if next != NIL && self.node(next).data == data {
+            return false;
+        }
+
+        let levels = self.random_levels();
+        while self.head.len() < levels {
+            self.head.push(NIL);
+            self.head_span.push(self.len + 1);
+            predecessors.push((None, 0));
+        }
+
+        // the new node comes right after the bottom-level predecessor
+        let position = predecessors[0].1 + 1;
+        let (next, span) = (0..levels)
+            .map(|level| {
+                let (predecessor, predecessor_position) = predecessors[level];
+                let span = self.span(predecessor, level);
+                (
+                    self.next(predecessor, level),
+                    predecessor_position + span + 1 - position,
+                )
+            })
+            .unzip();
+        let index = self.allocate(Node { data, next, span });
+
+        for (level, &(predecessor, predecessor_position)) in predecessors.iter().enumerate() {
+            if level < levels {
+                *self.next_mut(predecessor, level) = index;
+                *self.span_mut(predecessor, level) = position - predecessor_position;
+            } else {
+                *self.span_mut(predecessor, level) += 1;
+            }
+        }
+
+        self.len += 1;
+        true
+    }
+
+    /// Removes `key` from the list, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the list and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let predecessors = self.predecessors(|stored_data, _| key.cmp(stored_data.borrow()));
+        let index = self.successor(&predecessors);
+        if index == NIL || self.node(index).data.borrow() != key {
+            return None;
+        }
+
+        Some(self.unlink(&predecessors, index))
+    }
+
+    /// Removes and returns the element at position `rank` in order, counting
+    /// from zero
+    pub fn remove_by_rank(&mut self, rank: usize) -> Option<T> {
+        if rank >= self.len {
+            return None;
+        }
+
+        let predecessors = self.predecessors(|_, stored_rank| rank.cmp(&stored_rank));
+        let index = self.successor(&predecessors);
+        Some(self.unlink(&predecessors, index))
+    }
+
+    /// Unlinks the node at `index` from after `predecessors` and frees its
+    /// slot
+    fn unlink(&mut self, predecessors: &[(Option<u32>, usize)], index: u32) -> T {
+        let node = self.deallocate(index);
+
+        for (level, &(predecessor, _)) in predecessors.iter().enumerate() {
+            match (node.next.get(level), node.span.get(level)) {
+                (Some(&next), Some(&span)) => {
+                    *self.next_mut(predecessor, level) = next;
+                    *self.span_mut(predecessor, level) += span - 1;
+                }
+                _ => *self.span_mut(predecessor, level) -= 1,
+            }
+        }
+
+        while self.head.last() == Some(&NIL) {
+            self.head.pop();
+            self.head_span.pop();
+        }
+
+        self.len -= 1;
+        node.data
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let first = *self.head.first()?;
+        Some(&self.node(first).data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let last = self.predecessors(|_, _| Greater).first()?.0?;
+        Some(&self.node(last).data)
+    }
+
+    /// Returns a new iterator which iterates over this list in order
+    pub fn iter(&self) -> SkipListIterator<'_, T> {
+        SkipListIterator {
+            list: self,
+            next: self.head.first().copied().unwrap_or(NIL),
+            remaining: self.len,
+        }
+    }
+
+    /// Returns `true` if every level is in strictly increasing order, only
+    /// holds elements which are also on the level below, and every span
+    /// matches the bottom-level steps its link skips
+    pub fn is_valid(&self) -> bool {
+        let mut position = vec![0; self.nodes.len()];
+        let mut below: Vec<u32> = Vec::new();
+
+        for level in 0..self.head.len() {
+            let mut on_level = Vec::new();
+            let mut index = self.head[level];
+            let mut reached = self.head_span[level];
+            let mut spans_match = true;
+
+            while index != NIL && on_level.len() <= self.len {
+                if level == 0 {
+                    position[index as usize] = on_level.len() + 1;
+                }
+                spans_match &= position[index as usize] == reached;
+
+                on_level.push(index);
+                reached += self.node(index).span[level];
+                index = self.node(index).next[level];
+            }
+
+            let sorted = on_level
+                .windows(2)
+                .all(|pair| self.node(pair[0]).data < self.node(pair[1]).data);
+            let nested = level == 0 || on_level.iter().all(|index| below.contains(index));
+
+            if !sorted
+                || !nested
+                || !spans_match
+                || reached != self.len + 1
+                || on_level.is_empty()
+                || (level == 0 && on_level.len() != self.len)
+            {
+                return false;
+            }
+            below = on_level;
+        }
+        self.head.len() <= MAX_LEVELS
+            && self.head_span.len() == self.head.len()
+            && (self.len == 0) == self.head.is_empty()
+    }
+
+    /// Returns, for every level from the bottom up, the last node for which
+    /// `locate` reports `Greater`, `None` standing for the start of the level,
+    /// and how many nodes come before it and itself
+    ///
+    /// `locate` is given each element with its position, counting from zero.
+    fn predecessors<F>(&self, mut locate: F) -> Vec<(Option<u32>, usize)>
+    where
+        F: FnMut(&T, usize) -> Ordering,
+    {
+        let mut predecessors = vec![(None, 0); self.head.len()];
+        let mut current = None;
+        let mut position = 0;
+
+        for level in (0..self.head.len()).rev() {
+            loop {
+                let next = self.next(current, level);
+                let next_position = position + self.span(current, level);
+                if next == NIL || locate(&self.node(next).data, next_position - 1) != Greater {
+                    break;
+                }
+                current = Some(next);
+                position = next_position;
+            }
+            predecessors[level] = (current, position);
+        }
+        predecessors
+    }
+
+    /// Returns the node after the predecessor on the bottom level, the first
+    /// one not reported `Greater`
+    fn successor(&self, predecessors: &[(Option<u32>, usize)]) -> u32 {
+        predecessors
+            .first()
+            .map_or(NIL, |&(predecessor, _)| self.next(predecessor, 0))
+    }
+
+    /// Draws how many levels a new node is on, one half as often for each
+    /// level more
+    fn random_levels(&mut self) -> usize {
+        let levels = 1 + self.rng.next_u64().trailing_ones() as usize;
+        // grow at most one level at a time so that the top level stays sparse
+        levels.min(self.head.len() + 1).min(MAX_LEVELS)
+    }
+
+    /// Returns the node after `predecessor` on `level`
+    fn next(&self, predecessor: Option<u32>, level: usize) -> u32 {
+        match predecessor {
+            None => self.head[level],
+            Some(index) => self.node(index).next[level],
+        }
+    }
+
+    fn next_mut(&mut self, predecessor: Option<u32>, level: usize) -> &mut u32 {
+        match predecessor {
+            None => &mut self.head[level],
+            Some(index) => &mut self.node_mut(index).next[level],
+        }
+    }
+
+    /// Returns the span of the link after `predecessor` on `level`
+    fn span(&self, predecessor: Option<u32>, level: usize) -> usize {
+        match predecessor {
+            None => self.head_span[level],
+            Some(index) => self.node(index).span[level],
+        }
+    }
+
+    fn span_mut(&mut self, predecessor: Option<u32>, level: usize) -> &mut usize {
+        match predecessor {
+            None => &mut self.head_span[level],
+            Some(index) => &mut self.node_mut(index).span[level],
+        }
+    }
+
+    fn node(&self, index: u32) -> &Node<T> {
+        match &self.nodes[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    fn node_mut(&mut self, index: u32) -> &mut Node<T> {
+        match &mut self.nodes[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    /// Stores `node` in a vacant slot, or a new one, and returns its index
+    fn allocate(&mut self, node: Node<T>) -> u32 {
+        let node = Slot::Occupied(node);
+
+        if self.free == NIL {
+            let index = self.nodes.len();
+            assert!(index < NIL as usize, "SkipList is full");
+
+            self.nodes.push(node);
+            index as u32
+        } else {
+            let index = self.free;
+
+            match mem::replace(&mut self.nodes[index as usize], node) {
+                Slot::Vacant { next_free } => self.free = next_free,
+                Slot::Occupied(_) => unreachable!("free list points to an occupied slot"),
+            }
+            index
+        }
+    }
+
+    /// Moves the node out of the slot at `index` and puts the slot on the free
+    /// list
+    fn deallocate(&mut self, index: u32) -> Node<T> {
+        let vacant = Slot::Vacant {
+            next_free: self.free,
+        };
+        self.free = index;
+
+        match mem::replace(&mut self.nodes[index as usize], vacant) {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("deallocating a vacant slot"),
+        }
+    }
+}
+
+impl<T> Default for SkipList<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for SkipList<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two lists are equal when they hold equal elements, regardless of their
+/// levels
+impl<T> PartialEq for SkipList<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for SkipList<T> where T: Ord {}
+
+impl<T> FromIterator<T> for SkipList<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = Self::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> Extend<T> for SkipList<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`SkipList`] in order,
+/// walking the bottom level
+pub struct SkipListIterator<'a, T>
+where
+    T: Ord,
+{
+    list: &'a SkipList<T>,
+    next: u32,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for SkipListIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NIL {
+            return None;
+        }
+
+        let node = self.list.node(self.next);
+        self.next = node.next[0];
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for SkipListIterator<'_, T> where T: Ord {}
+
+impl<'a, T> IntoIterator for &'a SkipList<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = SkipListIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::PREQUEL;
+
+    fn prequel_list() -> SkipList<u32> {
+        let mut list = SkipList::with_seed(42);
+        list.extend(PREQUEL);
+        list
+    }
+
+    #[test]
+    fn test_insert_search() {
+        let mut list = prequel_list();
+
+        assert_eq!(SkipList::<u32>::new().max(), None);
+        assert_eq!(list.len(), 7);
+        assert!(list.contains(&21));
+        assert!(!list.contains(&22));
+        assert!(!list.insert(21));
+        assert!(list.insert(22));
+        assert_eq!(list.get(&22), Some(&22));
+        assert_eq!((list.min(), list.max()), (Some(&3), Some(&70)));
+        assert_eq!(
+            list.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &22, &28, &36, &70]
+        );
+        assert!(list.is_valid());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut list = prequel_list();
+
+        assert_eq!(list.take(&16), Some(16));
+        assert!(!list.remove(&16));
+        assert!(list.remove(&3));
+        assert!(list.remove(&70));
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&7, &21, &28, &36]);
+        assert_eq!((list.min(), list.max()), (Some(&7), Some(&36)));
+        assert!(list.is_valid());
+
+        for data in [7, 21, 28, 36] {
+            assert!(list.remove(&data));
+        }
+        assert!(list.is_empty());
+        assert_eq!(list.levels(), 0);
+        assert!(list.is_valid());
+    }
+
+    #[test]
+    fn test_seeded_levels() {
+        let mut list = SkipList::with_seed(7);
+        list.extend((0..2_000u32).rev());
+
+        assert!(list.is_valid());
+        assert!((8..=MAX_LEVELS).contains(&list.levels()));
+        assert!(list.iter().copied().eq(0..2_000));
+
+        for data in (0..2_000).step_by(2) {
+            assert!(list.remove(&data));
+        }
+        assert!(list.is_valid());
+        assert_eq!(list.len(), 1_000);
+    }
+
+    #[test]
+    fn test_ranks() {
+        let list = prequel_list();
+
+        assert_eq!(list.get_by_rank(0), Some(&3));
+        assert_eq!(list.get_by_rank(3), Some(&21));
+        assert_eq!(list.get_by_rank(6), Some(&70));
+        assert_eq!(list.get_by_rank(7), None);
+        assert_eq!(list.rank_of(&3), Some(0));
+        assert_eq!(list.rank_of(&36), Some(5));
+        assert_eq!(list.rank_of(&22), None);
+        assert_eq!(SkipList::<u32>::new().get_by_rank(0), None);
+    }
+
+    #[test]
+    fn test_remove_by_rank() {
+        let mut list = prequel_list();
+
+        assert_eq!(list.remove_by_rank(2), Some(16));
+        assert_eq!(list.remove_by_rank(0), Some(3));
+        assert_eq!(list.remove_by_rank(4), Some(70));
+        assert_eq!(list.remove_by_rank(4), None);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&7, &21, &28, &36]);
+        assert!(list.is_valid());
+
+        let mut list = SkipList::with_seed(11);
+        list.extend((0..2_000u32).map(|i| i * 7_919 % 2_000));
+        assert!(list.is_valid());
+        assert!((0..2_000).all(|i| list.get_by_rank(i as usize) == Some(&i)));
+
+        // removing every other position keeps the odd elements
+        for rank in 0..1_000 {
+            assert_eq!(list.remove_by_rank(rank), Some(2 * rank as u32));
+        }
+        assert!(list.is_valid());
+        assert!(list.iter().copied().eq((1..2_000).step_by(2)));
+        assert_eq!(list.rank_of(&1_999), Some(999));
+    }
+}
@@ -0,0 +1,469 @@
+//! Traits shared by the ordered collections, so that code generic over them,
+//! benchmarks or tests, can be written once and run against any of them
+//!
+//! The methods mirror the inherent ones of each collection, which still take
+//! precedence when the concrete type is known. `range` is provided by walking
+//! [`SortedSet::iter`] from the smallest element, collections with a search
+//! of their own for the start of a range override it.
+
+use crate::{
+    AvlTree, BPlusTree, BinarySearchTree, BinarySearchTreeMap, RedBlackTree, SkipList, Treap,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{Bound, RangeBounds},
+};
+
+/// An ordered collection of distinct elements
+pub trait SortedSet<T>
+where
+    T: Ord,
+{
+    /// Returns the number of elements in the set
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the set contains no elements
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts `data`, returning whether it was inserted
+    fn insert(&mut self, data: T) -> bool;
+
+    /// Removes `key` from the set, returning whether it was present
+    fn remove(&mut self, key: &T) -> bool;
+
+    /// Returns the stored element equal to `key`
+    fn get(&self, key: &T) -> Option<&T>;
+
+    /// Returns `true` if an element equal to `key` is stored
+    fn contains(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns an iterator over the elements in order
+    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a;
+
+    /// Returns an iterator over the elements in `range`, in order
+    ///
+    /// The provided method walks from the smallest element, which costs
+    /// `O(n)` before the first element in range. [`AvlTree`],
+    /// [`RedBlackTree`], [`Treap`] and [`SkipList`] use it, while
+    /// [`BinarySearchTree`] and `BTreeSet` search for the start of the range.
+    fn range<'a, R>(&'a self, range: R) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+        R: RangeBounds<T>,
+    {
+        scan_range(self.iter(), range, |&data| data)
+    }
+}
+
+/// An ordered collection of values stored under distinct keys
+pub trait SortedMap<K, V>
+where
+    K: Ord,
+{
+    /// Returns the number of entries in the map
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the map contains no entries
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stores `value` under `key`, returning the value previously stored
+    /// there
+    fn insert(&mut self, key: K, value: V) -> Option<V>;
+
+    /// Removes the entry stored under `key` and returns its value
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// Returns the value stored under `key`
+    fn get(&self, key: &K) -> Option<&V>;
+
+    /// Returns `true` if an entry is stored under `key`
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns an iterator over the entries in key order
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a;
+
+    /// Returns an iterator over the entries whose keys are in `range`, in key
+    /// order
+    ///
+    /// The provided method walks from the smallest key, which costs `O(n)`
+    /// before the first entry in range. [`BinarySearchTreeMap`] uses it, while
+    /// [`BPlusTree`] and `BTreeMap` search for the start of the range.
+    fn range<'a, R>(&'a self, range: R) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+        R: RangeBounds<K>,
+    {
+        scan_range(self.iter(), range, |&(key, _)| key)
+    }
+}
+
+/// Skips the items of the ordered `iter` below `range` and stops at the first
+/// one above it
+fn scan_range<'a, T, I, R, F>(iter: I, range: R, key: F) -> impl Iterator<Item = I::Item>
+where
+    T: Ord + 'a,
+    I: Iterator,
+    R: RangeBounds<T>,
+    F: Fn(&I::Item) -> &'a T,
+{
+    let mut iter = iter.peekable();
+    let below_start = |item: &I::Item| match range.start_bound() {
+        Bound::Included(start) => key(item) < start,
+        Bound::Excluded(start) => key(item) <= start,
+        Bound::Unbounded => false,
+    };
+    while iter.next_if(below_start).is_some() {}
+
+    iter.take_while(move |item| before_end(&range, key(item)))
+}
+
+/// Returns `true` if `data` is not past the end of `range`
+fn before_end<T, R>(range: &R, data: &T) -> bool
+where
+    T: Ord,
+    R: RangeBounds<T>,
+{
+    match range.end_bound() {
+        Bound::Included(end) => data <= end,
+        Bound::Excluded(end) => data < end,
+        Bound::Unbounded => true,
+    }
+}
+
+/// Returns `true` if `range` starts after it ends, which makes the std
+/// collections' `range` panic where the others yield nothing
+fn is_inverted<T, R>(range: &R) -> bool
+where
+    T: Ord,
+    R: RangeBounds<T>,
+{
+    match range.start_bound() {
+        Bound::Included(start) | Bound::Excluded(start) => !before_end(range, start),
+        Bound::Unbounded => false,
+    }
+}
+
+/// Implements [`SortedSet`] by forwarding to the inherent methods of the
+/// same names
+macro_rules! impl_sorted_set {
+    ($($set:ident),*) => {
+        $(
+            impl<T> SortedSet<T> for $set<T>
+            where
+                T: Ord,
+            {
+                fn len(&self) -> usize {
+                    $set::len(self)
+                }
+
+                fn insert(&mut self, data: T) -> bool {
+                    $set::insert(self, data)
+                }
+
+                fn remove(&mut self, key: &T) -> bool {
+                    $set::remove(self, key)
+                }
+
+                fn get(&self, key: &T) -> Option<&T> {
+                    $set::get(self, key)
+                }
+
+                fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>
+                where
+                    T: 'a,
+                {
+                    $set::iter(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_sorted_set!(AvlTree, RedBlackTree, Treap, SkipList);
+
+/// The set behaviour needs [`crate::DuplicatePolicy::Reject`], under
+/// [`crate::DuplicatePolicy::Allow`] `insert` stores duplicates like the inherent
+/// method does
+impl<T> SortedSet<T> for BinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn len(&self) -> usize {
+        BinarySearchTree::len(self)
+    }
+
+    fn insert(&mut self, data: T) -> bool {
+        BinarySearchTree::insert(self, data)
+    }
+
+    fn remove(&mut self, key: &T) -> bool {
+        BinarySearchTree::remove(self, key)
+    }
+
+    fn get(&self, key: &T) -> Option<&T> {
+        BinarySearchTree::get(self, key)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        BinarySearchTree::iter(self)
+    }
+
+    /// Only visits the subtrees intersecting the range
+    fn range<'a, R>(&'a self, range: R) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+        R: RangeBounds<T>,
+    {
+        BinarySearchTree::range(self, range)
+    }
+}
+
+impl<T> SortedSet<T> for BTreeSet<T>
+where
+    T: Ord,
+{
+    fn len(&self) -> usize {
+        BTreeSet::len(self)
+    }
+
+    fn insert(&mut self, data: T) -> bool {
+        BTreeSet::insert(self, data)
+    }
+
+    fn remove(&mut self, key: &T) -> bool {
+        BTreeSet::remove(self, key)
+    }
+
+    fn get(&self, key: &T) -> Option<&T> {
+        BTreeSet::get(self, key)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        BTreeSet::iter(self)
+    }
+
+    fn range<'a, R>(&'a self, range: R) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+        R: RangeBounds<T>,
+    {
+        (!is_inverted(&range))
+            .then(|| BTreeSet::range::<T, _>(self, range))
+            .into_iter()
+            .flatten()
+    }
+}
+
+impl<K, V> SortedMap<K, V> for BinarySearchTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn len(&self) -> usize {
+        BinarySearchTreeMap::len(self)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        BinarySearchTreeMap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        BinarySearchTreeMap::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        BinarySearchTreeMap::get(self, key)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        BinarySearchTreeMap::iter(self)
+    }
+}
+
+impl<K, V> SortedMap<K, V> for BPlusTree<K, V>
+where
+    K: Ord + Clone,
+{
+    fn len(&self) -> usize {
+        BPlusTree::len(self)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        BPlusTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        BPlusTree::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        BPlusTree::get(self, key)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        BPlusTree::iter(self)
+    }
+
+    /// Descends once to each end of the range, then walks the leaves
+    fn range<'a, R>(&'a self, range: R) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+        R: RangeBounds<K>,
+    {
+        BPlusTree::range::<K, _>(self, range)
+    }
+}
+
+impl<K, V> SortedMap<K, V> for BTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        BTreeMap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        BTreeMap::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        BTreeMap::get(self, key)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        BTreeMap::iter(self)
+    }
+
+    fn range<'a, R>(&'a self, range: R) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+        R: RangeBounds<K>,
+    {
+        (!is_inverted(&range))
+            .then(|| BTreeMap::range::<K, _>(self, range))
+            .into_iter()
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DuplicatePolicy;
+
+    fn check_set<S>(mut set: S)
+    where
+        S: SortedSet<u32>,
+    {
+        for data in [16, 7, 28, 3, 21, 36, 70] {
+            assert!(set.insert(data));
+        }
+        assert!(!set.insert(21));
+        assert_eq!(set.len(), 7);
+        assert_eq!(set.get(&28), Some(&28));
+        assert!(!set.contains(&29));
+
+        assert!(set.remove(&16));
+        assert!(!set.remove(&16));
+        assert_eq!(
+            set.iter().copied().collect::<Vec<_>>(),
+            vec![3, 7, 21, 28, 36, 70]
+        );
+        assert_eq!(
+            set.range(7..36).copied().collect::<Vec<_>>(),
+            vec![7, 21, 28]
+        );
+        assert_eq!(set.range(..=7).count(), 2);
+        assert_eq!(
+            set.range((Bound::Excluded(28), Bound::Unbounded)).count(),
+            2
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = set.range(36..7);
+        assert_eq!(inverted.count(), 0);
+        assert!(!set.is_empty());
+    }
+
+    fn check_map<M>(mut map: M)
+    where
+        M: SortedMap<u32, u32>,
+    {
+        for key in [16, 7, 28, 3, 21, 36, 70] {
+            assert_eq!(map.insert(key, key * 10), None);
+        }
+        assert_eq!(map.insert(21, 0), Some(210));
+        assert_eq!(map.len(), 7);
+        assert_eq!(map.get(&21), Some(&0));
+        assert!(!map.contains_key(&22));
+
+        assert_eq!(map.remove(&3), Some(30));
+        assert_eq!(map.remove(&3), None);
+        assert_eq!(
+            map.range(16..=36).map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec![16, 21, 28, 36]
+        );
+        assert_eq!(
+            map.iter().map(|(_, value)| value).sum::<u32>(),
+            1_810 - 210 - 30
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = map.range(36..7);
+        assert_eq!(inverted.count(), 0);
+    }
+
+    #[test]
+    fn test_sorted_sets() {
+        check_set(BinarySearchTree::with_duplicate_policy(
+            DuplicatePolicy::Reject,
+        ));
+        check_set(AvlTree::new());
+        check_set(RedBlackTree::new());
+        check_set(Treap::new());
+        check_set(SkipList::new());
+        check_set(BTreeSet::new());
+    }
+
+    #[test]
+    fn test_sorted_maps() {
+        check_map(BinarySearchTreeMap::new());
+        check_map(BPlusTree::new());
+        check_map(BTreeMap::new());
+    }
+}
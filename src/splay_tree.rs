@@ -0,0 +1,752 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{self, Equal, Greater, Less},
+    fmt,
+};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+struct Node<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+    /// successful finds of this element, counted only with access statistics
+    accesses: u64,
+}
+
+impl<T> Node<T> {
+    fn new(data: T) -> Box<Self> {
+        Box::new(Self {
+            data,
+            left: None,
+            right: None,
+            accesses: 0,
+        })
+    }
+}
+
+/// Counters kept by a tree made with [`SplayTree::with_access_stats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessStats {
+    /// finds which reached an equal element
+    pub hits: u64,
+    /// finds which did not
+    pub misses: u64,
+    /// `depths[d]` counts the hits whose element was at depth `d` before being
+    /// splayed, the root being at depth 0
+    pub depths: Vec<u64>,
+}
+
+/// A self-adjusting binary search tree behaving like a set
+///
+/// Every access through [`Self::find`], [`Self::insert`] or [`Self::remove`]
+/// splays the reached node to the root, so recently used elements are cheap to
+/// reach again. Operations are `O(log n)` amortized, although a single one
+/// may take `O(n)`, and the tree can temporarily be as deep as it is long.
+///
+/// Made with [`Self::with_access_stats`], the tree also counts the finds of
+/// each element and the depths they were found at, to check whether a
+/// workload gets the shallow hits the working-set property promises.
+pub struct SplayTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+    len: usize,
+    stats: Option<AccessStats>,
+}
+
+impl<T> SplayTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            len: 0,
+            stats: None,
+        }
+    }
+
+    /// Creates an empty tree which records statistics on every find
+    ///
+    /// Each find then walks down to its element once more before splaying it,
+    /// to measure its depth.
+    pub fn with_access_stats() -> Self {
+        Self {
+            root: None,
+            len: 0,
+            stats: Some(AccessStats::default()),
+        }
+    }
+
+    /// Returns the statistics recorded so far, if the tree records any
+    pub fn access_stats(&self) -> Option<&AccessStats> {
+        self.stats.as_ref()
+    }
+
+    /// Zeroes the recorded statistics, along with the count of every element
+    pub fn reset_access_stats(&mut self) {
+        let Some(stats) = &mut self.stats else {
+            return;
+        };
+        *stats = AccessStats::default();
+
+        let mut stack: Vec<&mut Node<T>> = self.root.as_deref_mut().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            node.accesses = 0;
+            stack.extend(node.left.as_deref_mut());
+            stack.extend(node.right.as_deref_mut());
+        }
+    }
+
+    /// Returns up to `n` of the most found elements with their counts, most
+    /// found first and equally found ones in order
+    ///
+    /// Elements never found are left out, so this is empty for a tree which
+    /// does not record statistics.
+    pub fn hottest(&self, n: usize) -> Vec<(&T, u64)> {
+        let mut counted: Vec<(&T, u64)> = self
+            .nodes()
+            .filter(|node| node.accesses > 0)
+            .map(|node| (&node.data, node.accesses))
+            .collect();
+
+        let by_count = |a: &(&T, u64), b: &(&T, u64)| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0));
+        if n < counted.len() {
+            counted.select_nth_unstable_by(n, by_count);
+            counted.truncate(n);
+        }
+        counted.sort_unstable_by(by_count);
+        counted
+    }
+
+    /// Returns how many elements there are at each depth, the root being at
+    /// depth 0
+    pub fn depth_histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        let mut stack: Vec<(&Node<T>, usize)> = self
+            .root
+            .as_deref()
+            .map(|root| (root, 0))
+            .into_iter()
+            .collect();
+
+        while let Some((node, depth)) = stack.pop() {
+            if histogram.len() <= depth {
+                histogram.resize(depth + 1, 0);
+            }
+            histogram[depth] += 1;
+            stack.extend(
+                [node.left.as_deref(), node.right.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|child| (child, depth + 1)),
+            );
+        }
+        histogram
+    }
+
+    /// Returns the nodes in no particular order, without recursion
+    fn nodes(&self) -> impl Iterator<Item = &Node<T>> {
+        let mut stack: Vec<&Node<T>> = self.root.as_deref().into_iter().collect();
+
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.left.as_deref());
+            stack.extend(node.right.as_deref());
+            Some(node)
+        })
+    }
+
+    /// Counts a find of `key` in the statistics, if the tree records any
+    fn record_access<Q>(&mut self, key: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(stats) = &mut self.stats else {
+            return;
+        };
+        let mut node = self.root.as_deref_mut();
+        let mut depth = 0;
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => {
+                    current.accesses += 1;
+                    stats.hits += 1;
+                    if stats.depths.len() <= depth {
+                        stats.depths.resize(depth + 1, 0);
+                    }
+                    stats.depths[depth] += 1;
+                    return;
+                }
+                Less => current.left.as_deref_mut(),
+                Greater => current.right.as_deref_mut(),
+            };
+            depth += 1;
+        }
+        stats.misses += 1;
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        // dropped through `Drop`, which can handle deep trees
+        drop(Self {
+            root: self.root.take(),
+            len: 0,
+            stats: None,
+        });
+        self.len = 0;
+    }
+
+    /// Returns the root element, the one accessed most recently
+    pub fn root(&self) -> Option<&T> {
+        self.root.as_ref().map(|root| &root.data)
+    }
+
+    /// Returns the element equal to `key`, splaying it to the root
+    ///
+    /// When there is no such element the last node visited is splayed instead.
+    pub fn find<Q>(&mut self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.record_access(key);
+        self.splay(|stored_data| key.cmp(stored_data.borrow()));
+
+        self.root
+            .as_ref()
+            .map(|root| &root.data)
+            .filter(|&data| data.borrow() == key)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored, without
+    /// splaying
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return true,
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        false
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    ///
+    /// Either way, the element equal to `data` ends up at the root.
+    pub fn insert(&mut self, data: T) -> bool {
+        self.splay(|stored_data| data.cmp(stored_data));
+
+        let mut node = Node::new(data);
+
+        match self.root.take() {
+            None => {}
+            Some(root) if root.data == node.data => {
+                self.root = Some(root);
+                return false;
+            }
+            // the root is the neighbour of data, so it splits the tree in two
+            Some(mut root) if node.data < root.data => {
+                node.left = root.left.take();
+                node.right = Some(root);
+            }
+            Some(mut root) => {
+                node.right = root.right.take();
+                node.left = Some(root);
+            }
+        }
+
+        self.root = Some(node);
+        self.len += 1;
+        true
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key)?;
+
+        let mut root = self.root.take().expect("found at the root");
+        let right = root.right.take();
+
+        self.root = root.left.take();
+        self.len -= 1;
+
+        // join what is left, all of it is smaller than right
+        let mut right_tree = Self {
+            root: right,
+            len: 0,
+            stats: None,
+        };
+        self.join_root(&mut right_tree);
+
+        Some(root.data)
+    }
+
+    /// Moves every element greater than or equal to `key` into a new tree
+    ///
+    /// The lengths of both trees are found by counting the elements of the
+    /// smaller one, so on top of the amortized `O(log n)` splay the split costs
+    /// `O(min(m, n - m))` for `m` moved elements. The returned tree records
+    /// access statistics from scratch if this tree records any, the moved
+    /// elements keeping their counts.
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.splay(|stored_data| key.cmp(stored_data.borrow()));
+
+        let mut other = Self {
+            root: None,
+            len: 0,
+            stats: self.stats.as_ref().map(|_| AccessStats::default()),
+        };
+        let Some(mut root) = self.root.take() else {
+            return other;
+        };
+
+        if root.data.borrow() < key {
+            other.root = root.right.take();
+            self.root = Some(root);
+        } else {
+            self.root = root.left.take();
+            other.root = Some(root);
+        }
+
+        // count the smaller side only, stepping through both in turn
+        let total = self.len;
+        let moved_len = {
+            let (mut kept, mut moved) = (self.nodes(), other.nodes());
+            let mut counted = 0;
+            loop {
+                if moved.next().is_none() {
+                    break counted;
+                }
+                if kept.next().is_none() {
+                    break total - counted;
+                }
+                counted += 1;
+            }
+        };
+
+        other.len = moved_len;
+        self.len = total - moved_len;
+        other
+    }
+
+    /// Moves all the elements of `other`, which must all be greater than the
+    /// elements of `self`, into `self`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the smallest element of `other` is not greater than the largest
+    /// element of `self`.
+    pub fn join(&mut self, other: &mut Self) {
+        if let (Some(max), Some(min)) = (self.max(), other.min()) {
+            assert!(max < min, "joined trees must not overlap");
+        }
+
+        self.len += other.len;
+        other.len = 0;
+        self.join_root(other);
+    }
+
+    /// Attaches the nodes of `other` below the maximum of `self`, without
+    /// touching the lengths
+    fn join_root(&mut self, other: &mut Self) {
+        if self.root.is_none() {
+            self.root = other.root.take();
+            return;
+        }
+
+        // the maximum has no right child once splayed to the root
+        self.splay(|_| Greater);
+        self.root.as_mut().expect("checked above").right = other.root.take();
+    }
+
+    /// Splays the node for which `locate` reports `Equal` to the root, or the
+    /// last node visited if there is none
+    ///
+    /// Top-down: the nodes passed on the way are hung off two side trees,
+    /// holding everything smaller and everything greater than the target, which
+    /// are attached below the target at the end.
+    fn splay<F>(&mut self, mut locate: F)
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        let Some(mut node) = self.root.take() else {
+            return;
+        };
+
+        // in increasing order, each one the right child of the previous
+        let mut smaller: Vec<Box<Node<T>>> = Vec::new();
+        // in decreasing order, each one the left child of the previous
+        let mut greater: Vec<Box<Node<T>>> = Vec::new();
+
+        loop {
+            match locate(&node.data) {
+                Equal => break,
+                Less => {
+                    let Some(mut left) = node.left.take() else {
+                        break;
+                    };
+
+                    if locate(&left.data) == Less {
+                        // zig-zig, rotate right first
+                        node.left = left.right.take();
+                        left.right = Some(node);
+                        node = left;
+
+                        let Some(next) = node.left.take() else {
+                            break;
+                        };
+                        greater.push(node);
+                        node = next;
+                    } else {
+                        greater.push(node);
+                        node = left;
+                    }
+                }
+                Greater => {
+                    let Some(mut right) = node.right.take() else {
+                        break;
+                    };
+
+                    if locate(&right.data) == Greater {
+                        // zig-zig, rotate left first
+                        node.right = right.left.take();
+                        right.left = Some(node);
+                        node = right;
+
+                        let Some(next) = node.right.take() else {
+                            break;
+                        };
+                        smaller.push(node);
+                        node = next;
+                    } else {
+                        smaller.push(node);
+                        node = right;
+                    }
+                }
+            }
+        }
+
+        let mut left = node.left.take();
+        while let Some(mut parent) = smaller.pop() {
+            parent.right = left;
+            left = Some(parent);
+        }
+
+        let mut right = node.right.take();
+        while let Some(mut parent) = greater.pop() {
+            parent.left = right;
+            right = Some(parent);
+        }
+
+        node.left = left;
+        node.right = right;
+        self.root = Some(node);
+    }
+
+    /// Returns the smallest element, without splaying
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the largest element, without splaying
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut stack: Vec<(&Node<T>, usize)> = self
+            .root
+            .as_deref()
+            .map(|root| (root, 1))
+            .into_iter()
+            .collect();
+
+        while let Some((node, depth)) = stack.pop() {
+            height = height.max(depth);
+            stack.extend(
+                [node.left.as_deref(), node.right.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|child| (child, depth + 1)),
+            );
+        }
+        height
+    }
+
+    /// Returns a new iterator which iterates over this tree in order, without
+    /// splaying
+    pub fn iter(&self) -> SplayTreeIterator<'_, T> {
+        let mut iter = SplayTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+}
+
+impl<T> Default for SplayTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drops nodes iteratively, so that long spines can't overflow the stack
+impl<T> Drop for SplayTree<T>
+where
+    T: Ord,
+{
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Node<T>>> = self.root.take().into_iter().collect();
+
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+impl<T> fmt::Debug for SplayTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for SplayTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for SplayTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`SplayTree`] in order
+pub struct SplayTreeIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> SplayTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for SplayTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining = self.remaining.saturating_sub(1);
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SplayTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = SplayTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+
+    #[test]
+    fn test_find_splays() {
+        let mut tree: SplayTree<u32> = prequel();
+
+        assert_eq!(tree.root(), Some(&70));
+        assert_eq!(tree.find(&21), Some(&21));
+        assert_eq!(tree.root(), Some(&21));
+        assert_eq!(tree.find(&22), None);
+        assert!(matches!(tree.root(), Some(&21) | Some(&28)));
+        assert!(tree.contains(&3));
+        assert!(!tree.insert(3));
+        assert_eq!(tree.root(), Some(&3));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &28, &36, &70]
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree: SplayTree<u32> = prequel();
+
+        assert_eq!(tree.take(&16), Some(16));
+        assert!(!tree.remove(&16));
+        assert!(tree.remove(&70));
+        assert!(tree.remove(&3));
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&7, &21, &28, &36]);
+        assert_eq!((tree.min(), tree.max()), (Some(&7), Some(&36)));
+    }
+
+    #[test]
+    fn test_split_join() {
+        let mut tree: SplayTree<u32> = prequel();
+        let mut upper = tree.split_off(&21);
+
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&3, &7, &16]);
+        assert_eq!(upper.iter().collect::<Vec<_>>(), vec![&21, &28, &36, &70]);
+        assert_eq!((tree.len(), upper.len()), (3, 4));
+
+        tree.join(&mut upper);
+        assert!(upper.is_empty());
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.iter().count(), 7);
+        assert!(tree.split_off(&100).is_empty());
+
+        // either side may be the one counted
+        let mut tree: SplayTree<u32> = (0..1_000).collect();
+        let upper = tree.split_off(&990);
+        assert_eq!((tree.len(), upper.len()), (990, 10));
+        let mut lower = tree;
+        let mut tree = lower.split_off(&5);
+        assert_eq!((lower.len(), tree.len()), (5, 985));
+        assert_eq!(tree.split_off(&0).len(), 985);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_sorted_access_amortizes() {
+        // sorted insertions build a single spine which accesses then fold up
+        let mut tree: SplayTree<u32> = (0..10_000).collect();
+        assert_eq!(tree.height(), 10_000);
+
+        assert_eq!(tree.find(&0), Some(&0));
+        assert!(tree.height() < 10_000);
+        assert_eq!(tree.len(), 10_000);
+    }
+
+    #[test]
+    fn test_access_stats() {
+        let mut tree = SplayTree::with_access_stats();
+        tree.extend([16, 7, 28, 3, 21, 36, 70]);
+
+        for key in [21, 21, 3, 21, 99, 3, 28] {
+            tree.find(&key);
+        }
+        let stats = tree.access_stats().expect("recorded");
+        assert_eq!((stats.hits, stats.misses), (6, 1));
+        assert_eq!(stats.depths.iter().sum::<u64>(), 6);
+        assert_eq!(stats.depths[0], 1);
+
+        assert_eq!(tree.hottest(2), vec![(&21, 3), (&3, 2)]);
+        assert_eq!(tree.hottest(10), vec![(&21, 3), (&3, 2), (&28, 1)]);
+        assert_eq!(tree.depth_histogram().iter().sum::<usize>(), 7);
+        assert_eq!(tree.depth_histogram()[0], 1);
+
+        tree.reset_access_stats();
+        assert_eq!(tree.access_stats(), Some(&AccessStats::default()));
+        assert!(tree.hottest(3).is_empty());
+        assert_eq!(prequel::<SplayTree<u32>>().access_stats(), None);
+    }
+
+    #[test]
+    fn test_working_set_hits_stay_shallow() {
+        let mut tree: SplayTree<u32> = SplayTree::with_access_stats();
+        tree.extend(0..1024);
+        tree.find(&0);
+        tree.reset_access_stats();
+
+        // a small working set, accessed over and over
+        for round in 0..100 {
+            tree.find(&(round % 8 * 100));
+        }
+        let stats = tree.access_stats().expect("recorded");
+        let shallow: u64 = stats.depths.iter().take(8).sum();
+        assert!(shallow >= 80, "{:?}", stats.depths);
+        assert_eq!(tree.hottest(1)[0].1, 13);
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+
+const NIL: u32 = u32::MAX;
+
+#[derive(Clone)]
+struct State {
+    /// length of the longest substring reaching this state
+    len: usize,
+    /// state of the longest suffix of those substrings reaching another
+    /// state, `NIL` for the initial state
+    link: u32,
+    /// transitions by byte, sorted
+    next: Vec<(u8, u32)>,
+}
+
+impl State {
+    fn transition(&self, byte: u8) -> Option<u32> {
+        let index = self
+            .next
+            .binary_search_by_key(&byte, |&(byte, _)| byte)
+            .ok()?;
+        Some(self.next[index].1)
+    }
+
+    fn set_transition(&mut self, byte: u8, state: u32) {
+        match self.next.binary_search_by_key(&byte, |&(byte, _)| byte) {
+            Ok(index) => self.next[index].1 = state,
+            Err(index) => self.next.insert(index, (byte, state)),
+        }
+    }
+}
+
+/// The smallest automaton accepting every substring of a text, also known as
+/// a directed acyclic word graph
+///
+/// Each state stands for a class of substrings ending at the same positions,
+/// so a text of `n` bytes needs at most `2n - 1` states and `3n - 4`
+/// transitions, without keeping the text itself. It is built online, one byte
+/// at a time, and checks whether a pattern is a substring in time linear in
+/// the pattern.
+#[derive(Clone)]
+pub struct SuffixAutomaton {
+    states: Vec<State>,
+    /// state reached by the whole text
+    last: u32,
+}
+
+impl SuffixAutomaton {
+    /// Creates the automaton of the empty text
+    pub fn new() -> Self {
+        Self {
+            states: vec![State {
+                len: 0,
+                link: NIL,
+                next: Vec::new(),
+            }],
+            last: 0,
+        }
+    }
+
+    /// Appends `byte` to the text, adding the substrings ending with it
+    ///
+    /// # Panics
+    ///
+    /// Panics if the automaton is too large to index its states with `u32`.
+    pub fn push(&mut self, byte: u8) {
+        assert!(
+            self.states.len() + 2 < NIL as usize,
+            "SuffixAutomaton is full"
+        );
+
+        let current = self.states.len() as u32;
+        self.states.push(State {
+            len: self.states[self.last as usize].len + 1,
+            link: 0,
+            next: Vec::new(),
+        });
+
+        // states of the suffixes of the old text that can't be followed by
+        // `byte` yet
+        let mut state = self.last;
+        while state != NIL && self.states[state as usize].transition(byte).is_none() {
+            self.states[state as usize].set_transition(byte, current);
+            state = self.states[state as usize].link;
+        }
+
+        if state != NIL {
+            let next = self.states[state as usize]
+                .transition(byte)
+                .expect("found above");
+            if self.states[state as usize].len + 1 == self.states[next as usize].len {
+                self.states[current as usize].link = next;
+            } else {
+                // `next` holds longer substrings too, which end at fewer
+                // positions, so the shorter ones move to a clone
+                let clone = self.states.len() as u32;
+                let mut cloned = self.states[next as usize].clone();
+                cloned.len = self.states[state as usize].len + 1;
+                self.states.push(cloned);
+
+                while state != NIL && self.states[state as usize].transition(byte) == Some(next) {
+                    self.states[state as usize].set_transition(byte, clone);
+                    state = self.states[state as usize].link;
+                }
+                self.states[next as usize].link = clone;
+                self.states[current as usize].link = clone;
+            }
+        }
+        self.last = current;
+    }
+
+    /// Returns the number of bytes in the text
+    pub fn len(&self) -> usize {
+        self.states[self.last as usize].len
+    }
+
+    /// Returns `true` if the text is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of states, including the initial one
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns `true` if `pattern` is a substring of the text
+    pub fn contains<P>(&self, pattern: P) -> bool
+    where
+        P: AsRef<[u8]>,
+    {
+        pattern
+            .as_ref()
+            .iter()
+            .try_fold(0, |state, &byte| {
+                self.states[state as usize].transition(byte)
+            })
+            .is_some()
+    }
+
+    /// Returns the number of distinct non-empty substrings of the text
+    ///
+    /// Each state adds the lengths between its own and its link's, which
+    /// counts every substring once without listing them.
+    pub fn distinct_substrings(&self) -> u64 {
+        self.states[1..]
+            .iter()
+            .map(|state| (state.len - self.states[state.link as usize].len) as u64)
+            .sum()
+    }
+
+    /// Returns `true` if each link leads to a shorter state, which every state
+    /// but the initial one has, transitions are sorted and lengthen the
+    /// substrings, and the number of states is within the bound for the text
+    pub fn is_valid(&self) -> bool {
+        let initial = &self.states[0];
+        if initial.len != 0 || initial.link != NIL {
+            return false;
+        }
+
+        let bound = (2 * self.len()).saturating_sub(1).max(self.len() + 1);
+        self.states.len() <= bound
+            && self.states.iter().enumerate().all(|(index, state)| {
+                let linked = index == 0
+                    || self
+                        .states
+                        .get(state.link as usize)
+                        .is_some_and(|link| link.len < state.len);
+                let sorted = state.next.windows(2).all(|pair| pair[0].0 < pair[1].0);
+                let longer = state
+                    .next
+                    .iter()
+                    .all(|&(_, next)| self.states[next as usize].len > state.len);
+                linked && sorted && longer
+            })
+    }
+}
+
+impl Default for SuffixAutomaton {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SuffixAutomaton {
+    /// Formats the length of the text and the number of states
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SuffixAutomaton")
+            .field("len", &self.len())
+            .field("states", &self.states.len())
+            .finish()
+    }
+}
+
+impl FromIterator<u8> for SuffixAutomaton {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        let mut automaton = Self::new();
+        automaton.extend(iter);
+        automaton
+    }
+}
+
+impl Extend<u8> for SuffixAutomaton {
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+        for byte in iter {
+            self.push(byte);
+        }
+    }
+}
+
+impl<'a> Extend<&'a u8> for SuffixAutomaton {
+    fn extend<I: IntoIterator<Item = &'a u8>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_substrings() {
+        let automaton: SuffixAutomaton = b"banana".iter().copied().collect();
+
+        assert_eq!(automaton.len(), 6);
+        assert!(automaton.is_valid());
+        assert!(automaton.contains(""));
+        assert!(automaton.contains("nan"));
+        assert!(automaton.contains("banana"));
+        assert!(!automaton.contains("nab"));
+        assert!(!automaton.contains("bananas"));
+        assert_eq!(automaton.distinct_substrings(), 15);
+        assert_eq!(SuffixAutomaton::new().distinct_substrings(), 0);
+    }
+
+    #[test]
+    fn test_matches_naive_substrings() {
+        let texts: [&[u8]; 5] = [
+            b"a",
+            b"aaaaaaaa",
+            b"abcabxabcd",
+            b"mississippi",
+            &[0, 255, 0, 255, 0, 0, 255],
+        ];
+
+        for text in texts {
+            let mut automaton = SuffixAutomaton::new();
+            let mut substrings = HashSet::new();
+
+            for (end, &byte) in text.iter().enumerate() {
+                automaton.push(byte);
+                substrings.extend((0..=end).map(|start| &text[start..=end]));
+
+                assert!(automaton.is_valid());
+                assert_eq!(automaton.distinct_substrings(), substrings.len() as u64);
+            }
+            for start in 0..text.len() {
+                for end in start..=text.len() {
+                    assert!(automaton.contains(&text[start..end]));
+                    let mut other = text[start..end].to_vec();
+                    other.push(1);
+                    assert_eq!(automaton.contains(&other), substrings.contains(&other[..]));
+                }
+            }
+        }
+    }
+}
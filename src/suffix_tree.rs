@@ -0,0 +1,384 @@
+use std::{fmt, mem};
+
+const ROOT: u32 = 0;
+
+/// The symbol past the last byte of the text, which ends every suffix at a
+/// leaf of its own
+const END: u16 = 256;
+
+#[derive(Clone)]
+struct Node {
+    /// text range of the edge into this node, `None` as end for leaves,
+    /// whose edges grow with the text while it is being built
+    start: usize,
+    end: Option<usize>,
+    /// children by the first symbol on their edge, sorted
+    children: Vec<(u16, u32)>,
+    /// node for the same path without its first symbol
+    suffix_link: u32,
+    /// start of the suffix spelled by the path to a leaf
+    suffix: usize,
+}
+
+impl Node {
+    fn new(start: usize, end: Option<usize>, suffix: usize) -> Self {
+        Self {
+            start,
+            end,
+            children: Vec::new(),
+            suffix_link: ROOT,
+            suffix,
+        }
+    }
+
+    fn child(&self, symbol: u16) -> Option<u32> {
+        let index = self
+            .children
+            .binary_search_by_key(&symbol, |&(first, _)| first)
+            .ok()?;
+        Some(self.children[index].1)
+    }
+
+    fn set_child(&mut self, symbol: u16, child: u32) {
+        match self
+            .children
+            .binary_search_by_key(&symbol, |&(first, _)| first)
+        {
+            Ok(index) => self.children[index].1 = child,
+            Err(index) => self.children.insert(index, (symbol, child)),
+        }
+    }
+}
+
+/// A compressed trie of all the suffixes of a text, answering whether a
+/// pattern occurs in it and where, in time linear in the pattern
+///
+/// The tree is built with Ukkonen's algorithm in `O(n)` for a text of `n`
+/// bytes, and has at most `2n + 1` nodes whose edges are ranges of the text.
+#[derive(Clone)]
+pub struct SuffixTree {
+    text: Vec<u8>,
+    nodes: Vec<Node>,
+}
+
+impl SuffixTree {
+    /// Builds the suffix tree of `text`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the text is too long to index its nodes with `u32`.
+    pub fn new<T>(text: T) -> Self
+    where
+        T: AsRef<[u8]>,
+    {
+        let text = text.as_ref().to_vec();
+        assert!(
+            text.len() < (u32::MAX as usize - 2) / 2,
+            "SuffixTree text is too long"
+        );
+
+        let mut tree = Self {
+            nodes: Vec::with_capacity(2 * text.len() + 2),
+            text,
+        };
+        tree.nodes.push(Node::new(0, Some(0), 0));
+        tree.build();
+        tree
+    }
+
+    /// Runs Ukkonen's algorithm, adding the symbols of the text one at a time
+    /// and keeping the tree of all suffixes of the text read so far
+    fn build(&mut self) {
+        let mut active_node = ROOT;
+        let mut active_edge = 0;
+        let mut active_length = 0;
+        // suffixes of the text read so far that are still implicit in the tree
+        let mut remainder = 0;
+
+        for position in 0..=self.text.len() {
+            let symbol = self.symbol(position);
+            remainder += 1;
+            // internal node created last in this phase, waiting for its link
+            let mut linkless: Option<u32> = None;
+
+            while remainder > 0 {
+                if active_length == 0 {
+                    active_edge = position;
+                }
+
+                let suffix = position + 1 - remainder;
+                let first = self.symbol(active_edge);
+                match self.nodes[active_node as usize].child(first) {
+                    None => {
+                        let leaf = self.push(Node::new(position, None, suffix));
+                        self.nodes[active_node as usize].set_child(first, leaf);
+                        if let Some(node) = linkless.take() {
+                            self.nodes[node as usize].suffix_link = active_node;
+                        }
+                    }
+                    Some(next) => {
+                        let length = self.edge_length(next, position);
+                        if active_length >= length {
+                            // walk down to the node the active point is past
+                            active_edge += length;
+                            active_length -= length;
+                            active_node = next;
+                            continue;
+                        }
+
+                        let start = self.nodes[next as usize].start;
+                        if self.symbol(start + active_length) == symbol {
+                            // the suffix is already there, and so are all
+                            // shorter ones
+                            if let Some(node) = linkless.take() {
+                                self.nodes[node as usize].suffix_link = active_node;
+                            }
+                            active_length += 1;
+                            break;
+                        }
+
+                        let split = start + active_length;
+                        let inner = self.push(Node::new(start, Some(split), 0));
+                        self.nodes[active_node as usize].set_child(first, inner);
+                        let leaf = self.push(Node::new(position, None, suffix));
+                        self.nodes[inner as usize].set_child(symbol, leaf);
+                        self.nodes[next as usize].start = split;
+                        let symbol = self.symbol(split);
+                        self.nodes[inner as usize].set_child(symbol, next);
+
+                        if let Some(node) = linkless.replace(inner) {
+                            self.nodes[node as usize].suffix_link = inner;
+                        }
+                    }
+                }
+
+                remainder -= 1;
+                if active_node == ROOT && active_length > 0 {
+                    active_length -= 1;
+                    active_edge = position + 1 - remainder;
+                } else if active_node != ROOT {
+                    active_node = self.nodes[active_node as usize].suffix_link;
+                }
+            }
+        }
+
+        let end = self.text.len() + 1;
+        for node in &mut self.nodes[1..] {
+            node.end.get_or_insert(end);
+        }
+    }
+
+    fn push(&mut self, node: Node) -> u32 {
+        self.nodes.push(node);
+        (self.nodes.len() - 1) as u32
+    }
+
+    fn symbol(&self, position: usize) -> u16 {
+        self.text.get(position).map_or(END, |&byte| u16::from(byte))
+    }
+
+    /// Returns the length of the edge into `node` once `position` is read
+    fn edge_length(&self, node: u32, position: usize) -> usize {
+        let node = &self.nodes[node as usize];
+        node.end.unwrap_or(position + 1) - node.start
+    }
+
+    /// Returns the indexed text
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    /// Returns the number of bytes in the text
+    pub fn len(&self) -> usize {
+        self.text.len()
+    }
+
+    /// Returns `true` if the text is empty
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Returns the number of nodes in the tree, including the root
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the highest node whose path starts with `pattern`
+    fn locate(&self, pattern: &[u8]) -> Option<u32> {
+        let mut node = ROOT;
+        let mut matched = 0;
+
+        while matched < pattern.len() {
+            node = self.nodes[node as usize].child(u16::from(pattern[matched]))?;
+            let Node { start, end, .. } = self.nodes[node as usize];
+            let edge = &self.text[start..end.expect("set once built").min(self.text.len())];
+            let rest = &pattern[matched..];
+            let common = edge.len().min(rest.len());
+            if edge[..common] != rest[..common] {
+                return None;
+            }
+            matched += common;
+        }
+        Some(node)
+    }
+
+    /// Returns `true` if `pattern` occurs in the text
+    pub fn contains<P>(&self, pattern: P) -> bool
+    where
+        P: AsRef<[u8]>,
+    {
+        self.locate(pattern.as_ref()).is_some()
+    }
+
+    /// Returns the positions where `pattern` starts in the text, in ascending
+    /// order
+    ///
+    /// The empty pattern occurs at every position, including the end of the
+    /// text.
+    pub fn occurrences<P>(&self, pattern: P) -> Vec<usize>
+    where
+        P: AsRef<[u8]>,
+    {
+        let Some(top) = self.locate(pattern.as_ref()) else {
+            return Vec::new();
+        };
+
+        let mut positions = Vec::new();
+        let mut stack = vec![top];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node as usize];
+            if node.children.is_empty() {
+                positions.push(node.suffix);
+            }
+            stack.extend(node.children.iter().map(|&(_, child)| child));
+        }
+        positions.sort_unstable();
+        positions
+    }
+
+    /// Returns the number of times `pattern` occurs in the text
+    pub fn count<P>(&self, pattern: P) -> usize
+    where
+        P: AsRef<[u8]>,
+    {
+        self.occurrences(pattern).len()
+    }
+
+    /// Returns `true` if every inner node but the root branches, children are
+    /// keyed by the first symbol of their edges, and the leaves spell each
+    /// suffix of the text exactly once
+    pub fn is_valid(&self) -> bool {
+        let mut suffixes = vec![false; self.text.len() + 1];
+        let mut stack = vec![(ROOT, 0)];
+
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index as usize];
+            let Some(end) = node.end else {
+                return false;
+            };
+            let depth = depth + end - node.start;
+
+            if node.children.is_empty() {
+                // the path to a leaf spells its suffix up to the end symbol
+                if index == ROOT
+                    || depth != self.text.len() + 1 - node.suffix
+                    || end != self.text.len() + 1
+                    || mem::replace(&mut suffixes[node.suffix], true)
+                {
+                    return false;
+                }
+                continue;
+            }
+            if index != ROOT && node.children.len() < 2 {
+                return false;
+            }
+
+            for window in node.children.windows(2) {
+                if window[0].0 >= window[1].0 {
+                    return false;
+                }
+            }
+            for &(first, child) in &node.children {
+                let child_node = &self.nodes[child as usize];
+                if child_node.end.is_some_and(|end| end <= child_node.start)
+                    || self.symbol(child_node.start) != first
+                {
+                    return false;
+                }
+                stack.push((child, depth));
+            }
+        }
+
+        suffixes.iter().all(|&found| found)
+    }
+}
+
+impl fmt::Debug for SuffixTree {
+    /// Formats the text, with invalid UTF-8 replaced, and the number of nodes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SuffixTree")
+            .field("text", &String::from_utf8_lossy(&self.text))
+            .field("nodes", &self.nodes.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the start of every occurrence of `pattern` by direct search
+    fn naive_occurrences(text: &[u8], pattern: &[u8]) -> Vec<usize> {
+        (0..=text.len())
+            .filter(|&start| text[start..].starts_with(pattern))
+            .collect()
+    }
+
+    #[test]
+    fn test_occurrences() {
+        let tree = SuffixTree::new("banana");
+
+        assert_eq!(tree.len(), 6);
+        assert!(tree.is_valid());
+        assert!(tree.contains("nan"));
+        assert!(tree.contains("banana"));
+        assert!(!tree.contains("nab"));
+        assert!(!tree.contains("bananas"));
+        assert_eq!(tree.occurrences("ana"), vec![1, 3]);
+        assert_eq!(tree.occurrences("a"), vec![1, 3, 5]);
+        assert_eq!(tree.occurrences("b"), vec![0]);
+        assert_eq!(tree.occurrences(""), (0..=6).collect::<Vec<_>>());
+        assert_eq!(tree.count("na"), 2);
+        assert!(tree.occurrences("x").is_empty());
+        assert!(tree.node_count() <= 2 * tree.len() + 1);
+    }
+
+    #[test]
+    fn test_matches_naive_search() {
+        let texts: [&[u8]; 5] = [
+            b"",
+            b"aaaaaaaa",
+            b"abcabxabcd",
+            b"mississippi",
+            &[0, 255, 0, 255, 0, 0, 255],
+        ];
+
+        for text in texts {
+            let tree = SuffixTree::new(text);
+            assert!(tree.is_valid());
+
+            for start in 0..=text.len() {
+                for end in start..=text.len() {
+                    let pattern = &text[start..end];
+                    assert_eq!(tree.occurrences(pattern), naive_occurrences(text, pattern));
+                }
+            }
+            for pattern in [&b"ab"[..], b"ss", b"ipx", b"aaaaaaaaa", &[255, 255]] {
+                assert_eq!(
+                    tree.contains(pattern),
+                    text.windows(pattern.len()).any(|w| w == pattern)
+                );
+            }
+        }
+    }
+}
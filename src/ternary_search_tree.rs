@@ -0,0 +1,737 @@
+use std::{
+    cmp::Ordering::{self, Equal, Greater, Less},
+    fmt,
+};
+
+type Link<V> = Option<Box<Node<V>>>;
+
+struct Node<V> {
+    byte: u8,
+    /// value of the key ending with `byte` here
+    value: Option<V>,
+    /// keys with a smaller byte at this position
+    lo: Link<V>,
+    /// keys continuing after `byte`
+    eq: Link<V>,
+    /// keys with a greater byte at this position
+    hi: Link<V>,
+}
+
+impl<V> Node<V> {
+    fn new(byte: u8) -> Box<Self> {
+        Box::new(Self {
+            byte,
+            value: None,
+            lo: None,
+            eq: None,
+            hi: None,
+        })
+    }
+
+    /// Returns the child to follow for a key byte comparing `turn` to `byte`
+    fn child(&self, turn: Ordering) -> &Link<V> {
+        match turn {
+            Less => &self.lo,
+            Equal => &self.eq,
+            Greater => &self.hi,
+        }
+    }
+
+    fn child_mut(&mut self, turn: Ordering) -> &mut Link<V> {
+        match turn {
+            Less => &mut self.lo,
+            Equal => &mut self.eq,
+            Greater => &mut self.hi,
+        }
+    }
+}
+
+/// Removes the node at `link` if it no longer leads to any key, and replaces
+/// it by its only sibling subtree if it just has one
+fn splice<V>(link: &mut Link<V>) {
+    let Some(node) = link else {
+        return;
+    };
+
+    if node.value.is_none() && node.eq.is_none() {
+        match (node.lo.take(), node.hi.take()) {
+            (None, None) => *link = None,
+            (Some(child), None) | (None, Some(child)) => *link = Some(child),
+            (lo, hi) => (node.lo, node.hi) = (lo, hi),
+        }
+    }
+}
+
+/// Releases the nodes under `link` one at a time, so that dropping very long
+/// keys can't overflow the stack
+fn drop_iteratively<V>(link: Link<V>) {
+    let mut stack: Vec<Box<Node<V>>> = link.into_iter().collect();
+
+    while let Some(mut node) = stack.pop() {
+        stack.extend(node.lo.take());
+        stack.extend(node.eq.take());
+        stack.extend(node.hi.take());
+    }
+}
+
+/// Copies the nodes under `link` one at a time, so that cloning very long keys
+/// can't overflow the stack
+fn clone_iteratively<V>(link: &Link<V>) -> Link<V>
+where
+    V: Clone,
+{
+    let mut stack: Vec<(&Node<V>, bool)> = link.iter().map(|node| (&**node, false)).collect();
+    // the copies of the finished subtrees, from the lo to the hi child
+    let mut copies: Vec<Box<Node<V>>> = Vec::new();
+
+    while let Some((node, children_done)) = stack.pop() {
+        if !children_done {
+            stack.push((node, true));
+            for child in [&node.hi, &node.eq, &node.lo] {
+                stack.extend(child.as_deref().map(|child| (child, false)));
+            }
+            continue;
+        }
+
+        let mut copy = |child: &Link<V>| match child {
+            Some(_) => copies.pop(),
+            None => None,
+        };
+        let hi = copy(&node.hi);
+        let eq = copy(&node.eq);
+        let lo = copy(&node.lo);
+        copies.push(Box::new(Node {
+            byte: node.byte,
+            value: node.value.clone(),
+            lo,
+            eq,
+            hi,
+        }));
+    }
+    copies.pop()
+}
+
+/// A map keyed by byte strings where each node holds one byte and three
+/// children, for the keys with a smaller byte, the same byte and a greater
+/// byte at that position
+///
+/// The nodes for one position of the keys form a small binary search tree,
+/// and the middle child moves on to the next position, so a lookup in a tree
+/// of `n` keys takes about `m + log n` comparisons for a key of `m` bytes.
+/// Nodes only exist for bytes some key has, which uses far less memory than a
+/// trie with a child table per node, while sharing prefixes unlike a binary
+/// search tree of whole keys.
+pub struct TernarySearchTree<V> {
+    root: Link<V>,
+    /// value of the empty key, which has no byte to hold it
+    empty: Option<V>,
+    len: usize,
+}
+
+impl<V> TernarySearchTree<V> {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            empty: None,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of keys in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all keys
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Stores `value` under `key`, returning the value previously stored there
+    pub fn insert<K>(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        let old = if key.is_empty() {
+            self.empty.replace(value)
+        } else {
+            let mut link = &mut self.root;
+            let mut index = 0;
+
+            loop {
+                let node = link.get_or_insert_with(|| Node::new(key[index]));
+                let turn = key[index].cmp(&node.byte);
+                if turn == Equal {
+                    if index + 1 == key.len() {
+                        break node.value.replace(value);
+                    }
+                    index += 1;
+                }
+                link = node.child_mut(turn);
+            }
+        };
+
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Returns `true` if a value is stored under `key`
+    pub fn contains_key<K>(&self, key: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get<K>(&self, key: K) -> Option<&V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        if key.is_empty() {
+            return self.empty.as_ref();
+        }
+        self.find(key)?.value.as_ref()
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut<K>(&mut self, key: K) -> Option<&mut V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        if key.is_empty() {
+            return self.empty.as_mut();
+        }
+
+        let mut node = self.root.as_deref_mut()?;
+        let mut index = 0;
+        loop {
+            let turn = key[index].cmp(&node.byte);
+            if turn == Equal {
+                if index + 1 == key.len() {
+                    return node.value.as_mut();
+                }
+                index += 1;
+            }
+            node = node.child_mut(turn).as_deref_mut()?;
+        }
+    }
+
+    /// Returns the node holding the last byte of the non-empty `key`
+    fn find(&self, key: &[u8]) -> Option<&Node<V>> {
+        let mut node = self.root.as_deref()?;
+        let mut index = 0;
+
+        loop {
+            let turn = key[index].cmp(&node.byte);
+            if turn == Equal {
+                if index + 1 == key.len() {
+                    return Some(node);
+                }
+                index += 1;
+            }
+            node = node.child(turn).as_deref()?;
+        }
+    }
+
+    /// Removes `key` from the tree, returning the value stored under it
+    ///
+    /// The nodes that only led to `key` are removed with it, and a node left
+    /// with no key of its own and a single smaller or greater sibling is
+    /// replaced by that sibling.
+    pub fn remove<K>(&mut self, key: K) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        if key.is_empty() {
+            let value = self.empty.take()?;
+            self.len -= 1;
+            return Some(value);
+        }
+
+        // the path from the root, and the turn taken after each node
+        let mut path = Vec::new();
+        let mut turns = Vec::new();
+        let mut node = self.root.as_deref()?;
+        let mut index = 0;
+        loop {
+            path.push(node);
+            let turn = key[index].cmp(&node.byte);
+            if turn == Equal {
+                if index + 1 == key.len() {
+                    break;
+                }
+                index += 1;
+            }
+            turns.push(turn);
+            node = node.child(turn).as_deref()?;
+        }
+        node.value.as_ref()?;
+
+        // the subtree under path[cut] is left with no key once `key` is gone
+        let is_bare = |node: &Node<V>| node.lo.is_none() && node.eq.is_none() && node.hi.is_none();
+        let mut cut = None;
+        if is_bare(node) {
+            let mut top = path.len() - 1;
+            while top > 0 && path[top - 1].value.is_none() && {
+                let parent = path[top - 1];
+                [&parent.lo, &parent.eq, &parent.hi]
+                    .iter()
+                    .filter(|child| child.is_some())
+                    .count()
+                    == 1
+            } {
+                top -= 1;
+            }
+            cut = Some(top);
+        }
+
+        let value = match cut {
+            Some(0) => {
+                let mut branch = self.root.take();
+                let value = Self::take_value(&mut branch, &turns);
+                drop_iteratively(branch);
+                value
+            }
+            Some(cut) => {
+                let mut link = &mut self.root;
+                for &turn in &turns[..cut - 1] {
+                    link = link.as_mut().expect("on the path").child_mut(turn);
+                }
+
+                let parent = link.as_mut().expect("on the path");
+                let mut branch = parent.child_mut(turns[cut - 1]).take();
+                let value = Self::take_value(&mut branch, &turns[cut..]);
+                drop_iteratively(branch);
+                splice(link);
+                value
+            }
+            None => {
+                let mut link = &mut self.root;
+                for &turn in &turns {
+                    link = link.as_mut().expect("on the path").child_mut(turn);
+                }
+
+                let value = link.as_mut().expect("on the path").value.take();
+                splice(link);
+                value
+            }
+        };
+
+        self.len -= 1;
+        value
+    }
+
+    /// Follows `turns` down from `link` and takes the value of the node there
+    fn take_value(link: &mut Link<V>, turns: &[Ordering]) -> Option<V> {
+        let mut node = link.as_deref_mut()?;
+        for &turn in turns {
+            node = node.child_mut(turn).as_deref_mut()?;
+        }
+        node.value.take()
+    }
+
+    /// Returns an iterator over the entries in lexicographic order of their
+    /// keys
+    pub fn iter(&self) -> TernarySearchTreeIterator<'_, V> {
+        self.iter_prefix([])
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`, in
+    /// lexicographic order of their keys
+    pub fn iter_prefix<K>(&self, prefix: K) -> TernarySearchTreeIterator<'_, V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let prefix = prefix.as_ref();
+        let mut iter = TernarySearchTreeIterator {
+            key: prefix.to_vec(),
+            empty: None,
+            stack: Vec::new(),
+        };
+
+        if prefix.is_empty() {
+            iter.empty = self.empty.as_ref();
+            iter.stack.extend(
+                self.root
+                    .as_deref()
+                    .map(|root| (Frame::Node(root), 0, None)),
+            );
+        } else if let Some(node) = self.find(prefix) {
+            let depth = prefix.len();
+            iter.stack
+                .extend(node.eq.as_deref().map(|eq| (Frame::Node(eq), depth, None)));
+            if node.value.is_some() {
+                iter.stack.push((Frame::Value(node), depth - 1, None));
+            }
+        }
+        iter
+    }
+
+    /// Returns the entries whose keys have as many bytes as `key` and differ
+    /// from it in at most `distance` of them, in lexicographic order of their
+    /// keys
+    ///
+    /// Subtrees are skipped as soon as they need more than `distance`
+    /// substitutions, so close neighbors of a key are found without visiting
+    /// much of the tree.
+    pub fn near_neighbors<K>(&self, key: K, distance: usize) -> Vec<(Vec<u8>, &V)>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        if key.is_empty() {
+            return self.empty.iter().map(|value| (Vec::new(), value)).collect();
+        }
+
+        let mut found = Vec::new();
+        let mut prefix = Vec::new();
+        // nodes left to visit with the position of `key` they compare with,
+        // the substitutions still allowed, the byte to append to `prefix`
+        // before them and whether they end a found key
+        let mut stack = Vec::new();
+        stack.extend(
+            self.root
+                .as_deref()
+                .map(|root| (root, 0, distance, None, false)),
+        );
+
+        while let Some((node, index, budget, byte, is_match)) = stack.pop() {
+            match byte {
+                Some(byte) => {
+                    prefix.truncate(index - 1);
+                    prefix.push(byte);
+                }
+                None => prefix.truncate(index),
+            }
+            if is_match {
+                prefix.push(node.byte);
+                let value = node.value.as_ref().expect("only pushed with a value");
+                found.push((prefix.clone(), value));
+                continue;
+            }
+
+            let wanted = key[index];
+            let cost = usize::from(wanted != node.byte);
+            if budget > 0 || wanted > node.byte {
+                stack.extend(
+                    node.hi
+                        .as_deref()
+                        .map(|hi| (hi, index, budget, None, false)),
+                );
+            }
+            if cost <= budget {
+                if index + 1 == key.len() {
+                    if node.value.is_some() {
+                        stack.push((node, index, budget, None, true));
+                    }
+                } else if let Some(eq) = node.eq.as_deref() {
+                    stack.push((eq, index + 1, budget - cost, Some(node.byte), false));
+                }
+            }
+            if budget > 0 || wanted < node.byte {
+                stack.extend(
+                    node.lo
+                        .as_deref()
+                        .map(|lo| (lo, index, budget, None, false)),
+                );
+            }
+        }
+        found
+    }
+
+    /// Returns `true` if the bytes of each node's smaller and greater subtrees
+    /// are on the right side of its own byte, every node leads to a key,
+    /// unless it has both smaller and greater siblings, and `len` matches the
+    /// number of values
+    pub fn is_valid(&self) -> bool {
+        let mut values = usize::from(self.empty.is_some());
+        let mut stack: Vec<(&Node<V>, Option<u8>, Option<u8>)> = Vec::new();
+        stack.extend(self.root.as_deref().map(|root| (root, None, None)));
+
+        while let Some((node, lower, upper)) = stack.pop() {
+            let in_order = lower.is_none_or(|lower| lower < node.byte)
+                && upper.is_none_or(|upper| node.byte < upper);
+            let leads_to_key = node.value.is_some()
+                || node.eq.is_some()
+                || (node.lo.is_some() && node.hi.is_some());
+            if !in_order || !leads_to_key {
+                return false;
+            }
+
+            values += usize::from(node.value.is_some());
+            stack.extend(node.lo.as_deref().map(|lo| (lo, lower, Some(node.byte))));
+            stack.extend(node.eq.as_deref().map(|eq| (eq, None, None)));
+            stack.extend(node.hi.as_deref().map(|hi| (hi, Some(node.byte), upper)));
+        }
+        values == self.len
+    }
+}
+
+impl<V> Clone for TernarySearchTree<V>
+where
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            root: clone_iteratively(&self.root),
+            empty: self.empty.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<V> Default for TernarySearchTree<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Drop for TernarySearchTree<V> {
+    fn drop(&mut self) {
+        drop_iteratively(self.root.take());
+    }
+}
+
+impl<V> fmt::Debug for TernarySearchTree<V>
+where
+    V: fmt::Debug,
+{
+    /// Formats the entries in key order, like a map, showing the keys as
+    /// strings with invalid UTF-8 replaced
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter()
+                    .map(|(key, value)| (String::from_utf8_lossy(&key).into_owned(), value)),
+            )
+            .finish()
+    }
+}
+
+impl<V> PartialEq for TernarySearchTree<V>
+where
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<V> Eq for TernarySearchTree<V> where V: Eq {}
+
+impl<K, V> FromIterator<(K, V)> for TernarySearchTree<V>
+where
+    K: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<K, V> Extend<(K, V)> for TernarySearchTree<V>
+where
+    K: AsRef<[u8]>,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+enum Frame<'a, V> {
+    /// a subtree left to walk
+    Node(&'a Node<V>),
+    /// the key ending at a node, yielded after its smaller siblings
+    Value(&'a Node<V>),
+}
+
+/// An iterator over the entries of a [`TernarySearchTree`] in lexicographic
+/// order of their keys
+pub struct TernarySearchTreeIterator<'a, V> {
+    /// key of the last node visited
+    key: Vec<u8>,
+    /// value of the empty key, yielded first
+    empty: Option<&'a V>,
+    /// frames left to visit, with the length of the key above them and the
+    /// byte to append before them, the next one on top
+    stack: Vec<(Frame<'a, V>, usize, Option<u8>)>,
+}
+
+impl<'a, V> Iterator for TernarySearchTreeIterator<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(value) = self.empty.take() {
+            return Some((Vec::new(), value));
+        }
+
+        loop {
+            let (frame, depth, byte) = self.stack.pop()?;
+            self.key.truncate(depth);
+            self.key.extend(byte);
+            let depth = self.key.len();
+
+            match frame {
+                Frame::Value(node) => {
+                    self.key.push(node.byte);
+                    let value = node.value.as_ref().expect("only pushed with a value");
+                    return Some((self.key.clone(), value));
+                }
+                Frame::Node(node) => {
+                    self.stack
+                        .extend(node.hi.as_deref().map(|hi| (Frame::Node(hi), depth, None)));
+                    self.stack.extend(
+                        node.eq
+                            .as_deref()
+                            .map(|eq| (Frame::Node(eq), depth, Some(node.byte))),
+                    );
+                    if node.value.is_some() {
+                        self.stack.push((Frame::Value(node), depth, None));
+                    }
+                    self.stack
+                        .extend(node.lo.as_deref().map(|lo| (Frame::Node(lo), depth, None)));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a TernarySearchTree<V> {
+    type Item = (Vec<u8>, &'a V);
+    type IntoIter = TernarySearchTreeIterator<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel_words;
+
+    fn keys<'a, I>(entries: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = (Vec<u8>, &'a u32)>,
+    {
+        entries
+            .into_iter()
+            .map(|(key, _)| String::from_utf8(key).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_get_iter() {
+        let mut tree: TernarySearchTree<u32> = prequel_words();
+
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.get("ten"), Some(&1));
+        assert_eq!(tree.get("te"), None);
+        assert_eq!(tree.get("tens"), None);
+        assert_eq!(tree.insert("ten", 10), Some(1));
+        assert_eq!(tree.insert("", 99), None);
+        *tree.get_mut("inn").unwrap() += 20;
+
+        assert_eq!(tree.get(b"ten"), Some(&10));
+        assert_eq!(tree.get(""), Some(&99));
+        assert_eq!(tree.get("inn"), Some(&23));
+        assert_eq!(
+            keys(tree.iter()),
+            vec!["", "i", "in", "inn", "tea", "team", "ten", "to"]
+        );
+        assert_eq!(keys(tree.iter_prefix("te")), vec!["tea", "team", "ten"]);
+        assert_eq!(keys(tree.iter_prefix("in")), vec!["in", "inn"]);
+        assert!(tree.iter_prefix("x").next().is_none());
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree: TernarySearchTree<u32> = prequel_words();
+
+        assert_eq!(tree.remove("team"), Some(6));
+        assert_eq!(tree.remove("team"), None);
+        assert_eq!(tree.remove("te"), None);
+        assert_eq!(tree.remove("in"), Some(4));
+        assert!(tree.is_valid());
+        assert_eq!(keys(tree.iter()), vec!["i", "inn", "tea", "ten", "to"]);
+
+        for key in ["tea", "i", "to", "inn", "ten"] {
+            assert!(tree.remove(key).is_some());
+            assert!(tree.is_valid());
+        }
+        assert!(tree.is_empty());
+        assert!(tree.root.is_none());
+
+        // a long key makes a deep chain of nodes
+        let long = vec![b'a'; 100_000];
+        tree.insert(&long, 1);
+        tree.insert(&long[..10], 2);
+        assert_eq!(tree.remove(&long), Some(1));
+        assert_eq!(tree.get(&long[..10]), Some(&2));
+        assert!(tree.is_valid());
+        tree.insert(&long, 1);
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut tree: TernarySearchTree<u32> = prequel_words();
+        tree.insert("", 99);
+        let mut clone = tree.clone();
+        assert_eq!(clone, tree);
+        assert!(clone.is_valid());
+
+        clone.insert("tan", 7);
+        assert_eq!(clone.remove("tea"), Some(0));
+        assert_eq!(clone.remove(""), Some(99));
+        assert_eq!(tree.get("tea"), Some(&0));
+        assert_eq!(tree.get(""), Some(&99));
+        assert!(!tree.contains_key("tan"));
+
+        // a long key makes a deep chain of nodes
+        let mut tree = TernarySearchTree::new();
+        let long = vec![b'a'; 1_000_000];
+        tree.insert(&long, 1);
+        tree.insert(&long[..10], 2);
+        tree.insert("b", 3);
+        let clone = tree.clone();
+        assert_eq!(clone.len(), 3);
+        assert_eq!(clone.get(&long), Some(&1));
+        assert_eq!(clone.get(&long[..10]), Some(&2));
+        assert_eq!(clone.get("b"), Some(&3));
+        assert!(clone.is_valid());
+    }
+
+    #[test]
+    fn test_near_neighbors() {
+        let tree: TernarySearchTree<u32> = ["cat", "cot", "cut", "car", "bat", "dog", "cats"]
+            .into_iter()
+            .zip(0..)
+            .collect();
+
+        assert_eq!(keys(tree.near_neighbors("cat", 0)), vec!["cat"]);
+        assert_eq!(
+            keys(tree.near_neighbors("cat", 1)),
+            vec!["bat", "car", "cat", "cot", "cut"]
+        );
+        assert_eq!(keys(tree.near_neighbors("dot", 1)), vec!["cot", "dog"]);
+        assert_eq!(tree.near_neighbors("cat", 3).len(), 6);
+        assert!(tree.near_neighbors("zzz", 1).is_empty());
+        assert!(tree.near_neighbors("", 2).is_empty());
+    }
+}
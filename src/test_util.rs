@@ -0,0 +1,40 @@
+//! Fixtures shared by the tests of the structures
+
+/// The elements the structures are tested with, in insertion order, which
+/// builds a binary search tree of height 4
+pub(crate) const PREQUEL: [u32; 7] = [16, 7, 28, 3, 21, 36, 70];
+
+/// The keys the tries are tested with, in insertion order
+pub(crate) const PREQUEL_WORDS: [&str; 7] = ["tea", "ten", "to", "inn", "in", "i", "team"];
+
+/// Collects [`PREQUEL`]
+pub(crate) fn prequel<C>() -> C
+where
+    C: FromIterator<u32>,
+{
+    PREQUEL.into_iter().collect()
+}
+
+/// Collects [`PREQUEL`] as keys, each one mapped to ten times itself
+pub(crate) fn prequel_entries<C>() -> C
+where
+    C: FromIterator<(u32, u32)>,
+{
+    PREQUEL.into_iter().map(|key| (key, key * 10)).collect()
+}
+
+/// Collects [`PREQUEL_WORDS`] as keys, each one mapped to its position
+pub(crate) fn prequel_words<C>() -> C
+where
+    C: FromIterator<(&'static str, u32)>,
+{
+    PREQUEL_WORDS.into_iter().zip(0..).collect()
+}
+
+/// Returns `0..n` in a deterministic pseudo-random order
+///
+/// Every number appears once as long as `n` is coprime to 7919.
+pub(crate) fn shuffled(n: u64) -> Vec<u64> {
+    // multiplying by a number coprime to n permutes 0..n, and 7919 is prime
+    (0..n).map(|i| i * 7919 % n).collect()
+}
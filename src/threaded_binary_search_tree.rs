@@ -0,0 +1,597 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    fmt, mem,
+};
+
+/// Marks a missing child, the end of the threads, or the end of the free list
+const NIL: u32 = u32::MAX;
+
+/// A binary search tree behaving like a set, whose empty right links are
+/// threads to the in-order successor
+///
+/// Following a thread instead of popping a stack, the iterator walks the tree
+/// in order with `O(1)` extra space however deep the tree is. Nodes are kept
+/// in a single `Vec` and linked by `u32` indices, like
+/// [`crate::BinarySearchTreeArena`], so nothing recurses on drop either. The
+/// tree is not rebalanced.
+#[derive(Clone)]
+pub struct ThreadedBinarySearchTree<T>
+where
+    T: Ord,
+{
+    nodes: Vec<Slot<T>>,
+    root: u32,
+    /// head of the list of vacant slots, threaded through `Slot::Vacant`
+    free: u32,
+    len: usize,
+}
+
+#[derive(Clone)]
+enum Slot<T> {
+    Occupied(Node<T>),
+    Vacant { next_free: u32 },
+}
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: u32,
+    /// the right child, or the in-order successor if `right_thread` is set
+    right: u32,
+    right_thread: bool,
+}
+
+/// Where the index of a node is stored
+#[derive(Clone, Copy)]
+enum Link {
+    Root,
+    Left(u32),
+    Right(u32),
+}
+
+impl<T> ThreadedBinarySearchTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty tree with room for `capacity` nodes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            nodes: Vec::with_capacity(capacity),
+            root: NIL,
+            free: NIL,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements, keeping the allocated node storage
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = NIL;
+        self.free = NIL;
+        self.len = 0;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut stack = vec![(self.root, 1)];
+
+        while let Some((index, depth)) = stack.pop() {
+            if index != NIL {
+                let node = self.node(index);
+                height = height.max(depth);
+                stack.push((node.left, depth + 1));
+                stack.push((self.right_child(index), depth + 1));
+            }
+        }
+        height
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut index = self.root;
+
+        while index != NIL {
+            let node = self.node(index);
+
+            index = match key.cmp(node.data.borrow()) {
+                Equal => return Some(&node.data),
+                Less => node.left,
+                Greater => self.right_child(index),
+            };
+        }
+        None
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        if self.root == NIL {
+            self.root = self.allocate(data, NIL);
+            self.len += 1;
+            return true;
+        }
+
+        let mut index = self.root;
+
+        loop {
+            let node = self.node(index);
+
+            match data.cmp(&node.data) {
+                Equal => return false,
+                Less if node.left == NIL => {
+                    // the parent becomes the successor of its new left child
+                    let child = self.allocate(data, index);
+                    self.node_mut(index).left = child;
+                    break;
+                }
+                Less => index = node.left,
+                Greater if node.right_thread => {
+                    // the new right child takes over the thread of its parent
+                    let child = self.allocate(data, node.right);
+                    let node = self.node_mut(index);
+                    node.right = child;
+                    node.right_thread = false;
+                    break;
+                }
+                Greater => index = node.right,
+            }
+        }
+
+        self.len += 1;
+        true
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut link = Link::Root;
+
+        let index = loop {
+            let index = self.link(link);
+            if index == NIL {
+                return None;
+            }
+
+            link = match key.cmp(self.node(index).data.borrow()) {
+                Equal => break index,
+                Less => Link::Left(index),
+                Greater => Link::Right(index),
+            };
+        };
+
+        self.len -= 1;
+
+        if self.node(index).left == NIL || self.node(index).right_thread {
+            return Some(self.unlink(link, index));
+        }
+
+        // two children: the in-order successor, which has no left child, is
+        // unlinked and its element moves into the node, keeping the thread of
+        // the predecessor pointing at the right slot
+        let mut successor_link = Link::Right(index);
+        while self.node(self.link(successor_link)).left != NIL {
+            successor_link = Link::Left(self.link(successor_link));
+        }
+
+        let successor = self.link(successor_link);
+        let data = self.unlink(successor_link, successor);
+        Some(mem::replace(&mut self.node_mut(index).data, data))
+    }
+
+    /// Removes the node at `index`, stored at `link`, which lacks a left
+    /// child or a right child, and returns its element
+    fn unlink(&mut self, link: Link, index: u32) -> T {
+        let Node {
+            left,
+            right,
+            right_thread,
+            ..
+        } = *self.node(index);
+
+        if left != NIL {
+            // the largest node on the left threads to the removed node, it
+            // now threads to the successor of the removed node instead
+            let mut predecessor = left;
+            while !self.node(predecessor).right_thread {
+                predecessor = self.node(predecessor).right;
+            }
+            self.node_mut(predecessor).right = right;
+
+            self.set_link(link, left);
+        } else if !right_thread {
+            self.set_link(link, right);
+        } else {
+            match link {
+                Link::Root => self.root = NIL,
+                Link::Left(parent) => self.node_mut(parent).left = NIL,
+                // the parent threads to what the removed node threaded to
+                Link::Right(parent) => {
+                    let parent = self.node_mut(parent);
+                    parent.right = right;
+                    parent.right_thread = true;
+                }
+            }
+        }
+
+        self.deallocate(index)
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let index = self.leftmost(self.root);
+        (index != NIL).then(|| &self.node(index).data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut index = self.root;
+        if index == NIL {
+            return None;
+        }
+
+        while !self.node(index).right_thread {
+            index = self.node(index).right;
+        }
+        Some(&self.node(index).data)
+    }
+
+    /// Returns a new iterator which iterates over this tree in order, using
+    /// no memory besides the iterator itself
+    pub fn iter(&self) -> ThreadedBinarySearchTreeIterator<'_, T> {
+        ThreadedBinarySearchTreeIterator {
+            tree: self,
+            index: self.leftmost(self.root),
+            remaining: self.len,
+        }
+    }
+
+    /// Returns the number of heap bytes used by the node buffer
+    ///
+    /// Counts the whole capacity, including vacant and spare slots. Heap memory
+    /// owned by the elements themselves is not included.
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.capacity() * mem::size_of::<Slot<T>>()
+    }
+
+    /// Returns `true` if the elements are in order and every thread points
+    /// to the in-order successor, the largest node threading to nothing
+    pub fn is_valid(&self) -> bool {
+        // in-order over the child links only, ignoring the threads
+        let mut order = Vec::with_capacity(self.len);
+        let mut stack = Vec::new();
+        let mut index = self.root;
+
+        while index != NIL || !stack.is_empty() {
+            while index != NIL {
+                stack.push(index);
+                index = self.node(index).left;
+            }
+
+            let next = stack.pop().expect("checked by the loop");
+            order.push(next);
+            index = self.right_child(next);
+        }
+
+        let sorted = order
+            .windows(2)
+            .all(|pair| self.node(pair[0]).data < self.node(pair[1]).data);
+        let threads_ok = order.iter().enumerate().all(|(position, &index)| {
+            let node = self.node(index);
+            let successor = order.get(position + 1).copied().unwrap_or(NIL);
+
+            !node.right_thread || node.right == successor
+        });
+
+        sorted && threads_ok && order.len() == self.len
+    }
+
+    /// Returns the leftmost node under `index`, or `NIL` if `index` is
+    fn leftmost(&self, mut index: u32) -> u32 {
+        while index != NIL && self.node(index).left != NIL {
+            index = self.node(index).left;
+        }
+        index
+    }
+
+    /// Returns the right child of the node at `index`, `NIL` for a thread
+    fn right_child(&self, index: u32) -> u32 {
+        let node = self.node(index);
+        if node.right_thread {
+            NIL
+        } else {
+            node.right
+        }
+    }
+
+    fn node(&self, index: u32) -> &Node<T> {
+        match &self.nodes[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    fn node_mut(&mut self, index: u32) -> &mut Node<T> {
+        match &mut self.nodes[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    /// Returns the child stored at `link`, `NIL` for a thread
+    fn link(&self, link: Link) -> u32 {
+        match link {
+            Link::Root => self.root,
+            Link::Left(index) => self.node(index).left,
+            Link::Right(index) => self.right_child(index),
+        }
+    }
+
+    /// Stores the child `child` at `link`, which must not be `NIL` on the
+    /// right
+    fn set_link(&mut self, link: Link, child: u32) {
+        match link {
+            Link::Root => self.root = child,
+            Link::Left(index) => self.node_mut(index).left = child,
+            Link::Right(index) => {
+                let node = self.node_mut(index);
+                node.right = child;
+                node.right_thread = false;
+            }
+        }
+    }
+
+    /// Stores a leaf holding `data`, threaded to `successor`, in a vacant
+    /// slot or a new one and returns its index
+    fn allocate(&mut self, data: T, successor: u32) -> u32 {
+        let node = Slot::Occupied(Node {
+            data,
+            left: NIL,
+            right: successor,
+            right_thread: true,
+        });
+
+        if self.free == NIL {
+            let index = self.nodes.len();
+            assert!(index < NIL as usize, "ThreadedBinarySearchTree is full");
+
+            self.nodes.push(node);
+            index as u32
+        } else {
+            let index = self.free;
+
+            match mem::replace(&mut self.nodes[index as usize], node) {
+                Slot::Vacant { next_free } => self.free = next_free,
+                Slot::Occupied(_) => unreachable!("free list points to an occupied slot"),
+            }
+            index
+        }
+    }
+
+    /// Moves the data out of the slot at `index` and puts the slot on the free list
+    fn deallocate(&mut self, index: u32) -> T {
+        let vacant = Slot::Vacant {
+            next_free: self.free,
+        };
+        self.free = index;
+
+        match mem::replace(&mut self.nodes[index as usize], vacant) {
+            Slot::Occupied(node) => node.data,
+            Slot::Vacant { .. } => unreachable!("deallocating a vacant slot"),
+        }
+    }
+}
+
+impl<T> Default for ThreadedBinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for ThreadedBinarySearchTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements, regardless of their shape
+impl<T> PartialEq for ThreadedBinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for ThreadedBinarySearchTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for ThreadedBinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for ThreadedBinarySearchTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+
+        self.nodes.reserve(iter.size_hint().0);
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a
+/// [`ThreadedBinarySearchTree`] in order, following the threads
+pub struct ThreadedBinarySearchTreeIterator<'a, T>
+where
+    T: Ord,
+{
+    tree: &'a ThreadedBinarySearchTree<T>,
+    index: u32,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for ThreadedBinarySearchTreeIterator<'a, T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == NIL {
+            return None;
+        }
+
+        let node = self.tree.node(self.index);
+        self.index = if node.right_thread {
+            node.right
+        } else {
+            self.tree.leftmost(node.right)
+        };
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for ThreadedBinarySearchTreeIterator<'_, T> where T: Ord {}
+
+impl<'a, T> IntoIterator for &'a ThreadedBinarySearchTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = ThreadedBinarySearchTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+
+    #[test]
+    fn test_insert_iterate() {
+        let mut tree: ThreadedBinarySearchTree<u32> = prequel();
+
+        assert!(!tree.insert(21));
+        assert!(tree.insert(22));
+        assert_eq!(tree.len(), 8);
+        assert_eq!(tree.height(), 4);
+        assert_eq!(tree.get(&22), Some(&22));
+        assert!(!tree.contains(&23));
+        assert_eq!((tree.min(), tree.max()), (Some(&3), Some(&70)));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &22, &28, &36, &70]
+        );
+        assert_eq!(tree.iter().len(), 8);
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_remove_rethreads() {
+        let mut tree: ThreadedBinarySearchTree<u32> = prequel();
+
+        // a node with only a left child whose largest descendant threads to it,
+        // a threaded leaf on the right, two children, and the root
+        tree.insert(5);
+        assert!(tree.remove(&7));
+        assert!(tree.is_valid());
+        assert!(tree.remove(&70));
+        assert!(tree.is_valid());
+        assert_eq!(tree.take(&28), Some(28));
+        assert!(tree.is_valid());
+        assert!(tree.remove(&16));
+        assert!(!tree.remove(&16));
+        assert!(tree.is_valid());
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&3, &5, &21, &36]);
+
+        for data in [3, 5, 21, 36] {
+            assert!(tree.remove(&data));
+            assert!(tree.is_valid());
+        }
+        assert!(tree.is_empty());
+        assert_eq!((tree.min(), tree.max()), (None, None));
+        assert_eq!(tree.iter().next(), None);
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // a single spine, iterated and dropped without recursing
+        let mut tree: ThreadedBinarySearchTree<u32> = (0..10_000).collect();
+
+        assert_eq!(tree.height(), 10_000);
+        assert!(tree.iter().copied().eq(0..10_000));
+
+        for data in (0..10_000).step_by(3) {
+            assert!(tree.remove(&data));
+        }
+        assert!(tree.is_valid());
+        assert!(tree.iter().all(|data| data % 3 != 0));
+        assert_eq!(tree.len(), 6_666);
+    }
+}
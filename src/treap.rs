@@ -0,0 +1,524 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+};
+
+/// A small xorshift generator for node priorities
+///
+/// Priorities only need to look random to the inserted elements, so this is
+/// far from cryptographic but cheap and reproducible from a seed.
+#[derive(Clone, Debug)]
+pub(crate) struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub(crate) fn with_seed(seed: u64) -> Self {
+        // the all zero state would only ever produce zeroes
+        Self {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    /// Seeds from the per-process random keys of the std hasher
+    pub(crate) fn from_entropy() -> Self {
+        Self::with_seed(RandomState::new().build_hasher().finish())
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    /// no greater than the priority of the parent
+    priority: u64,
+    left: Link<T>,
+    right: Link<T>,
+    /// number of nodes in the subtree rooted here
+    size: usize,
+}
+
+impl<T> Node<T> {
+    fn new(data: T, priority: u64) -> Box<Self> {
+        Box::new(Self {
+            data,
+            priority,
+            left: None,
+            right: None,
+            size: 1,
+        })
+    }
+
+    fn update_size(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// Splits `link` into the nodes for which `goes_left` holds and the rest
+///
+/// `goes_left` must hold for a prefix of the elements in order.
+fn split<T, F>(link: Link<T>, goes_left: &mut F) -> (Link<T>, Link<T>)
+where
+    F: FnMut(&T) -> bool,
+{
+    let Some(mut node) = link else {
+        return (None, None);
+    };
+
+    if goes_left(&node.data) {
+        let (middle, right) = split(node.right.take(), goes_left);
+        node.right = middle;
+        node.update_size();
+        (Some(node), right)
+    } else {
+        let (left, middle) = split(node.left.take(), goes_left);
+        node.left = middle;
+        node.update_size();
+        (left, Some(node))
+    }
+}
+
+/// Joins two treaps, every element of `left` being smaller than those of
+/// `right`
+fn merge<T>(left: Link<T>, right: Link<T>) -> Link<T> {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority >= right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update_size();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update_size();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// A randomized binary search tree behaving like a set
+///
+/// Elements are ordered like a binary search tree while their random
+/// priorities are ordered like a max-heap, which keeps the tree `O(log n)`
+/// deep in expectation whatever order the elements arrive in. Seed it with
+/// [`Self::with_seed`] to get the same shape on every run.
+#[derive(Clone)]
+pub struct Treap<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+    rng: XorShift,
+}
+
+impl<T> Treap<T>
+where
+    T: Ord,
+{
+    /// Creates an empty treap drawing priorities from a randomly seeded
+    /// generator
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            rng: XorShift::from_entropy(),
+        }
+    }
+
+    /// Creates an empty treap whose priorities are generated from `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            root: None,
+            rng: XorShift::with_seed(seed),
+        }
+    }
+
+    /// Returns the number of elements in the treap
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    /// Returns `true` if the treap contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        fn height<T>(link: &Link<T>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return Some(&current.data),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        if self.contains(&data) {
+            return false;
+        }
+
+        let (left, right) = split(self.root.take(), &mut |stored_data| *stored_data < data);
+        let node = Node::new(data, self.rng.next_u64());
+
+        self.root = merge(merge(left, Some(node)), right);
+        true
+    }
+
+    /// Removes `key` from the treap, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the treap and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (left, rest) = split(self.root.take(), &mut |stored_data: &T| {
+            stored_data.borrow() < key
+        });
+        // at most the one node equal to key
+        let (middle, right) = split(rest, &mut |stored_data: &T| stored_data.borrow() <= key);
+
+        self.root = merge(left, right);
+        middle.map(|node| node.data)
+    }
+
+    /// Moves every element greater than or equal to `key` into a new treap,
+    /// whose generator is seeded from this one's
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (left, right) = split(self.root.take(), &mut |stored_data: &T| {
+            stored_data.borrow() < key
+        });
+
+        self.root = left;
+        Self {
+            root: right,
+            rng: XorShift::with_seed(self.rng.next_u64()),
+        }
+    }
+
+    /// Moves all the elements of `other`, which must all be greater than the
+    /// elements of `self`, into `self`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the smallest element of `other` is not greater than the largest
+    /// element of `self`.
+    pub fn merge(&mut self, mut other: Self) {
+        if let (Some(max), Some(min)) = (self.max(), other.min()) {
+            assert!(max < min, "merged treaps must not overlap");
+        }
+
+        self.root = merge(self.root.take(), other.root.take());
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns a new iterator which iterates over this treap in order
+    pub fn iter(&self) -> TreapIterator<'_, T> {
+        let mut iter = TreapIterator {
+            stack: Vec::new(),
+            remaining: self.len(),
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the elements are in search order, the priorities in
+    /// heap order and every subtree size is correct
+    pub fn is_valid(&self) -> bool {
+        fn check<T: Ord>(
+            link: &Link<T>,
+            lower: Option<&T>,
+            upper: Option<&T>,
+            max_priority: u64,
+        ) -> Option<usize> {
+            let Some(node) = link else {
+                return Some(0);
+            };
+
+            if lower.is_some_and(|lower| node.data <= *lower)
+                || upper.is_some_and(|upper| node.data >= *upper)
+                || node.priority > max_priority
+            {
+                return None;
+            }
+
+            let left = check(&node.left, lower, Some(&node.data), node.priority)?;
+            let right = check(&node.right, Some(&node.data), upper, node.priority)?;
+
+            (node.size == 1 + left + right).then_some(node.size)
+        }
+
+        check(&self.root, None, None, u64::MAX).is_some()
+    }
+}
+
+impl<T> Default for Treap<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Treap<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T> PartialEq for Treap<T>
+where
+    T: Ord,
+{
+    /// Compares the elements only, whatever the priorities and shapes
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for Treap<T> where T: Ord {}
+
+impl<T> FromIterator<T> for Treap<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut treap = Self::new();
+        treap.extend(iter);
+        treap
+    }
+}
+
+impl<T> Extend<T> for Treap<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`Treap`] in order
+pub struct TreapIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> TreapIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for TreapIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for TreapIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a Treap<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = TreapIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::PREQUEL;
+
+    fn prequel_tree() -> Treap<u32> {
+        let mut treap = Treap::with_seed(42);
+        treap.extend(PREQUEL);
+        treap
+    }
+
+    /// Returns the element and priority of every node, in preorder
+    fn preorder(treap: &Treap<u32>) -> Vec<(u32, u64)> {
+        let mut nodes = Vec::new();
+        let mut stack: Vec<&Node<u32>> = treap.root.as_deref().into_iter().collect();
+
+        while let Some(node) = stack.pop() {
+            nodes.push((node.data, node.priority));
+            stack.extend(node.right.as_deref());
+            stack.extend(node.left.as_deref());
+        }
+        nodes
+    }
+
+    #[test]
+    fn test_priorities_keep_heap_order() {
+        let mut treap = prequel_tree();
+
+        // every new element draws the next priority from the seeded generator
+        let mut rng = XorShift::with_seed(42);
+        let mut expected: Vec<(u32, u64)> = PREQUEL.map(|data| (data, rng.next_u64())).into();
+        let sorted = |mut nodes: Vec<(u32, u64)>| {
+            nodes.sort_unstable();
+            nodes
+        };
+        assert_eq!(sorted(preorder(&treap)), sorted(expected.clone()));
+
+        // a rejected duplicate draws none
+        assert!(!treap.insert(21));
+        assert!(treap.insert(50));
+        expected.push((50, rng.next_u64()));
+        assert_eq!(sorted(preorder(&treap)), sorted(expected.clone()));
+
+        // removing the root lifts the highest remaining priority in its place,
+        // while every other element keeps its own
+        while let Some(&(root, priority)) = preorder(&treap).first() {
+            let highest = expected.iter().map(|&(_, priority)| priority).max();
+            assert_eq!(Some(priority), highest);
+
+            assert_eq!(treap.take(&root), Some(root));
+            assert!(!treap.remove(&root));
+            expected.retain(|&(data, _)| data != root);
+            assert_eq!(sorted(preorder(&treap)), sorted(expected.clone()));
+            assert!(treap.is_valid());
+        }
+        assert!(expected.is_empty());
+    }
+
+    #[test]
+    fn test_split_merge() {
+        let mut treap = prequel_tree();
+        let upper = treap.split_off(&21);
+
+        assert_eq!(treap.iter().collect::<Vec<_>>(), vec![&3, &7, &16]);
+        assert_eq!(upper.iter().collect::<Vec<_>>(), vec![&21, &28, &36, &70]);
+        assert!(treap.is_valid() && upper.is_valid());
+
+        treap.merge(upper);
+        assert_eq!(treap, prequel_tree());
+        assert!(treap.is_valid());
+
+        // the priorities fix the shape, so merging restores the same tree
+        assert_eq!(preorder(&treap), preorder(&prequel_tree()));
+    }
+
+    #[test]
+    fn test_seeded_shape() {
+        let mut first = Treap::with_seed(7);
+        let mut second = Treap::with_seed(7);
+        first.extend(0..2_000u32);
+        second.extend(0..2_000u32);
+
+        // sorted input, yet the depth stays logarithmic
+        assert!(first.height() < 60);
+        assert_eq!(first.height(), second.height());
+        assert!(first.is_valid());
+    }
+}
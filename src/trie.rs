@@ -0,0 +1,672 @@
+use crate::LoudsTrie;
+use std::{collections::VecDeque, fmt, mem};
+
+/// A map keyed by byte strings, sharing the nodes of common prefixes
+///
+/// Each node stands for one prefix of the stored keys and holds the value of
+/// the key ending there, if any, and the children for every byte the keys
+/// continue with, kept sorted by that byte. Lookups take `O(m log σ)` for a
+/// key of `m` bytes, whatever the number of keys, `σ` being the number of
+/// distinct bytes following a prefix.
+///
+/// Keys are anything that can be viewed as bytes, so `&str`, `String`,
+/// `&[u8]` and `Vec<u8>` all work and a string and its UTF-8 bytes are the
+/// same key.
+pub struct Trie<V> {
+    root: Node<V>,
+    len: usize,
+}
+
+struct Node<V> {
+    value: Option<V>,
+    /// the child for each byte following this prefix, sorted by byte
+    children: Vec<(u8, Node<V>)>,
+}
+
+impl<V> Node<V> {
+    fn new() -> Self {
+        Self {
+            value: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn child(&self, byte: u8) -> Option<&Node<V>> {
+        let position = self.position(byte).ok()?;
+        Some(&self.children[position].1)
+    }
+
+    fn child_mut(&mut self, byte: u8) -> Option<&mut Node<V>> {
+        let position = self.position(byte).ok()?;
+        Some(&mut self.children[position].1)
+    }
+
+    /// Returns the child for `byte`, adding an empty one if there is none
+    fn child_or_insert(&mut self, byte: u8) -> &mut Node<V> {
+        let position = match self.position(byte) {
+            Ok(position) => position,
+            Err(position) => {
+                self.children.insert(position, (byte, Node::new()));
+                position
+            }
+        };
+        &mut self.children[position].1
+    }
+
+    fn position(&self, byte: u8) -> Result<usize, usize> {
+        self.children
+            .binary_search_by_key(&byte, |&(child_byte, _)| child_byte)
+    }
+}
+
+impl<V> Trie<V> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the trie contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all keys
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Stores `value` under `key`, returning the value previously stored there
+    pub fn insert<K>(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut node = &mut self.root;
+        for &byte in key.as_ref() {
+            node = node.child_or_insert(byte);
+        }
+
+        let old = node.value.replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Returns `true` if a value is stored under `key`
+    pub fn contains_key<K>(&self, key: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get<K>(&self, key: K) -> Option<&V>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.find(key.as_ref())?.value.as_ref()
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut<K>(&mut self, key: K) -> Option<&mut V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut node = &mut self.root;
+        for &byte in key.as_ref() {
+            node = node.child_mut(byte)?;
+        }
+        node.value.as_mut()
+    }
+
+    /// Returns `true` if some stored key starts with `prefix`
+    ///
+    /// Every key starts with the empty prefix, so it is only contained when
+    /// the trie is not empty.
+    pub fn contains_prefix<K>(&self, prefix: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        // nodes without a value are only kept while some key goes through them
+        !self.is_empty() && self.find(prefix.as_ref()).is_some()
+    }
+
+    /// Removes `key` from the trie, returning the value stored under it
+    ///
+    /// The nodes that only led to `key` are removed with it.
+    pub fn remove<K>(&mut self, key: K) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+
+        // the nodes below the last one still needed by another key are pruned
+        let mut node = &self.root;
+        let mut keep = 0;
+        for (depth, &byte) in key.iter().enumerate() {
+            if node.value.is_some() || node.children.len() > 1 {
+                keep = depth;
+            }
+            node = node.child(byte)?;
+        }
+        node.value.as_ref()?;
+        let prune = node.children.is_empty();
+
+        let mut node = &mut self.root;
+        for &byte in &key[..keep] {
+            node = node.child_mut(byte).expect("checked above");
+        }
+
+        let value = if prune && keep < key.len() {
+            let position = node.position(key[keep]).expect("checked above");
+            let (_, mut branch) = node.children.remove(position);
+            let mut target = &mut branch;
+            for &byte in &key[keep + 1..] {
+                target = target.child_mut(byte).expect("checked above");
+            }
+            let value = target.value.take();
+            drop_iteratively(branch);
+            value
+        } else {
+            for &byte in &key[keep..] {
+                node = node.child_mut(byte).expect("checked above");
+            }
+            node.value.take()
+        };
+
+        self.len -= 1;
+        value
+    }
+
+    /// Returns an iterator over the entries in lexicographic order of their
+    /// keys
+    pub fn iter(&self) -> TrieIterator<'_, V> {
+        self.iter_prefix([])
+    }
+
+    /// Returns an iterator over the keys in lexicographic order
+    pub fn keys(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values in lexicographic order of their
+    /// keys
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`, in
+    /// lexicographic order of their keys
+    ///
+    /// The node for `prefix` is found once, then only the keys below it are
+    /// visited, a key coming before the keys it is a prefix of.
+    pub fn iter_prefix<K>(&self, prefix: K) -> TrieIterator<'_, V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let prefix = prefix.as_ref();
+
+        TrieIterator {
+            key: prefix.to_vec(),
+            stack: self
+                .find(prefix)
+                .map(|node| (prefix.len(), None, node))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Returns the first `limit` entries whose keys start with `prefix`, in
+    /// lexicographic order of their keys, as completions for `prefix`
+    pub fn suggest<K>(&self, prefix: K, limit: usize) -> Vec<(Vec<u8>, &V)>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.iter_prefix(prefix).take(limit).collect()
+    }
+
+    /// Returns the entries whose keys are at most `max_edits` insertions,
+    /// deletions or substitutions of a byte away from `word`, with that
+    /// Levenshtein distance, in lexicographic order of their keys
+    ///
+    /// Each node on the way down extends one row of the edit distance table
+    /// between its prefix and the prefixes of `word`, and a branch is left as
+    /// soon as every entry of its row exceeds `max_edits`, so only the part of
+    /// the trie near `word` is visited.
+    pub fn search_within<K>(&self, word: K, max_edits: usize) -> Vec<(Vec<u8>, &V, usize)>
+    where
+        K: AsRef<[u8]>,
+    {
+        let word = word.as_ref();
+        let mut found = Vec::new();
+        let mut key = Vec::new();
+        let mut stack = vec![(0, None, &self.root, (0..=word.len()).collect::<Vec<_>>())];
+
+        while let Some((depth, byte, node, row)) = stack.pop() {
+            key.truncate(depth);
+            key.extend(byte);
+
+            let distance = row[word.len()];
+            if distance <= max_edits {
+                if let Some(value) = &node.value {
+                    found.push((key.clone(), value, distance));
+                }
+            }
+
+            let depth = key.len();
+            for (byte, child) in node.children.iter().rev() {
+                let mut next = Vec::with_capacity(row.len());
+                next.push(row[0] + 1);
+                for (index, &expected) in word.iter().enumerate() {
+                    let substitution = row[index] + usize::from(expected != *byte);
+                    next.push(substitution.min(row[index + 1] + 1).min(next[index] + 1));
+                }
+
+                if next.iter().any(|&edits| edits <= max_edits) {
+                    stack.push((depth, Some(*byte), child, next));
+                }
+            }
+        }
+        found
+    }
+
+    /// Returns the node standing for `prefix`
+    fn find(&self, prefix: &[u8]) -> Option<&Node<V>> {
+        let mut node = &self.root;
+        for &byte in prefix {
+            node = node.child(byte)?;
+        }
+        Some(node)
+    }
+
+    /// Freezes the trie into a [`LoudsTrie`], a read-only copy taking a few
+    /// bits per node instead of several words
+    pub fn freeze(&self) -> LoudsTrie<V>
+    where
+        V: Clone,
+    {
+        LoudsTrie::from(self)
+    }
+
+    /// Calls `visit` with the number of the parent, the byte and the value of
+    /// every node but the root, in breadth-first order and by byte among
+    /// siblings, numbering the root 0 and the other nodes from 1 in the order
+    /// they are visited
+    pub(crate) fn for_each_breadth_first<F>(&self, mut visit: F)
+    where
+        F: FnMut(usize, u8, Option<&V>),
+    {
+        let mut queue = VecDeque::from([(0, &self.root)]);
+        let mut visited = 0;
+
+        while let Some((number, node)) = queue.pop_front() {
+            for (byte, child) in &node.children {
+                visited += 1;
+                visit(number, *byte, child.value.as_ref());
+                queue.push_back((visited, child));
+            }
+        }
+    }
+
+    /// Returns `true` if every node other than the root leads to a value,
+    /// the children of each node are sorted by distinct bytes and `len`
+    /// matches the number of values
+    pub fn is_valid(&self) -> bool {
+        let mut values = 0;
+        let mut stack = vec![(&self.root, true)];
+
+        while let Some((node, is_root)) = stack.pop() {
+            let sorted = node.children.windows(2).all(|pair| pair[0].0 < pair[1].0);
+            let leads_to_value = is_root || node.value.is_some() || !node.children.is_empty();
+            if !sorted || !leads_to_value {
+                return false;
+            }
+
+            values += usize::from(node.value.is_some());
+            stack.extend(node.children.iter().map(|(_, child)| (child, false)));
+        }
+        values == self.len
+    }
+}
+
+/// Releases the nodes under `node` one at a time, so that dropping very long
+/// keys can't overflow the stack
+fn drop_iteratively<V>(node: Node<V>) {
+    let mut stack = vec![node];
+
+    while let Some(mut node) = stack.pop() {
+        stack.extend(
+            mem::take(&mut node.children)
+                .into_iter()
+                .map(|(_, child)| child),
+        );
+    }
+}
+
+/// Copies the nodes under `node` one at a time, so that cloning very long keys
+/// can't overflow the stack
+fn clone_iteratively<V>(node: &Node<V>) -> Node<V>
+where
+    V: Clone,
+{
+    let mut stack = vec![(node, false)];
+    // the copies of the finished subtrees, siblings in the order of their bytes
+    let mut copies = Vec::new();
+
+    while let Some((node, children_done)) = stack.pop() {
+        if !children_done {
+            stack.push((node, true));
+            stack.extend(node.children.iter().rev().map(|(_, child)| (child, false)));
+            continue;
+        }
+
+        let children = copies.split_off(copies.len() - node.children.len());
+        copies.push(Node {
+            value: node.value.clone(),
+            children: node
+                .children
+                .iter()
+                .map(|&(byte, _)| byte)
+                .zip(children)
+                .collect(),
+        });
+    }
+    copies.pop().unwrap()
+}
+
+impl<V> Clone for Trie<V>
+where
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            root: clone_iteratively(&self.root),
+            len: self.len,
+        }
+    }
+}
+
+impl<V> Default for Trie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Drop for Trie<V> {
+    fn drop(&mut self) {
+        drop_iteratively(mem::replace(&mut self.root, Node::new()));
+    }
+}
+
+impl<V> fmt::Debug for Trie<V>
+where
+    V: fmt::Debug,
+{
+    /// Formats the entries in key order, like a map, showing the keys as
+    /// strings with invalid UTF-8 replaced
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter()
+                    .map(|(key, value)| (String::from_utf8_lossy(&key).into_owned(), value)),
+            )
+            .finish()
+    }
+}
+
+impl<V> PartialEq for Trie<V>
+where
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<V> Eq for Trie<V> where V: Eq {}
+
+impl<K, V> FromIterator<(K, V)> for Trie<V>
+where
+    K: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<K, V> Extend<(K, V)> for Trie<V>
+where
+    K: AsRef<[u8]>,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the entries of a [`Trie`] in lexicographic order of
+/// their keys, walking the nodes depth first
+pub struct TrieIterator<'a, V> {
+    /// key of the last node visited
+    key: Vec<u8>,
+    /// nodes left to visit, with the length of the key above them and the
+    /// byte leading to them, the next one on top
+    stack: Vec<(usize, Option<u8>, &'a Node<V>)>,
+}
+
+impl<'a, V> Iterator for TrieIterator<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (depth, byte, node) = self.stack.pop()?;
+            self.key.truncate(depth);
+            self.key.extend(byte);
+
+            let depth = self.key.len();
+            self.stack.extend(
+                node.children
+                    .iter()
+                    .rev()
+                    .map(|(byte, child)| (depth, Some(*byte), child)),
+            );
+
+            if let Some(value) = &node.value {
+                return Some((self.key.clone(), value));
+            }
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a Trie<V> {
+    type Item = (Vec<u8>, &'a V);
+    type IntoIter = TrieIterator<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel_words;
+
+    #[test]
+    fn test_insert_get() {
+        let mut trie: Trie<u32> = prequel_words();
+
+        assert_eq!(trie.len(), 7);
+        assert_eq!(trie.get("ten"), Some(&1));
+        assert_eq!(trie.get(b"in"), Some(&4));
+        assert_eq!(trie.get("te"), None);
+        assert_eq!(trie.get(""), None);
+        assert_eq!(trie.insert("ten", 10), Some(1));
+        assert_eq!(trie.insert(String::from(""), 99), None);
+        *trie.get_mut("to").unwrap() += 20;
+
+        assert_eq!(trie.len(), 8);
+        assert_eq!(trie.get(vec![b't', b'e', b'n']), Some(&10));
+        assert_eq!(trie.get(""), Some(&99));
+        assert_eq!(trie.get("to"), Some(&22));
+        assert!(trie.contains_key("team"));
+        assert!(!trie.contains_key("teams"));
+        assert!(trie.is_valid());
+    }
+
+    #[test]
+    fn test_contains_prefix() {
+        let trie: Trie<u32> = prequel_words();
+
+        assert!(trie.contains_prefix("te"));
+        assert!(trie.contains_prefix("team"));
+        assert!(trie.contains_prefix(""));
+        assert!(!trie.contains_prefix("teams"));
+        assert!(!trie.contains_prefix("a"));
+        assert!(!Trie::<u32>::new().contains_prefix(""));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut trie: Trie<u32> = prequel_words();
+
+        assert_eq!(trie.remove("team"), Some(6));
+        assert_eq!(trie.remove("team"), None);
+        assert!(!trie.contains_prefix("team"));
+        assert!(trie.contains_key("tea"));
+
+        assert_eq!(trie.remove("in"), Some(4));
+        assert!(trie.contains_key("inn") && trie.contains_key("i"));
+        assert_eq!(trie.remove("te"), None);
+        assert_eq!(trie.remove("inn"), Some(3));
+        assert!(!trie.contains_prefix("in"));
+        assert!(trie.is_valid());
+
+        for key in ["tea", "ten", "to", "i"] {
+            assert!(trie.remove(key).is_some());
+        }
+        assert!(trie.is_empty());
+        assert!(trie.root.children.is_empty());
+        assert!(trie.is_valid());
+
+        // a long key makes a deep chain of nodes
+        let long = vec![b'a'; 100_000];
+        trie.insert(&long, 1);
+        trie.insert(&long[..10], 2);
+        assert_eq!(trie.remove(&long), Some(1));
+        assert_eq!(trie.get(&long[..10]), Some(&2));
+        assert!(!trie.contains_prefix(&long[..11]));
+        trie.insert(&long, 1);
+    }
+
+    #[test]
+    fn test_clone() {
+        let trie: Trie<u32> = prequel_words();
+        let mut clone = trie.clone();
+        assert_eq!(clone, trie);
+        assert!(clone.is_valid());
+
+        clone.insert("tan", 7);
+        assert_eq!(clone.remove("tea"), Some(0));
+        assert_eq!(trie.get("tea"), Some(&0));
+        assert!(!trie.contains_key("tan"));
+
+        // a long key makes a deep chain of nodes
+        let mut trie = Trie::new();
+        let long = vec![b'a'; 1_000_000];
+        trie.insert(&long, 1);
+        trie.insert(&long[..10], 2);
+        trie.insert("b", 3);
+        let clone = trie.clone();
+        assert_eq!(clone.len(), 3);
+        assert_eq!(clone.get(&long), Some(&1));
+        assert_eq!(clone.get(&long[..10]), Some(&2));
+        assert_eq!(clone.get("b"), Some(&3));
+        assert!(clone.is_valid());
+    }
+
+    #[test]
+    fn test_iter_prefix() {
+        let trie: Trie<u32> = prequel_words();
+        let keys = |iter: TrieIterator<'_, u32>| {
+            iter.map(|(key, _)| String::from_utf8(key).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            keys(trie.iter()),
+            vec!["i", "in", "inn", "tea", "team", "ten", "to"]
+        );
+        assert_eq!(keys(trie.iter_prefix("te")), vec!["tea", "team", "ten"]);
+        assert_eq!(keys(trie.iter_prefix("tea")), vec!["tea", "team"]);
+        assert_eq!(keys(trie.iter_prefix(b"inn")), vec!["inn"]);
+        assert!(trie.iter_prefix("x").next().is_none());
+        assert!(trie.iter_prefix("teams").next().is_none());
+        assert_eq!(
+            trie.values().copied().collect::<Vec<_>>(),
+            vec![5, 4, 3, 0, 6, 1, 2]
+        );
+        assert_eq!(
+            format!("{:?}", trie.iter_prefix("t").collect::<Trie<_>>()),
+            r#"{"tea": 0, "team": 6, "ten": 1, "to": 2}"#
+        );
+        assert_eq!(trie.clone(), trie);
+    }
+
+    #[test]
+    fn test_search_within() {
+        let trie: Trie<u32> = prequel_words();
+        let search = |word: &str, max_edits| {
+            trie.search_within(word, max_edits)
+                .into_iter()
+                .map(|(key, _, distance)| (String::from_utf8(key).unwrap(), distance))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(search("ten", 0), vec![("ten".to_string(), 0)]);
+        assert_eq!(
+            search("tean", 1),
+            vec![
+                ("tea".to_string(), 1),
+                ("team".to_string(), 1),
+                ("ten".to_string(), 1)
+            ]
+        );
+        assert_eq!(search("on", 1), vec![("in".to_string(), 1)]);
+        assert_eq!(search("", 1), vec![("i".to_string(), 1)]);
+        assert_eq!(search("inn", 3).len(), 6);
+        assert!(search("xyz", 1).is_empty());
+    }
+
+    #[test]
+    fn test_suggest() {
+        let trie: Trie<usize> = ["car", "card", "care", "careful", "cart", "cat", ""]
+            .into_iter()
+            .map(|word| (word, word.len()))
+            .collect();
+
+        assert_eq!(
+            trie.suggest("car", 3),
+            vec![
+                (b"car".to_vec(), &3),
+                (b"card".to_vec(), &4),
+                (b"care".to_vec(), &4)
+            ]
+        );
+        assert_eq!(trie.suggest("care", 10).len(), 2);
+        assert_eq!(trie.suggest("", 2)[0], (Vec::new(), &0));
+        assert_eq!(trie.suggest("ca", 0), Vec::new());
+        assert_eq!(trie.suggest("dog", 5), Vec::new());
+    }
+}
@@ -0,0 +1,634 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    fmt, mem,
+};
+
+/// A 4-node holds three elements and has to split before anything is added
+const MAX_KEYS: usize = 3;
+
+/// A 2-node, 3-node or 4-node
+///
+/// Leaves have no children, any other node one child more than it has
+/// elements. All the leaves are on the same depth.
+#[derive(Clone)]
+struct Node<T> {
+    keys: Vec<T>,
+    children: Vec<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn leaf(keys: Vec<T>) -> Self {
+        Self {
+            keys,
+            children: Vec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn search<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.keys
+            .binary_search_by(|stored_key| stored_key.borrow().cmp(key))
+    }
+
+    /// Splits the full child at `index` around its middle element, which moves
+    /// up into this node
+    fn split_child(&mut self, index: usize) {
+        let child = &mut self.children[index];
+        let right = Node {
+            keys: child.keys.split_off(2),
+            children: if child.is_leaf() {
+                Vec::new()
+            } else {
+                child.children.split_off(2)
+            },
+        };
+        let middle = child.keys.pop().expect("a full child");
+
+        self.keys.insert(index, middle);
+        self.children.insert(index + 1, right);
+    }
+
+    /// Makes sure the child at `index` holds at least two elements before
+    /// descending into it, returning the index it ends up at
+    ///
+    /// Borrows through this node from a sibling with elements to spare, or
+    /// else merges the child with a sibling and the element separating them.
+    fn fill_child(&mut self, index: usize) -> usize {
+        if self.children[index].keys.len() > 1 {
+            return index;
+        }
+
+        if index > 0 && self.children[index - 1].keys.len() > 1 {
+            let left = &mut self.children[index - 1];
+            let key = left.keys.pop().expect("spare element");
+            let child = left.children.pop();
+
+            let separator = mem::replace(&mut self.keys[index - 1], key);
+            let target = &mut self.children[index];
+            target.keys.insert(0, separator);
+            target.children.splice(0..0, child);
+            index
+        } else if index + 1 < self.children.len() && self.children[index + 1].keys.len() > 1 {
+            let right = &mut self.children[index + 1];
+            let key = right.keys.remove(0);
+            let child = (!right.is_leaf()).then(|| right.children.remove(0));
+
+            let separator = mem::replace(&mut self.keys[index], key);
+            let target = &mut self.children[index];
+            target.keys.push(separator);
+            target.children.extend(child);
+            index
+        } else {
+            let left_index = if index > 0 { index - 1 } else { index };
+            self.merge_children(left_index);
+            left_index
+        }
+    }
+
+    /// Merges the 2-node children at `index` and `index + 1` with the element
+    /// between them into a 4-node
+    fn merge_children(&mut self, index: usize) {
+        let separator = self.keys.remove(index);
+        let right = self.children.remove(index + 1);
+        let left = &mut self.children[index];
+
+        left.keys.push(separator);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> T
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match (self.search(key), self.is_leaf()) {
+            (Ok(index), true) => self.keys.remove(index),
+            (Err(_), true) => unreachable!("the key is present"),
+            // replace with a neighbour from a child that can spare one
+            (Ok(index), false) if self.children[index].keys.len() > 1 => {
+                let predecessor = self.children[index].remove_max();
+                mem::replace(&mut self.keys[index], predecessor)
+            }
+            (Ok(index), false) if self.children[index + 1].keys.len() > 1 => {
+                let successor = self.children[index + 1].remove_min();
+                mem::replace(&mut self.keys[index], successor)
+            }
+            // or pull the element down into the merge of both children
+            (Ok(index), false) => {
+                self.merge_children(index);
+                self.children[index].remove(key)
+            }
+            (Err(index), false) => {
+                let index = self.fill_child(index);
+                self.children[index].remove(key)
+            }
+        }
+    }
+
+    fn remove_min(&mut self) -> T {
+        if self.is_leaf() {
+            return self.keys.remove(0);
+        }
+
+        let index = self.fill_child(0);
+        self.children[index].remove_min()
+    }
+
+    fn remove_max(&mut self) -> T {
+        if self.is_leaf() {
+            return self
+                .keys
+                .pop()
+                .expect("nodes below the root are never empty");
+        }
+
+        let index = self.fill_child(self.children.len() - 1);
+        self.children[index].remove_max()
+    }
+}
+
+/// A balanced search tree behaving like a set, whose nodes hold one to three
+/// elements
+///
+/// Both updates work in a single pass from the root: insertions split every
+/// 4-node they meet so that the leaf reached always has room, and removals
+/// grow every 2-node they meet so that the element finally removed never
+/// leaves a node empty.
+///
+/// A 2-3-4 tree is what a red-black tree encodes: a 2-node is a black node, a
+/// 3-node a black node with one red child and a 4-node a black node with two
+/// red children. Splitting a 4-node is the color flip, and the leaves being
+/// on the same depth is the equal black height of every path.
+#[derive(Clone)]
+pub struct TwoThreeFourTree<T>
+where
+    T: Ord,
+{
+    root: Option<Box<Node<T>>>,
+    len: usize,
+}
+
+impl<T> TwoThreeFourTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Returns the number of nodes on the path from the root to any leaf
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            height += 1;
+            node = current.children.first();
+        }
+        height
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref()?;
+
+        loop {
+            match node.search(key) {
+                Ok(index) => return Some(&node.keys[index]),
+                Err(index) => node = node.children.get(index)?,
+            }
+        }
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        let Some(root) = &mut self.root else {
+            self.root = Some(Box::new(Node::leaf(vec![data])));
+            self.len = 1;
+            return true;
+        };
+
+        // the tree grows at the root, keeping all leaves on the same depth
+        if root.keys.len() == MAX_KEYS {
+            let old_root = mem::replace(&mut **root, Node::leaf(Vec::new()));
+            root.children.push(old_root);
+            root.split_child(0);
+        }
+
+        let mut node = &mut **root;
+        loop {
+            let mut index = match node.search(&data) {
+                Ok(_) => return false,
+                Err(index) => index,
+            };
+
+            if node.is_leaf() {
+                node.keys.insert(index, data);
+                break;
+            }
+
+            if node.children[index].keys.len() == MAX_KEYS {
+                node.split_child(index);
+
+                // the middle element moved up next to the search position
+                match data.cmp(&node.keys[index]) {
+                    Less => {}
+                    Equal => return false,
+                    Greater => index += 1,
+                }
+            }
+            node = &mut node.children[index];
+        }
+
+        self.len += 1;
+        true
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // the top-down pass below merges nodes on the way, so it assumes that
+        // `key` is present
+        if !self.contains(key) {
+            return None;
+        }
+
+        let root = self.root.as_mut().expect("key is present");
+        let removed = root.remove(key);
+
+        // the tree shrinks at the root once its two children were merged
+        if root.keys.is_empty() {
+            self.root = root.children.pop().map(Box::new);
+        }
+
+        self.len -= 1;
+        Some(removed)
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(child) = node.children.first() {
+            node = child;
+        }
+        node.keys.first()
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(child) = node.children.last() {
+            node = child;
+        }
+        node.keys.last()
+    }
+
+    /// Returns the number of 2-nodes, 3-nodes and 4-nodes, in that order
+    pub fn node_counts(&self) -> [usize; 3] {
+        let mut counts = [0; 3];
+        let mut stack: Vec<&Node<T>> = self.root.as_deref().into_iter().collect();
+
+        while let Some(node) = stack.pop() {
+            counts[node.keys.len() - 1] += 1;
+            stack.extend(&node.children);
+        }
+        counts
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> TwoThreeFourTreeIterator<'_, T> {
+        let mut iter = TwoThreeFourTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        if let Some(root) = &self.root {
+            iter.stack_push_left(root);
+        }
+        iter
+    }
+
+    /// Returns `true` if the elements are in order and all the 2-3-4
+    /// invariants hold: every node has one to three elements, every inner
+    /// node one child more than elements, and all the leaves are on the same
+    /// depth
+    pub fn is_valid(&self) -> bool {
+        /// Returns the height of the subtree
+        fn check<T: Ord>(node: &Node<T>, lower: Option<&T>, upper: Option<&T>) -> Option<usize> {
+            let keys = &node.keys;
+
+            if !(1..=MAX_KEYS).contains(&keys.len())
+                || keys.windows(2).any(|pair| pair[0] >= pair[1])
+                || lower.is_some_and(|lower| keys[0] <= *lower)
+                || upper.is_some_and(|upper| keys[keys.len() - 1] >= *upper)
+            {
+                return None;
+            }
+
+            if node.is_leaf() {
+                return Some(1);
+            }
+            if node.children.len() != keys.len() + 1 {
+                return None;
+            }
+
+            let mut height = None;
+            for (index, child) in node.children.iter().enumerate() {
+                let lower = if index == 0 {
+                    lower
+                } else {
+                    Some(&keys[index - 1])
+                };
+                let upper = keys.get(index).or(upper);
+                let child_height = check(child, lower, upper)?;
+
+                if height.is_some_and(|height| height != child_height) {
+                    return None;
+                }
+                height = Some(child_height);
+            }
+            height.map(|height| height + 1)
+        }
+
+        let shape_ok = self
+            .root
+            .as_deref()
+            .is_none_or(|root| check(root, None, None).is_some());
+        shape_ok && self.iter().count() == self.len
+    }
+}
+
+impl<T> Default for TwoThreeFourTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for TwoThreeFourTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements, regardless of their shape
+impl<T> PartialEq for TwoThreeFourTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for TwoThreeFourTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for TwoThreeFourTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for TwoThreeFourTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`TwoThreeFourTree`] in
+/// order
+pub struct TwoThreeFourTreeIterator<'a, T> {
+    /// nodes on the path to the next element, with the index of their next
+    /// element
+    stack: Vec<(&'a Node<T>, usize)>,
+    remaining: usize,
+}
+
+impl<'a, T> TwoThreeFourTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: &'a Node<T>) {
+        loop {
+            self.stack.push((node, 0));
+
+            match node.children.first() {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+}
+
+impl<'a, T> Iterator for TwoThreeFourTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, index) = self.stack.pop()?;
+            let Some(key) = node.keys.get(index) else {
+                continue;
+            };
+
+            self.stack.push((node, index + 1));
+            if let Some(child) = node.children.get(index + 1) {
+                self.stack_push_left(child);
+            }
+
+            self.remaining -= 1;
+            return Some(key);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for TwoThreeFourTreeIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a TwoThreeFourTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = TwoThreeFourTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{prequel, shuffled};
+
+    #[test]
+    fn test_insert_search() {
+        let mut tree: TwoThreeFourTree<u32> = prequel();
+
+        assert_eq!(tree.len(), 7);
+        assert!(!tree.insert(21));
+        assert!(tree.insert(22));
+        assert_eq!(tree.get(&22), Some(&22));
+        assert_eq!((tree.min(), tree.max()), (Some(&3), Some(&70)));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![&3, &7, &16, &21, &22, &28, &36, &70]
+        );
+        assert!(tree.is_valid());
+
+        let [two, three, four] = tree.node_counts();
+        assert_eq!(two + 2 * three + 3 * four, tree.len());
+
+        let tree: TwoThreeFourTree<u32> = (0..1023).collect();
+        assert!(tree.is_valid());
+        assert!(tree.height() <= 10);
+    }
+
+    /// Returns the elements of every node, level by level
+    fn levels(tree: &TwoThreeFourTree<u32>) -> Vec<Vec<Vec<u32>>> {
+        let mut levels = Vec::new();
+        let mut level: Vec<&Node<u32>> = tree.root.as_deref().into_iter().collect();
+
+        while !level.is_empty() {
+            levels.push(level.iter().map(|node| node.keys.clone()).collect());
+            level = level.iter().flat_map(|node| &node.children).collect();
+        }
+        levels
+    }
+
+    #[test]
+    fn test_remove_borrows_from_siblings() {
+        // the 2-node on the way down takes an element through the parent
+        let mut tree: TwoThreeFourTree<u32> = [10, 20, 30, 15].into_iter().collect();
+        assert_eq!(
+            levels(&tree),
+            vec![vec![vec![20]], vec![vec![10, 15], vec![30]]]
+        );
+        assert!(tree.remove(&30));
+        assert_eq!(
+            levels(&tree),
+            vec![vec![vec![15]], vec![vec![10], vec![20]]]
+        );
+
+        let mut tree: TwoThreeFourTree<u32> = [10, 20, 30, 25].into_iter().collect();
+        assert_eq!(
+            levels(&tree),
+            vec![vec![vec![20]], vec![vec![10], vec![25, 30]]]
+        );
+        assert!(tree.remove(&10));
+        assert_eq!(
+            levels(&tree),
+            vec![vec![vec![25]], vec![vec![20], vec![30]]]
+        );
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_remove_merges_at_leaf_boundaries() {
+        // both leaves are 2-nodes, so they merge with the root element and
+        // the tree loses a level
+        let mut tree: TwoThreeFourTree<u32> = [10, 20, 30, 40].into_iter().collect();
+        assert!(tree.remove(&40));
+        assert_eq!(
+            levels(&tree),
+            vec![vec![vec![20]], vec![vec![10], vec![30]]]
+        );
+        assert!(tree.remove(&10));
+        assert_eq!(levels(&tree), vec![vec![vec![20, 30]]]);
+        assert_eq!(tree.height(), 1);
+
+        // an inner element is replaced by its predecessor or successor from a
+        // leaf that can spare one, or merged down otherwise
+        let mut tree: TwoThreeFourTree<u32> = [10, 20, 30, 15].into_iter().collect();
+        assert!(tree.remove(&20));
+        assert_eq!(
+            levels(&tree),
+            vec![vec![vec![15]], vec![vec![10], vec![30]]]
+        );
+        assert!(tree.remove(&15));
+        assert_eq!(levels(&tree), vec![vec![vec![10, 30]]]);
+
+        let mut tree: TwoThreeFourTree<u32> = [10, 20, 30, 25].into_iter().collect();
+        assert!(tree.remove(&20));
+        assert_eq!(
+            levels(&tree),
+            vec![vec![vec![25]], vec![vec![10], vec![30]]]
+        );
+
+        // removals from a deep tree empty it through merges at every level
+        let mut tree: TwoThreeFourTree<u64> = shuffled(1009).into_iter().collect();
+        for data in 0..1009 {
+            assert_eq!(tree.take(&data), Some(data));
+            assert!(tree.is_valid());
+        }
+        assert!(tree.is_empty());
+    }
+}
@@ -0,0 +1,652 @@
+use std::{borrow::Borrow, fmt, mem};
+
+/// A 3-node holds two entries, one more and it has to split
+const MAX_ENTRIES: usize = 2;
+
+/// A 2-node or a 3-node
+///
+/// Leaves have no children, any other node one child more than it has
+/// entries. All the leaves are on the same depth.
+#[derive(Clone)]
+struct Node<K, V> {
+    entries: Vec<(K, V)>,
+    children: Vec<Node<K, V>>,
+}
+
+impl<K, V> Node<K, V> {
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+/// What an insertion below a node reports back to it
+enum Insertion<K, V> {
+    /// The key was already present and its value replaced
+    Replaced(V),
+    /// The entry was added and the subtree keeps its height
+    Added,
+    /// The entry was added and a node overflowed, its middle entry has to move
+    /// up into the parent along with the new right sibling
+    Split((K, V), Node<K, V>),
+}
+
+/// An ordered map stored as a 2-3 tree
+///
+/// Every node holds one or two entries and every leaf is on the same depth,
+/// so the height is at most `log2(n + 1)`. Nodes grow and split on the way
+/// back up from an insertion and borrow from or merge with a sibling on the
+/// way back up from a removal, which is easy to check exhaustively with
+/// [`Self::is_valid`].
+#[derive(Clone)]
+pub struct TwoThreeTree<K, V>
+where
+    K: Ord,
+{
+    root: Option<Box<Node<K, V>>>,
+    len: usize,
+}
+
+impl<K, V> TwoThreeTree<K, V>
+where
+    K: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// Returns the number of entries in the map
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the map contains no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all entries
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Consumes the map, returning its entries in key order
+    pub fn into_sorted_vec(self) -> Vec<(K, V)> {
+        fn flatten<K, V>(node: Node<K, V>, out: &mut Vec<(K, V)>) {
+            let mut children = node.children.into_iter();
+
+            for entry in node.entries {
+                if let Some(child) = children.next() {
+                    flatten(child, out);
+                }
+                out.push(entry);
+            }
+            if let Some(child) = children.next() {
+                flatten(child, out);
+            }
+        }
+
+        let mut entries = Vec::with_capacity(self.len);
+        if let Some(root) = self.root {
+            flatten(*root, &mut entries);
+        }
+        entries
+    }
+
+    /// Returns the number of nodes on the path from the root to any leaf
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            height += 1;
+            node = current.children.first();
+        }
+        height
+    }
+
+    /// Returns `true` if the map contains a value for `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    /// Returns a reference to the value stored under `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Returns the stored key and value for `key`
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref()?;
+
+        loop {
+            match Self::search(node, key) {
+                Ok(index) => {
+                    let (key, value) = &node.entries[index];
+                    return Some((key, value));
+                }
+                Err(index) => node = node.children.get(index)?,
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the value stored under `key`
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref_mut()?;
+
+        loop {
+            match Self::search(node, key) {
+                Ok(index) => return Some(&mut node.entries[index].1),
+                Err(index) => node = node.children.get_mut(index)?,
+            }
+        }
+    }
+
+    /// Finds `key` among the entries of `node`, or the child to descend into
+    fn search<Q>(node: &Node<K, V>, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        node.entries
+            .binary_search_by(|(stored_key, _)| stored_key.borrow().cmp(key))
+    }
+
+    /// Inserts `value` under `key`, returning the value previously stored
+    /// under an equal key
+    ///
+    /// Like `BTreeMap`, the stored key is kept when its value is replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let Some(root) = &mut self.root else {
+            self.root = Some(Box::new(Node {
+                entries: vec![(key, value)],
+                children: Vec::new(),
+            }));
+            self.len = 1;
+            return None;
+        };
+
+        match Self::insert_into(root, key, value) {
+            Insertion::Replaced(old) => return Some(old),
+            Insertion::Added => {}
+            // the tree grows at the root, keeping all leaves on the same depth
+            Insertion::Split(middle, right) => {
+                let left = self.root.take().expect("split a root");
+                self.root = Some(Box::new(Node {
+                    entries: vec![middle],
+                    children: vec![*left, right],
+                }));
+            }
+        }
+
+        self.len += 1;
+        None
+    }
+
+    fn insert_into(node: &mut Node<K, V>, key: K, value: V) -> Insertion<K, V> {
+        let index = match Self::search(node, &key) {
+            Ok(index) => {
+                return Insertion::Replaced(mem::replace(&mut node.entries[index].1, value))
+            }
+            Err(index) => index,
+        };
+
+        if node.is_leaf() {
+            node.entries.insert(index, (key, value));
+        } else {
+            match Self::insert_into(&mut node.children[index], key, value) {
+                Insertion::Split(middle, right) => {
+                    node.entries.insert(index, middle);
+                    node.children.insert(index + 1, right);
+                }
+                unchanged => return unchanged,
+            }
+        }
+
+        if node.entries.len() <= MAX_ENTRIES {
+            return Insertion::Added;
+        }
+
+        // a 4-node splits into two 2-nodes around its middle entry
+        let right = Node {
+            entries: node.entries.split_off(2),
+            children: if node.is_leaf() {
+                Vec::new()
+            } else {
+                node.children.split_off(2)
+            },
+        };
+        let middle = node.entries.pop().expect("three entries");
+        Insertion::Split(middle, right)
+    }
+
+    /// Removes `key` from the map, returning the value stored under it
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes `key` from the map, returning the stored key and value
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let root = self.root.as_mut()?;
+        let removed = Self::remove_from(root, key)?;
+
+        // the tree shrinks at the root once it has lost its last entry
+        if root.entries.is_empty() {
+            self.root = root.children.pop().map(Box::new);
+        }
+
+        self.len -= 1;
+        Some(removed)
+    }
+
+    fn remove_from<Q>(node: &mut Node<K, V>, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let removed = match (Self::search(node, key), node.is_leaf()) {
+            (Ok(index), true) => return Some(node.entries.remove(index)),
+            (Err(_), true) => return None,
+            // replace with the in-order predecessor, the largest of the left
+            (Ok(index), false) => {
+                let predecessor = Self::remove_max(&mut node.children[index]);
+                let removed = mem::replace(&mut node.entries[index], predecessor);
+                Self::fix_underflow(node, index);
+                removed
+            }
+            (Err(index), false) => {
+                let removed = Self::remove_from(&mut node.children[index], key)?;
+                Self::fix_underflow(node, index);
+                removed
+            }
+        };
+        Some(removed)
+    }
+
+    /// Removes the largest entry under `node`
+    fn remove_max(node: &mut Node<K, V>) -> (K, V) {
+        if node.is_leaf() {
+            return node.entries.pop().expect("nodes are never empty");
+        }
+
+        let last = node.children.len() - 1;
+        let max = Self::remove_max(&mut node.children[last]);
+        Self::fix_underflow(node, last);
+        max
+    }
+
+    /// Refills the child at `index` if it lost its only entry, by rotating an
+    /// entry through the parent from a 3-node sibling or else by merging it
+    /// with a sibling and the entry separating them
+    fn fix_underflow(node: &mut Node<K, V>, index: usize) {
+        if !node.children[index].entries.is_empty() {
+            return;
+        }
+
+        if index > 0 && node.children[index - 1].entries.len() > 1 {
+            let left = &mut node.children[index - 1];
+            let entry = left.entries.pop().expect("3-node");
+            let child = left.children.pop();
+
+            let separator = mem::replace(&mut node.entries[index - 1], entry);
+            let underflowed = &mut node.children[index];
+            underflowed.entries.insert(0, separator);
+            underflowed.children.splice(0..0, child);
+        } else if index + 1 < node.children.len() && node.children[index + 1].entries.len() > 1 {
+            let right = &mut node.children[index + 1];
+            let entry = right.entries.remove(0);
+            let child = (!right.is_leaf()).then(|| right.children.remove(0));
+
+            let separator = mem::replace(&mut node.entries[index], entry);
+            let underflowed = &mut node.children[index];
+            underflowed.entries.push(separator);
+            underflowed.children.extend(child);
+        } else {
+            // both neighbours are 2-nodes, taking the separator down makes a
+            // 3-node out of one of them and the empty node
+            let left_index = if index > 0 { index - 1 } else { index };
+            let separator = node.entries.remove(left_index);
+            let right = node.children.remove(left_index + 1);
+            let left = &mut node.children[left_index];
+
+            left.entries.push(separator);
+            left.entries.extend(right.entries);
+            left.children.extend(right.children);
+        }
+    }
+
+    /// Returns the entry with the smallest key
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(child) = node.children.first() {
+            node = child;
+        }
+        node.entries.first().map(|(key, value)| (key, value))
+    }
+
+    /// Returns the entry with the largest key
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(child) = node.children.last() {
+            node = child;
+        }
+        node.entries.last().map(|(key, value)| (key, value))
+    }
+
+    /// Returns an iterator over the entries in key order
+    pub fn iter(&self) -> TwoThreeTreeIterator<'_, K, V> {
+        let mut iter = TwoThreeTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        if let Some(root) = &self.root {
+            iter.stack_push_left(root);
+        }
+        iter
+    }
+
+    /// Returns an iterator over the keys in order
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values in key order
+    pub fn values(&self) -> impl ExactSizeIterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns `true` if the keys are in order and all the 2-3 invariants
+    /// hold: every node has one or two entries, every inner node one child
+    /// more than entries, and all the leaves are on the same depth
+    pub fn is_valid(&self) -> bool {
+        /// Returns the height of the subtree
+        fn check<K: Ord, V>(
+            node: &Node<K, V>,
+            lower: Option<&K>,
+            upper: Option<&K>,
+        ) -> Option<usize> {
+            let keys: Vec<&K> = node.entries.iter().map(|(key, _)| key).collect();
+
+            if !(1..=MAX_ENTRIES).contains(&keys.len())
+                || keys.windows(2).any(|pair| pair[0] >= pair[1])
+                || lower.is_some_and(|lower| keys[0] <= lower)
+                || upper.is_some_and(|upper| keys[keys.len() - 1] >= upper)
+            {
+                return None;
+            }
+
+            if node.is_leaf() {
+                return Some(1);
+            }
+            if node.children.len() != keys.len() + 1 {
+                return None;
+            }
+
+            let mut height = None;
+            for (index, child) in node.children.iter().enumerate() {
+                let lower = if index == 0 {
+                    lower
+                } else {
+                    Some(keys[index - 1])
+                };
+                let upper = keys.get(index).copied().or(upper);
+                let child_height = check(child, lower, upper)?;
+
+                if height.is_some_and(|height| height != child_height) {
+                    return None;
+                }
+                height = Some(child_height);
+            }
+            height.map(|height| height + 1)
+        }
+
+        let shape_ok = self
+            .root
+            .as_deref()
+            .is_none_or(|root| check(root, None, None).is_some());
+        shape_ok && self.iter().count() == self.len
+    }
+}
+
+impl<K, V> Default for TwoThreeTree<K, V>
+where
+    K: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for TwoThreeTree<K, V>
+where
+    K: Ord + fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Two maps are equal when they hold equal entries, regardless of their shape
+impl<K, V> PartialEq for TwoThreeTree<K, V>
+where
+    K: Ord,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<K, V> Eq for TwoThreeTree<K, V>
+where
+    K: Ord,
+    V: Eq,
+{
+}
+
+impl<K, V> FromIterator<(K, V)> for TwoThreeTree<K, V>
+where
+    K: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V> Extend<(K, V)> for TwoThreeTree<K, V>
+where
+    K: Ord,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the entries of a [`TwoThreeTree`] in key order
+pub struct TwoThreeTreeIterator<'a, K, V> {
+    /// nodes on the path to the next entry, with the index of their next entry
+    stack: Vec<(&'a Node<K, V>, usize)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> TwoThreeTreeIterator<'a, K, V> {
+    fn stack_push_left(&mut self, mut node: &'a Node<K, V>) {
+        loop {
+            self.stack.push((node, 0));
+
+            match node.children.first() {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for TwoThreeTreeIterator<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, index) = self.stack.pop()?;
+            let Some((key, value)) = node.entries.get(index) else {
+                continue;
+            };
+
+            self.stack.push((node, index + 1));
+            if let Some(child) = node.children.get(index + 1) {
+                self.stack_push_left(child);
+            }
+
+            self.remaining -= 1;
+            return Some((key, value));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for TwoThreeTreeIterator<'_, K, V> {}
+
+impl<'a, K, V> IntoIterator for &'a TwoThreeTree<K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = TwoThreeTreeIterator<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel_entries;
+
+    /// Every ordering of `0..n`, in lexicographic order
+    fn permutations(n: u32) -> Vec<Vec<u32>> {
+        if n == 0 {
+            return vec![Vec::new()];
+        }
+
+        let mut all = Vec::new();
+        for first in 0..n {
+            for rest in permutations(n - 1) {
+                let mut permutation = vec![first];
+                permutation.extend(rest.into_iter().map(|key| key + u32::from(key >= first)));
+                all.push(permutation);
+            }
+        }
+        all
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut tree: TwoThreeTree<u32, u32> = prequel_entries();
+
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.height(), 2);
+        assert_eq!(tree.get(&21), Some(&210));
+        assert_eq!(tree.insert(21, 0), Some(210));
+        *tree.get_mut(&21).unwrap() += 5;
+        assert_eq!(tree.get_key_value(&21), Some((&21, &5)));
+        assert_eq!(tree.first_key_value(), Some((&3, &30)));
+        assert_eq!(tree.last_key_value(), Some((&70, &700)));
+
+        assert_eq!(tree.remove(&16), Some(160));
+        assert_eq!(tree.remove(&16), None);
+        assert_eq!(
+            tree.keys().copied().collect::<Vec<_>>(),
+            vec![3, 7, 21, 28, 36, 70]
+        );
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_every_insertion_and_removal_order() {
+        let orders = permutations(6);
+
+        for insertion in &orders {
+            let mut tree = TwoThreeTree::new();
+            for &key in insertion {
+                assert_eq!(tree.insert(key, key), None);
+                assert!(tree.is_valid(), "inserting {insertion:?}");
+            }
+
+            for (removed, &key) in insertion.iter().enumerate() {
+                assert_eq!(tree.remove(&key), Some(key));
+                assert!(tree.is_valid(), "inserting and removing {insertion:?}");
+                assert_eq!(tree.len(), 5 - removed);
+            }
+            assert!(tree.is_empty());
+        }
+
+        for removal in &orders {
+            let mut tree: TwoThreeTree<u32, ()> = (0..6).map(|key| (key, ())).collect();
+            for &key in removal {
+                assert!(tree.remove(&key).is_some());
+                assert!(tree.is_valid(), "removing {removal:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_large() {
+        let mut tree: TwoThreeTree<u32, u32> = (0..10_000).map(|key| (key, key)).collect();
+        assert!(tree.is_valid());
+        assert!(tree.height() <= 14);
+
+        for key in (0..10_000).step_by(2) {
+            assert_eq!(tree.remove(&key), Some(key));
+        }
+        assert!(tree.is_valid());
+        assert_eq!(tree.len(), 5_000);
+        assert!(tree.values().copied().eq((1..10_000).step_by(2)));
+
+        let entries = tree.into_sorted_vec();
+        assert_eq!(entries.len(), 5_000);
+        assert!(entries
+            .iter()
+            .all(|&(key, value)| key == value && key % 2 == 1));
+        assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(
+            prequel_entries::<TwoThreeTree<u32, u32>>().into_sorted_vec()[2],
+            (16, 160)
+        );
+    }
+}
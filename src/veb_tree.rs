@@ -0,0 +1,631 @@
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+/// An unsigned integer usable as a key of the integer-universe trees, which
+/// work on its bits
+pub trait IntegerKey: Copy + Ord {
+    /// Number of bits of the type, the size of its universe being `2^BITS`
+    const BITS: u32;
+
+    /// Widens the key losslessly
+    fn to_u64(self) -> u64;
+
+    /// Narrows a value known to fit in the type
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! impl_integer_key {
+    ($($int:ty),*) => {
+        $(
+            impl IntegerKey for $int {
+                const BITS: u32 = <$int>::BITS;
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_u64(value: u64) -> Self {
+                    value as $int
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_key!(u8, u16, u32, u64);
+
+/// A van Emde Boas structure over the keys `0..2^bits`
+///
+/// The minimum is kept out of the clusters, so inserting into an empty
+/// cluster, or removing its last key, recurses into a single level.
+#[derive(Clone)]
+struct Node {
+    bits: u32,
+    min: Option<u64>,
+    /// meaningless while `min` is `None`
+    max: u64,
+    /// indices of the non-empty clusters, over the high half of the bits
+    summary: Option<Box<Node>>,
+    /// clusters over the low half of the bits, only allocated when non-empty
+    clusters: HashMap<u64, Node>,
+}
+
+impl Node {
+    fn new(bits: u32) -> Self {
+        Self {
+            bits,
+            min: None,
+            max: 0,
+            summary: None,
+            clusters: HashMap::new(),
+        }
+    }
+
+    fn low_bits(&self) -> u32 {
+        self.bits / 2
+    }
+
+    /// Splits `key` into its cluster and its position inside the cluster
+    fn split(&self, key: u64) -> (u64, u64) {
+        let low_bits = self.low_bits();
+        (key >> low_bits, key & ((1 << low_bits) - 1))
+    }
+
+    fn join(&self, high: u64, low: u64) -> u64 {
+        (high << self.low_bits()) | low
+    }
+
+    fn contains(&self, key: u64) -> bool {
+        match self.min {
+            None => false,
+            Some(min) if key == min || key == self.max => true,
+            Some(_) if self.bits == 1 => false,
+            Some(_) => {
+                let (high, low) = self.split(key);
+                self.clusters
+                    .get(&high)
+                    .is_some_and(|cluster| cluster.contains(low))
+            }
+        }
+    }
+
+    /// Inserts `key`, which must not be stored yet
+    fn insert(&mut self, mut key: u64) {
+        let Some(min) = self.min else {
+            self.min = Some(key);
+            self.max = key;
+            return;
+        };
+
+        if key < min {
+            // the new key becomes the minimum, the old one goes down instead
+            self.min = Some(key);
+            key = min;
+        }
+
+        if self.bits > 1 {
+            let (high, low) = self.split(key);
+            let low_bits = self.low_bits();
+            let high_bits = self.bits - low_bits;
+            let cluster = self
+                .clusters
+                .entry(high)
+                .or_insert_with(|| Node::new(low_bits));
+
+            if cluster.min.is_none() {
+                self.summary
+                    .get_or_insert_with(|| Box::new(Node::new(high_bits)))
+                    .insert(high);
+            }
+            cluster.insert(low);
+        }
+
+        self.max = self.max.max(key);
+    }
+
+    /// Removes `key`, which must be stored
+    fn remove(&mut self, mut key: u64) {
+        let min = self.min.expect("removing from an empty node");
+
+        if min == self.max {
+            self.min = None;
+            return;
+        }
+
+        if self.bits == 1 {
+            // both keys of the universe are stored, the other one remains
+            let other = 1 - key;
+            self.min = Some(other);
+            self.max = other;
+            return;
+        }
+
+        let summary = self.summary.as_ref().expect("more than one key is stored");
+        if key == min {
+            // the smallest key of the first cluster is pulled up as the minimum
+            let high = summary.min.expect("more than one key is stored");
+            key = self.join(
+                high,
+                self.clusters[&high].min.expect("clusters are non-empty"),
+            );
+            self.min = Some(key);
+        }
+
+        let (high, low) = self.split(key);
+        let cluster = self.clusters.get_mut(&high).expect("key is stored");
+        cluster.remove(low);
+        let cluster_max = cluster.min.map(|_| cluster.max);
+
+        match cluster_max {
+            None => {
+                self.clusters.remove(&high);
+                let summary = self.summary.as_mut().expect("more than one key is stored");
+                summary.remove(high);
+
+                if key == self.max {
+                    self.max = match summary.min.map(|_| summary.max) {
+                        None => self.min.expect("the minimum remains"),
+                        Some(high) => self.join(high, self.clusters[&high].max),
+                    };
+                }
+            }
+            Some(cluster_max) if key == self.max => self.max = self.join(high, cluster_max),
+            Some(_) => {}
+        }
+    }
+
+    /// Returns the smallest stored key greater than `key`
+    fn successor(&self, key: u64) -> Option<u64> {
+        let min = self.min?;
+
+        if key < min {
+            return Some(min);
+        }
+        if key >= self.max {
+            return None;
+        }
+        if self.bits == 1 {
+            return Some(self.max);
+        }
+
+        let (high, low) = self.split(key);
+        if let Some(cluster) = self.clusters.get(&high) {
+            if low < cluster.max {
+                let low = cluster.successor(low).expect("below the cluster maximum");
+                return Some(self.join(high, low));
+            }
+        }
+
+        let high = self.summary.as_ref()?.successor(high)?;
+        Some(self.join(
+            high,
+            self.clusters[&high].min.expect("clusters are non-empty"),
+        ))
+    }
+
+    /// Returns the largest stored key less than `key`
+    fn predecessor(&self, key: u64) -> Option<u64> {
+        let min = self.min?;
+
+        if key > self.max {
+            return Some(self.max);
+        }
+        if key <= min {
+            return None;
+        }
+        if self.bits == 1 {
+            return Some(min);
+        }
+
+        let (high, low) = self.split(key);
+        if let Some(cluster) = self.clusters.get(&high) {
+            if cluster.min.is_some_and(|cluster_min| low > cluster_min) {
+                let low = cluster.predecessor(low).expect("above the cluster minimum");
+                return Some(self.join(high, low));
+            }
+        }
+
+        // the minimum lives outside the clusters
+        match self
+            .summary
+            .as_ref()
+            .and_then(|summary| summary.predecessor(high))
+        {
+            Some(high) => Some(self.join(high, self.clusters[&high].max)),
+            None => Some(min),
+        }
+    }
+
+    /// Returns the number of keys if the summary lists exactly the non-empty
+    /// clusters and every minimum and maximum is right
+    fn check(&self) -> Option<usize> {
+        let Some(min) = self.min else {
+            return (self.clusters.is_empty()
+                && self
+                    .summary
+                    .as_ref()
+                    .is_none_or(|summary| summary.min.is_none()))
+            .then_some(0);
+        };
+
+        if self.bits == 1 {
+            return (min <= self.max && self.max <= 1).then_some(1 + usize::from(min != self.max));
+        }
+
+        let mut count = 1;
+        let mut highs = Vec::new();
+        for (&high, cluster) in &self.clusters {
+            count += cluster.check().filter(|&count| count > 0)?;
+            highs.push(high);
+        }
+        highs.sort_unstable();
+
+        let summary_highs: Vec<u64> = match &self.summary {
+            None => Vec::new(),
+            Some(summary) => {
+                summary.check()?;
+                let mut highs = Vec::new();
+                let mut high = summary.min;
+                while let Some(current) = high {
+                    highs.push(current);
+                    high = summary.successor(current);
+                }
+                highs
+            }
+        };
+
+        let max = match highs.last() {
+            None => min,
+            Some(&high) => self.join(high, self.clusters[&high].max),
+        };
+        let first_ok = highs.first().is_none_or(|&high| {
+            min < self.join(high, self.clusters[&high].min.expect("checked above"))
+        });
+
+        (highs == summary_highs && max == self.max && first_ok).then_some(count)
+    }
+}
+
+/// A van Emde Boas tree, an ordered set of unsigned integers answering
+/// successor and predecessor queries in `O(log log U)` for a universe of `U`
+/// keys
+///
+/// Each level splits the bits of the keys in half: the high half picks a
+/// cluster, the low half is stored recursively inside it, and a summary tree
+/// records which clusters are in use. Clusters are allocated on demand in a
+/// hash map, so memory grows with the number of keys rather than with `U`,
+/// which makes even a full `u64` universe practical.
+#[derive(Clone)]
+pub struct VebTree<K>
+where
+    K: IntegerKey,
+{
+    root: Node,
+    len: usize,
+    key: PhantomData<K>,
+}
+
+impl<K> VebTree<K>
+where
+    K: IntegerKey,
+{
+    /// Creates an empty tree over every value of `K`
+    pub fn new() -> Self {
+        Self::with_universe_bits(K::BITS)
+    }
+
+    /// Creates an empty tree over the keys `0..2^bits`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is zero or wider than `K`.
+    pub fn with_universe_bits(bits: u32) -> Self {
+        assert!(
+            (1..=K::BITS).contains(&bits),
+            "universe must have 1 to {} bits, got {bits}",
+            K::BITS
+        );
+
+        Self {
+            root: Node::new(bits),
+            len: 0,
+            key: PhantomData,
+        }
+    }
+
+    /// Returns the number of bits of the keys in the universe
+    pub fn universe_bits(&self) -> u32 {
+        self.root.bits
+    }
+
+    /// Returns the number of keys in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all keys
+    pub fn clear(&mut self) {
+        self.root = Node::new(self.root.bits);
+        self.len = 0;
+    }
+
+    fn in_universe(&self, key: u64) -> bool {
+        self.root.bits == u64::BITS || key >> self.root.bits == 0
+    }
+
+    /// Returns `true` if `key` is stored
+    pub fn contains(&self, key: K) -> bool {
+        let key = key.to_u64();
+        self.in_universe(key) && self.root.contains(key)
+    }
+
+    /// Inserts `key`, returning `false` if it is already stored
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is outside the universe.
+    pub fn insert(&mut self, key: K) -> bool {
+        let key = key.to_u64();
+        assert!(
+            self.in_universe(key),
+            "key {key} is outside the universe of {} bits",
+            self.root.bits
+        );
+
+        if self.root.contains(key) {
+            return false;
+        }
+
+        self.root.insert(key);
+        self.len += 1;
+        true
+    }
+
+    /// Removes `key`, returning whether it was present
+    pub fn remove(&mut self, key: K) -> bool {
+        if !self.contains(key) {
+            return false;
+        }
+
+        self.root.remove(key.to_u64());
+        self.len -= 1;
+        true
+    }
+
+    /// Returns the smallest key
+    pub fn min(&self) -> Option<K> {
+        self.root.min.map(K::from_u64)
+    }
+
+    /// Returns the largest key
+    pub fn max(&self) -> Option<K> {
+        self.root.min.map(|_| K::from_u64(self.root.max))
+    }
+
+    /// Returns the smallest key strictly greater than `key`
+    pub fn successor(&self, key: K) -> Option<K> {
+        let key = key.to_u64();
+        if !self.in_universe(key) {
+            return None;
+        }
+
+        self.root.successor(key).map(K::from_u64)
+    }
+
+    /// Returns the largest key strictly less than `key`
+    pub fn predecessor(&self, key: K) -> Option<K> {
+        let key = key.to_u64();
+        if !self.in_universe(key) {
+            return self.max();
+        }
+
+        self.root.predecessor(key).map(K::from_u64)
+    }
+
+    /// Returns a new iterator over the keys in increasing order, stepping
+    /// from each key to its successor
+    pub fn iter(&self) -> VebTreeIterator<'_, K> {
+        VebTreeIterator {
+            tree: self,
+            next: self.min(),
+            remaining: self.len,
+        }
+    }
+
+    /// Returns `true` if every summary lists exactly the non-empty clusters of
+    /// its level and every stored minimum and maximum is right
+    pub fn is_valid(&self) -> bool {
+        self.root.check() == Some(self.len)
+    }
+}
+
+impl<K> Default for VebTree<K>
+where
+    K: IntegerKey,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> fmt::Debug for VebTree<K>
+where
+    K: IntegerKey + fmt::Debug,
+{
+    /// Formats the keys in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold the same keys, whatever their universes
+impl<K> PartialEq for VebTree<K>
+where
+    K: IntegerKey,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<K> Eq for VebTree<K> where K: IntegerKey {}
+
+impl<K> FromIterator<K> for VebTree<K>
+where
+    K: IntegerKey,
+{
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<K> Extend<K> for VebTree<K>
+where
+    K: IntegerKey,
+{
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+/// An iterator over the keys of a [`VebTree`] in increasing order
+pub struct VebTreeIterator<'a, K>
+where
+    K: IntegerKey,
+{
+    tree: &'a VebTree<K>,
+    next: Option<K>,
+    remaining: usize,
+}
+
+impl<K> Iterator for VebTreeIterator<'_, K>
+where
+    K: IntegerKey,
+{
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.next?;
+
+        self.next = self.tree.successor(key);
+        self.remaining -= 1;
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K> ExactSizeIterator for VebTreeIterator<'_, K> where K: IntegerKey {}
+
+impl<'a, K> IntoIterator for &'a VebTree<K>
+where
+    K: IntegerKey,
+{
+    type Item = K;
+    type IntoIter = VebTreeIterator<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+    use std::{
+        collections::BTreeSet,
+        ops::Bound::{Excluded, Unbounded},
+    };
+
+    #[test]
+    fn test_successor_predecessor() {
+        let mut tree: VebTree<u32> = prequel();
+
+        assert!(!tree.insert(21));
+        assert_eq!(tree.len(), 7);
+        assert!(tree.contains(36));
+        assert!(!tree.contains(37));
+        assert_eq!((tree.min(), tree.max()), (Some(3), Some(70)));
+        assert_eq!(tree.successor(16), Some(21));
+        assert_eq!(tree.successor(22), Some(28));
+        assert_eq!(tree.successor(70), None);
+        assert_eq!(tree.predecessor(3), None);
+        assert_eq!(tree.predecessor(4), Some(3));
+        assert_eq!(tree.predecessor(u32::MAX), Some(70));
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![3, 7, 16, 21, 28, 36, 70]
+        );
+        assert!(tree.is_valid());
+
+        assert!(tree.remove(3));
+        assert!(tree.remove(70));
+        assert!(!tree.remove(70));
+        assert_eq!((tree.min(), tree.max()), (Some(7), Some(36)));
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_small_universe() {
+        let mut tree = VebTree::<u8>::with_universe_bits(3);
+
+        assert!(!tree.contains(200));
+        assert_eq!(tree.predecessor(200), None);
+        tree.extend([5, 0, 7, 1]);
+        assert_eq!(tree.successor(1), Some(5));
+        assert_eq!(tree.predecessor(200), Some(7));
+        assert_eq!(tree.successor(200), None);
+
+        for key in [0, 7, 1, 5] {
+            assert!(tree.remove(key));
+            assert!(tree.is_valid());
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.min(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "key 8 is outside the universe of 3 bits")]
+    fn test_key_outside_universe() {
+        VebTree::<u8>::with_universe_bits(3).insert(8);
+    }
+
+    #[test]
+    fn test_matches_btree_set() {
+        let mut tree = VebTree::<u64>::new();
+        let mut set = BTreeSet::new();
+
+        // spread over the whole universe, with clusters shared by neighbours
+        let keys: Vec<u64> = (0..1009u64)
+            .map(|i| (i * 7919 % 1009).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (i % 3))
+            .collect();
+        for &key in &keys {
+            assert_eq!(tree.insert(key), set.insert(key));
+        }
+        assert!(tree.is_valid());
+        assert!(tree.iter().eq(set.iter().copied()));
+
+        for &key in keys.iter().step_by(2) {
+            assert_eq!(tree.remove(key), set.remove(&key));
+        }
+        assert!(tree.is_valid());
+        for &key in &keys {
+            assert_eq!(
+                tree.successor(key),
+                set.range((Excluded(key), Unbounded)).next().copied()
+            );
+            assert_eq!(tree.predecessor(key), set.range(..key).next_back().copied());
+        }
+    }
+}
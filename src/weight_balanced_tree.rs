@@ -0,0 +1,565 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    fmt, mem,
+};
+
+/// A subtree may weigh at most this many times its sibling
+const DELTA: usize = 3;
+
+/// Above this ratio between the inner and outer grandchildren, a rotation
+/// needs to be double
+const GAMMA: usize = 2;
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+    /// number of nodes in the subtree rooted here
+    size: usize,
+}
+
+impl<T> Node<T> {
+    fn update_size(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// Weights count the empty links, so that empty subtrees weigh one
+fn weight<T>(link: &Link<T>) -> usize {
+    size(link) + 1
+}
+
+/// A self-balancing binary search tree behaving like a set, balanced on
+/// subtree sizes instead of heights
+///
+/// The weight of a subtree, its size plus one, is never more than three times
+/// the weight of its sibling, which bounds the height by about `2.4 log2(n)`.
+/// Because the balance information is the subtree size itself,
+/// [`Self::select`] and [`Self::rank`] come for free in `O(log n)`.
+#[derive(Clone)]
+pub struct WeightBalancedTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+}
+
+impl<T> WeightBalancedTree<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        fn height<T>(link: &Link<T>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return Some(&current.data),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Returns the `k`-th smallest element, counting from zero
+    pub fn select(&self, k: usize) -> Option<&T> {
+        let mut k = k;
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            let left_size = size(&current.left);
+
+            match k.cmp(&left_size) {
+                Equal => return Some(&current.data),
+                Less => node = current.left.as_deref(),
+                Greater => {
+                    // skip the left subtree and the current node
+                    k -= left_size + 1;
+                    node = current.right.as_deref();
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the number of elements strictly less than `key`
+    ///
+    /// `key` does not need to be stored in the tree.
+    pub fn rank<Q>(&self, key: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut rank = 0;
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            match key.cmp(current.data.borrow()) {
+                Equal => return rank + size(&current.left),
+                Less => node = current.left.as_deref(),
+                Greater => {
+                    rank += size(&current.left) + 1;
+                    node = current.right.as_deref();
+                }
+            }
+        }
+        rank
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        let mut inserted = false;
+
+        self.root = Some(Self::insert_into(self.root.take(), data, &mut inserted));
+        inserted
+    }
+
+    fn insert_into(link: Link<T>, data: T, inserted: &mut bool) -> Box<Node<T>> {
+        let Some(mut node) = link else {
+            *inserted = true;
+            return Box::new(Node {
+                data,
+                left: None,
+                right: None,
+                size: 1,
+            });
+        };
+
+        match data.cmp(&node.data) {
+            Equal => return node,
+            Less => node.left = Some(Self::insert_into(node.left.take(), data, inserted)),
+            Greater => node.right = Some(Self::insert_into(node.right.take(), data, inserted)),
+        }
+
+        Self::balance(node)
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // leaves the path untouched instead of rebalancing it for nothing
+        if !self.contains(key) {
+            return None;
+        }
+
+        let mut removed = None;
+        let root = self.root.take().expect("key is present");
+        self.root = Self::take_from(root, key, &mut removed);
+        removed
+    }
+
+    fn take_from<Q>(mut node: Box<Node<T>>, key: &Q, removed: &mut Option<T>) -> Link<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match key.cmp(node.data.borrow()) {
+            Less => {
+                let left = node.left.take().expect("key is in the left subtree");
+                node.left = Self::take_from(left, key, removed);
+            }
+            Greater => {
+                let right = node.right.take().expect("key is in the right subtree");
+                node.right = Self::take_from(right, key, removed);
+            }
+            Equal => match (node.left.take(), node.right.take()) {
+                (None, child) | (child, None) => {
+                    *removed = Some(node.data);
+                    return child;
+                }
+                // replace with the neighbour taken from the heavier side
+                (Some(left), Some(right)) => {
+                    let replacement = if left.size > right.size {
+                        let (left, max) = Self::take_max(left);
+                        node.left = left;
+                        node.right = Some(right);
+                        max
+                    } else {
+                        let (right, min) = Self::take_min(right);
+                        node.left = Some(left);
+                        node.right = right;
+                        min
+                    };
+                    *removed = Some(mem::replace(&mut node.data, replacement));
+                }
+            },
+        }
+
+        Some(Self::balance(node))
+    }
+
+    /// Removes the smallest element under `node`, returning what is left of
+    /// the subtree along with the element
+    fn take_min(mut node: Box<Node<T>>) -> (Link<T>, T) {
+        let Some(left) = node.left.take() else {
+            return (node.right.take(), node.data);
+        };
+
+        let (left, min) = Self::take_min(left);
+        node.left = left;
+
+        (Some(Self::balance(node)), min)
+    }
+
+    /// Removes the largest element under `node`, returning what is left of
+    /// the subtree along with the element
+    fn take_max(mut node: Box<Node<T>>) -> (Link<T>, T) {
+        let Some(right) = node.right.take() else {
+            return (node.left.take(), node.data);
+        };
+
+        let (right, max) = Self::take_max(right);
+        node.right = right;
+
+        (Some(Self::balance(node)), max)
+    }
+
+    /// Restores the weight balance of a node whose subtrees changed by at
+    /// most one element, and updates its size
+    fn balance(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        let left_weight = weight(&node.left);
+        let right_weight = weight(&node.right);
+
+        if right_weight > DELTA * left_weight {
+            let right = node.right.as_ref().expect("heavier than an empty link");
+            if weight(&right.left) >= GAMMA * weight(&right.right) {
+                let right = node.right.take().expect("checked above");
+                node.right = Some(Self::rotate_right(right));
+            }
+            Self::rotate_left(node)
+        } else if left_weight > DELTA * right_weight {
+            let left = node.left.as_ref().expect("heavier than an empty link");
+            if weight(&left.right) >= GAMMA * weight(&left.left) {
+                let left = node.left.take().expect("checked above");
+                node.left = Some(Self::rotate_left(left));
+            }
+            Self::rotate_right(node)
+        } else {
+            node.update_size();
+            node
+        }
+    }
+
+    fn rotate_left(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        let mut right = node.right.take().expect("rotated node has a right child");
+
+        node.right = right.left.take();
+        node.update_size();
+        right.left = Some(node);
+        right.update_size();
+        right
+    }
+
+    fn rotate_right(mut node: Box<Node<T>>) -> Box<Node<T>> {
+        let mut left = node.left.take().expect("rotated node has a left child");
+
+        node.left = left.right.take();
+        node.update_size();
+        left.right = Some(node);
+        left.update_size();
+        left
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> WeightBalancedTreeIterator<'_, T> {
+        let mut iter = WeightBalancedTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len(),
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the elements are in order, every subtree size is
+    /// correct and no subtree weighs more than three times its sibling
+    pub fn is_valid(&self) -> bool {
+        fn check<T: Ord>(link: &Link<T>, lower: Option<&T>, upper: Option<&T>) -> Option<usize> {
+            let Some(node) = link else {
+                return Some(0);
+            };
+
+            if lower.is_some_and(|lower| node.data <= *lower)
+                || upper.is_some_and(|upper| node.data >= *upper)
+            {
+                return None;
+            }
+
+            let left = check(&node.left, lower, Some(&node.data))?;
+            let right = check(&node.right, Some(&node.data), upper)?;
+            let (left_weight, right_weight) = (left + 1, right + 1);
+            let balanced =
+                right_weight <= DELTA * left_weight && left_weight <= DELTA * right_weight;
+
+            (balanced && node.size == 1 + left + right).then_some(node.size)
+        }
+
+        check(&self.root, None, None).is_some()
+    }
+}
+
+impl<T> Default for WeightBalancedTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for WeightBalancedTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements, regardless of their shape
+impl<T> PartialEq for WeightBalancedTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for WeightBalancedTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for WeightBalancedTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for WeightBalancedTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`WeightBalancedTree`] in
+/// order
+pub struct WeightBalancedTreeIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> WeightBalancedTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for WeightBalancedTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for WeightBalancedTreeIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a WeightBalancedTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = WeightBalancedTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+
+    #[test]
+    fn test_select_rank() {
+        let tree: WeightBalancedTree<u32> = prequel();
+
+        assert_eq!(tree.select(0), Some(&3));
+        assert_eq!(tree.select(4), Some(&28));
+        assert_eq!(tree.select(7), None);
+        assert_eq!(tree.rank(&28), 4);
+        assert_eq!(tree.rank(&29), 5);
+        assert_eq!(tree.rank(&0), 0);
+        assert!(tree.is_valid());
+
+        let tree: WeightBalancedTree<u32> = (0..1000).collect();
+        assert!(tree.is_valid());
+        assert!((0..1000).all(|k| tree.select(k) == Some(&(k as u32))));
+        assert!(tree.height() <= 27);
+    }
+
+    /// Returns the elements in preorder, which shows the shape of the tree
+    fn preorder(tree: &WeightBalancedTree<u32>) -> Vec<u32> {
+        let mut elements = Vec::new();
+        let mut stack = vec![&tree.root];
+
+        while let Some(link) = stack.pop() {
+            if let Some(node) = link {
+                elements.push(node.data);
+                stack.push(&node.right);
+                stack.push(&node.left);
+            }
+        }
+        elements
+    }
+
+    #[test]
+    fn test_balance_bounds() {
+        // a subtree may weigh exactly three times its sibling
+        let mut tree: WeightBalancedTree<u32> = [1, 2, 3].into_iter().collect();
+        assert_eq!(preorder(&tree), vec![1, 2, 3]);
+        assert_eq!(tree.height(), 3);
+
+        // one more is a single rotation, the outer grandchild being heavier
+        tree.insert(4);
+        assert_eq!(preorder(&tree), vec![2, 1, 3, 4]);
+
+        // and a double one when the inner grandchild weighs at least twice
+        // as much as the outer one
+        let tree: WeightBalancedTree<u32> = [10, 30, 20, 25].into_iter().collect();
+        assert_eq!(preorder(&tree), vec![20, 10, 30, 25]);
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn test_skewed_updates_stay_balanced() {
+        let n = 1000;
+        let ascending: Vec<u32> = (0..n).collect();
+        let descending: Vec<u32> = (0..n).rev().collect();
+        // alternating between both ends
+        let zigzag: Vec<u32> = (0..n / 2).flat_map(|i| [i, n - 1 - i]).collect();
+
+        for order in [ascending, descending, zigzag] {
+            let mut tree: WeightBalancedTree<u32> = order.into_iter().collect();
+            assert!(tree.is_valid());
+            assert!(tree.height() <= 24);
+
+            // emptying one side leans the whole tree against the other
+            for data in 0..n / 2 {
+                assert!(tree.remove(&data));
+                if data % 25 == 0 {
+                    assert!(tree.is_valid());
+                }
+            }
+            assert!(tree.is_valid());
+            assert_eq!(tree.select(0), Some(&(n / 2)));
+            assert_eq!(tree.rank(&(n - 1)), (n / 2 - 1) as usize);
+            assert!(tree.height() <= 22);
+        }
+    }
+}
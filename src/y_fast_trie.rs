@@ -0,0 +1,615 @@
+use crate::{
+    order_statistic_tree::OrderStatisticTreeIterator, veb_tree::IntegerKey, OrderStatisticTree,
+};
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+/// A group of consecutive keys, those from its representative up to the
+/// representative of the next bucket
+#[derive(Clone)]
+struct Bucket {
+    keys: OrderStatisticTree<u64>,
+    /// representatives of the neighbouring buckets
+    prev: Option<u64>,
+    next: Option<u64>,
+}
+
+/// A Y-fast trie, an ordered set of unsigned integers answering successor
+/// and predecessor queries in `O(log log U)` for a universe of `U` keys
+///
+/// The keys are split into buckets of about `log U` consecutive keys, each an
+/// [`OrderStatisticTree`]. Only one representative per bucket, its lower
+/// bound, is stored in an X-fast trie: a hash map per prefix length holding
+/// every prefix of a representative, along with the smallest and largest
+/// representative below it. A binary search over the prefix lengths finds the
+/// bucket of a key, and the bucket, small as it is, is searched in
+/// `O(log log U)`. Unlike a [`crate::VebTree`], memory stays linear in the
+/// number of keys too.
+#[derive(Clone)]
+pub struct YFastTrie<K>
+where
+    K: IntegerKey,
+{
+    bits: u32,
+    /// `levels[l]` maps each `l`-bit prefix of a representative to the
+    /// smallest and the largest representative sharing it
+    levels: Vec<HashMap<u64, (u64, u64)>>,
+    /// buckets by representative, the first one always being `0`
+    buckets: HashMap<u64, Bucket>,
+    len: usize,
+    key: PhantomData<K>,
+}
+
+impl<K> YFastTrie<K>
+where
+    K: IntegerKey,
+{
+    /// Creates an empty trie over every value of `K`
+    pub fn new() -> Self {
+        Self::with_universe_bits(K::BITS)
+    }
+
+    /// Creates an empty trie over the keys `0..2^bits`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is zero or wider than `K`.
+    pub fn with_universe_bits(bits: u32) -> Self {
+        assert!(
+            (1..=K::BITS).contains(&bits),
+            "universe must have 1 to {} bits, got {bits}",
+            K::BITS
+        );
+
+        Self {
+            bits,
+            levels: (0..=bits).map(|_| HashMap::new()).collect(),
+            buckets: HashMap::new(),
+            len: 0,
+            key: PhantomData,
+        }
+    }
+
+    /// Returns the number of bits of the keys in the universe
+    pub fn universe_bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Returns the number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the trie contains no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all keys
+    pub fn clear(&mut self) {
+        self.levels.iter_mut().for_each(HashMap::clear);
+        self.buckets.clear();
+        self.len = 0;
+    }
+
+    fn in_universe(&self, key: u64) -> bool {
+        self.bits == u64::BITS || key >> self.bits == 0
+    }
+
+    /// Returns the first `length` bits of `key`
+    fn prefix(&self, key: u64, length: u32) -> u64 {
+        key.checked_shr(self.bits - length).unwrap_or(0)
+    }
+
+    /// Returns the largest representative not greater than `key`, the trie
+    /// being non-empty
+    fn bucket_of(&self, key: u64) -> u64 {
+        if self.buckets.contains_key(&key) {
+            return key;
+        }
+
+        // the longest prefix of `key` shared with a representative
+        let (mut shared, mut unshared) = (0, self.bits);
+        while unshared - shared > 1 {
+            let middle = shared + (unshared - shared) / 2;
+            if self.levels[middle as usize].contains_key(&self.prefix(key, middle)) {
+                shared = middle;
+            } else {
+                unshared = middle;
+            }
+        }
+
+        let (min, max) = self.levels[shared as usize][&self.prefix(key, shared)];
+        let next_bit = (key >> (self.bits - shared - 1)) & 1;
+
+        // only the other child exists below the shared prefix
+        if next_bit == 1 {
+            max
+        } else {
+            self.buckets[&min]
+                .prev
+                .expect("the first representative is 0, covering every key")
+        }
+    }
+
+    /// Stores an empty bucket for `representative`, which must not be stored
+    fn insert_bucket(&mut self, representative: u64) {
+        let prev = (!self.buckets.is_empty()).then(|| self.bucket_of(representative));
+        let next = prev.and_then(|prev| self.buckets[&prev].next);
+
+        if let Some(prev) = prev {
+            self.buckets.get_mut(&prev).expect("linked bucket").next = Some(representative);
+        }
+        if let Some(next) = next {
+            self.buckets.get_mut(&next).expect("linked bucket").prev = Some(representative);
+        }
+        self.buckets.insert(
+            representative,
+            Bucket {
+                keys: OrderStatisticTree::new(),
+                prev,
+                next,
+            },
+        );
+
+        for length in 0..=self.bits {
+            let prefix = self.prefix(representative, length);
+            let (min, max) = self.levels[length as usize]
+                .entry(prefix)
+                .or_insert((representative, representative));
+
+            *min = (*min).min(representative);
+            *max = (*max).max(representative);
+        }
+    }
+
+    /// Unlinks the bucket of `representative`, returning its keys
+    fn remove_bucket(&mut self, representative: u64) -> OrderStatisticTree<u64> {
+        let bucket = self.buckets.remove(&representative).expect("bucket exists");
+
+        if let Some(prev) = bucket.prev {
+            self.buckets.get_mut(&prev).expect("linked bucket").next = bucket.next;
+        }
+        if let Some(next) = bucket.next {
+            self.buckets.get_mut(&next).expect("linked bucket").prev = bucket.prev;
+        }
+
+        // bottom-up, every prefix is recomputed from its two children
+        self.levels[self.bits as usize].remove(&representative);
+        for length in (0..self.bits).rev() {
+            let prefix = self.prefix(representative, length);
+            let children = &self.levels[length as usize + 1];
+            let left = children.get(&(prefix << 1)).copied();
+            let right = children.get(&(prefix << 1 | 1)).copied();
+
+            let level = &mut self.levels[length as usize];
+            match (left, right) {
+                (None, None) => level.remove(&prefix),
+                (Some((min, max)), None) | (None, Some((min, max))) => {
+                    level.insert(prefix, (min, max))
+                }
+                (Some((min, _)), Some((_, max))) => level.insert(prefix, (min, max)),
+            };
+        }
+
+        bucket.keys
+    }
+
+    /// Most keys a bucket holds before it is split in two
+    fn max_bucket_len(&self) -> usize {
+        2 * self.bits as usize
+    }
+
+    /// Fewest keys a bucket holds before it is merged with a neighbour
+    fn min_bucket_len(&self) -> usize {
+        (self.bits as usize / 2).max(1)
+    }
+
+    /// Moves the upper half of an overfull bucket into a new bucket
+    fn split_bucket(&mut self, representative: u64) {
+        let keys = &mut self
+            .buckets
+            .get_mut(&representative)
+            .expect("bucket exists")
+            .keys;
+        if keys.len() <= 2 * self.bits as usize {
+            return;
+        }
+
+        let half = keys.len() / 2;
+        let upper: Vec<u64> = (half..keys.len())
+            .map(|_| keys.remove_kth(half).expect("within the bucket"))
+            .collect();
+
+        self.insert_bucket(upper[0]);
+        let bucket = self.buckets.get_mut(&upper[0]).expect("just inserted");
+        bucket.keys.extend(upper);
+    }
+
+    /// Returns `true` if `key` is stored
+    pub fn contains(&self, key: K) -> bool {
+        let key = key.to_u64();
+        !self.is_empty()
+            && self.in_universe(key)
+            && self.buckets[&self.bucket_of(key)].keys.contains(&key)
+    }
+
+    /// Inserts `key`, returning `false` if it is already stored
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is outside the universe.
+    pub fn insert(&mut self, key: K) -> bool {
+        let key = key.to_u64();
+        assert!(
+            self.in_universe(key),
+            "key {key} is outside the universe of {} bits",
+            self.bits
+        );
+
+        if self.is_empty() {
+            self.insert_bucket(0);
+        }
+
+        let representative = self.bucket_of(key);
+        let bucket = self
+            .buckets
+            .get_mut(&representative)
+            .expect("bucket exists");
+        if !bucket.keys.insert(key) {
+            return false;
+        }
+
+        self.len += 1;
+        if bucket.keys.len() > self.max_bucket_len() {
+            self.split_bucket(representative);
+        }
+        true
+    }
+
+    /// Removes `key`, returning whether it was present
+    pub fn remove(&mut self, key: K) -> bool {
+        if !self.contains(key) {
+            return false;
+        }
+
+        let key = key.to_u64();
+        let representative = self.bucket_of(key);
+        let bucket = self
+            .buckets
+            .get_mut(&representative)
+            .expect("bucket exists");
+        bucket.keys.remove(&key);
+        self.len -= 1;
+
+        let (bucket_len, prev, next) = (bucket.keys.len(), bucket.prev, bucket.next);
+        if bucket_len >= self.min_bucket_len() {
+            return true;
+        }
+
+        // an underfull bucket absorbs its successor, or is absorbed by its
+        // predecessor, keeping `0` as the first representative
+        let (kept, merged) = match (prev, next) {
+            (_, Some(next)) => (representative, next),
+            (Some(prev), None) => (prev, representative),
+            (None, None) => {
+                if self.is_empty() {
+                    self.remove_bucket(representative);
+                }
+                return true;
+            }
+        };
+
+        let merged_keys = self.remove_bucket(merged);
+        let kept_keys = &mut self.buckets.get_mut(&kept).expect("bucket exists").keys;
+        kept_keys.extend(merged_keys.iter().copied());
+        self.split_bucket(kept);
+        true
+    }
+
+    /// Returns the smallest key
+    pub fn min(&self) -> Option<K> {
+        let bucket = self.buckets.get(&0)?;
+        bucket.keys.min().copied().map(K::from_u64)
+    }
+
+    /// Returns the largest key
+    pub fn max(&self) -> Option<K> {
+        let (_, last) = self.levels[0].get(&0)?;
+        self.buckets[last].keys.max().copied().map(K::from_u64)
+    }
+
+    /// Returns the smallest key strictly greater than `key`
+    pub fn successor(&self, key: K) -> Option<K> {
+        let key = key.to_u64();
+        if self.is_empty() || !self.in_universe(key) {
+            return None;
+        }
+
+        let bucket = &self.buckets[&self.bucket_of(key)];
+        let rank = bucket.keys.rank(&key) + usize::from(bucket.keys.contains(&key));
+        let successor = match bucket.keys.select(rank) {
+            Some(&successor) => Some(successor),
+            None => bucket
+                .next
+                .and_then(|next| self.buckets[&next].keys.min().copied()),
+        };
+        successor.map(K::from_u64)
+    }
+
+    /// Returns the largest key strictly less than `key`
+    pub fn predecessor(&self, key: K) -> Option<K> {
+        let key = key.to_u64();
+        if self.is_empty() || !self.in_universe(key) {
+            return self.max();
+        }
+
+        let bucket = &self.buckets[&self.bucket_of(key)];
+        let predecessor = match bucket.keys.rank(&key).checked_sub(1) {
+            Some(rank) => bucket.keys.select(rank).copied(),
+            None => bucket
+                .prev
+                .and_then(|prev| self.buckets[&prev].keys.max().copied()),
+        };
+        predecessor.map(K::from_u64)
+    }
+
+    /// Returns a new iterator over the keys in increasing order
+    pub fn iter(&self) -> YFastTrieIterator<'_, K> {
+        let bucket = self.buckets.get(&0);
+
+        YFastTrieIterator {
+            trie: self,
+            keys: bucket.map(|bucket| bucket.keys.iter()),
+            next: bucket.and_then(|bucket| bucket.next),
+            remaining: self.len,
+        }
+    }
+
+    /// Returns `true` if the buckets are linked in order, hold only keys of
+    /// their range, have sizes within bounds, and every prefix is stored with
+    /// the right smallest and largest representative
+    pub fn is_valid(&self) -> bool {
+        let mut representatives = Vec::new();
+        let mut current = self.buckets.get(&0).map(|_| 0);
+        let mut prev = None;
+
+        while let Some(representative) = current {
+            let Some(bucket) = self.buckets.get(&representative) else {
+                return false;
+            };
+            if bucket.prev != prev {
+                return false;
+            }
+
+            representatives.push(representative);
+            prev = current;
+            current = bucket.next;
+        }
+
+        let single = representatives.len() == 1;
+        let buckets_ok = representatives
+            .iter()
+            .enumerate()
+            .all(|(i, &representative)| {
+                let keys = &self.buckets[&representative].keys;
+                let upper = representatives.get(i + 1);
+                let size_ok = keys.len() <= self.max_bucket_len()
+                    && (single || keys.len() >= self.min_bucket_len());
+
+                keys.is_valid()
+                    && size_ok
+                    && keys.min().is_some_and(|&min| min >= representative)
+                    && upper.is_none_or(|upper| keys.max().is_some_and(|max| max < upper))
+            });
+        let count: usize = self.buckets.values().map(|bucket| bucket.keys.len()).sum();
+
+        let mut levels: Vec<HashMap<u64, (u64, u64)>> =
+            (0..=self.bits).map(|_| HashMap::new()).collect();
+        for &representative in &representatives {
+            for length in 0..=self.bits {
+                let prefix = self.prefix(representative, length);
+                levels[length as usize]
+                    .entry(prefix)
+                    .and_modify(|(_, max)| *max = representative)
+                    .or_insert((representative, representative));
+            }
+        }
+
+        representatives.windows(2).all(|pair| pair[0] < pair[1])
+            && representatives.len() == self.buckets.len()
+            && buckets_ok
+            && count == self.len
+            && levels == self.levels
+    }
+}
+
+impl<K> Default for YFastTrie<K>
+where
+    K: IntegerKey,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> fmt::Debug for YFastTrie<K>
+where
+    K: IntegerKey + fmt::Debug,
+{
+    /// Formats the keys in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two tries are equal when they hold the same keys, whatever their universes
+impl<K> PartialEq for YFastTrie<K>
+where
+    K: IntegerKey,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<K> Eq for YFastTrie<K> where K: IntegerKey {}
+
+impl<K> FromIterator<K> for YFastTrie<K>
+where
+    K: IntegerKey,
+{
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<K> Extend<K> for YFastTrie<K>
+where
+    K: IntegerKey,
+{
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+/// An iterator over the keys of a [`YFastTrie`] in increasing order, bucket
+/// after bucket
+pub struct YFastTrieIterator<'a, K>
+where
+    K: IntegerKey,
+{
+    trie: &'a YFastTrie<K>,
+    keys: Option<OrderStatisticTreeIterator<'a, u64>>,
+    /// representative of the bucket after the current one
+    next: Option<u64>,
+    remaining: usize,
+}
+
+impl<K> Iterator for YFastTrieIterator<'_, K>
+where
+    K: IntegerKey,
+{
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(&key) = self.keys.as_mut()?.next() {
+                self.remaining -= 1;
+                return Some(K::from_u64(key));
+            }
+
+            let bucket = &self.trie.buckets[&self.next?];
+            self.keys = Some(bucket.keys.iter());
+            self.next = bucket.next;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K> ExactSizeIterator for YFastTrieIterator<'_, K> where K: IntegerKey {}
+
+impl<'a, K> IntoIterator for &'a YFastTrie<K>
+where
+    K: IntegerKey,
+{
+    type Item = K;
+    type IntoIter = YFastTrieIterator<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::prequel;
+    use std::{
+        collections::BTreeSet,
+        ops::Bound::{Excluded, Unbounded},
+    };
+
+    #[test]
+    fn test_successor_predecessor() {
+        let mut trie: YFastTrie<u32> = prequel();
+
+        assert!(!trie.insert(21));
+        assert_eq!(trie.len(), 7);
+        assert!(trie.contains(36));
+        assert!(!trie.contains(37));
+        assert_eq!((trie.min(), trie.max()), (Some(3), Some(70)));
+        assert_eq!(trie.successor(16), Some(21));
+        assert_eq!(trie.successor(70), None);
+        assert_eq!(trie.predecessor(3), None);
+        assert_eq!(trie.predecessor(u32::MAX), Some(70));
+        assert_eq!(
+            trie.iter().collect::<Vec<_>>(),
+            vec![3, 7, 16, 21, 28, 36, 70]
+        );
+        assert!(trie.is_valid());
+
+        for key in [3, 7, 16, 21, 28, 36, 70] {
+            assert!(trie.remove(key));
+            assert!(trie.is_valid());
+        }
+        assert!(!trie.remove(3));
+        assert!(trie.is_empty());
+        assert_eq!(
+            (trie.min(), trie.max(), trie.successor(0)),
+            (None, None, None)
+        );
+    }
+
+    #[test]
+    fn test_buckets_split_and_merge() {
+        let mut trie = YFastTrie::<u16>::with_universe_bits(10);
+
+        // 10 bits: buckets of 5 to 20 keys
+        trie.extend(0..1024);
+        assert!(trie.is_valid());
+        assert!(trie.buckets.len() >= 1024 / 20);
+        assert!(trie.iter().eq(0..1024));
+
+        for key in (0..1024).filter(|key| key % 4 != 0) {
+            assert!(trie.remove(key));
+        }
+        assert!(trie.is_valid());
+        assert_eq!(trie.len(), 256);
+        assert_eq!(trie.successor(1), Some(4));
+        assert_eq!(trie.predecessor(1021), Some(1020));
+    }
+
+    #[test]
+    fn test_matches_btree_set() {
+        let mut trie = YFastTrie::<u64>::new();
+        let mut set = BTreeSet::new();
+
+        let keys: Vec<u64> = (0..1009u64)
+            .map(|i| (i * 7919 % 1009).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (i % 40))
+            .collect();
+        for &key in &keys {
+            assert_eq!(trie.insert(key), set.insert(key));
+        }
+        assert!(trie.is_valid());
+        assert!(trie.iter().eq(set.iter().copied()));
+
+        for &key in keys.iter().step_by(2) {
+            assert_eq!(trie.remove(key), set.remove(&key));
+        }
+        assert!(trie.is_valid());
+        for &key in &keys {
+            assert_eq!(
+                trie.successor(key),
+                set.range((Excluded(key), Unbounded)).next().copied()
+            );
+            assert_eq!(trie.predecessor(key), set.range(..key).next_back().copied());
+        }
+    }
+}
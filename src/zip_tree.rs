@@ -0,0 +1,488 @@
+use crate::treap::XorShift;
+use std::{
+    borrow::Borrow,
+    cmp::Ordering::{Equal, Greater, Less},
+    fmt,
+};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    data: T,
+    /// geometrically distributed, no greater than the rank of the parent
+    rank: u8,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+/// Splits `link` into the nodes less than `key` and the nodes greater than
+/// it, each side keeping its relative shape
+fn unzip<T: Ord>(link: Link<T>, key: &T) -> (Link<T>, Link<T>) {
+    let Some(mut node) = link else {
+        return (None, None);
+    };
+
+    if node.data < *key {
+        let (middle, right) = unzip(node.right.take(), key);
+        node.right = middle;
+        (Some(node), right)
+    } else {
+        let (left, middle) = unzip(node.left.take(), key);
+        node.left = middle;
+        (left, Some(node))
+    }
+}
+
+/// Joins two subtrees, every element of `left` being smaller than those of
+/// `right`, by interleaving their facing spines in rank order
+fn zip<T>(left: Link<T>, right: Link<T>) -> Link<T> {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        // on equal ranks the smaller element is the ancestor
+        (Some(mut left), Some(mut right)) => {
+            if left.rank >= right.rank {
+                left.right = zip(left.right.take(), Some(right));
+                Some(left)
+            } else {
+                right.left = zip(Some(left), right.left.take());
+                Some(right)
+            }
+        }
+    }
+}
+
+/// A randomized binary search tree behaving like a set, updated by zipping
+/// and unzipping paths instead of rotating
+///
+/// Every node gets a random rank, `k` with probability `1 / 2^(k + 1)`, and
+/// the tree is a max-heap on ranks with ties broken by the smaller element.
+/// An insertion unzips the path below where the new node belongs into its two
+/// subtrees, a removal zips the two subtrees of the node back into one path.
+/// Ranks fit in a byte where a [`crate::Treap`] needs a whole priority word,
+/// for the same `O(log n)` expected depth.
+#[derive(Clone)]
+pub struct ZipTree<T>
+where
+    T: Ord,
+{
+    root: Link<T>,
+    len: usize,
+    rng: XorShift,
+}
+
+impl<T> ZipTree<T>
+where
+    T: Ord,
+{
+    /// Creates an empty tree drawing ranks from a randomly seeded generator
+    pub fn new() -> Self {
+        Self::with_rng(XorShift::from_entropy())
+    }
+
+    /// Creates an empty tree whose ranks are generated from `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(XorShift::with_seed(seed))
+    }
+
+    fn with_rng(rng: XorShift) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            rng,
+        }
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all elements
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    pub fn height(&self) -> usize {
+        fn height<T>(link: &Link<T>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    /// Returns `true` if an element equal to `key` is stored
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored element equal to `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            node = match key.cmp(current.data.borrow()) {
+                Equal => return Some(&current.data),
+                Less => current.left.as_deref(),
+                Greater => current.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Inserts `data`, returning `false` if an equal element is already stored
+    pub fn insert(&mut self, data: T) -> bool {
+        if self.contains(&data) {
+            return false;
+        }
+
+        let rank = self.rng.next_u64().trailing_ones() as u8;
+        self.insert_ranked(data, rank);
+        true
+    }
+
+    /// Inserts `data`, which must not be stored yet, as a node of `rank`
+    fn insert_ranked(&mut self, data: T, rank: u8) {
+        let mut link = &mut self.root;
+
+        // descend past the nodes which stay ancestors of the new one
+        loop {
+            let go_left = match link.as_deref() {
+                Some(node) if node.rank > rank || (node.rank == rank && node.data < data) => {
+                    data < node.data
+                }
+                _ => break,
+            };
+
+            let node = link.as_mut().expect("checked above");
+            link = if go_left {
+                &mut node.left
+            } else {
+                &mut node.right
+            };
+        }
+
+        let (left, right) = unzip(link.take(), &data);
+        *link = Some(Box::new(Node {
+            data,
+            rank,
+            left,
+            right,
+        }));
+
+        self.len += 1;
+    }
+
+    /// Removes `key` from the tree, returning whether it was present
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(key).is_some()
+    }
+
+    /// Removes the element equal to `key` from the tree and returns it
+    pub fn take<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut link = &mut self.root;
+
+        loop {
+            let go_left = match key.cmp(link.as_deref()?.data.borrow()) {
+                Equal => break,
+                Less => true,
+                Greater => false,
+            };
+
+            let node = link.as_mut().expect("checked above");
+            link = if go_left {
+                &mut node.left
+            } else {
+                &mut node.right
+            };
+        }
+
+        let mut node = link.take().expect("found above");
+        *link = zip(node.left.take(), node.right.take());
+
+        self.len -= 1;
+        Some(node.data)
+    }
+
+    /// Returns the smallest element
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns the largest element
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.as_deref()?;
+
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(&node.data)
+    }
+
+    /// Returns a new iterator which iterates over this tree in order
+    pub fn iter(&self) -> ZipTreeIterator<'_, T> {
+        let mut iter = ZipTreeIterator {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        iter.stack_push_left(self.root.as_deref());
+        iter
+    }
+
+    /// Returns `true` if the elements are in search order and the ranks in
+    /// heap order, left children ranking strictly lower than their parent
+    pub fn is_valid(&self) -> bool {
+        fn check<T: Ord>(link: &Link<T>, lower: Option<&T>, upper: Option<&T>) -> Option<usize> {
+            let Some(node) = link else {
+                return Some(0);
+            };
+
+            let ranks_ok = node.left.as_ref().is_none_or(|left| left.rank < node.rank)
+                && node
+                    .right
+                    .as_ref()
+                    .is_none_or(|right| right.rank <= node.rank);
+            if !ranks_ok
+                || lower.is_some_and(|lower| node.data <= *lower)
+                || upper.is_some_and(|upper| node.data >= *upper)
+            {
+                return None;
+            }
+
+            let left = check(&node.left, lower, Some(&node.data))?;
+            let right = check(&node.right, Some(&node.data), upper)?;
+            Some(1 + left + right)
+        }
+
+        check(&self.root, None, None) == Some(self.len)
+    }
+}
+
+impl<T> Default for ZipTree<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for ZipTree<T>
+where
+    T: Ord + fmt::Debug,
+{
+    /// Formats the elements in order, like a set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Two trees are equal when they hold equal elements, regardless of their shape
+impl<T> PartialEq for ZipTree<T>
+where
+    T: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for ZipTree<T> where T: Ord {}
+
+impl<T> FromIterator<T> for ZipTree<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T> Extend<T> for ZipTree<T>
+where
+    T: Ord,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`ZipTree`] in order
+pub struct ZipTreeIterator<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> ZipTreeIterator<'a, T> {
+    fn stack_push_left(&mut self, mut node: Option<&'a Node<T>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for ZipTreeIterator<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.stack_push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some(&node.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for ZipTreeIterator<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a ZipTree<T>
+where
+    T: Ord,
+{
+    type Item = &'a T;
+    type IntoIter = ZipTreeIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{shuffled, PREQUEL};
+    use crate::Treap;
+
+    fn prequel_tree() -> ZipTree<u32> {
+        let mut tree = ZipTree::with_seed(42);
+        tree.extend(PREQUEL);
+        tree
+    }
+
+    /// Builds a tree of `elements` in that order, each ranked by `rank`
+    fn ranked_tree<I, F>(elements: I, rank: F) -> ZipTree<u32>
+    where
+        I: IntoIterator<Item = u32>,
+        F: Fn(u32) -> u8,
+    {
+        let mut tree = ZipTree::with_seed(42);
+        for data in elements {
+            tree.insert_ranked(data, rank(data));
+        }
+        tree
+    }
+
+    /// Returns the element and rank of every node, in preorder
+    fn preorder(tree: &ZipTree<u32>) -> Vec<(u32, u8)> {
+        let mut nodes = Vec::new();
+        let mut stack: Vec<&Node<u32>> = tree.root.as_deref().into_iter().collect();
+
+        while let Some(node) = stack.pop() {
+            nodes.push((node.data, node.rank));
+            stack.extend(node.right.as_deref());
+            stack.extend(node.left.as_deref());
+        }
+        nodes
+    }
+
+    #[test]
+    fn test_rank_ties() {
+        // with a single rank the smaller element is always the ancestor, so
+        // the tree is a right spine whatever the insertion order
+        let mut tree = ranked_tree(PREQUEL, |_| 0);
+        let spine: Vec<_> = [3, 7, 16, 21, 28, 36, 70].map(|data| (data, 0)).into();
+        assert_eq!(preorder(&tree), spine);
+        assert!(tree.is_valid());
+
+        // a new element of the same rank goes below the smaller elements and
+        // above the greater ones
+        assert!(!tree.insert(21));
+        tree.insert_ranked(20, 0);
+        let spine: Vec<_> = [3, 7, 16, 20, 21, 28, 36, 70].map(|data| (data, 0)).into();
+        assert_eq!(preorder(&tree), spine);
+        assert_eq!(tree.take(&3), Some(3));
+        assert_eq!(preorder(&tree), spine[1..]);
+        assert!(tree.is_valid());
+
+        // zipping two equally ranked children makes the smaller their parent
+        let mut tree = ranked_tree([10, 5, 15, 3, 20], |data| match data {
+            10 => 2,
+            5 | 15 => 1,
+            _ => 0,
+        });
+        assert_eq!(
+            preorder(&tree),
+            vec![(10, 2), (5, 1), (3, 0), (15, 1), (20, 0)]
+        );
+        assert!(tree.remove(&10));
+        assert_eq!(preorder(&tree), vec![(5, 1), (3, 0), (15, 1), (20, 0)]);
+        assert!(tree.is_valid());
+
+        // ranks with ties decide the shape alone, not the insertion order
+        let rank = |data: u32| (data % 3) as u8;
+        let forward = ranked_tree(0..100, rank);
+        let backward = ranked_tree((0..100).rev(), rank);
+        let shuffled = ranked_tree(shuffled(100).into_iter().map(|data| data as u32), rank);
+        assert_eq!(preorder(&forward), preorder(&backward));
+        assert_eq!(preorder(&forward), preorder(&shuffled));
+        assert!(forward.is_valid());
+    }
+
+    #[test]
+    fn test_depth_matches_treap() {
+        let mut zip_tree = ZipTree::with_seed(7);
+        let mut treap = Treap::with_seed(7);
+        zip_tree.extend(0..4_000u32);
+        treap.extend(0..4_000u32);
+
+        // sorted input, yet both stay logarithmic
+        assert!(zip_tree.is_valid());
+        assert!(zip_tree.height() < 60);
+        assert!(zip_tree.height().abs_diff(treap.height()) < 30);
+
+        for data in (0..4_000).step_by(2) {
+            assert!(zip_tree.remove(&data));
+        }
+        assert!(zip_tree.is_valid());
+        assert!(zip_tree.iter().copied().eq((1..4_000).step_by(2)));
+    }
+}